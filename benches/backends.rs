@@ -0,0 +1,39 @@
+//! Compare the bytecode VM with the tree-walking evaluator on call heavy
+//! programs, run with `cargo bench`
+
+use std::time::{Duration, Instant};
+
+use rslisp::{Backend, Interpreter};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    ("fib 25", "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))\n(fib 25)"),
+    ("loop 1M", "(define (count n) (let loop ((i 0) (s 0)) (if (< i n) (loop (+ i 1) (+ s i)) s)))\n(count 1000000)"),
+];
+
+/// The number of runs the fastest one is kept of
+const RUNS: usize = 5;
+
+fn time(backend: Backend, source: &str) -> Duration {
+    let interpreter = Interpreter::with_backend(backend);
+    let start = Instant::now();
+    interpreter.eval_str(source).expect("the benchmark fails");
+    start.elapsed()
+}
+
+fn main() {
+    for (name, source) in PROGRAMS {
+        // The runs of the backends alternate so both see the same load
+        let (mut tree, mut vm) = (Duration::MAX, Duration::MAX);
+        for _ in 0..RUNS {
+            tree = tree.min(time(Backend::TreeWalk, source));
+            vm = vm.min(time(Backend::Vm, source));
+        }
+        println!(
+            "{:<8} tree-walker {:>5} ms   vm {:>5} ms   {:.1}x faster",
+            name,
+            tree.as_millis(),
+            vm.as_millis(),
+            tree.as_secs_f64() / vm.as_secs_f64()
+        );
+    }
+}
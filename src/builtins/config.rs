@@ -0,0 +1,89 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use yaml_rust2::{Yaml, YamlLoader};
+
+use super::expect_args;
+use super::json::null;
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::parser::{HashKey, Object};
+
+/// The content of the file at the path given to the builtin
+fn read_file(name: &str, args: &[Object]) -> Result<String, EvalError> {
+    expect_args(name, args, 1)?;
+    let path = match &args[0] {
+        Object::Str { value, .. } => value,
+        obj => return Err(EvalError::type_mismatch(&format!("a path for {}", name), obj)),
+    };
+    std::fs::read_to_string(path).map_err(|e| EvalError::Io { path: path.clone(), message: e.to_string() })
+}
+
+fn decode_error(format: &str, message: String) -> EvalError {
+    EvalError::Decode { format: format.to_string(), message, loc: None }
+}
+
+fn table(entries: Vec<(HashKey, Object)>) -> Object {
+    let table: HashMap<_, _> = entries.into_iter().collect();
+    Object::HashTable { value: Rc::new(RefCell::new(table)), loc: None }
+}
+
+/// Tables are read as hash tables with string keys, arrays as vectors
+/// and the dates as strings
+fn from_toml(value: toml::Value) -> Object {
+    match value {
+        toml::Value::String(value) => Object::Str { value, loc: None },
+        toml::Value::Integer(value) => Object::Integer { value: value as i128, loc: None },
+        toml::Value::Float(value) => Object::Float { value, loc: None },
+        toml::Value::Boolean(value) => Object::Bool { value, loc: None },
+        toml::Value::Datetime(value) => Object::Str { value: value.to_string(), loc: None },
+        toml::Value::Array(items) => Object::Vector { value: Rc::new(items.into_iter().map(from_toml).collect()), loc: None },
+        toml::Value::Table(fields) => table(fields.into_iter().map(|(key, value)| (HashKey::Str(key), from_toml(value))).collect()),
+    }
+}
+
+/// Mappings are read as hash tables, sequences as vectors and null as the symbol `null`
+fn from_yaml(value: Yaml) -> Result<Object, EvalError> {
+    let obj = match value {
+        Yaml::Null => null(),
+        Yaml::Boolean(value) => Object::Bool { value, loc: None },
+        Yaml::Integer(value) => Object::Integer { value: value as i128, loc: None },
+        Yaml::Real(ref text) => match value.as_f64() {
+            Some(value) => Object::Float { value, loc: None },
+            None => return Err(decode_error("YAML", format!("invalid number {}", text))),
+        },
+        Yaml::String(value) => Object::Str { value, loc: None },
+        Yaml::Array(items) => {
+            let items = items.into_iter().map(from_yaml).collect::<Result<_, _>>()?;
+            Object::Vector { value: Rc::new(items), loc: None }
+        },
+        Yaml::Hash(fields) => {
+            let mut entries = vec![];
+            for (key, value) in fields {
+                let key = from_yaml(key)?;
+                let key = HashKey::from_object(&key).ok_or_else(|| decode_error("YAML", format!("{} cannot be a key", key)))?;
+                entries.push((key, from_yaml(value)?));
+            }
+            table(entries)
+        },
+        Yaml::Alias(_) => return Err(decode_error("YAML", "aliases are not supported".to_string())),
+        Yaml::BadValue => return Err(decode_error("YAML", "bad value".to_string())),
+    };
+    Ok(obj)
+}
+
+pub fn read_toml(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (read-toml "config.toml") reads the whole document as a hash table
+    let text = read_file("read-toml", args)?;
+    let document = text.parse::<toml::Table>().map_err(|e| decode_error("TOML", e.message().to_string()))?;
+    Ok(from_toml(toml::Value::Table(document)))
+}
+
+pub fn read_yaml(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (read-yaml "config.yaml") reads the first document, an empty file is null
+    let text = read_file("read-yaml", args)?;
+    let documents = YamlLoader::load_from_str(&text).map_err(|e| decode_error("YAML", e.to_string()))?;
+    match documents.into_iter().next() {
+        Some(document) => from_yaml(document),
+        None => Ok(null()),
+    }
+}
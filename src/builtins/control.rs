@@ -0,0 +1,100 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::{apply, eval_obj, Environment};
+use crate::gc;
+use crate::parser::{Object, Parameter, Promise};
+
+pub fn dynamic_wind(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (dynamic-wind before thunk after) calls the three functions in order,
+    // after is called even when thunk exits by raising an error
+    expect_args("dynamic-wind", args, 3)?;
+    apply(&args[0], &[], env)?;
+    let result = apply(&args[1], &[], env);
+    apply(&args[2], &[], env)?;
+    result
+}
+
+pub fn force(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (force promise) evaluates the delayed expression the first time
+    // and remembers its value, other objects are returned as is
+    expect_args("force", args, 1)?;
+    let promise = match &args[0] {
+        Object::Promise { value, .. } => value,
+        obj => return Ok(obj.clone())
+    };
+
+    let (expr, env) = match &*promise.borrow() {
+        Promise::Forced(value) => return Ok(value.clone()),
+        Promise::Delayed(expr, env) => (expr.clone(), env.clone()),
+    };
+    let value = eval_obj(&expr, &env)?;
+    // Forcing the promise again inside expr may have already set the value
+    let mut promise = promise.borrow_mut();
+    if let Promise::Forced(value) = &*promise {
+        return Ok(value.clone());
+    }
+    *promise = Promise::Forced(value.clone());
+    Ok(value)
+}
+
+pub fn is_promise(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("promise?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Promise { .. }), loc: None })
+}
+
+pub fn make_parameter(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (make-parameter value [converter]) creates a parameter object, called
+    // without arguments it gives its value, which is passed through the
+    // converter first
+    let (value, converter) = match args {
+        [value] => (value.clone(), None),
+        [_, converter] => (apply(converter, &args[..1], env)?, Some(converter.clone())),
+        _ => return Err(EvalError::ArityMismatch {
+            name: "make-parameter".to_string(),
+            expected: Arity::Between(1, 2),
+            found: args.len(),
+            loc: None
+        })
+    };
+    let param = Parameter { value: RefCell::new(value), converter };
+    Ok(Object::Parameter { value: Rc::new(param), loc: None })
+}
+
+pub fn is_parameter(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("parameter?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Parameter { .. }), loc: None })
+}
+
+/// The value of the parameter object called with the arguments
+pub(crate) fn parameter_value(param: &Parameter, args: &[Object]) -> Result<Object, EvalError> {
+    expect_args("parameter", args, 0)?;
+    Ok(param.value.borrow().clone())
+}
+
+pub fn exit(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (exit), (exit code) or (exit #f) ends the program with the status,
+    // 0 unless it is given or #f which gives 1. Only the lowest byte of the
+    // status is seen by the parent process so the others are rejected
+    let code = match args {
+        [] | [Object::Bool { value: true, .. }] => 0,
+        [Object::Bool { value: false, .. }] => 1,
+        [Object::Integer { value, .. }] if (0..=255).contains(value) => *value as i32,
+        [Object::Integer { .. }] => return Err(EvalError::type_mismatch("an exit status between 0 and 255", &args[0])),
+        [obj] => return Err(EvalError::type_mismatch("an integer or a boolean as exit status", obj)),
+        _ => return Err(EvalError::ArityMismatch {
+            name: "exit".to_string(),
+            expected: Arity::Between(0, 1),
+            found: args.len(),
+            loc: None
+        })
+    };
+    Err(EvalError::Exit { code })
+}
+
+pub fn collect_garbage(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (gc) frees the unreachable closure environments and gives their number
+    expect_args("gc", args, 0)?;
+    Ok(Object::Integer { value: gc::collect(env) as i128, loc: None })
+}
@@ -0,0 +1,25 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::parser::Object;
+
+pub fn is_eq(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // Numbers, characters and strings are copied rather than shared so
+    // they have no identity of their own and eq? is the same as eqv?
+    expect_args("eq?", args, 2)?;
+    Ok(Object::Bool { value: args[0].is_eqv(&args[1]), loc: None })
+}
+
+pub fn is_eqv(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (eqv? 2 2) is true but (eqv? 2 2.0) and (eqv? '(1) '(1)) are false
+    expect_args("eqv?", args, 2)?;
+    Ok(Object::Bool { value: args[0].is_eqv(&args[1]), loc: None })
+}
+
+pub fn is_equal(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (equal? '(1 #(2 "a")) (list 1 (vector 2 "a"))) is true
+    expect_args("equal?", args, 2)?;
+    Ok(Object::Bool { value: args[0].is_equal(&args[1]), loc: None })
+}
@@ -0,0 +1,47 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::{ErrorObject, ListItems, Object};
+
+fn as_error<'a>(name: &str, obj: &'a Object) -> Result<&'a ErrorObject, EvalError> {
+    match obj {
+        Object::Error { value, .. } => Ok(value),
+        _ => Err(EvalError::type_mismatch(&format!("an error object for {}", name), obj))
+    }
+}
+
+pub fn raise(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (raise obj) raises any object, which is caught by guard as is
+    expect_args("raise", args, 1)?;
+    Err(EvalError::Raised { value: Box::new(args[0].clone()), loc: None })
+}
+
+pub fn error(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (error "message" irritant ...) raises an error object
+    let (message, irritants) = match args.split_first() {
+        Some((Object::Str { value, .. }, irritants)) => (value.clone(), irritants.to_vec()),
+        Some((obj, _)) => return Err(EvalError::type_mismatch("a message for error", obj)),
+        None => return Err(EvalError::ArityMismatch { name: "error".to_string(), expected: Arity::AtLeast(1), found: 0, loc: None })
+    };
+    let value = Object::Error { value: Rc::new(ErrorObject { message, irritants }), loc: None };
+    Err(EvalError::Raised { value: Box::new(value), loc: None })
+}
+
+pub fn is_error_object(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("error-object?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Error { .. }), loc: None })
+}
+
+pub fn error_object_message(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("error-object-message", args, 1)?;
+    let error = as_error("error-object-message", &args[0])?;
+    Ok(Object::Str { value: error.message.clone(), loc: None })
+}
+
+pub fn error_object_irritants(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("error-object-irritants", args, 1)?;
+    let error = as_error("error-object-irritants", &args[0])?;
+    Ok(Object::List { value: ListItems::new(error.irritants.clone()), loc: None })
+}
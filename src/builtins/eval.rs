@@ -0,0 +1,130 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::{eval_obj, expand, expand_1, Environment};
+use crate::lexer::tokenize;
+use crate::parser::{parse, Object};
+use crate::resolve::resolve;
+
+pub fn eval(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (eval expr [env]) evaluates the data in the caller's environment by default
+    let env = match args {
+        [_] => env.clone(),
+        [_, Object::Environment { value, .. }] => value.clone(),
+        [_, obj] => return Err(EvalError::type_mismatch("an environment for eval", obj)),
+        _ => return Err(EvalError::ArityMismatch { name: "eval".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    eval_obj(&resolve(&expand(&args[0], &env)?), &env)
+}
+
+pub fn macroexpand(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (macroexpand form) expands every macro call in the form, the
+    // macros are looked up in the caller's environment
+    expect_args("macroexpand", args, 1)?;
+    expand(&args[0], env)
+}
+
+pub fn macroexpand_1(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (macroexpand-1 form) expands the form once when it is a macro call
+    expect_args("macroexpand-1", args, 1)?;
+    expand_1(&args[0], env)
+}
+
+pub fn set_reader_macro(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (set-reader-macro! #\d func) calls func with the datum following #d
+    // where it is read, the value of the call is evaluated in its place
+    expect_args("set-reader-macro!", args, 2)?;
+    let c = match &args[0] {
+        Object::Char { value, .. } => *value,
+        obj => return Err(EvalError::type_mismatch("a character for set-reader-macro!", obj)),
+    };
+    if !matches!(args[1], Object::Lambda { .. } | Object::NativeFunction { .. }) {
+        return Err(EvalError::type_mismatch("a function for set-reader-macro!", &args[1]));
+    }
+    Environment::with_runtime(env, |runtime| runtime.reader_macros.insert(c, args[1].clone()));
+    Ok(Object::Void { loc: None })
+}
+
+pub fn interaction_environment(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("interaction-environment", args, 0)?;
+    Ok(Object::Environment { value: Environment::root(env), loc: None })
+}
+
+/// Evaluate the file in the environment, the file is the current file
+/// of the runtime until its evaluation ends
+pub fn eval_file(path: &Path, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let fname = path.to_string_lossy();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| EvalError::Io { path: fname.to_string(), message: e.to_string() })?;
+
+    Environment::with_runtime(env, |runtime| runtime.files.push(path.to_path_buf()));
+    let result = tokenize(&fname, content.as_str())
+        .map_err(EvalError::from)
+        .and_then(|mut tokens| Ok(parse(&mut tokens)?))
+        .and_then(|program| eval_obj(&program, env));
+    Environment::with_runtime(env, |runtime| runtime.files.pop());
+    result
+}
+
+pub fn load(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (load "path") evaluates the file in the caller's environment
+    // and returns the value of its last expression
+    expect_args("load", args, 1)?;
+    let path = match &args[0] {
+        Object::Str { value, .. } => value.as_str(),
+        obj => return Err(EvalError::type_mismatch("a path for load", obj))
+    };
+    eval_file(Path::new(path), env)
+}
+
+/// Look the module file up in the directories of `RSLISP_PATH` and then
+/// next to the current file, `name` without extension is given `.rsl`
+fn find_module(name: &str, env: &Rc<RefCell<Environment>>) -> Option<PathBuf> {
+    let mut file = PathBuf::from(name);
+    if file.extension().is_none() {
+        file.set_extension("rsl");
+    }
+
+    let mut dirs: Vec<PathBuf> = std::env::var_os("RSLISP_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    let current_dir = Environment::with_runtime(env, |runtime| {
+        runtime.current_file().and_then(|file| file.parent()).map(Path::to_path_buf)
+    });
+    dirs.push(current_dir.unwrap_or_default());
+
+    dirs.iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .and_then(|path| path.canonicalize().ok())
+}
+
+pub fn require(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (require 'name) evaluates the module in the global environment
+    // the first time only, later requires return the cached value
+    expect_args("require", args, 1)?;
+    let name = match &args[0] {
+        Object::Symbol { value, .. } => value.as_str(),
+        Object::Str { value, .. } => value.as_str(),
+        obj => return Err(EvalError::type_mismatch("a module name for require", obj))
+    };
+    let path = find_module(name, env)
+        .ok_or_else(|| EvalError::ModuleNotFound { name: name.to_string(), loc: args[0].loc().cloned() })?;
+
+    if let Some(value) = Environment::with_runtime(env, |runtime| runtime.modules.get(&path).cloned()) {
+        return Ok(value);
+    }
+    // Register the module before evaluating it so that cyclic requires end
+    Environment::with_runtime(env, |runtime| runtime.modules.insert(path.clone(), Object::Void { loc: None }));
+    let result = eval_file(&path, &Environment::root(env));
+    Environment::with_runtime(env, |runtime| match &result {
+        Ok(value) => runtime.modules.insert(path, value.clone()),
+        Err(_) => runtime.modules.remove(&path),
+    });
+    result
+}
@@ -0,0 +1,75 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::{HashKey, Object};
+
+type Table = Rc<RefCell<HashMap<HashKey, Object>>>;
+
+fn as_table<'a>(name: &str, obj: &'a Object) -> Result<&'a Table, EvalError> {
+    match obj {
+        Object::HashTable { value, .. } => Ok(value),
+        _ => Err(EvalError::type_mismatch(&format!("a hash table for {}", name), obj))
+    }
+}
+
+fn as_key(name: &str, obj: &Object) -> Result<HashKey, EvalError> {
+    HashKey::from_object(obj).ok_or_else(|| EvalError::type_mismatch(&format!("a hashable key for {}", name), obj))
+}
+
+pub fn make_hash(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("make-hash", args, 0)?;
+    Ok(Object::HashTable { value: Rc::new(RefCell::new(HashMap::new())), loc: None })
+}
+
+pub fn is_hash(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("hash?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::HashTable { .. }), loc: None })
+}
+
+pub fn hash_ref(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (hash-ref table key [default]), a missing key without default is an error
+    if args.len() != 2 && args.len() != 3 {
+        return Err(EvalError::ArityMismatch { name: "hash-ref".to_string(), expected: Arity::Between(2, 3), found: args.len(), loc: None });
+    }
+    let table = as_table("hash-ref", &args[0])?;
+    let key = as_key("hash-ref", &args[1])?;
+    let value = table.borrow().get(&key).cloned();
+    match (value, args.get(2)) {
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(EvalError::KeyNotFound { key: args[1].to_string(), loc: args[1].loc().cloned() })
+    }
+}
+
+pub fn hash_set(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("hash-set!", args, 3)?;
+    let table = as_table("hash-set!", &args[0])?;
+    let key = as_key("hash-set!", &args[1])?;
+    table.borrow_mut().insert(key, args[2].clone());
+    Ok(Object::Void { loc: None })
+}
+
+pub fn hash_remove(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("hash-remove!", args, 2)?;
+    let table = as_table("hash-remove!", &args[0])?;
+    let key = as_key("hash-remove!", &args[1])?;
+    table.borrow_mut().remove(&key);
+    Ok(Object::Void { loc: None })
+}
+
+pub fn hash_has_key(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("hash-has-key?", args, 2)?;
+    let table = as_table("hash-has-key?", &args[0])?;
+    let key = as_key("hash-has-key?", &args[1])?;
+    let value = table.borrow().contains_key(&key);
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn hash_count(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("hash-count", args, 1)?;
+    let table = as_table("hash-count", &args[0])?;
+    let value = table.borrow().len() as i128;
+    Ok(Object::Integer { value, loc: None })
+}
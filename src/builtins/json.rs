@@ -0,0 +1,100 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde_json::{Map, Number, Value};
+
+use super::expect_args;
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::parser::{HashKey, Object};
+use crate::symbol::Symbol;
+
+/// The object JSON's null is read as, i.e. the symbol `null`
+pub fn null() -> Object {
+    Object::Symbol { value: Symbol::intern("null"), loc: None }
+}
+
+/// JSON objects are read as hash tables with string keys and arrays as vectors,
+/// integers too large for a fixnum are read as big integers and the other
+/// numbers too large for a float as infinities, like JavaScript reads them
+pub fn to_object(value: Value) -> Object {
+    match value {
+        Value::Null => null(),
+        Value::Bool(value) => Object::Bool { value, loc: None },
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => Object::Integer { value: value as i128, loc: None },
+            None => match number.to_string().parse::<BigInt>() {
+                Ok(value) => match value.to_i128() {
+                    Some(value) => Object::Integer { value, loc: None },
+                    None => Object::BigInteger { value, loc: None },
+                },
+                Err(_) => Object::Float { value: number.to_string().parse().expect("a JSON number is a float"), loc: None },
+            },
+        },
+        Value::String(value) => Object::Str { value, loc: None },
+        Value::Array(items) => Object::Vector { value: Rc::new(items.into_iter().map(to_object).collect()), loc: None },
+        Value::Object(fields) => {
+            let table: HashMap<_, _> = fields.into_iter().map(|(key, value)| (HashKey::Str(key), to_object(value))).collect();
+            Object::HashTable { value: Rc::new(RefCell::new(table)), loc: None }
+        },
+    }
+}
+
+/// The inverse of [`to_object`], lists are written as arrays too and the keys of
+/// the hash tables must be strings, symbols or keywords
+pub fn from_object(name: &str, obj: &Object) -> Result<Value, EvalError> {
+    let number = |text: String| text.parse::<Number>().map(Value::Number).map_err(|_| EvalError::type_mismatch("a JSON number", obj));
+    let value = match obj {
+        Object::Void { .. } => Value::Null,
+        Object::Symbol { value, .. } if value.as_str() == "null" => Value::Null,
+        Object::Symbol { value, .. } | Object::Keyword { value, .. } => Value::String(value.to_string()),
+        Object::Bool { value, .. } => Value::Bool(*value),
+        Object::Integer { value, .. } => number(value.to_string())?,
+        Object::BigInteger { value, .. } => number(value.to_string())?,
+        Object::Rational { value, .. } => match value.to_f64().and_then(Number::from_f64) {
+            Some(number) => Value::Number(number),
+            None => return Err(EvalError::type_mismatch(&format!("a finite number for {}", name), obj)),
+        },
+        Object::Float { value, .. } => match Number::from_f64(*value) {
+            Some(number) => Value::Number(number),
+            None => return Err(EvalError::type_mismatch(&format!("a finite number for {}", name), obj)),
+        },
+        Object::Char { value, .. } => Value::String(value.to_string()),
+        Object::Str { value, .. } => Value::String(value.clone()),
+        Object::List { value, .. } => Value::Array(value.iter().map(|item| from_object(name, item)).collect::<Result<_, _>>()?),
+        Object::Vector { value, .. } => Value::Array(value.iter().map(|item| from_object(name, item)).collect::<Result<_, _>>()?),
+        Object::HashTable { value, .. } => {
+            let mut fields = Map::new();
+            for (key, item) in value.borrow().iter() {
+                let key = match key {
+                    HashKey::Str(key) => key.clone(),
+                    HashKey::Symbol(key) | HashKey::Keyword(key) => key.to_string(),
+                    key => return Err(EvalError::type_mismatch(&format!("string keys for {}", name), &key.to_object())),
+                };
+                fields.insert(key, from_object(name, item)?);
+            }
+            Value::Object(fields)
+        },
+        obj => return Err(EvalError::type_mismatch(&format!("data representable in JSON for {}", name), obj)),
+    };
+    Ok(value)
+}
+
+pub fn json_parse(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (json-parse "{\"a\": [1, 2]}")
+    expect_args("json-parse", args, 1)?;
+    let text = match &args[0] {
+        Object::Str { value, .. } => value,
+        obj => return Err(EvalError::type_mismatch("a string for json-parse", obj)),
+    };
+    let value = serde_json::from_str(text)
+        .map_err(|e| EvalError::Decode { format: "JSON".to_string(), message: e.to_string(), loc: None })?;
+    Ok(to_object(value))
+}
+
+pub fn json_stringify(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("json-stringify", args, 1)?;
+    let value = from_object("json-stringify", &args[0])?;
+    Ok(Object::Str { value: value.to_string(), loc: None })
+}
@@ -0,0 +1,183 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::{apply, is_truthy, Environment};
+use crate::parser::{ListItems, Object};
+
+pub fn cons(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (cons 1 '(2 3)) is the list (1 2 3), while (cons 1 2) is the pair (1 . 2)
+    expect_args("cons", args, 2)?;
+    let car = args[0].clone();
+    match &args[1] {
+        Object::List { value, .. } => Ok(Object::List { value: value.cons(car), loc: None }),
+        cdr => Ok(Object::Pair { value: Rc::new((car, cdr.clone())), loc: None })
+    }
+}
+
+pub fn car(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("car", args, 1)?;
+    match &args[0] {
+        // The first item is taken without copying the items of the cells
+        Object::List { value, .. } if !value.is_empty() => Ok(value.first().expect("the list is not empty").clone()),
+        Object::Pair { value, .. } => Ok(value.0.clone()),
+        obj => Err(EvalError::type_mismatch("a pair for car", obj))
+    }
+}
+
+pub fn cdr(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("cdr", args, 1)?;
+    match &args[0] {
+        Object::List { value, .. } if !value.is_empty() => Ok(Object::List { value: value.rest(), loc: None }),
+        Object::Pair { value, .. } => Ok(value.1.clone()),
+        obj => Err(EvalError::type_mismatch("a pair for cdr", obj))
+    }
+}
+
+pub fn list(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    Ok(Object::List { value: ListItems::new(args.to_vec()), loc: None })
+}
+
+pub fn is_pair(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("pair?", args, 1)?;
+    let value = match &args[0] {
+        Object::List { value, .. } => !value.is_empty(),
+        Object::Pair { .. } => true,
+        _ => false
+    };
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn is_null(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("null?", args, 1)?;
+    let value = matches!(&args[0], Object::List { value, .. } if value.is_empty());
+    Ok(Object::Bool { value, loc: None })
+}
+
+/// Borrow the elements of a proper list
+fn items<'a>(name: &str, obj: &'a Object) -> Result<&'a [Object], EvalError> {
+    match obj {
+        Object::List { value, .. } => Ok(value.as_slice()),
+        _ => Err(EvalError::type_mismatch(&format!("a list for {}", name), obj))
+    }
+}
+
+/// The arguments of the calls made by `name` with the i-th elements of
+/// every list, stopping at the end of the shortest list. The arguments
+/// before the lists, e.g. the function, are counted in `min_args`
+fn parallel(name: &str, args: &[Object], min_args: usize) -> Result<Vec<Vec<Object>>, EvalError> {
+    if args.len() < min_args {
+        return Err(EvalError::ArityMismatch { name: name.to_string(), expected: Arity::AtLeast(min_args), found: args.len(), loc: None });
+    }
+    let lists = args[min_args - 1..]
+        .iter()
+        .map(|obj| items(name, obj))
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = lists.iter().map(|list| list.len()).min().unwrap_or(0);
+    Ok((0..len).map(|i| lists.iter().map(|list| list[i].clone()).collect()).collect())
+}
+
+pub fn map(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (map f xs ys ...) calls f with the i-th elements of every list,
+    // stopping at the end of the shortest list
+    let value = parallel("map", args, 2)?
+        .iter()
+        .map(|call_args| apply(&args[0], call_args, env))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Object::List { value: ListItems::new(value), loc: None })
+}
+
+pub fn for_each(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (for-each f xs ys ...) is map for the side effects of f only
+    for call_args in parallel("for-each", args, 2)? {
+        apply(&args[0], &call_args, env)?;
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn filter(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (filter pred xs) keeps the elements satisfying pred
+    expect_args("filter", args, 2)?;
+    let mut value = vec![];
+    for item in items("filter", &args[1])? {
+        if is_truthy(&apply(&args[0], std::slice::from_ref(item), env)?) {
+            value.push(item.clone());
+        }
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: None })
+}
+
+pub fn fold(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (fold f init xs) computes (f x3 (f x2 (f x1 init)))
+    expect_args("fold", args, 3)?;
+    items("fold", &args[2])?
+        .iter()
+        .try_fold(args[1].clone(), |acc, item| apply(&args[0], &[item.clone(), acc], env))
+}
+
+pub fn fold_left(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (fold-left f init xs ys ...) computes (f (f init x1 y1) x2 y2),
+    // the accumulated value comes first
+    parallel("fold-left", args, 3)?.into_iter().try_fold(args[1].clone(), |acc, mut call_args| {
+        call_args.insert(0, acc);
+        apply(&args[0], &call_args, env)
+    })
+}
+
+pub fn fold_right(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (fold-right f init xs ys ...) computes (f x1 y1 (f x2 y2 init)),
+    // the accumulated value comes last
+    parallel("fold-right", args, 3)?.into_iter().rev().try_fold(args[1].clone(), |acc, mut call_args| {
+        call_args.push(acc);
+        apply(&args[0], &call_args, env)
+    })
+}
+
+pub fn reduce(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (reduce f init xs) is like fold but uses the first element as the
+    // initial value, init is only returned for the empty list
+    expect_args("reduce", args, 3)?;
+    match items("reduce", &args[2])?.split_first() {
+        Some((first, rest)) => rest
+            .iter()
+            .try_fold(first.clone(), |acc, item| apply(&args[0], &[item.clone(), acc], env)),
+        None => Ok(args[1].clone())
+    }
+}
+
+pub fn sort(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (sort xs less?) sorts a list or a vector into a new one, the sort is
+    // stable so the elements which are not less than each other keep
+    // their order
+    expect_args("sort", args, 2)?;
+    let mut less = |a: &Object, b: &Object| Ok(is_truthy(&apply(&args[1], &[a.clone(), b.clone()], env)?));
+    match &args[0] {
+        Object::List { value, .. } => Ok(Object::List { value: ListItems::new(merge_sort(value, &mut less)?), loc: None }),
+        Object::Vector { value, .. } => Ok(Object::Vector { value: Rc::new(merge_sort(value, &mut less)?), loc: None }),
+        obj => Err(EvalError::type_mismatch("a list or a vector for sort", obj))
+    }
+}
+
+/// Merge sort with a comparison which may fail, `sort_by` is not used
+/// as it may panic when the order given by the program is not total
+fn merge_sort(
+    items: &[Object],
+    less: &mut dyn FnMut(&Object, &Object) -> Result<bool, EvalError>,
+) -> Result<Vec<Object>, EvalError> {
+    if items.len() <= 1 {
+        return Ok(items.to_vec());
+    }
+    let (left, right) = items.split_at(items.len() / 2);
+    let mut left = merge_sort(left, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(items.len());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Taking the left element unless the right one is less keeps
+        // the sort stable
+        let item = if less(b, a)? { right.next() } else { left.next() };
+        merged.extend(item);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -0,0 +1,203 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::native::NativeFunction;
+use crate::parser::Object;
+
+pub mod config;
+pub mod control;
+pub mod equal;
+pub mod error;
+pub mod eval;
+pub mod hash;
+pub mod json;
+pub mod list;
+pub mod record;
+pub mod numeric;
+pub mod os;
+pub mod port;
+pub mod random;
+pub mod string;
+pub mod symbol;
+pub mod test;
+pub mod trace;
+pub mod vector;
+
+/// Signature shared by every builtin function, the arguments are
+/// already evaluated when the builtin is called
+pub type BuiltinFn = fn(&[Object], &Rc<RefCell<Environment>>) -> Result<Object, EvalError>;
+
+/// Every builtin function installed in the root environment with the
+/// number of arguments it accepts, which is checked before it is called
+pub const BUILTINS: &[(&str, Arity, BuiltinFn)] = &[
+    ("+", Arity::AtLeast(0), numeric::add),
+    ("-", Arity::AtLeast(1), numeric::sub),
+    ("*", Arity::AtLeast(0), numeric::mul),
+    ("/", Arity::AtLeast(1), numeric::div),
+    ("%", Arity::AtLeast(1), numeric::rem),
+    ("quotient", Arity::Exact(2), numeric::quotient),
+    ("remainder", Arity::Exact(2), numeric::remainder),
+    ("modulo", Arity::Exact(2), numeric::modulo),
+    (">", Arity::AtLeast(1), numeric::gt),
+    ("<", Arity::AtLeast(1), numeric::lt),
+    ("=", Arity::AtLeast(1), numeric::eq),
+    (">=", Arity::AtLeast(1), numeric::ge),
+    ("<=", Arity::AtLeast(1), numeric::le),
+    ("/=", Arity::AtLeast(1), numeric::ne),
+    ("rational?", Arity::Exact(1), numeric::is_rational),
+    ("numerator", Arity::Exact(1), numeric::numerator),
+    ("denominator", Arity::Exact(1), numeric::denominator),
+    ("string->number", Arity::Between(1, 2), numeric::string_to_number),
+    ("number->string", Arity::Between(1, 2), numeric::number_to_string),
+    ("sqrt", Arity::Exact(1), numeric::sqrt),
+    ("expt", Arity::Exact(2), numeric::expt),
+    ("exp", Arity::Exact(1), numeric::exp),
+    ("log", Arity::Between(1, 2), numeric::log),
+    ("sin", Arity::Exact(1), numeric::sin),
+    ("cos", Arity::Exact(1), numeric::cos),
+    ("tan", Arity::Exact(1), numeric::tan),
+    ("atan", Arity::Between(1, 2), numeric::atan),
+    ("floor", Arity::Exact(1), numeric::floor),
+    ("ceiling", Arity::Exact(1), numeric::ceiling),
+    ("round", Arity::Exact(1), numeric::round),
+    ("truncate", Arity::Exact(1), numeric::truncate),
+    ("eq?", Arity::Exact(2), equal::is_eq),
+    ("eqv?", Arity::Exact(2), equal::is_eqv),
+    ("equal?", Arity::Exact(2), equal::is_equal),
+    ("gensym", Arity::Between(0, 1), symbol::gensym),
+    ("symbol->string", Arity::Exact(1), symbol::symbol_to_string),
+    ("string->symbol", Arity::Exact(1), symbol::string_to_symbol),
+    ("keyword?", Arity::Exact(1), symbol::is_keyword),
+    ("keyword->string", Arity::Exact(1), symbol::keyword_to_string),
+    ("cons", Arity::Exact(2), list::cons),
+    ("car", Arity::Exact(1), list::car),
+    ("cdr", Arity::Exact(1), list::cdr),
+    ("list", Arity::AtLeast(0), list::list),
+    ("pair?", Arity::Exact(1), list::is_pair),
+    ("null?", Arity::Exact(1), list::is_null),
+    ("map", Arity::AtLeast(2), list::map),
+    ("for-each", Arity::AtLeast(2), list::for_each),
+    ("filter", Arity::Exact(2), list::filter),
+    ("fold", Arity::Exact(3), list::fold),
+    ("fold-left", Arity::AtLeast(3), list::fold_left),
+    ("fold-right", Arity::AtLeast(3), list::fold_right),
+    ("reduce", Arity::Exact(3), list::reduce),
+    ("sort", Arity::Exact(2), list::sort),
+    ("string?", Arity::Exact(1), string::is_string),
+    ("string-length", Arity::Exact(1), string::string_length),
+    ("substring", Arity::Between(2, 3), string::substring),
+    ("string-append", Arity::AtLeast(0), string::string_append),
+    ("string-ref", Arity::Exact(2), string::string_ref),
+    ("format", Arity::AtLeast(1), string::format),
+    ("string->list", Arity::Exact(1), string::string_to_list),
+    ("list->string", Arity::Exact(1), string::list_to_string),
+    ("char?", Arity::Exact(1), string::is_char),
+    ("vector", Arity::AtLeast(0), vector::vector),
+    ("make-vector", Arity::Between(1, 2), vector::make_vector),
+    ("vector?", Arity::Exact(1), vector::is_vector),
+    ("vector-length", Arity::Exact(1), vector::vector_length),
+    ("vector-ref", Arity::Exact(2), vector::vector_ref),
+    ("make-hash", Arity::Exact(0), hash::make_hash),
+    ("hash?", Arity::Exact(1), hash::is_hash),
+    ("hash-ref", Arity::Between(2, 3), hash::hash_ref),
+    ("hash-set!", Arity::Exact(3), hash::hash_set),
+    ("hash-remove!", Arity::Exact(2), hash::hash_remove),
+    ("hash-has-key?", Arity::Exact(2), hash::hash_has_key),
+    ("hash-count", Arity::Exact(1), hash::hash_count),
+    ("json-parse", Arity::Exact(1), json::json_parse),
+    ("json-stringify", Arity::Exact(1), json::json_stringify),
+    ("read-toml", Arity::Exact(1), config::read_toml),
+    ("read-yaml", Arity::Exact(1), config::read_yaml),
+    ("open-input-file", Arity::Exact(1), port::open_input_file),
+    ("open-output-file", Arity::Exact(1), port::open_output_file),
+    ("read-line", Arity::Exact(1), port::read_line),
+    ("read", Arity::Between(0, 1), port::read),
+    ("read-string", Arity::Exact(1), port::read_string),
+    ("write-string", Arity::Between(1, 2), port::write_string),
+    ("display", Arity::Between(1, 2), port::display),
+    ("write", Arity::Between(1, 2), port::write),
+    ("pp", Arity::Between(1, 2), port::pp),
+    ("newline", Arity::Between(0, 1), port::newline),
+    ("println", Arity::AtLeast(0), port::println),
+    ("current-output-port", Arity::Exact(0), port::current_output_port),
+    ("current-error-port", Arity::Exact(0), port::current_error_port),
+    ("close-port", Arity::Exact(1), port::close_port),
+    ("eof-object", Arity::Exact(0), port::eof_object),
+    ("eof-object?", Arity::Exact(1), port::is_eof_object),
+    ("make-record", Arity::AtLeast(1), record::make_record),
+    ("record?", Arity::Between(1, 2), record::is_record),
+    ("record-ref", Arity::Exact(3), record::record_ref),
+    ("record-set!", Arity::Exact(4), record::record_set),
+    ("dynamic-wind", Arity::Exact(3), control::dynamic_wind),
+    ("force", Arity::Exact(1), control::force),
+    ("gc", Arity::Exact(0), control::collect_garbage),
+    ("promise?", Arity::Exact(1), control::is_promise),
+    ("make-parameter", Arity::Between(1, 2), control::make_parameter),
+    ("parameter?", Arity::Exact(1), control::is_parameter),
+    ("exit", Arity::Between(0, 1), control::exit),
+    ("raise", Arity::Exact(1), error::raise),
+    ("error", Arity::AtLeast(1), error::error),
+    ("error-object?", Arity::Exact(1), error::is_error_object),
+    ("error-object-message", Arity::Exact(1), error::error_object_message),
+    ("error-object-irritants", Arity::Exact(1), error::error_object_irritants),
+    ("eval", Arity::Between(1, 2), eval::eval),
+    ("macroexpand", Arity::Exact(1), eval::macroexpand),
+    ("macroexpand-1", Arity::Exact(1), eval::macroexpand_1),
+    ("set-reader-macro!", Arity::Exact(2), eval::set_reader_macro),
+    ("trace", Arity::AtLeast(0), trace::trace),
+    ("untrace", Arity::AtLeast(0), trace::untrace),
+    ("interaction-environment", Arity::Exact(0), eval::interaction_environment),
+    ("load", Arity::Exact(1), eval::load),
+    ("require", Arity::Exact(1), eval::require),
+    ("getenv", Arity::Exact(1), os::getenv),
+    ("setenv", Arity::Exact(2), os::setenv),
+    ("system", Arity::Exact(1), os::system),
+    ("run-process", Arity::AtLeast(1), os::run_process),
+    ("current-time", Arity::Exact(0), os::current_time),
+    ("current-milliseconds", Arity::Exact(0), os::current_milliseconds),
+    ("sleep", Arity::Exact(1), os::sleep),
+    ("random", Arity::Exact(0), random::random),
+    ("random-integer", Arity::Exact(1), random::random_integer),
+    ("random-seed!", Arity::Exact(1), random::random_seed),
+    ("assert", Arity::Between(1, 2), test::assert),
+    ("assert-equal", Arity::Exact(2), test::assert_equal),
+    ("run-tests", Arity::Exact(0), test::run_tests),
+];
+
+/// The builtins acting on the world outside of the program, i.e. the files,
+/// the processes, the environment variables, the time and the standard input,
+/// they are disabled in a sandbox unless allowed, see [`crate::Interpreter::set_sandbox`].
+/// `read` reads the standard input without a port, `read-line` always takes a port
+pub const SIDE_EFFECTS: &[&str] = &[
+    "open-input-file",
+    "open-output-file",
+    "read",
+    "read-toml",
+    "read-yaml",
+    "load",
+    "require",
+    "getenv",
+    "setenv",
+    "system",
+    "run-process",
+    "current-time",
+    "current-milliseconds",
+    "sleep",
+];
+
+/// Find the builtin function by its name
+pub fn lookup(name: &str) -> Option<NativeFunction> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _, _)| *builtin == name)
+        .map(|(name, arity, func)| NativeFunction::builtin(name, *arity, *func))
+}
+
+/// Check the number of arguments given to the builtin function
+pub fn expect_args(name: &str, args: &[Object], count: usize) -> Result<(), EvalError> {
+    if args.len() != count {
+        return Err(EvalError::ArityMismatch { name: name.to_string(), expected: Arity::Exact(count), found: args.len(), loc: None });
+    }
+    Ok(())
+}
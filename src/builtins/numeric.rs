@@ -0,0 +1,552 @@
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::lexer::{tokenize, TokenKind};
+use crate::native::{NativeFn, NativeFunction};
+use crate::parser::Object;
+
+/// The numeric value of an Object, Integers and Rationals are computed
+/// exactly and promoted to Float as soon as a Float takes part in the operation.
+/// Integers overflowing i128 are promoted to BigInteger and demoted again
+/// whenever the result fits in i128.
+///
+/// Dividing by an exact zero is a DivisionByZero error, while dividing
+/// by a Float zero follows IEEE 754 and gives inf or NaN.
+#[derive(Debug, Clone)]
+enum Number {
+    Integer(i128),
+    BigInteger(BigInt),
+    Rational(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    fn from_object(obj: &Object) -> Result<Self, EvalError> {
+        match obj {
+            Object::Integer { value, .. } => Ok(Number::Integer(*value)),
+            Object::BigInteger { value, .. } => Ok(Number::BigInteger(value.clone())),
+            Object::Rational { value, .. } => Ok(Number::Rational(value.clone())),
+            Object::Float { value, .. } => Ok(Number::Float(*value)),
+            _ => Err(EvalError::type_mismatch("number", obj)),
+        }
+    }
+
+    /// BigIntegers fitting in i128 become Integers
+    fn from_bigint(value: BigInt) -> Self {
+        match value.to_i128() {
+            Some(n) => Number::Integer(n),
+            None => Number::BigInteger(value),
+        }
+    }
+
+    /// Rationals with a denominator of 1 become Integers
+    fn from_ratio(value: BigRational) -> Self {
+        if value.is_integer() {
+            Number::from_bigint(value.to_integer())
+        } else {
+            Number::Rational(value)
+        }
+    }
+
+    fn into_object(self) -> Object {
+        match self {
+            Number::Integer(value) => Object::Integer { value, loc: None },
+            Number::BigInteger(value) => Object::BigInteger { value, loc: None },
+            Number::Rational(value) => Object::Rational { value, loc: None },
+            Number::Float(value) => Object::Float { value, loc: None },
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Number::Integer(n) => *n as f64,
+            Number::BigInteger(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => *n,
+        }
+    }
+
+    /// The value of an Integer or BigInteger
+    fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Number::Integer(n) => Some(BigInt::from(*n)),
+            Number::BigInteger(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
+    /// The exact value of the number, None for Floats
+    fn as_ratio(&self) -> Option<BigRational> {
+        match self {
+            Number::Rational(n) => Some(n.clone()),
+            Number::Float(_) => None,
+            n => n.as_bigint().map(BigRational::from_integer),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(n) => *n == 0,
+            Number::BigInteger(n) => n.is_zero(),
+            Number::Rational(n) => n.is_zero(),
+            Number::Float(n) => *n == 0.0,
+        }
+    }
+
+    /// Apply the operation matching the most precise representation
+    /// able to hold both of the numbers, the i128 operation falls back
+    /// to the BigInt one when it overflows
+    fn combine(
+        &self,
+        other: &Self,
+        int_op: fn(i128, i128) -> Option<i128>,
+        big_op: fn(BigInt, BigInt) -> BigInt,
+        ratio_op: fn(BigRational, BigRational) -> BigRational,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Number {
+        if let (Number::Integer(a), Number::Integer(b)) = (self, other) {
+            if let Some(n) = int_op(*a, *b) {
+                return Number::Integer(n);
+            }
+        }
+        if let (Some(a), Some(b)) = (self.as_bigint(), other.as_bigint()) {
+            return Number::from_bigint(big_op(a, b));
+        }
+        match (self.as_ratio(), other.as_ratio()) {
+            (Some(a), Some(b)) => Number::from_ratio(ratio_op(a, b)),
+            _ => Number::Float(float_op(self.as_f64(), other.as_f64())),
+        }
+    }
+
+    fn add(self, other: Self) -> Result<Self, EvalError> {
+        Ok(self.combine(&other, i128::checked_add, |a, b| a + b, |a, b| a + b, |a, b| a + b))
+    }
+
+    fn sub(self, other: Self) -> Result<Self, EvalError> {
+        Ok(self.combine(&other, i128::checked_sub, |a, b| a - b, |a, b| a - b, |a, b| a - b))
+    }
+
+    fn mul(self, other: Self) -> Result<Self, EvalError> {
+        Ok(self.combine(&other, i128::checked_mul, |a, b| a * b, |a, b| a * b, |a, b| a * b))
+    }
+
+    fn div(self, other: Self) -> Result<Self, EvalError> {
+        if other.is_zero() && !matches!(other, Number::Float(_)) {
+            return Err(EvalError::DivisionByZero { loc: None });
+        }
+        if let (Number::Integer(a), Number::Integer(b)) = (&self, &other) {
+            if let Some(n) = a.checked_rem(*b).filter(|r| *r == 0).and_then(|_| a.checked_div(*b)) {
+                return Ok(Number::Integer(n));
+            }
+        }
+        // Dividing Integers gives a Rational unless the division is exact
+        match (self.as_ratio(), other.as_ratio()) {
+            (Some(a), Some(b)) => Ok(Number::from_ratio(a / b)),
+            _ => Ok(Number::Float(self.as_f64() / other.as_f64())),
+        }
+    }
+
+    fn rem(self, other: Self) -> Result<Self, EvalError> {
+        if other.is_zero() && !matches!(other, Number::Float(_)) {
+            return Err(EvalError::DivisionByZero { loc: None });
+        }
+        // The remainder has the sign of the dividend, a - b * trunc(a / b)
+        Ok(self.combine(
+            &other,
+            i128::checked_rem,
+            |a, b| a % b,
+            |a, b| &a - &b * (&a / &b).trunc(),
+            |a, b| a % b,
+        ))
+    }
+
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        if let (Number::Integer(a), Number::Integer(b)) = (self, other) {
+            return Some(a.cmp(b));
+        }
+        match (self.as_ratio(), other.as_ratio()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => self.as_f64().partial_cmp(&other.as_f64()),
+        }
+    }
+}
+
+/// The value of any number as a Float
+pub(crate) fn to_f64(obj: &Object) -> Result<f64, EvalError> {
+    Number::from_object(obj).map(|n| n.as_f64())
+}
+
+fn numbers(args: &[Object]) -> Result<Vec<Number>, EvalError> {
+    args.iter().map(Number::from_object).collect()
+}
+
+/// Fold the numbers from left to right starting with the first one,
+/// a single argument is combined with the identity instead, e.g. (- 1) is (- 0 1)
+fn fold(
+    name: &str,
+    args: &[Object],
+    identity: Option<Number>,
+    op: fn(Number, Number) -> Result<Number, EvalError>,
+) -> Result<Object, EvalError> {
+    let nums = numbers(args)?;
+    let result = match (nums.split_first(), identity) {
+        (Some((first, [])), Some(identity)) => op(identity, first.clone())?,
+        (Some((first, rest)), _) => rest.iter().try_fold(first.clone(), |acc, n| op(acc, n.clone()))?,
+        (None, _) => return Err(EvalError::ArityMismatch { name: name.to_string(), expected: Arity::AtLeast(1), found: 0, loc: None }),
+    };
+    Ok(result.into_object())
+}
+
+/// The value of a builtin arithmetic operation or comparison of two
+/// integers, computed without converting the arguments to numbers. None
+/// for the other functions or arguments, or when the result overflows
+pub(crate) fn integer_op(func: &NativeFunction, args: &[Object]) -> Option<Object> {
+    let NativeFn::Builtin(_) = func.func else {
+        return None;
+    };
+    let [Object::Integer { value: a, .. }, Object::Integer { value: b, .. }] = args else {
+        return None;
+    };
+    let integer = |value: Option<i128>| value.map(|value| Object::Integer { value, loc: None });
+    let bool = |value: bool| Some(Object::Bool { value, loc: None });
+    match func.name.as_str() {
+        "+" => integer(a.checked_add(*b)),
+        "-" => integer(a.checked_sub(*b)),
+        "*" => integer(a.checked_mul(*b)),
+        "<" => bool(a < b),
+        ">" => bool(a > b),
+        "=" => bool(a == b),
+        "<=" => bool(a <= b),
+        ">=" => bool(a >= b),
+        _ => None,
+    }
+}
+
+pub fn add(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let nums = numbers(args)?;
+    nums.into_iter()
+        .try_fold(Number::Integer(0), Number::add)
+        .map(Number::into_object)
+}
+
+pub fn sub(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    fold("-", args, Some(Number::Integer(0)), Number::sub)
+}
+
+pub fn mul(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let nums = numbers(args)?;
+    nums.into_iter()
+        .try_fold(Number::Integer(1), Number::mul)
+        .map(Number::into_object)
+}
+
+pub fn div(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    fold("/", args, Some(Number::Integer(1)), Number::div)
+}
+
+pub fn rem(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    fold("%", args, None, Number::rem)
+}
+
+/// Divide two integers, Floats holding an integral value are accepted
+/// as well and give a Float. The i128 operation falls back to the BigInt
+/// one when it overflows, e.g. dividing i128::MIN by -1
+fn integer_division(
+    name: &str,
+    args: &[Object],
+    int_op: fn(i128, i128) -> Option<i128>,
+    big_op: fn(BigInt, BigInt) -> BigInt,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<Object, EvalError> {
+    expect_args(name, args, 2)?;
+    let (a, b) = (Number::from_object(&args[0])?, Number::from_object(&args[1])?);
+    for (n, obj) in [(&a, &args[0]), (&b, &args[1])] {
+        if matches!(n, Number::Rational(_)) || matches!(n, Number::Float(f) if f.fract() != 0.0) {
+            return Err(EvalError::type_mismatch(&format!("an integer for {}", name), obj));
+        }
+    }
+    if b.is_zero() {
+        return Err(EvalError::DivisionByZero { loc: None });
+    }
+    if let (Number::Integer(a), Number::Integer(b)) = (&a, &b) {
+        if let Some(n) = int_op(*a, *b) {
+            return Ok(Number::Integer(n).into_object());
+        }
+    }
+    let result = match (a.as_bigint(), b.as_bigint()) {
+        (Some(a), Some(b)) => Number::from_bigint(big_op(a, b)),
+        _ => Number::Float(float_op(a.as_f64(), b.as_f64())),
+    };
+    Ok(result.into_object())
+}
+
+pub fn quotient(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The quotient is truncated toward zero, e.g. (quotient -7 2) is -3
+    integer_division("quotient", args, i128::checked_div, |a, b| a / b, |a, b| (a / b).trunc())
+}
+
+pub fn remainder(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The remainder has the sign of the dividend, e.g. (remainder -7 2) is -1
+    integer_division("remainder", args, i128::checked_rem, |a, b| a % b, |a, b| a % b)
+}
+
+pub fn modulo(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The modulo has the sign of the divisor, e.g. (modulo -7 2) is 1
+    integer_division(
+        "modulo",
+        args,
+        |a, b| a.checked_rem(b).map(|r| if r != 0 && (r < 0) != (b < 0) { r + b } else { r }),
+        |a, b| {
+            let r = &a % &b;
+            if !r.is_zero() && r.is_negative() != b.is_negative() { r + b } else { r }
+        },
+        |a, b| {
+            let r = a % b;
+            if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r }
+        },
+    )
+}
+
+/// Compare each number with the next one, e.g. (< 1 2 3) holds when 1 < 2 and 2 < 3.
+/// NaN never satisfies any of the comparisons
+fn compare(name: &str, args: &[Object], pred: fn(Ordering) -> bool) -> Result<Object, EvalError> {
+    if args.is_empty() {
+        return Err(EvalError::ArityMismatch { name: name.to_string(), expected: Arity::AtLeast(1), found: 0, loc: None });
+    }
+    let nums = numbers(args)?;
+    let value = nums.windows(2).all(|pair| pair[0].compare(&pair[1]).is_some_and(pred));
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn gt(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    compare(">", args, |o| o == Ordering::Greater)
+}
+
+pub fn lt(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    compare("<", args, |o| o == Ordering::Less)
+}
+
+pub fn eq(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    compare("=", args, |o| o == Ordering::Equal)
+}
+
+pub fn ge(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    compare(">=", args, |o| o != Ordering::Less)
+}
+
+pub fn le(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    compare("<=", args, |o| o != Ordering::Greater)
+}
+
+pub fn ne(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // Unlike the other comparisons every pair is compared, (/= 1 2 1) is false
+    if args.is_empty() {
+        return Err(EvalError::ArityMismatch { name: "/=".to_string(), expected: Arity::AtLeast(1), found: 0, loc: None });
+    }
+    let nums = numbers(args)?;
+    let value = nums
+        .iter()
+        .enumerate()
+        .all(|(i, a)| nums[i + 1..].iter().all(|b| a.compare(b) != Some(Ordering::Equal)));
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn is_rational(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("rational?", args, 1)?;
+    let value = matches!(args[0], Object::Integer { .. } | Object::BigInteger { .. } | Object::Rational { .. });
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn numerator(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("numerator", args, 1)?;
+    match Number::from_object(&args[0])?.as_ratio() {
+        Some(n) => Ok(Number::from_bigint(n.numer().clone()).into_object()),
+        None => Err(EvalError::type_mismatch("an exact number for numerator", &args[0]))
+    }
+}
+
+pub fn denominator(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("denominator", args, 1)?;
+    match Number::from_object(&args[0])?.as_ratio() {
+        Some(n) => Ok(Number::from_bigint(n.denom().clone()).into_object()),
+        None => Err(EvalError::type_mismatch("an exact number for denominator", &args[0]))
+    }
+}
+
+/// Apply the Float function to any number, the result is always a Float
+fn inexact(name: &str, args: &[Object], op: fn(f64) -> f64) -> Result<Object, EvalError> {
+    expect_args(name, args, 1)?;
+    Ok(Object::Float { value: op(Number::from_object(&args[0])?.as_f64()), loc: None })
+}
+
+pub fn exp(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    inexact("exp", args, f64::exp)
+}
+
+pub fn sin(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    inexact("sin", args, f64::sin)
+}
+
+pub fn cos(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    inexact("cos", args, f64::cos)
+}
+
+pub fn tan(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    inexact("tan", args, f64::tan)
+}
+
+pub fn log(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (log z) is the natural logarithm, (log z base) the logarithm in the base
+    let value = match numbers(args)?.as_slice() {
+        [z] => z.as_f64().ln(),
+        [z, base] => z.as_f64().ln() / base.as_f64().ln(),
+        _ => return Err(EvalError::ArityMismatch { name: "log".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    Ok(Object::Float { value, loc: None })
+}
+
+pub fn atan(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (atan y) is the arctangent, (atan y x) the angle of the point (x, y)
+    let value = match numbers(args)?.as_slice() {
+        [y] => y.as_f64().atan(),
+        [y, x] => y.as_f64().atan2(x.as_f64()),
+        _ => return Err(EvalError::ArityMismatch { name: "atan".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    Ok(Object::Float { value, loc: None })
+}
+
+pub fn sqrt(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The root of an exact number is exact when it exists, e.g. (sqrt 16/9) is 4/3,
+    // otherwise it is a Float, NaN for negative numbers
+    expect_args("sqrt", args, 1)?;
+    let n = Number::from_object(&args[0])?;
+    if let Some(ratio) = n.as_ratio().filter(|ratio| !ratio.is_negative()) {
+        let (numer, denom) = (ratio.numer().sqrt(), ratio.denom().sqrt());
+        if &numer * &numer == *ratio.numer() && &denom * &denom == *ratio.denom() {
+            return Ok(Number::from_ratio(BigRational::new(numer, denom)).into_object());
+        }
+    }
+    Ok(Object::Float { value: n.as_f64().sqrt(), loc: None })
+}
+
+pub fn expt(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (expt base power) is exact for an exact base raised to an integer,
+    // e.g. (expt 2 100) or (expt 2 -2) which is 1/4, otherwise it is a Float
+    expect_args("expt", args, 2)?;
+    let base = Number::from_object(&args[0])?;
+    let power = Number::from_object(&args[1])?;
+    if let (Some(base), Some(power)) = (base.as_ratio(), power.as_bigint()) {
+        if let Some(exponent) = power.magnitude().to_u32() {
+            let (numer, denom) = (base.numer().pow(exponent), base.denom().pow(exponent));
+            if power.is_negative() {
+                if numer.is_zero() {
+                    return Err(EvalError::DivisionByZero { loc: None });
+                }
+                return Ok(Number::from_ratio(BigRational::new(denom, numer)).into_object());
+            }
+            return Ok(Number::from_ratio(BigRational::new(numer, denom)).into_object());
+        }
+    }
+    Ok(Object::Float { value: base.as_f64().powf(power.as_f64()), loc: None })
+}
+
+/// Round the number to an integer, exact numbers give an exact
+/// integer and Floats give an integral Float
+fn round_with(name: &str, args: &[Object], ratio_op: fn(&BigRational) -> BigRational, float_op: fn(f64) -> f64) -> Result<Object, EvalError> {
+    expect_args(name, args, 1)?;
+    let rounded = match Number::from_object(&args[0])? {
+        Number::Rational(n) => Number::from_bigint(ratio_op(&n).to_integer()),
+        Number::Float(n) => Number::Float(float_op(n)),
+        n => n,
+    };
+    Ok(rounded.into_object())
+}
+
+pub fn floor(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    round_with("floor", args, BigRational::floor, f64::floor)
+}
+
+pub fn ceiling(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    round_with("ceiling", args, BigRational::ceil, f64::ceil)
+}
+
+pub fn truncate(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    round_with("truncate", args, BigRational::trunc, f64::trunc)
+}
+
+pub fn round(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // Halfway values are rounded to the even integer, e.g. (round 5/2) is 2
+    let half_even = |n: &BigRational| {
+        let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+        let rounded = (n + &half).floor();
+        if &rounded - n == half && !(rounded.to_integer() % BigInt::from(2)).is_zero() {
+            rounded - BigRational::from_integer(BigInt::from(1))
+        } else {
+            rounded
+        }
+    };
+    round_with("round", args, half_even, f64::round_ties_even)
+}
+
+/// The radix given as optional last argument of the conversions, 10 by default
+fn radix(name: &str, arg: Option<&Object>) -> Result<u32, EvalError> {
+    match arg {
+        None => Ok(10),
+        Some(Object::Integer { value, .. }) if (2..=36).contains(value) => Ok(*value as u32),
+        Some(obj) => Err(EvalError::type_mismatch(&format!("a radix between 2 and 36 for {}", name), obj)),
+    }
+}
+
+pub fn string_to_number(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (string->number "1/2") reads the number the way it is written in the source,
+    // (string->number "ff" 16) reads an integer in the radix. Strings which are
+    // not a number give #f rather than an error, e.g. (string->number "1 2")
+    let (s, radix) = match args {
+        [Object::Str { value, .. }] | [Object::Str { value, .. }, _] => (value.as_str(), radix("string->number", args.get(1))?),
+        [obj] | [obj, _] => return Err(EvalError::type_mismatch("a string for string->number", obj)),
+        _ => return Err(EvalError::ArityMismatch { name: "string->number".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    let number = if radix == 10 {
+        let tokens = tokenize("<string>", s).unwrap_or_default();
+        match tokens.iter().map(|token| token.kind()).collect::<Vec<_>>().as_slice() {
+            [TokenKind::Integer(n)] => Some(Number::Integer(*n)),
+            [TokenKind::BigInteger(n)] => Some(Number::BigInteger(n.clone())),
+            [TokenKind::Rational(n)] => Some(Number::Rational(n.clone())),
+            [TokenKind::Float(n)] => Some(Number::Float(*n)),
+            _ => None,
+        }
+    } else {
+        // The sign is only allowed once, parse_bytes accepts a leading sign as well
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        BigInt::parse_bytes(digits.as_bytes(), radix)
+            .filter(|_| !digits.starts_with(['+', '-']))
+            .map(|n| Number::from_bigint(if negative { -n } else { n }))
+    };
+    Ok(number.map(Number::into_object).unwrap_or(Object::Bool { value: false, loc: None }))
+}
+
+pub fn number_to_string(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (number->string 255 16) is "ff", Floats are always written in
+    // radix 10 with a fraction so they are read back as Floats, e.g. "1.0"
+    if args.is_empty() || args.len() > 2 {
+        return Err(EvalError::ArityMismatch { name: "number->string".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None });
+    }
+    let radix = radix("number->string", args.get(1))?;
+    let value = match Number::from_object(&args[0])? {
+        Number::Float(n) if radix == 10 => format!("{:?}", n),
+        Number::Float(_) => return Err(EvalError::type_mismatch("an exact number for number->string in radix other than 10", &args[0])),
+        Number::Integer(n) => BigInt::from(n).to_str_radix(radix),
+        Number::BigInteger(n) => n.to_str_radix(radix),
+        Number::Rational(n) => format!("{}/{}", n.numer().to_str_radix(radix), n.denom().to_str_radix(radix)),
+    };
+    Ok(Object::Str { value, loc: None })
+}
@@ -0,0 +1,162 @@
+use std::{
+    cell::RefCell,
+    process::Command,
+    rc::Rc,
+    time::Duration,
+};
+
+use super::{expect_args, numeric};
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::{ListItems, Object};
+
+fn var_name<'a>(name: &str, obj: &'a Object) -> Result<&'a str, EvalError> {
+    match obj {
+        Object::Str { value, .. } if !value.is_empty() && !value.contains(['=', '\0']) => Ok(value.as_str()),
+        obj => Err(EvalError::type_mismatch(&format!("an environment variable name for {}", name), obj))
+    }
+}
+
+pub fn getenv(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (getenv "HOME") gives the value of the variable, #f when it is not set
+    expect_args("getenv", args, 1)?;
+    let name = var_name("getenv", &args[0])?;
+    Ok(match std::env::var(name) {
+        Ok(value) => Object::Str { value, loc: None },
+        Err(_) => Object::Bool { value: false, loc: None }
+    })
+}
+
+pub fn setenv(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (setenv "NAME" "value") sets the variable for the program and the
+    // processes it starts, (setenv "NAME" #f) unsets it
+    expect_args("setenv", args, 2)?;
+    let name = var_name("setenv", &args[0])?;
+    match &args[1] {
+        Object::Str { value, .. } if !value.contains('\0') => std::env::set_var(name, value),
+        Object::Bool { value: false, .. } => std::env::remove_var(name),
+        obj => return Err(EvalError::type_mismatch("a string or #f as value for setenv", obj))
+    }
+    Ok(Object::Void { loc: None })
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn since_epoch() -> Duration {
+    // The clock is set before 1970 on broken systems only
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+}
+
+/// The standard library has no clock in the browser, JavaScript's is used
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn since_epoch() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+}
+
+pub fn current_time(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (current-time) gives the number of seconds since 1970-01-01 UTC
+    expect_args("current-time", args, 0)?;
+    Ok(Object::Integer { value: since_epoch().as_secs() as i128, loc: None })
+}
+
+pub fn current_milliseconds(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (current-milliseconds) is the same as current-time in milliseconds,
+    // the difference of two calls measures the time spent in between
+    expect_args("current-milliseconds", args, 0)?;
+    Ok(Object::Integer { value: since_epoch().as_millis() as i128, loc: None })
+}
+
+pub fn sleep(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (sleep 0.5) pauses the program for the number of seconds
+    expect_args("sleep", args, 1)?;
+    let seconds = numeric::to_f64(&args[0])?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => std::thread::sleep(duration),
+        Err(_) => return Err(EvalError::type_mismatch("a non-negative number of seconds", &args[0]))
+    }
+    Ok(Object::Void { loc: None })
+}
+
+/// The status of the process, #f when it is killed by a signal
+fn status_object(status: std::process::ExitStatus) -> Object {
+    match status.code() {
+        Some(code) => Object::Integer { value: code as i128, loc: None },
+        None => Object::Bool { value: false, loc: None }
+    }
+}
+
+fn spawn_error(program: &str, err: std::io::Error) -> EvalError {
+    EvalError::Io { path: program.to_string(), message: err.to_string() }
+}
+
+pub fn system(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (system "ls -l | wc -l") runs the command with the shell, sharing the
+    // standard streams of the program, and gives its exit status
+    expect_args("system", args, 1)?;
+    let command = match &args[0] {
+        Object::Str { value, .. } => value,
+        obj => return Err(EvalError::type_mismatch("a command string for system", obj))
+    };
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let status = Command::new(shell)
+        .args([flag, command.as_str()])
+        .status()
+        .map_err(|e| spawn_error(shell, e))?;
+    Ok(status_object(status))
+}
+
+pub fn run_process(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (run-process "git" '("status" "-s") :capture #t) runs the program
+    // without a shell and gives the list (status stdout stderr), the
+    // outputs are #f unless they are captured as strings
+    let (program, rest) = match args {
+        [Object::Str { value, .. }, rest @ ..] => (value, rest),
+        [obj, ..] => return Err(EvalError::type_mismatch("a program name for run-process", obj)),
+        [] => return Err(EvalError::ArityMismatch {
+            name: "run-process".to_string(),
+            expected: Arity::AtLeast(1),
+            found: 0,
+            loc: None
+        })
+    };
+    let (arguments, options) = match rest {
+        [Object::List { value, .. }, options @ ..] => (value.as_slice(), options),
+        options => (&[][..], options)
+    };
+
+    let mut capture = false;
+    for option in options.chunks(2) {
+        let (keyword, loc) = match &option[0] {
+            Object::Keyword { value, loc } => (value, loc),
+            obj => return Err(EvalError::type_mismatch("a list of arguments or :capture for run-process", obj))
+        };
+        let invalid = |message: &str| EvalError::InvalidKeywordArgument {
+            keyword: option[0].to_string(),
+            message: message.to_string(),
+            loc: loc.clone()
+        };
+        match (keyword.as_str(), option.get(1)) {
+            ("capture", Some(Object::Bool { value, .. })) => capture = *value,
+            ("capture", Some(_)) => return Err(invalid("expect #t or #f")),
+            (_, None) => return Err(invalid("expect a value after the keyword")),
+            _ => return Err(invalid("run-process has no such keyword parameter"))
+        }
+    }
+
+    let mut command = Command::new(program);
+    for arg in arguments {
+        match arg {
+            Object::Str { value, .. } => command.arg(value),
+            obj => return Err(EvalError::type_mismatch("a string as argument of the process", obj))
+        };
+    }
+
+    let result = if capture {
+        let output = command.output().map_err(|e| spawn_error(program, e))?;
+        let text = |bytes: &[u8]| Object::Str { value: String::from_utf8_lossy(bytes).into_owned(), loc: None };
+        vec![status_object(output.status), text(&output.stdout), text(&output.stderr)]
+    } else {
+        let status = command.status().map_err(|e| spawn_error(program, e))?;
+        vec![status_object(status), Object::Bool { value: false, loc: None }, Object::Bool { value: false, loc: None }]
+    };
+    Ok(Object::List { value: ListItems::new(result), loc: None })
+}
@@ -0,0 +1,291 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufReader, BufWriter},
+    rc::Rc,
+};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError, LexError, ParseError};
+use crate::evaluator::{expand_reader_macros, Environment};
+use crate::format::{pretty_print, WIDTH};
+use crate::lexer::tokenize;
+use crate::parser::{parse_datum, Object, Port, Written};
+
+fn as_path<'a>(name: &str, obj: &'a Object) -> Result<&'a str, EvalError> {
+    match obj {
+        Object::Str { value, .. } => Ok(value.as_str()),
+        _ => Err(EvalError::type_mismatch(&format!("a path for {}", name), obj))
+    }
+}
+
+fn as_port<'a>(name: &str, obj: &'a Object) -> Result<&'a Rc<RefCell<Port>>, EvalError> {
+    match obj {
+        Object::Port { value, .. } => Ok(value),
+        _ => Err(EvalError::type_mismatch(&format!("a port for {}", name), obj))
+    }
+}
+
+fn io_error(port: &str, e: std::io::Error) -> EvalError {
+    EvalError::Io { path: port.to_string(), message: e.to_string() }
+}
+
+fn closed(port: &str) -> EvalError {
+    EvalError::Io { path: port.to_string(), message: "the port is closed".to_string() }
+}
+
+pub fn open_input_file(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("open-input-file", args, 1)?;
+    let path = as_path("open-input-file", &args[0])?;
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+    let port = Port::Input { name: path.to_string(), reader: Some(Box::new(BufReader::new(file))) };
+    Ok(Object::Port { value: Rc::new(RefCell::new(port)), loc: None })
+}
+
+pub fn open_output_file(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("open-output-file", args, 1)?;
+    let path = as_path("open-output-file", &args[0])?;
+    let file = File::create(path).map_err(|e| io_error(path, e))?;
+    let port = Port::Output { name: path.to_string(), writer: Some(Box::new(BufWriter::new(file))) };
+    Ok(Object::Port { value: Rc::new(RefCell::new(port)), loc: None })
+}
+
+pub fn read_line(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (read-line port) gives the next line without its line ending,
+    // or the eof object at the end of the port
+    expect_args("read-line", args, 1)?;
+    let port = as_port("read-line", &args[0])?;
+    if !matches!(*port.borrow(), Port::Input { .. }) {
+        return Err(EvalError::type_mismatch("an input port for read-line", &args[0]));
+    }
+    let mut port = port.borrow_mut();
+    let Port::Input { name, reader } = &mut *port else { unreachable!() };
+    let reader = reader.as_mut().ok_or_else(|| closed(name))?;
+
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(|e| io_error(name, e))? == 0 {
+        return Ok(Object::Eof { loc: None });
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Object::Str { value: line, loc: None })
+}
+
+/// Parse the next datum of the text, None is returned when the
+/// text ends before the datum is complete
+fn parse_first(name: &str, text: &str) -> Result<Option<Object>, EvalError> {
+    let mut tokens = match tokenize(name, text) {
+        Ok(tokens) => tokens,
+        Err(LexError::UnterminatedString { .. } | LexError::UnterminatedComment { .. }) => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    match parse_datum(&mut tokens) {
+        Ok(datum) => Ok(datum),
+        Err(ParseError::UnclosedList { .. }
+            | ParseError::MissingQuotedExpression { .. }
+            | ParseError::MissingDispatchedExpression { .. }) => Ok(None),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Read lines until they hold a complete datum, the rest of
+/// the line after the datum is dropped
+fn read_datum(name: &str, reader: &mut dyn std::io::BufRead) -> Result<Object, EvalError> {
+    let mut text = String::new();
+    loop {
+        let read = reader.read_line(&mut text).map_err(|e| io_error(name, e))?;
+        if read == 0 {
+            // An incomplete datum is reported as an error at the end of the input
+            let mut tokens = tokenize(name, &text)?;
+            return Ok(parse_datum(&mut tokens)?.unwrap_or(Object::Eof { loc: None }));
+        }
+        if let Some(datum) = parse_first(name, &text)? {
+            return Ok(datum);
+        }
+    }
+}
+
+pub fn read(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (read [port]) reads the next datum from the port or the standard input,
+    // the reader macros are applied to it
+    if args.len() > 1 {
+        return Err(EvalError::ArityMismatch { name: "read".to_string(), expected: Arity::Between(0, 1), found: args.len(), loc: None });
+    }
+    let port = match args.first() {
+        Some(obj) => as_port("read", obj)?,
+        None => return expand_reader_macros(&read_datum("<stdin>", &mut std::io::stdin().lock())?, env)
+    };
+
+    if !matches!(*port.borrow(), Port::Input { .. }) {
+        return Err(EvalError::type_mismatch("an input port for read", &args[0]));
+    }
+    let datum = {
+        let mut port = port.borrow_mut();
+        let Port::Input { name, reader } = &mut *port else { unreachable!() };
+        let reader = reader.as_mut().ok_or_else(|| closed(name))?;
+        read_datum(name, reader)?
+    };
+    // The port is released since the reader macros may read from it
+    expand_reader_macros(&datum, env)
+}
+
+pub fn read_string(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (read-string "(1 2)") gives the first datum of the string, the eof object if there is none
+    expect_args("read-string", args, 1)?;
+    match &args[0] {
+        Object::Str { value, .. } => {
+            let mut tokens = tokenize("<string>", value)?;
+            let datum = parse_datum(&mut tokens)?.unwrap_or(Object::Eof { loc: None });
+            expand_reader_macros(&datum, env)
+        },
+        obj => Err(EvalError::type_mismatch("a string for read-string", obj))
+    }
+}
+
+/// The standard output collected by the runtime instead of being printed,
+/// see [`crate::Interpreter::set_capture_output`]
+struct Captured(Rc<RefCell<String>>);
+
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().push_str(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the text to the standard output, or to the output captured by the runtime
+pub(crate) fn print(env: &Rc<RefCell<Environment>>, text: &str) {
+    match Environment::with_runtime(env, |runtime| runtime.output.clone()) {
+        Some(output) => output.borrow_mut().push_str(text),
+        None => print!("{}", text),
+    }
+}
+
+/// Write the text to the output port, or to the standard output without port
+fn output(name: &str, port: Option<&Object>, text: &str, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    let port = match port {
+        Some(obj) => as_port(name, obj)?,
+        None => {
+            print(env, text);
+            return Ok(());
+        }
+    };
+
+    if !matches!(*port.borrow(), Port::Output { .. }) {
+        let obj = Object::Port { value: port.clone(), loc: None };
+        return Err(EvalError::type_mismatch(&format!("an output port for {}", name), &obj));
+    }
+    let mut port = port.borrow_mut();
+    let Port::Output { name, writer } = &mut *port else { unreachable!() };
+    let writer = writer.as_mut().ok_or_else(|| closed(name))?;
+    writer.write_all(text.as_bytes()).map_err(|e| io_error(name, e))
+}
+
+/// Split the arguments of `(name obj [port])`
+fn with_port<'a>(name: &str, args: &'a [Object]) -> Result<(&'a Object, Option<&'a Object>), EvalError> {
+    match args {
+        [obj] => Ok((obj, None)),
+        [obj, port] => Ok((obj, Some(port))),
+        _ => Err(EvalError::ArityMismatch { name: name.to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    }
+}
+
+pub fn write_string(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (write-string string [port]) writes to the standard output without port
+    let (s, port) = with_port("write-string", args)?;
+    match s {
+        Object::Str { value, .. } => output("write-string", port, value, env)?,
+        obj => return Err(EvalError::type_mismatch("a string for write-string", obj))
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn display(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (display obj [port])
+    let (obj, port) = with_port("display", args)?;
+    output("display", port, &obj.to_string(), env)?;
+    Ok(Object::Void { loc: None })
+}
+
+pub fn write(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (write obj [port]), e.g. (write "a") prints "a" with the quotes
+    let (obj, port) = with_port("write", args)?;
+    if Written(obj).has_void() {
+        return Err(EvalError::InvalidArgument {
+            name: "write".to_string(),
+            message: format!("{} holds a Void, which cannot be read back", obj),
+            loc: None
+        });
+    }
+    output("write", port, &Written(obj).to_string(), env)?;
+    Ok(Object::Void { loc: None })
+}
+
+pub fn pp(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (pp obj [port]) writes the object on as many lines as needed to
+    // keep them within the width of the formatter, and a line break
+    let (obj, port) = with_port("pp", args)?;
+    output("pp", port, &format!("{}\n", pretty_print(obj, WIDTH)), env)?;
+    Ok(Object::Void { loc: None })
+}
+
+pub fn newline(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (newline [port])
+    if args.len() > 1 {
+        return Err(EvalError::ArityMismatch { name: "newline".to_string(), expected: Arity::Between(0, 1), found: args.len(), loc: None });
+    }
+    output("newline", args.first(), "\n", env)?;
+    Ok(Object::Void { loc: None })
+}
+
+pub fn println(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (println obj ...) displays the objects separated by spaces on one line
+    let line: Vec<_> = args.iter().map(|obj| obj.to_string()).collect();
+    print(env, &format!("{}\n", line.join(" ")));
+    Ok(Object::Void { loc: None })
+}
+
+pub fn current_output_port(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("current-output-port", args, 0)?;
+    let writer: Box<dyn std::io::Write> = match Environment::with_runtime(env, |runtime| runtime.output.clone()) {
+        Some(output) => Box::new(Captured(output)),
+        None => Box::new(std::io::stdout()),
+    };
+    let port = Port::Output { name: "stdout".to_string(), writer: Some(writer) };
+    Ok(Object::Port { value: Rc::new(RefCell::new(port)), loc: None })
+}
+
+pub fn current_error_port(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("current-error-port", args, 0)?;
+    let port = Port::Output { name: "stderr".to_string(), writer: Some(Box::new(std::io::stderr())) };
+    Ok(Object::Port { value: Rc::new(RefCell::new(port)), loc: None })
+}
+
+pub fn close_port(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // Closing a port again has no effect, output is flushed before closing
+    expect_args("close-port", args, 1)?;
+    let mut port = as_port("close-port", &args[0])?.borrow_mut();
+    match &mut *port {
+        Port::Input { reader, .. } => *reader = None,
+        Port::Output { name, writer } => {
+            if let Some(mut writer) = writer.take() {
+                writer.flush().map_err(|e| io_error(name, e))?;
+            }
+        }
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn eof_object(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("eof-object", args, 0)?;
+    Ok(Object::Eof { loc: None })
+}
+
+pub fn is_eof_object(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("eof-object?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Eof { .. }), loc: None })
+}
@@ -0,0 +1,36 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::parser::Object;
+
+pub fn random(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (random) gives a Float between 0 inclusive and 1 exclusive
+    expect_args("random", args, 0)?;
+    let value = Environment::with_runtime(env, |runtime| runtime.random.f64());
+    Ok(Object::Float { value, loc: None })
+}
+
+pub fn random_integer(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (random-integer 6) gives an Integer between 0 inclusive and 6 exclusive
+    expect_args("random-integer", args, 1)?;
+    let bound = match &args[0] {
+        Object::Integer { value, .. } if *value > 0 => *value,
+        obj => return Err(EvalError::type_mismatch("a positive integer as bound for random-integer", obj))
+    };
+    let value = Environment::with_runtime(env, |runtime| runtime.random.i128(0..bound));
+    Ok(Object::Integer { value, loc: None })
+}
+
+pub fn random_seed(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (random-seed! 42) restarts the sequence of random numbers, the same
+    // seed always gives the same sequence
+    expect_args("random-seed!", args, 1)?;
+    let seed = match &args[0] {
+        Object::Integer { value, .. } => *value as u64,
+        obj => return Err(EvalError::type_mismatch("an integer as seed", obj))
+    };
+    Environment::with_runtime(env, |runtime| runtime.random.seed(seed));
+    Ok(Object::Void { loc: None })
+}
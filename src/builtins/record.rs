@@ -0,0 +1,78 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::{Object, Record, RecordType};
+
+// The builtins below back the functions generated by `define-record-type`
+
+fn as_type<'a>(name: &str, obj: &'a Object) -> Result<&'a Rc<RecordType>, EvalError> {
+    match obj {
+        Object::RecordType { value, .. } => Ok(value),
+        _ => Err(EvalError::type_mismatch(&format!("a record type for {}", name), obj))
+    }
+}
+
+/// Borrow the record if it is of the given type
+fn as_record<'a>(obj: &'a Object, kind: &Rc<RecordType>) -> Result<&'a Rc<RefCell<Record>>, EvalError> {
+    match obj {
+        Object::Record { value, .. } if Rc::ptr_eq(&value.borrow().kind, kind) => Ok(value),
+        _ => Err(EvalError::type_mismatch(&format!("a {} record", kind.name), obj))
+    }
+}
+
+fn as_field(name: &str, obj: &Object, kind: &RecordType) -> Result<usize, EvalError> {
+    match obj {
+        Object::Integer { value, .. } if (0..kind.fields.len() as i128).contains(value) => Ok(*value as usize),
+        _ => Err(EvalError::type_mismatch(&format!("a field index of {} for {}", kind.name, name), obj))
+    }
+}
+
+pub fn make_record(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (make-record type field ...)
+    let (kind, fields) = match args.split_first() {
+        Some((kind, fields)) => (as_type("make-record", kind)?, fields),
+        None => return Err(EvalError::ArityMismatch { name: "make-record".to_string(), expected: Arity::AtLeast(1), found: 0, loc: None })
+    };
+    if fields.len() != kind.fields.len() {
+        return Err(EvalError::ArityMismatch {
+            name: format!("make-{}", kind.name),
+            expected: Arity::Exact(kind.fields.len()),
+            found: fields.len(),
+            loc: None
+        });
+    }
+    let record = Record { kind: kind.clone(), fields: fields.to_vec() };
+    Ok(Object::Record { value: Rc::new(RefCell::new(record)), loc: None })
+}
+
+pub fn is_record(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (record? obj [type]), any record satisfies the predicate without type
+    let value = match args {
+        [obj] => matches!(obj, Object::Record { .. }),
+        [obj, kind] => as_record(obj, as_type("record?", kind)?).is_ok(),
+        _ => return Err(EvalError::ArityMismatch { name: "record?".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    Ok(Object::Bool { value, loc: None })
+}
+
+pub fn record_ref(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (record-ref obj type index)
+    expect_args("record-ref", args, 3)?;
+    let kind = as_type("record-ref", &args[1])?;
+    let record = as_record(&args[0], kind)?;
+    let index = as_field("record-ref", &args[2], kind)?;
+    let value = record.borrow().fields[index].clone();
+    Ok(value)
+}
+
+pub fn record_set(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (record-set! obj type index value)
+    expect_args("record-set!", args, 4)?;
+    let kind = as_type("record-set!", &args[1])?;
+    let record = as_record(&args[0], kind)?;
+    let index = as_field("record-set!", &args[2], kind)?;
+    record.borrow_mut().fields[index] = args[3].clone();
+    Ok(Object::Void { loc: None })
+}
@@ -0,0 +1,189 @@
+//! The strings are indexed by characters, that is by Unicode scalar values
+//! rather than bytes or graphemes: `"é"` written with the combining accent
+//! `"e\u{301}"` is 2 characters long and `(string-ref s 1)` is the accent
+//! alone.
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::{ListItems, Object, Written};
+
+fn as_str<'a>(name: &str, obj: &'a Object) -> Result<&'a str, EvalError> {
+    match obj {
+        Object::Str { value, .. } => Ok(value.as_str()),
+        _ => Err(EvalError::type_mismatch(&format!("a string for {}", name), obj))
+    }
+}
+
+fn as_index(name: &str, obj: &Object) -> Result<usize, EvalError> {
+    // Indices past usize are rejected instead of wrapping around
+    match obj {
+        Object::Integer { value, .. } => usize::try_from(*value)
+            .map_err(|_| EvalError::type_mismatch(&format!("a non-negative index for {}", name), obj)),
+        _ => Err(EvalError::type_mismatch(&format!("a non-negative index for {}", name), obj))
+    }
+}
+
+/// Slice the string between the character indices
+fn slice(s: &str, start: usize, end: usize) -> Result<&str, EvalError> {
+    let length = s.chars().count();
+    if end > length {
+        return Err(EvalError::IndexOutOfRange { index: end, length, loc: None });
+    }
+    if start > end {
+        let message = format!("start {} is greater than end {}", start, end);
+        return Err(EvalError::InvalidArgument { name: "substring".to_string(), message, loc: None });
+    }
+    let offset = |index| s.char_indices().nth(index).map_or(s.len(), |(offset, _)| offset);
+    Ok(&s[offset(start)..offset(end)])
+}
+
+pub fn is_string(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("string?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Str { .. }), loc: None })
+}
+
+pub fn string_length(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("string-length", args, 1)?;
+    let s = as_str("string-length", &args[0])?;
+    Ok(Object::Integer { value: s.chars().count() as i128, loc: None })
+}
+
+pub fn substring(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (substring s start [end]) with end defaulting to the length of s
+    if args.len() != 2 && args.len() != 3 {
+        return Err(EvalError::ArityMismatch { name: "substring".to_string(), expected: Arity::Between(2, 3), found: args.len(), loc: None });
+    }
+    let s = as_str("substring", &args[0])?;
+    let start = as_index("substring", &args[1])?;
+    let end = match args.get(2) {
+        Some(obj) => as_index("substring", obj)?,
+        None => s.chars().count()
+    };
+    let value = slice(s, start, end)?.to_string();
+    Ok(Object::Str { value, loc: None })
+}
+
+pub fn string_append(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let value = args
+        .iter()
+        .map(|obj| as_str("string-append", obj))
+        .collect::<Result<String, _>>()?;
+    Ok(Object::Str { value, loc: None })
+}
+
+pub fn string_ref(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("string-ref", args, 2)?;
+    let s = as_str("string-ref", &args[0])?;
+    let index = as_index("string-ref", &args[1])?;
+    let value = s
+        .chars()
+        .nth(index)
+        .ok_or_else(|| EvalError::IndexOutOfRange { index, length: s.chars().count(), loc: None })?;
+    Ok(Object::Char { value, loc: None })
+}
+
+pub fn string_to_list(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("string->list", args, 1)?;
+    let s = as_str("string->list", &args[0])?;
+    let chars = s.chars().map(|c| Object::Char { value: c, loc: None }).collect();
+    Ok(Object::List { value: ListItems::new(chars), loc: None })
+}
+
+pub fn list_to_string(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // Every element of the list must be a character
+    expect_args("list->string", args, 1)?;
+    let Object::List { value: items, .. } = &args[0] else {
+        return Err(EvalError::type_mismatch("a list of characters for list->string", &args[0]));
+    };
+    let value = items
+        .iter()
+        .map(|item| match item {
+            Object::Char { value, .. } => Ok(*value),
+            obj => Err(EvalError::type_mismatch("a character for list->string", obj)),
+        })
+        .collect::<Result<String, _>>()?;
+    Ok(Object::Str { value, loc: None })
+}
+
+pub fn is_char(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("char?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Char { .. }), loc: None })
+}
+
+pub fn format(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (format "~a is ~5d~%" name n), the directives are
+    //   ~a  the argument as printed by display
+    //   ~s  the argument as printed by write
+    //   ~d  the argument which must be a number
+    //   ~%  a newline and ~~ a tilde
+    // A width between the tilde and the directive pads the argument with
+    // spaces, ~a and ~s are left aligned and ~d is right aligned, e.g. ~8a
+    // or ~5d, a width starting with 0 pads ~d with zeros, e.g. ~05d
+    let template = match args.first() {
+        Some(obj) => as_str("format", obj)?,
+        None => return Err(EvalError::ArityMismatch { name: "format".to_string(), expected: Arity::AtLeast(1), found: 0, loc: None })
+    };
+    let mut values = args[1..].iter();
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    let mut directives = 0;
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            result.push(c);
+            continue;
+        }
+
+        let zero = chars.next_if_eq(&'0').is_some();
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        let directive = chars.next();
+        let text = match directive {
+            Some('%') => "\n".to_string(),
+            Some('~') => "~".to_string(),
+            Some(directive @ ('a' | 's' | 'd')) => {
+                directives += 1;
+                let value = values.next().ok_or_else(|| EvalError::ArityMismatch {
+                    name: "format".to_string(),
+                    expected: Arity::AtLeast(directives + 1),
+                    found: args.len(),
+                    loc: None
+                })?;
+                match (directive, value) {
+                    ('a', value) => format!("{:<width$}", value.to_string()),
+                    ('s', value) if Written(value).has_void() =>
+                        return Err(invalid_format(format!("~s cannot write {}, which holds a Void", value))),
+                    ('s', value) => format!("{:<width$}", Written(value).to_string()),
+                    (_, Object::Integer { .. } | Object::BigInteger { .. } | Object::Rational { .. } | Object::Float { .. }) if zero =>
+                        format!("{:0>width$}", value.to_string()),
+                    (_, Object::Integer { .. } | Object::BigInteger { .. } | Object::Rational { .. } | Object::Float { .. }) =>
+                        format!("{:>width$}", value.to_string()),
+                    (_, value) => return Err(EvalError::type_mismatch("a number for ~d in format", value))
+                }
+            },
+            Some(other) => return Err(invalid_format(format!("unknown directive ~{}", other))),
+            None => return Err(invalid_format("expect a directive after ~".to_string()))
+        };
+        result.push_str(&text);
+    }
+
+    let extra = values.count();
+    if extra > 0 {
+        return Err(EvalError::ArityMismatch {
+            name: "format".to_string(),
+            expected: Arity::Exact(directives + 1),
+            found: args.len(),
+            loc: None
+        });
+    }
+    Ok(Object::Str { value: result, loc: None })
+}
+
+fn invalid_format(message: String) -> EvalError {
+    EvalError::InvalidArgument { name: "format".to_string(), message, loc: None }
+}
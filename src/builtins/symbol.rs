@@ -0,0 +1,56 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::Object;
+use crate::symbol::Symbol;
+
+pub fn gensym(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (gensym [prefix]) makes a fresh uninterned symbol such as #:g1, the
+    // prefix replaces the leading g, e.g. (gensym "tmp") gives #:tmp2
+    let prefix = match args {
+        [] => "g",
+        [Object::Str { value, .. }] => value.as_str(),
+        [Object::Symbol { value, .. }] => value.as_str(),
+        [obj] => return Err(EvalError::type_mismatch("a string or a symbol as prefix for gensym", obj)),
+        _ => return Err(EvalError::ArityMismatch { name: "gensym".to_string(), expected: Arity::Between(0, 1), found: args.len(), loc: None })
+    };
+    let counter = Environment::with_runtime(env, |runtime| {
+        runtime.gensym_counter += 1;
+        runtime.gensym_counter
+    });
+    let name = format!("#:{}{}", prefix, counter);
+    Ok(Object::Symbol { value: Symbol::uninterned(&name), loc: None })
+}
+
+pub fn symbol_to_string(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("symbol->string", args, 1)?;
+    match &args[0] {
+        Object::Symbol { value, .. } => Ok(Object::Str { value: value.as_str().to_string(), loc: None }),
+        obj => Err(EvalError::type_mismatch("a symbol for symbol->string", obj))
+    }
+}
+
+pub fn is_keyword(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("keyword?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Keyword { .. }), loc: None })
+}
+
+pub fn keyword_to_string(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The name without the colon, (keyword->string :width) gives "width"
+    expect_args("keyword->string", args, 1)?;
+    match &args[0] {
+        Object::Keyword { value, .. } => Ok(Object::Str { value: value.as_str().to_string(), loc: None }),
+        obj => Err(EvalError::type_mismatch("a keyword for keyword->string", obj))
+    }
+}
+
+pub fn string_to_symbol(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The symbol is interned, (eq? (string->symbol "a") 'a) is true
+    expect_args("string->symbol", args, 1)?;
+    match &args[0] {
+        Object::Str { value, .. } => Ok(Object::Symbol { value: Symbol::intern(value), loc: None }),
+        obj => Err(EvalError::type_mismatch("a string for string->symbol", obj))
+    }
+}
@@ -0,0 +1,58 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use super::port::print;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::{apply, is_truthy, Environment};
+use crate::parser::{Object, Written};
+
+pub fn assert(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (assert expr [message]) fails unless the value of expr is true
+    let (value, message) = match args {
+        [value] => (value, String::new()),
+        [value, Object::Str { value: message, .. }] => (value, message.clone()),
+        [_, obj] => return Err(EvalError::type_mismatch("a string as message for assert", obj)),
+        _ => return Err(EvalError::ArityMismatch { name: "assert".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None })
+    };
+    if !is_truthy(value) {
+        return Err(EvalError::AssertionFailed { message, loc: None });
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn assert_equal(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (assert-equal expected actual) fails unless both are equal?
+    expect_args("assert-equal", args, 2)?;
+    let (expected, actual) = (&args[0], &args[1]);
+    if !expected.is_equal(actual) {
+        let message = format!("expected {} but got {}", Written(expected), Written(actual));
+        return Err(EvalError::AssertionFailed { message, loc: None });
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn run_tests(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (run-tests) runs every test in the order of definition and prints
+    // the outcome of each one, the result is #t when all of them pass
+    // so a test file can end with (exit (run-tests))
+    expect_args("run-tests", args, 0)?;
+    let tests = Environment::with_runtime(env, |runtime| runtime.tests.clone());
+    let mut failed = 0;
+    for (name, thunk) in tests.iter() {
+        match apply(thunk, &[], env) {
+            Ok(_) => print(env, &format!("test {} ... ok\n", name)),
+            Err(err @ (EvalError::Exit { .. } | EvalError::OutOfFuel { .. })) => return Err(err),
+            Err(err) => {
+                failed += 1;
+                Environment::with_runtime(env, |runtime| runtime.backtrace.clear());
+                print(env, &format!("test {} ... FAILED\n", name));
+                match err.loc() {
+                    Some(loc) => print(env, &format!("    {} at {}:{}:{}\n", err.message(), loc.filename(), loc.rol(), loc.col())),
+                    None => print(env, &format!("    {}\n", err.message())),
+                }
+            },
+        }
+    }
+    print(env, &format!("{} passed; {} failed\n", tests.len() - failed, failed));
+    Ok(Object::Bool { value: failed == 0, loc: None })
+}
@@ -0,0 +1,77 @@
+//! `trace` and `untrace`, printing the calls of the traced functions
+//!
+//! The traced functions are kept by the runtime rather than replaced by
+//! wrappers, so the recursive calls made through the name bound before
+//! `trace` are printed too. Each call is printed with its arguments when
+//! it starts and with its value when it returns, indented by the number
+//! of traced calls it is nested in:
+//!
+//! ```text
+//! [1] (fact 2)
+//!   [2] (fact 1)
+//!   [2] => 1
+//! [1] => 2
+//! ```
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::port::print;
+use crate::error::EvalError;
+use crate::evaluator::{eval_body, Environment};
+use crate::parser::{FunctionDefinition, Object, Written};
+
+pub fn trace(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (trace f ...) prints the calls of the functions until they are untraced
+    for func in args {
+        let Object::Lambda { value, .. } = func else {
+            return Err(EvalError::type_mismatch("a function defined with lambda for trace", func));
+        };
+        let key = Rc::as_ptr(value) as usize;
+        Environment::with_runtime(env, |runtime| runtime.traced.insert(key, func.clone()));
+    }
+    Ok(Object::Void { loc: None })
+}
+
+pub fn untrace(args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (untrace f ...) stops printing the calls of the functions,
+    // (untrace) of every traced function
+    if args.is_empty() {
+        Environment::with_runtime(env, |runtime| runtime.traced.clear());
+    }
+    for func in args {
+        let Object::Lambda { value, .. } = func else {
+            return Err(EvalError::type_mismatch("a function defined with lambda for untrace", func));
+        };
+        let key = Rc::as_ptr(value) as usize;
+        Environment::with_runtime(env, |runtime| runtime.traced.remove(&key));
+    }
+    Ok(Object::Void { loc: None })
+}
+
+/// Whether the calls of the function are printed
+pub(crate) fn is_traced(func: &FunctionDefinition, env: &Rc<RefCell<Environment>>) -> bool {
+    let key = func as *const FunctionDefinition as usize;
+    Environment::with_runtime(env, |runtime| runtime.traced.contains_key(&key))
+}
+
+/// Evaluate the body of the traced function in the scope holding its
+/// arguments, printing the call and its value. It is not inlined so the
+/// frames of the calls which are not traced stay small
+#[inline(never)]
+pub(crate) fn call(func: &FunctionDefinition, args: &[Object], scope: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let depth = Environment::with_runtime(scope, |runtime| {
+        runtime.trace_depth += 1;
+        runtime.trace_depth
+    });
+    let indent = "  ".repeat(depth - 1);
+    let args: Vec<_> = args.iter().map(|arg| format!(" {}", Written(arg))).collect();
+    print(scope, &format!("{}[{}] ({}{})\n", indent, depth, func.label(), args.concat()));
+
+    let result = eval_body(&func.body.0, scope);
+    Environment::with_runtime(scope, |runtime| runtime.trace_depth -= 1);
+    match &result {
+        Ok(obj) => print(scope, &format!("{}[{}] => {}\n", indent, depth, Written(obj))),
+        Err(e) => print(scope, &format!("{}[{}] raised {}\n", indent, depth, e)),
+    }
+    result
+}
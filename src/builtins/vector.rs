@@ -0,0 +1,57 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::expect_args;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::Environment;
+use crate::parser::Object;
+
+fn as_vector<'a>(name: &str, obj: &'a Object) -> Result<&'a [Object], EvalError> {
+    match obj {
+        Object::Vector { value, .. } => Ok(value.as_slice()),
+        _ => Err(EvalError::type_mismatch(&format!("a vector for {}", name), obj))
+    }
+}
+
+fn as_index(name: &str, obj: &Object) -> Result<usize, EvalError> {
+    // Indices past usize are rejected instead of wrapping around
+    match obj {
+        Object::Integer { value, .. } => usize::try_from(*value)
+            .map_err(|_| EvalError::type_mismatch(&format!("a non-negative index for {}", name), obj)),
+        _ => Err(EvalError::type_mismatch(&format!("a non-negative index for {}", name), obj))
+    }
+}
+
+pub fn vector(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    Ok(Object::Vector { value: Rc::new(args.to_vec()), loc: None })
+}
+
+pub fn make_vector(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (make-vector n [fill]) with fill defaulting to 0
+    if args.len() != 1 && args.len() != 2 {
+        return Err(EvalError::ArityMismatch { name: "make-vector".to_string(), expected: Arity::Between(1, 2), found: args.len(), loc: None });
+    }
+    let len = as_index("make-vector", &args[0])?;
+    let fill = args.get(1).cloned().unwrap_or(Object::Integer { value: 0, loc: None });
+    Ok(Object::Vector { value: Rc::new(vec![fill; len]), loc: None })
+}
+
+pub fn is_vector(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("vector?", args, 1)?;
+    Ok(Object::Bool { value: matches!(args[0], Object::Vector { .. }), loc: None })
+}
+
+pub fn vector_length(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("vector-length", args, 1)?;
+    let vector = as_vector("vector-length", &args[0])?;
+    Ok(Object::Integer { value: vector.len() as i128, loc: None })
+}
+
+pub fn vector_ref(args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    expect_args("vector-ref", args, 2)?;
+    let vector = as_vector("vector-ref", &args[0])?;
+    let index = as_index("vector-ref", &args[1])?;
+    vector
+        .get(index)
+        .cloned()
+        .ok_or(EvalError::IndexOutOfRange { index, length: vector.len(), loc: args[1].loc().cloned() })
+}
@@ -0,0 +1,363 @@
+//! The step debugger of `rslisp debug`
+//!
+//! The [`Debugger`] is a [`Hook`] which stops the program before the first
+//! list it evaluates, then reads commands until one of them resumes it:
+//!
+//! - `step` or `s` stops before the next list, entering the calls,
+//! - `next` or `n` stops before the next list once the current one is evaluated,
+//! - `continue` or `c` runs until a breakpoint,
+//! - `break name` or `break file:line`, `b` for short, stops in the function
+//!   defined in the program once its arguments are bound, or before the first
+//!   list starting on the line,
+//! - `clear` removes the breakpoints,
+//! - `locals` or `l` prints the variables of the innermost scope,
+//! - `print expr` or `p expr` evaluates the expression in that scope,
+//! - `quit` or `q` ends the program.
+//!
+//! The debugger only sees the programs evaluated by the tree-walking
+//! evaluator. It resumes the program for good once its input ends.
+//!
+//! ```
+//! use std::rc::Rc;
+//! use rslisp::debugger::Debugger;
+//! use rslisp::{hook, Interpreter};
+//!
+//! let commands = "break square\ncontinue\nlocals\nstep\nlocals\ncontinue\n";
+//! let debugger = Rc::new(Debugger::new(Box::new(commands.as_bytes()), Box::new(std::io::sink())));
+//! let interp = Interpreter::new();
+//! let result = hook::with_hook(debugger, || interp.eval_str("(define (square x) (* x x)) (square 3)"));
+//! assert_eq!(result.unwrap().to_string(), "9");
+//! ```
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{BufRead, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::error::EvalError;
+use crate::evaluator::{eval_obj, Environment};
+use crate::hook::Hook;
+use crate::lexer::tokenize;
+use crate::location::Location;
+use crate::native::NativeFn;
+use crate::parser::{parse, Object, Written};
+
+/// The most characters of a form shown where the program stops
+const SHOWN: usize = 72;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Stop in the function bound to the name when it is called, once its
+    /// arguments are bound, the builtins are not stopped in
+    Function(String),
+    /// Stop before the first list starting on the line, the file
+    /// matches the end of the path of the evaluated file
+    Line { file: String, line: usize },
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Function(name) => write!(f, "{}", name),
+            Breakpoint::Line { file, line } => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+/// When the program stops next
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Step,
+    /// Before a list nested in at most this many lists being evaluated
+    Next(usize),
+    Continue,
+    /// The input ended, the program is never stopped again
+    Detached,
+}
+
+pub struct Debugger {
+    input: RefCell<Box<dyn BufRead>>,
+    output: RefCell<Box<dyn Write>>,
+    breakpoints: RefCell<Vec<Breakpoint>>,
+    mode: Cell<Mode>,
+    /// The number of lists being evaluated
+    depth: Cell<usize>,
+    /// The file and the line of the latest list evaluated
+    line: RefCell<Option<(String, usize)>>,
+    /// Unset while the expressions given to `print` are evaluated
+    active: Cell<bool>,
+}
+
+impl Debugger {
+    /// The commands are read from `input` and the debugger writes to `output`
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Debugger {
+            input: RefCell::new(input),
+            output: RefCell::new(output),
+            breakpoints: RefCell::new(vec![]),
+            mode: Cell::new(Mode::Step),
+            depth: Cell::new(0),
+            line: RefCell::new(None),
+            active: Cell::new(true),
+        }
+    }
+
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) {
+        self.breakpoints.borrow_mut().push(breakpoint);
+    }
+
+    fn print(&self, text: &str) {
+        let mut output = self.output.borrow_mut();
+        // The program goes on even when the output is gone
+        let _ = writeln!(output, "{}", text);
+        let _ = output.flush();
+    }
+
+    /// Read the commands until one of them resumes the program
+    fn prompt(&self, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        loop {
+            let _ = write!(self.output.borrow_mut(), "(debug) ");
+            let _ = self.output.borrow_mut().flush();
+            let mut line = String::new();
+            if !matches!(self.input.borrow_mut().read_line(&mut line), Ok(n) if n > 0) {
+                self.mode.set(Mode::Detached);
+                return Ok(());
+            }
+
+            let line = line.trim();
+            let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let arg = arg.trim();
+            match command {
+                "s" | "step" => self.mode.set(Mode::Step),
+                "n" | "next" => self.mode.set(Mode::Next(self.depth.get())),
+                "c" | "continue" => self.mode.set(Mode::Continue),
+                "q" | "quit" => return Err(EvalError::Exit { code: 0 }),
+                "b" | "break" => {
+                    match parse_breakpoint(arg) {
+                        Some(breakpoint) => {
+                            self.print(&format!("breakpoint at {}", breakpoint));
+                            self.add_breakpoint(breakpoint);
+                        },
+                        None => self.print("expect break name or break file:line"),
+                    }
+                    continue;
+                },
+                "clear" => {
+                    self.breakpoints.borrow_mut().clear();
+                    continue;
+                },
+                "l" | "locals" => {
+                    self.locals(env);
+                    continue;
+                },
+                "p" | "print" => {
+                    self.active.set(false);
+                    let result = evaluate(arg, env);
+                    self.active.set(true);
+                    match result {
+                        Ok(obj) => self.print(&Written(&obj).to_string()),
+                        Err(e) => match e.loc() {
+                            Some(loc) => self.print(&format!("{} at {}", e.message(), position(loc))),
+                            None => self.print(&e.message()),
+                        },
+                    }
+                    continue;
+                },
+                "" => continue,
+                _ => {
+                    self.print("commands: step, next, continue, break name|file:line, clear, locals, print expr, quit");
+                    continue;
+                },
+            }
+            return Ok(());
+        }
+    }
+
+    /// Print the variables bound by the innermost scope, the builtins
+    /// are left out of the globals
+    fn locals(&self, env: &Rc<RefCell<Environment>>) {
+        let env = env.borrow();
+        let bindings: Vec<_> = env
+            .bindings()
+            .into_iter()
+            .filter(|(_, obj)| !matches!(obj, Object::NativeFunction { value, .. } if matches!(value.func, NativeFn::Builtin(_))))
+            .collect();
+        if bindings.is_empty() {
+            self.print("no variables");
+        }
+        for (name, obj) in bindings {
+            self.print(&format!("{} = {}", name, shorten(Written(&obj).to_string())));
+        }
+    }
+
+    fn stop(&self, reason: &str, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        self.print(reason);
+        self.prompt(env)
+    }
+}
+
+impl Hook for Debugger {
+    fn before_eval(&self, form: &Object, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        if !self.active.get() {
+            return Ok(());
+        }
+        let depth = self.depth.get();
+        let line = form.loc().map(|loc| (loc.filename().to_string(), loc.rol()));
+        let entered = line.is_some() && *self.line.borrow() != line;
+        if line.is_some() {
+            *self.line.borrow_mut() = line.clone();
+        }
+
+        let at_breakpoint = entered && self.breakpoints.borrow().iter().any(|breakpoint| match (breakpoint, &line) {
+            (Breakpoint::Line { file, line }, Some((path, rol))) => line == rol && Path::new(path).ends_with(file),
+            _ => false,
+        });
+        let stop = match self.mode.get() {
+            Mode::Step => true,
+            Mode::Next(max) => depth <= max,
+            Mode::Continue => at_breakpoint,
+            Mode::Detached => false,
+        };
+        if stop {
+            let position = match form.loc() {
+                Some(loc) => position(loc),
+                None => "an expanded form".to_string(),
+            };
+            self.stop(&format!("stopped at {}\n  {}", position, shorten(Written(form).to_string())), env)?;
+        }
+        self.depth.set(depth + 1);
+        Ok(())
+    }
+
+    fn after_eval(&self, _form: &Object, _result: &Result<Object, EvalError>) {
+        if self.active.get() {
+            self.depth.set(self.depth.get().saturating_sub(1));
+        }
+    }
+
+    fn enter_function(&self, name: &str, args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        if !self.active.get() || matches!(self.mode.get(), Mode::Detached) {
+            return Ok(());
+        }
+        let breakpoint = Breakpoint::Function(name.to_string());
+        if self.breakpoints.borrow().contains(&breakpoint) {
+            let args: Vec<_> = args.iter().map(|arg| Written(arg).to_string()).collect();
+            let call = format!("({}{}{})", name, if args.is_empty() { "" } else { " " }, args.join(" "));
+            self.stop(&format!("breakpoint at {}\n  {}", name, shorten(call)), env)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read `name` or `file:line`
+fn parse_breakpoint(arg: &str) -> Option<Breakpoint> {
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        return None;
+    }
+    match arg.rsplit_once(':') {
+        Some((file, line)) if !file.is_empty() => line
+            .parse()
+            .ok()
+            .map(|line| Breakpoint::Line { file: file.to_string(), line }),
+        _ => Some(Breakpoint::Function(arg.to_string())),
+    }
+}
+
+/// Evaluate the expressions of the text in the environment, the value
+/// of the last one is returned
+fn evaluate(text: &str, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let program = parse(&mut tokenize("<debug>", text)?)?;
+    eval_obj(&program, env)
+}
+
+/// The place in the source shown to the user, e.g. `main.rsl:3:5`
+fn position(loc: &Location) -> String {
+    format!("{}:{}:{}", loc.filename(), loc.rol(), loc.col())
+}
+
+fn shorten(text: String) -> String {
+    let text = text.replace('\n', " ");
+    match text.char_indices().nth(SHOWN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::with_hook;
+    use crate::Interpreter;
+
+    /// The output is kept in a buffer shared with the test
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debug(prog: &str, commands: &str) -> (Result<Object, EvalError>, String) {
+        let output = Rc::new(RefCell::new(vec![]));
+        let debugger = Debugger::new(Box::new(std::io::Cursor::new(commands.to_string())), Box::new(Shared(output.clone())));
+        let interp = Interpreter::new();
+        let result = with_hook(Rc::new(debugger), || interp.eval_str(prog));
+        let output = String::from_utf8(output.take()).unwrap();
+        (result, output)
+    }
+
+    #[test]
+    fn test_debugger() {
+        let prog = "(define (square x)\n  (* x x))\n(define y (+ 1 2))\n(square y)";
+
+        // Stepping enters the calls while next goes over them
+        let (result, output) = debug(prog, "n\nn\ns\nlocals\nc\n");
+        assert!(matches!(result, Ok(Object::Integer { value: 9, .. })));
+        let lines: Vec<_> = output
+            .lines()
+            .filter(|line| line.contains("stopped at <string>:"))
+            .map(|line| line.split(':').nth(1).unwrap())
+            .collect();
+        assert_eq!(lines, ["1", "3", "4", "2"]);
+        assert!(output.contains("(debug) x = 3\n"), "{}", output);
+
+        let (_, output) = debug(prog, "b square\nb <string>:4\nc\np (* y 10)\nc\nc\n");
+        let breakpoints: Vec<_> = output.lines().filter(|line| line.contains("breakpoint at")).collect();
+        assert_eq!(breakpoints, [
+            "(debug) breakpoint at square",
+            "(debug) breakpoint at <string>:4",
+            "(debug) breakpoint at square",
+        ]);
+        assert!(output.contains("(debug) stopped at <string>:4:"), "{}", output);
+        assert!(output.contains("(debug) 30\n"), "{}", output);
+
+        // The function breakpoint stops in the function with its arguments bound
+        let (result, output) = debug(prog, "b square\nc\nlocals\np x\np z\nn\nc\n");
+        assert!(matches!(result, Ok(Object::Integer { value: 9, .. })));
+        assert!(output.contains("breakpoint at square\n  (square 3)\n(debug) x = 3\n(debug) 3\n"), "{}", output);
+        assert!(output.contains("(debug) Symbol not found: \"z\" at <debug>:1:1\n"), "{}", output);
+        assert!(output.contains("(debug) stopped at <string>:2:3\n  (* x x)\n"), "{}", output);
+
+        let (result, _) = debug(prog, "quit\n");
+        assert!(matches!(result, Err(EvalError::Exit { code: 0 })));
+        // The program runs to its end once the commands run out
+        let (result, _) = debug(prog, "");
+        assert!(matches!(result, Ok(Object::Integer { value: 9, .. })));
+    }
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(parse_breakpoint("f"), Some(Breakpoint::Function("f".to_string())));
+        assert_eq!(parse_breakpoint("dir/a.rsl:12"), Some(Breakpoint::Line { file: "dir/a.rsl".to_string(), line: 12 }));
+        assert_eq!(parse_breakpoint("a.rsl:x"), None);
+        assert_eq!(parse_breakpoint(""), None);
+    }
+}
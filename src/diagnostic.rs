@@ -0,0 +1,88 @@
+use crate::error::EvalError;
+use crate::location::Location;
+
+/// Render the error in the style of rustc diagnostics, the source line
+/// holding the error is printed with a caret pointing at the location
+///
+/// ```text
+/// error: Symbol not found: "foo"
+///  --> main.lisp:2:4
+///   |
+/// 2 |   (foo 1)
+///   |    ^ not defined in any enclosing scope
+/// ```
+///
+/// Only the message is rendered when the location is unknown or
+/// does not point into the source.
+pub fn render(err: &EvalError, source: &str) -> String {
+    let message = format!("error: {}", err.message());
+    match err.loc() {
+        Some(loc) => match excerpt(loc, source, err.label()) {
+            Some(excerpt) => format!("{}\n{}", message, excerpt),
+            None => format!("{}\n --> {}:{}:{}", message, loc.filename(), loc.rol(), loc.col()),
+        },
+        None => message,
+    }
+}
+
+/// The source line pointed at by the location, the span of the location
+/// is underlined with carets up to the end of the line
+fn excerpt(loc: &Location, source: &str, label: &str) -> Option<String> {
+    let line = source.lines().nth(loc.rol().checked_sub(1)?)?;
+    // Keep the caret on the line even if the column runs past its end
+    let len = line.chars().count();
+    let col = loc.col().clamp(1, len + 1);
+    let end_col = if loc.end_rol() == loc.rol() { loc.end_col() } else { len + 1 };
+    let padding: String = line
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(end_col.min(len + 1).saturating_sub(col).max(1));
+
+    let number = loc.rol().to_string();
+    let gutter = " ".repeat(number.len());
+    Some(format!(
+        "{gutter}--> {}:{}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {padding}{carets} {label}",
+        loc.filename(), loc.rol(), col
+    ).trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_render() {
+        let interp = Interpreter::new();
+        let err = interp.eval_str("(+ 1 yy)").unwrap_err();
+        let expected = [
+            "error: Symbol not found: \"yy\"",
+            " --> <string>:1:6",
+            "  |",
+            "1 | (+ 1 yy)",
+            "  |      ^^ not defined in any enclosing scope",
+        ].join("\n");
+        assert_eq!(render(&err, "(+ 1 yy)"), expected);
+
+        // The caret is under the column of the line holding the error
+        let source = "(define (f)\n  (list \"héllo\" yy))\n(f)";
+        let err = interp.eval_str(source).unwrap_err();
+        let expected = [
+            " --> <string>:2:17",
+            "  |",
+            "2 |   (list \"héllo\" yy))",
+            "  |                 ^^ not defined in any enclosing scope",
+        ].join("\n");
+        assert!(render(&err, source).ends_with(&expected));
+
+        // Errors without location only show the message
+        let err = interp.eval_file("missing.lisp").unwrap_err();
+        assert_eq!(render(&err, ""), format!("error: {}", err.message()));
+
+        // Errors inside a call without their own location point at the call
+        let err = interp.eval_str("(car 1 2)").unwrap_err();
+        assert!(render(&err, "(car 1 2)").ends_with("1 | (car 1 2)\n  | ^^^^^^^^^ wrong number of arguments"));
+    }
+}
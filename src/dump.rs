@@ -0,0 +1,90 @@
+use rslisp::evaluator::with_stack;
+use rslisp::format::format_forms;
+use rslisp::lexer::{tokenize, TokenKind};
+use rslisp::location::Location;
+use rslisp::parser::parse;
+use rslisp::{EvalError, Interpreter, Object};
+
+/// The tokens of the source one per line with their position,
+/// the whitespaces between them are left out
+pub fn tokens(fname: &str, source: &str) -> Result<String, EvalError> {
+    let tokens = tokenize(fname, source)?;
+    let lines: Vec<_> = tokens
+        .iter()
+        .filter(|token| !matches!(token.kind(), TokenKind::IGNORE))
+        .map(|token| format!("{:<8} {:?}", position(token.loc()), token.kind()))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// The tree of objects parsed from the source, each object on its own
+/// line indented under the list holding it
+///
+/// ```text
+/// Module
+///   List 1:1-1:8
+///     Symbol + 1:2
+///     Integer 1 1:4
+///     Integer 2 1:6
+/// ```
+pub fn ast(fname: &str, source: &str) -> Result<String, EvalError> {
+    let mut tokens = tokenize(fname, source)?;
+    let program = parse(&mut tokens)?;
+    let mut lines = vec![];
+    tree(&program, 0, &mut lines);
+    Ok(lines.join("\n"))
+}
+
+/// The program with every macro call expanded, laid out by the formatter.
+/// The macros are defined in a sandbox and the rest of the program is not run
+pub fn expanded(fname: &str, source: &str) -> Result<String, EvalError> {
+    let mut interp = Interpreter::new();
+    interp.set_sandbox(&[]);
+    let forms = interp.expand_source(fname, source)?;
+    Ok(format_forms(&forms).trim_end().to_string())
+}
+
+fn tree(obj: &Object, depth: usize, lines: &mut Vec<String>) {
+    let (node, children): (String, &[Object]) = match obj {
+        Object::Module { value, .. } => ("Module".to_string(), value),
+        Object::List { value, .. } => ("List".to_string(), value),
+        Object::Vector { value, .. } => ("Vector".to_string(), value),
+        Object::Pair { value, .. } => {
+            lines.push(format!("{}Pair{}", "  ".repeat(depth), span(obj)));
+            with_stack(|| tree(&value.0, depth + 1, lines));
+            with_stack(|| tree(&value.1, depth + 1, lines));
+            return;
+        },
+        Object::Integer { value, .. } => (format!("Integer {}", value), &[]),
+        Object::BigInteger { value, .. } => (format!("BigInteger {}", value), &[]),
+        Object::Rational { value, .. } => (format!("Rational {}", value), &[]),
+        Object::Float { value, .. } => (format!("Float {}", value), &[]),
+        Object::Bool { value, .. } => (format!("Bool {}", value), &[]),
+        Object::Char { value, .. } => (format!("Char {:?}", value), &[]),
+        Object::Str { value, .. } => (format!("Str {:?}", value), &[]),
+        Object::Symbol { value, .. } => (format!("Symbol {}", value), &[]),
+        Object::Keyword { value, .. } => (format!("Keyword {}", value), &[]),
+        obj => (obj.to_string(), &[]),
+    };
+    // The location of the module is not a position in the source
+    let span = if matches!(obj, Object::Module { .. }) { String::new() } else { span(obj) };
+    lines.push(format!("{}{}{}", "  ".repeat(depth), node, span));
+    for child in children {
+        with_stack(|| tree(child, depth + 1, lines));
+    }
+}
+
+fn position(loc: &Location) -> String {
+    format!("{}:{}", loc.rol(), loc.col())
+}
+
+/// The span of the object, only its start when it is a single position
+fn span(obj: &Object) -> String {
+    match obj.loc() {
+        Some(loc) if loc.end_rol() > loc.rol() || loc.end_col() > loc.col() + 1 => {
+            format!(" {}-{}:{}", position(loc), loc.end_rol(), loc.end_col())
+        },
+        Some(loc) => format!(" {}", position(loc)),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,366 @@
+use std::rc::Rc;
+
+use crate::location::Location;
+use crate::parser::{ErrorObject, Object};
+
+/// Errors found while splitting the source into tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    /// No token starts with this character
+    UnexpectedCharacter { found: char, loc: Location },
+    /// The text looks like a literal but is not a valid one, e.g. `#\bogus` or `1/0`
+    InvalidLiteral { text: String, loc: Location },
+    /// An escape sequence of a string literal which is not valid, e.g. `\q`,
+    /// the location is its backslash
+    InvalidEscape { text: String, loc: Location },
+    /// The location is the opening quote of the string, or the `#r` of a raw string
+    UnterminatedString { loc: Location },
+    /// The location is the opening `#|` of the block comment
+    UnterminatedComment { loc: Location },
+}
+
+/// Errors found while building objects out of the tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedRightParenthesis { loc: Location },
+    /// The location is the left parenthesis of the list
+    UnclosedList { loc: Location },
+    /// A quote character not followed by any expression, e.g. `(list ')`
+    MissingQuotedExpression { loc: Location },
+    /// A datum comment not followed by any expression, e.g. `(f #;)`
+    MissingCommentedExpression { loc: Location },
+    /// A reader macro character not followed by any expression, e.g. `(f #d)`
+    MissingDispatchedExpression { loc: Location },
+    /// A `}` or `]` not closing a list opened by `#{` or `#[`
+    UnexpectedClosingBracket { found: char, loc: Location },
+    /// A dot not between the elements and the last expression
+    /// of a list, e.g. `( . a)`, `(a . b c)` or `#(a . b)`
+    InvalidDottedList { loc: Location },
+}
+
+/// The number of arguments a function or a special form accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+            Arity::Between(min, max) => min <= count && count <= max,
+        }
+    }
+}
+
+/// Errors raised while evaluating a program
+#[derive(Debug, Clone)]
+pub enum EvalError {
+    Lex(LexError),
+    Parse(ParseError),
+    UndefinedSymbol { name: String, loc: Option<Location> },
+    /// A value of the wrong type is given, `expected` describes
+    /// the accepted values and `found` is the printed value
+    TypeMismatch { expected: String, found: String, loc: Option<Location> },
+    ArityMismatch { name: String, expected: Arity, found: usize, loc: Option<Location> },
+    NotCallable { found: String, loc: Option<Location> },
+    /// A special form is written the wrong way
+    InvalidSyntax { form: String, message: String, loc: Option<Location> },
+    IndexOutOfRange { index: usize, length: usize, loc: Option<Location> },
+    /// Arguments of the right type whose values the builtin `name` rejects
+    InvalidArgument { name: String, message: String, loc: Option<Location> },
+    /// `key` is the printed key missing from a hash table
+    KeyNotFound { key: String, loc: Option<Location> },
+    DivisionByZero { loc: Option<Location> },
+    Io { path: String, message: String },
+    ModuleNotFound { name: String, loc: Option<Location> },
+    /// A `:name value` argument not matching the keyword parameters
+    InvalidKeywordArgument { keyword: String, message: String, loc: Option<Location> },
+    /// An object raised by `raise` or `error` and not caught by `guard`
+    Raised { value: Box<Object>, loc: Option<Location> },
+    /// `(exit code)` unwinds the program, `guard` does not catch it
+    Exit { code: i32 },
+    /// An `assert` or `assert-equal` which does not hold, the message
+    /// is empty unless one is given to `assert`
+    AssertionFailed { message: String, loc: Option<Location> },
+    /// More calls are nested than the runtime allows, see [`crate::runtime::Runtime::max_depth`]
+    RecursionLimit { limit: usize, loc: Option<Location> },
+    /// The evaluation took all the steps it is allowed, see [`crate::fuel`],
+    /// `guard` does not catch it
+    OutOfFuel { loc: Option<Location> },
+    /// A builtin with side effects called in a sandbox which does not allow it
+    Forbidden { name: String, loc: Option<Location> },
+    /// Text which is not valid in the data `format` it is read as, e.g. JSON
+    Decode { format: String, message: String, loc: Option<Location> },
+}
+
+impl EvalError {
+    pub fn type_mismatch(expected: &str, found: &Object) -> Self {
+        EvalError::TypeMismatch {
+            expected: expected.to_string(),
+            found: found.to_string(),
+            loc: found.loc().cloned(),
+        }
+    }
+
+    pub fn invalid_syntax(form: &str, message: &str, loc: Option<&Location>) -> Self {
+        EvalError::InvalidSyntax {
+            form: form.to_string(),
+            message: message.to_string(),
+            loc: loc.cloned(),
+        }
+    }
+
+    /// The object bound by `guard` when the error is caught, errors
+    /// of the interpreter are turned into error objects
+    pub fn to_condition(&self) -> Object {
+        match self {
+            EvalError::Raised { value, .. } => value.as_ref().clone(),
+            _ => Object::Error {
+                value: Rc::new(ErrorObject { message: self.message(), irritants: vec![] }),
+                loc: self.loc().cloned(),
+            },
+        }
+    }
+
+    /// Attach the location to the error unless it already has one
+    pub fn or_loc(mut self, loc: Option<&Location>) -> Self {
+        match &mut self {
+            EvalError::UndefinedSymbol { loc: slot, .. }
+            | EvalError::TypeMismatch { loc: slot, .. }
+            | EvalError::ArityMismatch { loc: slot, .. }
+            | EvalError::NotCallable { loc: slot, .. }
+            | EvalError::InvalidSyntax { loc: slot, .. }
+            | EvalError::IndexOutOfRange { loc: slot, .. }
+            | EvalError::InvalidArgument { loc: slot, .. }
+            | EvalError::KeyNotFound { loc: slot, .. }
+            | EvalError::ModuleNotFound { loc: slot, .. }
+            | EvalError::InvalidKeywordArgument { loc: slot, .. }
+            | EvalError::Raised { loc: slot, .. }
+            | EvalError::AssertionFailed { loc: slot, .. }
+            | EvalError::RecursionLimit { loc: slot, .. }
+            | EvalError::OutOfFuel { loc: slot }
+            | EvalError::Forbidden { loc: slot, .. }
+            | EvalError::Decode { loc: slot, .. }
+            | EvalError::DivisionByZero { loc: slot } if slot.is_none() => *slot = loc.cloned(),
+            _ => {}
+        }
+        self
+    }
+}
+
+impl From<LexError> for EvalError {
+    fn from(e: LexError) -> Self {
+        EvalError::Lex(e)
+    }
+}
+
+impl From<ParseError> for EvalError {
+    fn from(e: ParseError) -> Self {
+        EvalError::Parse(e)
+    }
+}
+
+impl LexError {
+    pub fn loc(&self) -> &Location {
+        match self {
+            LexError::UnexpectedCharacter { loc, .. } => loc,
+            LexError::InvalidLiteral { loc, .. } => loc,
+            LexError::InvalidEscape { loc, .. } => loc,
+            LexError::UnterminatedString { loc } => loc,
+            LexError::UnterminatedComment { loc } => loc,
+        }
+    }
+
+    /// Describe the error without its location
+    pub fn message(&self) -> String {
+        match self {
+            LexError::UnexpectedCharacter { found, .. } => format!("Unexpected character {:?}", found),
+            LexError::InvalidLiteral { text, .. } => format!("Invalid literal `{}`", text),
+            LexError::InvalidEscape { text, .. } => format!("Invalid escape sequence `{}`", text),
+            LexError::UnterminatedString { .. } => "Unterminated string literal".to_string(),
+            LexError::UnterminatedComment { .. } => "Unterminated block comment".to_string(),
+        }
+    }
+
+    /// A short explanation shown under the offending source
+    pub fn label(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedCharacter { .. } => "no token starts with this character",
+            LexError::InvalidLiteral { .. } => "not a valid literal",
+            LexError::InvalidEscape { .. } => "not a valid escape sequence",
+            LexError::UnterminatedString { .. } => "string starting here is never closed",
+            LexError::UnterminatedComment { .. } => "comment starting here is never closed",
+        }
+    }
+}
+
+impl ParseError {
+    pub fn loc(&self) -> &Location {
+        match self {
+            ParseError::UnexpectedRightParenthesis { loc } => loc,
+            ParseError::UnclosedList { loc } => loc,
+            ParseError::MissingQuotedExpression { loc } => loc,
+            ParseError::MissingCommentedExpression { loc } => loc,
+            ParseError::MissingDispatchedExpression { loc } => loc,
+            ParseError::UnexpectedClosingBracket { loc, .. } => loc,
+            ParseError::InvalidDottedList { loc } => loc,
+        }
+    }
+
+    /// Describe the error without its location
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::UnexpectedRightParenthesis { .. } => "Unexpected Right parenthesis `)`".to_string(),
+            ParseError::UnclosedList { .. } => "Unclosed List, the left parenthesis is never matched".to_string(),
+            ParseError::MissingQuotedExpression { .. } => "Expect an expression after the quote".to_string(),
+            ParseError::MissingCommentedExpression { .. } => "Expect an expression after the datum comment `#;`".to_string(),
+            ParseError::MissingDispatchedExpression { .. } => "Expect an expression after the reader macro character".to_string(),
+            ParseError::UnexpectedClosingBracket { found, .. } => format!("Unexpected closing bracket `{}`", found),
+            ParseError::InvalidDottedList { .. } => "Invalid dotted list, expect (a b ... . c)".to_string(),
+        }
+    }
+
+    /// A short explanation shown under the offending source
+    pub fn label(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedRightParenthesis { .. } => "no list to close here",
+            ParseError::UnclosedList { .. } => "list opened here was never closed",
+            ParseError::MissingQuotedExpression { .. } => "nothing follows the quote",
+            ParseError::MissingCommentedExpression { .. } => "nothing follows the datum comment",
+            ParseError::MissingDispatchedExpression { .. } => "nothing follows the reader macro character",
+            ParseError::UnexpectedClosingBracket { .. } => "no bracket to close here",
+            ParseError::InvalidDottedList { .. } => "misplaced dot",
+        }
+    }
+}
+
+impl EvalError {
+    /// Where the error happened, if known
+    pub fn loc(&self) -> Option<&Location> {
+        match self {
+            EvalError::Lex(e) => Some(e.loc()),
+            EvalError::Parse(e) => Some(e.loc()),
+            EvalError::UndefinedSymbol { loc, .. }
+            | EvalError::TypeMismatch { loc, .. }
+            | EvalError::NotCallable { loc, .. }
+            | EvalError::ArityMismatch { loc, .. }
+            | EvalError::InvalidSyntax { loc, .. }
+            | EvalError::IndexOutOfRange { loc, .. }
+            | EvalError::InvalidArgument { loc, .. }
+            | EvalError::KeyNotFound { loc, .. }
+            | EvalError::ModuleNotFound { loc, .. }
+            | EvalError::InvalidKeywordArgument { loc, .. }
+            | EvalError::Raised { loc, .. }
+            | EvalError::AssertionFailed { loc, .. }
+            | EvalError::RecursionLimit { loc, .. }
+            | EvalError::OutOfFuel { loc }
+            | EvalError::Forbidden { loc, .. }
+            | EvalError::Decode { loc, .. }
+            | EvalError::DivisionByZero { loc } => loc.as_ref(),
+            EvalError::Io { .. } | EvalError::Exit { .. } => None,
+        }
+    }
+
+    /// Describe the error without its location
+    pub fn message(&self) -> String {
+        match self {
+            EvalError::Lex(e) => e.message(),
+            EvalError::Parse(e) => e.message(),
+            EvalError::UndefinedSymbol { name, .. } => format!("Symbol not found: {:?}", name),
+            EvalError::TypeMismatch { expected, found, .. } => format!("Expect {} but {} found", expected, found),
+            EvalError::ArityMismatch { name, expected, found, .. } =>
+                format!("Expect {} arguments but {} given when calling {}", expected, found, name),
+            EvalError::NotCallable { found, .. } => format!("Expect a function but {} found", found),
+            EvalError::InvalidSyntax { form, message, .. } => format!("Invalid {}: {}", form, message),
+            EvalError::IndexOutOfRange { index, length, .. } =>
+                format!("Index {} is out of range for length {}", index, length),
+            EvalError::InvalidArgument { name, message, .. } => format!("Invalid argument to {}: {}", name, message),
+            EvalError::KeyNotFound { key, .. } => format!("Key {} not found in the hash table", key),
+            EvalError::DivisionByZero { .. } => "Division by zero".to_string(),
+            EvalError::Io { path, message } => format!("Failed to access {}: {}", path, message),
+            EvalError::ModuleNotFound { name, .. } => format!("Module {} not found", name),
+            EvalError::InvalidKeywordArgument { keyword, message, .. } =>
+                format!("Invalid keyword argument {}: {}", keyword, message),
+            EvalError::Raised { value, .. } => match value.as_ref() {
+                Object::Error { value, .. } => {
+                    let irritants: Vec<_> = value.irritants.iter().map(|obj| obj.to_string()).collect();
+                    format!("{} {}", value.message, irritants.join(" ")).trim_end().to_string()
+                },
+                value => format!("Uncaught exception: {}", value),
+            },
+            EvalError::Exit { code } => format!("Exit with status {}", code),
+            EvalError::AssertionFailed { message, .. } if message.is_empty() => "Assertion failed".to_string(),
+            EvalError::AssertionFailed { message, .. } => format!("Assertion failed: {}", message),
+            EvalError::RecursionLimit { limit, .. } => format!("Maximum recursion depth of {} exceeded", limit),
+            EvalError::OutOfFuel { .. } => "Out of fuel, the evaluation took all the steps it is allowed".to_string(),
+            EvalError::Forbidden { name, .. } => format!("{} is not allowed in the sandbox", name),
+            EvalError::Decode { format, message, .. } => format!("Invalid {}: {}", format, message),
+        }
+    }
+
+    /// A short explanation shown under the offending source
+    pub fn label(&self) -> &'static str {
+        match self {
+            EvalError::Lex(e) => e.label(),
+            EvalError::Parse(e) => e.label(),
+            EvalError::UndefinedSymbol { .. } => "not defined in any enclosing scope",
+            EvalError::TypeMismatch { .. } => "value of the wrong type",
+            EvalError::ArityMismatch { .. } => "wrong number of arguments",
+            EvalError::NotCallable { .. } => "only functions and macros can be called",
+            EvalError::InvalidSyntax { .. } => "malformed special form",
+            EvalError::IndexOutOfRange { .. } => "index out of range",
+            EvalError::InvalidArgument { .. } => "invalid argument",
+            EvalError::KeyNotFound { .. } => "no such key",
+            EvalError::DivisionByZero { .. } => "the divisor is zero",
+            EvalError::Io { .. } => "",
+            EvalError::ModuleNotFound { .. } => "not found in RSLISP_PATH nor next to the current file",
+            EvalError::InvalidKeywordArgument { .. } => "invalid keyword argument",
+            EvalError::Raised { .. } => "raised here",
+            EvalError::Exit { .. } => "",
+            EvalError::AssertionFailed { .. } => "the assertion does not hold",
+            EvalError::RecursionLimit { .. } => "too many nested calls",
+            EvalError::OutOfFuel { .. } => "stopped here",
+            EvalError::Forbidden { .. } => "disabled by the sandbox",
+            EvalError::Decode { .. } => "cannot be decoded",
+        }
+    }
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message(), self.loc())
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message(), self.loc())
+    }
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::AtLeast(n) => write!(f, "at least {}", n),
+            Arity::Between(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.loc() {
+            Some(loc) => write!(f, "{} at {}", self.message(), loc),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+impl std::error::Error for ParseError {}
+impl std::error::Error for EvalError {}
@@ -1,178 +1,2644 @@
-use std::clone;
 use std::{
     rc::Rc,
     collections::HashMap,
-    cell::RefCell,
+    cell::{Cell, RefCell},
 };
-use crate::parser::{Object, FunctionBody, FunctionDefinition, Param, ParamKind};
+use crate::builtins;
+use crate::fuel;
+use crate::gc;
+use crate::hook;
+use crate::error::{Arity, EvalError};
+use crate::parser::{Object, ListItems, FunctionBody, FunctionDefinition, Param, ParamKind, Promise, RecordType, READER_MACRO};
 use crate::location::Location;
+use crate::native::{NativeFn, NativeFunction};
+use crate::resolve::{resolve, unresolve};
+use crate::runtime::{Frame, Runtime};
+use crate::symbol::Symbol;
+
+/// The number of variables from which the slots of a frame are indexed
+/// by name, the frames of the calls are searched through instead
+const INDEXED: usize = 8;
 
 pub struct Environment {
     parent: Option<Rc<RefCell<Environment>>>,
-    vars: HashMap<String, Object>
+    /// The slot of each variable in `values` once there are more than
+    /// [`INDEXED`] variables, empty before
+    slots: HashMap<Symbol, usize>,
+    /// The names of the variables in the order they are bound
+    names: Vec<Symbol>,
+    /// The values of the variables in the order they are bound
+    values: Vec<Object>,
+    /// The number of variables bound when the environment is sealed,
+    /// see [`Environment::seal`]
+    sealed: usize,
+    /// Only the root environment holds the runtime, boxed so the frames
+    /// of the calls stay small
+    runtime: Option<Box<Runtime>>
+}
+
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Only the names are printed, closures stored in the environment
+        // hold a reference back to the environment they are defined in
+        let mut names: Vec<_> = self.names.iter().map(Symbol::as_str).collect();
+        names.sort();
+        f.debug_struct("Environment").field("vars", &names).finish()
+    }
 }
 
 impl Environment {
-    /// initialize the environment with the built-in functions
-    /// the only identifier for the builtin function is that their 
-    /// location's filename is "__builtin__" while the rol and 
-    /// col are both equals to 0
-    fn create_builtin_funcdef() -> Object {
-        Object::Lambda { 
-            value: FunctionDefinition {
-                params: vec![Param {
-                    kind: ParamKind::Variadic ,
-                    loc: Some(Location::new("__builtin__".to_string(), 0, 0))
-                }],
-                body: FunctionBody(vec![])
-            },
-            loc: None
+    /// Create a new environment. The root environment (the one without
+    /// parent) is populated with the builtin functions, child environments
+    /// start empty and look the builtins up through their parent.
+    pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Self {
+        let mut env = Self {
+            parent,
+            slots: HashMap::new(),
+            names: vec![],
+            values: vec![],
+            sealed: 0,
+            runtime: None
+        };
+        if env.parent.is_some() {
+            return env;
         }
+
+        for (name, arity, func) in builtins::BUILTINS.iter() {
+            let builtin = NativeFunction::builtin(name, *arity, *func);
+            env.set(*name, Object::NativeFunction { value: Rc::new(builtin), loc: None });
+        }
+        env.runtime = Some(Box::default());
+        env
     }
 
-    /// check if the function is a builtin function
-    pub fn is_builtin(object: &Object) -> bool {
-        object
-        .loc()
-        .and_then(|l| Some(l.filename() == "__builtin__".to_string()))
-        .unwrap_or(false)
+    /// The root environment holding the builtins and the global variables
+    pub fn root(env: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        match env.borrow().parent {
+            Some(ref parent) => Environment::root(parent),
+            None => env.clone()
+        }
     }
 
-    pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Self {
-        let vars = HashMap::from_iter([
-            ("+".to_string(), Environment::create_builtin_funcdef()),
-            ("-".to_string(), Environment::create_builtin_funcdef()),
-            ("*".to_string(), Environment::create_builtin_funcdef()),
-            ("/".to_string(), Environment::create_builtin_funcdef()),
-            ("%".to_string(), Environment::create_builtin_funcdef()),
-            (">".to_string(), Environment::create_builtin_funcdef()),
-            ("<".to_string(), Environment::create_builtin_funcdef()),
-            ("=".to_string(), Environment::create_builtin_funcdef()),
-            (">=".to_string(), Environment::create_builtin_funcdef()),
-            ("<=".to_string(), Environment::create_builtin_funcdef()),
-            ("/=".to_string(), Environment::create_builtin_funcdef()),
-        ]);
-
-        Self {
-            parent,
-            vars
+    /// Access the runtime held by the root environment
+    pub fn with_runtime<R>(env: &Rc<RefCell<Environment>>, f: impl FnOnce(&mut Runtime) -> R) -> R {
+        let root = Environment::root(env);
+        let mut root = root.borrow_mut();
+        f(root.runtime.get_or_insert_with(Box::default))
+    }
+
+    /// Track the environment for the cycle collection when it is
+    /// captured by a closure or a promise, and collect the cycles once
+    /// enough environments are tracked, see [`crate::gc::collect`]
+    pub fn track(env: &Rc<RefCell<Environment>>) {
+        if Environment::with_runtime(env, |runtime| runtime.track(Rc::downgrade(env))) {
+            gc::collect(env);
+        }
+    }
+
+    /// The variables bound in this environment, not in its parents,
+    /// in the order they are bound
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        self.names.iter().copied().zip(self.values.iter().cloned()).collect()
+    }
+
+    pub(crate) fn parent(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.parent.as_ref()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Object> {
+        self.values.iter()
+    }
+
+    pub(crate) fn runtime(&self) -> Option<&Runtime> {
+        self.runtime.as_deref()
+    }
+
+    /// Drop the bindings and the parent, breaking the cycles going through them
+    pub(crate) fn clear(&mut self) {
+        self.slots.clear();
+        self.names.clear();
+        self.values.clear();
+        self.parent = None;
+    }
+
+    /// Fix the variables bound so far as the ones the code resolved
+    /// by [`crate::resolve`] expects in the frame, only the variables
+    /// defined later may shadow the ones of the parent frames
+    pub(crate) fn seal(&mut self) {
+        self.sealed = self.values.len();
+    }
+
+    /// The value of the variable in the slot of the environment `depth`
+    /// levels up. None if the slot does not hold the variable, e.g. it is
+    /// not defined yet, or an environment on the way defined a variable
+    /// of the same name after being sealed
+    pub(crate) fn get_at(&self, depth: usize, slot: usize, name: Symbol) -> Option<Object> {
+        if depth == 0 {
+            return match self.names.get(slot) {
+                Some(bound) if *bound == name => Some(self.values[slot].clone()),
+                _ => None
+            };
+        }
+        if self.names[self.sealed..].contains(&name) {
+            return None;
+        }
+        self.parent.as_ref()?.borrow().get_at(depth - 1, slot, name)
+    }
+
+    /// Replace the value in the slot like [`Environment::get_at`] reads
+    /// it, return false if the slot cannot be addressed
+    pub(crate) fn set_at(&mut self, depth: usize, slot: usize, name: Symbol, obj: Object) -> bool {
+        if depth == 0 {
+            return match self.names.get(slot) {
+                Some(bound) if *bound == name => {
+                    self.values[slot] = obj;
+                    true
+                },
+                _ => false
+            };
         }
+        match self.parent {
+            Some(ref parent) if !self.names[self.sealed..].contains(&name) => parent.borrow_mut().set_at(depth - 1, slot, name, obj),
+            _ => false
+        }
+    }
+
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Object> {
+        self.lookup(name.into())
     }
 
-    pub fn get(&self, name: &str) -> Option<Object> {
-        match self.vars.get(name) {
-            Some(value) => Some(value.clone()),
+    /// Look the name up in the frame and then in its parents, for the
+    /// variables which are not addressed by slot, see [`crate::resolve`]
+    fn lookup(&self, name: Symbol) -> Option<Object> {
+        match self.slot(name) {
+            Some(slot) => Some(self.values[slot].clone()),
             None => {
                 self.parent
                     .as_ref()
-                    .and_then(|e| e.borrow().get(name).clone())
+                    .and_then(|e| e.borrow().lookup(name))
+            }
+        }
+    }
+
+    /// Look the variable up like [`Environment::get`], trying first the
+    /// slot it was found in last time in the frame binding it, so the global
+    /// variables are found without hashing their name
+    pub(crate) fn get_hinted(&self, name: Symbol, hint: &Cell<usize>) -> Option<Object> {
+        let slot = match self.names.get(hint.get()) {
+            Some(bound) if *bound == name => Some(hint.get()),
+            _ => self.slot(name)
+        };
+        match slot {
+            Some(slot) => {
+                hint.set(slot);
+                Some(self.values[slot].clone())
+            },
+            None => self.parent.as_ref()?.borrow().get_hinted(name, hint)
+        }
+    }
+
+    /// The slot of the variable bound in this environment
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        if self.slots.is_empty() {
+            self.names.iter().position(|bound| *bound == name)
+        } else {
+            self.slots.get(&name).copied()
+        }
+    }
+
+    pub fn set(&mut self, name: impl Into<Symbol>, obj: Object) {
+        let name = name.into();
+        match self.slot(name) {
+            Some(slot) => self.values[slot] = obj,
+            None => {
+                self.names.push(name);
+                self.values.push(obj);
+                if self.names.len() > INDEXED {
+                    let slots = &mut self.slots;
+                    if slots.is_empty() {
+                        slots.extend(self.names.iter().enumerate().map(|(slot, name)| (*name, slot)));
+                    } else {
+                        slots.insert(name, self.names.len() - 1);
+                    }
+                }
             }
         }
     }
 
-    pub fn set(&mut self, name: &str, obj: Object) {
-        self.vars.insert(name.to_string(), obj);
+    /// Replace the value of the nearest existing binding, return false
+    /// if the name is not bound in this environment or any of its parents
+    pub fn update(&mut self, name: impl Into<Symbol>, obj: Object) -> bool {
+        let name = name.into();
+        if let Some(slot) = self.slot(name) {
+            self.values[slot] = obj;
+            return true;
+        }
+
+        match self.parent {
+            Some(ref parent) => parent.borrow_mut().update(name, obj),
+            None => false
+        }
     }
 }
 
-pub fn eval(object: Object, env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+pub fn eval(object: Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
     eval_obj(&object, env)
 }
 
-pub fn eval_obj(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+pub fn eval_obj(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    fuel::burn()?;
     match obj {
         Object::Void { .. }
         | Object::Lambda { .. }
+        | Object::Macro { .. }
+        | Object::NativeFunction { .. }
         | Object::Bool { .. }
         | Object::Integer { .. }
+        | Object::BigInteger { .. }
+        | Object::Rational { .. }
         | Object::Float { .. }
-        | Object::Str { .. } => Ok(obj.clone()),
-        Object::Symbol { value: ref s, .. } => eval_symbol(s.as_str(), env),
-        Object::List { value, .. }
-        | Object::Module { value, .. } => eval_list(&value.as_slice(), env),
+        | Object::Char { .. }
+        | Object::Str { .. }
+        | Object::Keyword { .. }
+        | Object::Vector { .. }
+        | Object::HashTable { .. }
+        | Object::RecordType { .. }
+        | Object::Record { .. }
+        | Object::Error { .. }
+        | Object::Promise { .. }
+        | Object::Port { .. }
+        | Object::Parameter { .. }
+        | Object::Eof { .. }
+        | Object::Environment { .. } => Ok(obj.clone()),
+        Object::Symbol { value, loc } => eval_symbol(*value, loc.as_ref(), env),
+        Object::Local { value, depth, slot, loc } => match env.borrow().get_at(*depth, *slot, *value) {
+            Some(value) => Ok(value),
+            None => eval_symbol(*value, loc.as_ref(), env),
+        },
+        // Errors raised without a location inside the call point at the call site
+        Object::List { .. } if hook::is_installed() => eval_hooked(obj, env),
+        Object::List { value, loc } => eval_list(value.as_slice(), env).map_err(|e| e.or_loc(loc.as_ref())),
+        Object::Pair { .. } => Err(EvalError::type_mismatch("a proper list to evaluate", obj)),
+        Object::Module { value, .. } => eval_module(value.as_slice(), env),
     }
 }
 
-pub fn eval_symbol(s: &str, env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+/// Evaluate the list telling the hook about it, kept out of `eval_obj`
+/// so the frames of the recursions without a hook stay small
+#[inline(never)]
+fn eval_hooked(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let Object::List { value, loc } = obj else { unreachable!() };
+    hook::before_eval(obj, env)?;
+    let result = eval_list(value.as_slice(), env).map_err(|e| e.or_loc(loc.as_ref()));
+    hook::after_eval(obj, &result);
+    result
+}
+
+pub fn eval_symbol(s: Symbol, loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
     env.borrow()
         .get(s)
-        .and_then(|s| Some(s.clone()))
-        .ok_or(format!("Symbol not found: {:?}", s))
+        .ok_or_else(|| EvalError::UndefinedSymbol { name: s.to_string(), loc: loc.cloned() })
 }
 
-pub fn eval_list(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
-    match list.first() {
+pub fn eval_list(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    finish(eval_tail_list(list, env)?, env)
+}
+
+/// The value of an expression in tail position, or the call of a function
+/// left to make, so the call can replace the call of the function whose
+/// body the expression ends instead of nesting in it
+pub enum Tail {
+    Value(Object),
+    /// The function, the arguments and the location of the call
+    Call(Rc<FunctionDefinition>, Vec<Object>, Option<Location>),
+}
+
+/// Make the call left by the expression in tail position
+pub fn finish(tail: Tail, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    match tail {
+        Tail::Value(value) => Ok(value),
+        Tail::Call(func, args, loc) => apply_function(&func, &args).map_err(|e| trace(e, func.label(), loc.as_ref(), env)),
+    }
+}
+
+/// Evaluate the object in tail position, the hooks see every call
+/// so no call is left to make while one is installed
+pub fn eval_tail(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    match obj {
+        Object::List { value, loc } if !hook::is_installed() => {
+            fuel::burn()?;
+            eval_tail_list(value.as_slice(), env).map_err(|e| e.or_loc(loc.as_ref()))
+        },
+        _ => eval_obj(obj, env).map(Tail::Value),
+    }
+}
+
+/// Evaluate the objects in order, the last one in tail position
+pub fn eval_tail_body(body: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    let Some((last, init)) = body.split_last() else {
+        return Ok(Tail::Value(Object::Void { loc: None }));
+    };
+    for obj in init {
+        eval_obj(obj, env)?;
+    }
+    eval_tail(last, env)
+}
+
+fn eval_tail_list(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    let value = match list.first() {
         Some(Object::Symbol { ref value, ..}) => match value.as_str() {
+            "if" => return eval_if(&list[1..], env),
+            "cond" => return eval_cond(&list[1..], env),
+            "case" => return eval_case(&list[1..], env),
+            "when" | "unless" => return eval_when(value.as_str(), &list[1..], env),
+            "begin" => return eval_tail_body(&list[1..], env),
+            "and" => return eval_and(&list[1..], env),
+            "or" => return eval_or(&list[1..], env),
+            "let" => return eval_let(&list[1..], env),
+            "let*" => return eval_let_star(&list[1..], env),
+            "letrec" => return eval_letrec(&list[1..], env),
             "define" => eval_define(&list[1..], env),
-            "if" => eval_if(&list[1..], env),
+            "set!" => eval_set(&list[1..], env),
             "lambda" => eval_function_definition(&list[1..], env),
-            _ => eval_function_call(&list[1..], env)
+            "defmacro" => eval_defmacro(&list[1..], env),
+            "guard" => eval_guard(&list[1..], env),
+            "delay" => eval_delay(&list[1..], env),
+            "parameterize" => eval_parameterize(&list[1..], env),
+            "define-record-type" => eval_define_record_type(&list[1..], env),
+            "define-test" => eval_define_test(&list[1..], env),
+            "quote" => eval_quote(&list[1..]),
+            "quasiquote" => eval_quasiquote(&list[1..], env),
+            "unquote" | "unquote-splicing" => Err(EvalError::invalid_syntax(
+                value.as_str(), "only allowed inside quasiquote", list[0].loc())),
+            _ => return eval_function_call(list, env)
         },
+        Some(_) => return eval_function_call(list, env),
         None => Ok(Object::Void { loc: None }),  // Empty list `()`
-        _ => {
-            unreachable!()
-        }
+    };
+    value.map(Tail::Value)
+}
+
+/// Expand and evaluate the top level forms one by one, so that a macro
+/// can be used by the forms following its definition
+pub fn eval_module(forms: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let mut result = Object::Void { loc: None };
+    for form in forms {
+        let form = resolve(&expand(form, env)?);
+        result = eval_obj(&form, env)?;
     }
+    Ok(result)
 }
 
-pub fn eval_define(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+/// Evaluate the objects in order and return the value of the last one
+pub fn eval_body(body: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    finish(eval_tail_body(body, env)?, env)
+}
+
+pub fn eval_define(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
     let object = if let Some(obj) = list.first() {
         obj
     } else {
-        return Err("".to_string());
+        return Err(EvalError::invalid_syntax("define", "expect a variable name", None));
     };
 
+    // (define (name x y) body) is (define name (lambda (x y) body))
+    // and (define (name . args) body) is (define name (lambda args body))
+    match object {
+        Object::List { value, loc } => {
+            let (name, params) = match value.split_first() {
+                Some((name, params)) => (name, Object::List { value: ListItems::new(params.to_vec()), loc: loc.clone() }),
+                None => return Err(EvalError::invalid_syntax("define", "expect a function name", loc.as_ref()))
+            };
+            return eval_define_function(name, params, &list[1..], env);
+        },
+        Object::Pair { value, .. } => return eval_define_function(&value.0, value.1.clone(), &list[1..], env),
+        _ => {}
+    }
+
     let name = if let Object::Symbol { value, .. } = object {
-        value.clone()
+        *value
     } else {
-        return Err(format!(
-            "Expect Symbol/identifier but {} found at {:?}", object, object.loc()))
+        return Err(EvalError::type_mismatch("Symbol/identifier", object))
     };
 
     let val = if let Some(obj) = list.get(1) {
         eval_obj(obj, env)
     } else {
-        Err(format!("Expect binding an Object to a variable in {:?}", object.loc()))
+        Err(EvalError::invalid_syntax("define", "expect binding an Object to the variable", object.loc()))
     }?;
 
-    env.borrow_mut().set(name.as_str(), val);  // update the environment
+    env.borrow_mut().set(name, val);  // update the environment
+    Ok(Object::Void { loc: None })
+}
+
+fn eval_define_function(
+    name: &Object,
+    params: Object,
+    body: &[Object],
+    env: &Rc<RefCell<Environment>>
+) -> Result<Object, EvalError> {
+    let name = match name {
+        Object::Symbol { value, .. } => *value,
+        obj => return Err(EvalError::type_mismatch("Symbol/identifier", obj))
+    };
+
+    let mut lambda = vec![params];
+    lambda.extend_from_slice(body);
+    let mut func = eval_function_definition(&lambda, env)?;
+    if let Object::Lambda { value, .. } = &mut func {
+        Rc::make_mut(value).name = Some(name.to_string());
+    }
+
+    env.borrow_mut().set(name, func);
+    Ok(Object::Void { loc: None })
+}
+
+pub fn eval_set(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (set! name expr) mutates the binding found by walking up the scopes,
+    // or the one in its slot when the variable is addressed
+    let (name, address, loc) = match list.first() {
+        Some(Object::Symbol { value, loc }) => (*value, None, loc.clone()),
+        Some(Object::Local { value, depth, slot, loc }) => (*value, Some((*depth, *slot)), loc.clone()),
+        Some(obj) => return Err(EvalError::type_mismatch("Symbol/identifier", obj)),
+        None => return Err(EvalError::invalid_syntax("set!", "expect a variable name", None))
+    };
+
+    let val = match list.get(1) {
+        Some(obj) => eval_obj(obj, env)?,
+        None => return Err(EvalError::invalid_syntax("set!", "expect binding an Object to the variable", loc.as_ref()))
+    };
+
+    if let Some((depth, slot)) = address {
+        if env.borrow_mut().set_at(depth, slot, name, val.clone()) {
+            return Ok(Object::Void { loc: None });
+        }
+    }
+    if !env.borrow_mut().update(name, val) {
+        return Err(EvalError::UndefinedSymbol { name: name.to_string(), loc });
+    }
     Ok(Object::Void { loc: None })
 }
 
-pub fn eval_if(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+/// Everything except the false boolean counts as true in a condition
+pub fn is_truthy(obj: &Object) -> bool {
+    !matches!(obj, Object::Bool { value: false, .. })
+}
+
+pub fn eval_if(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
     // (if (boolean-expression) true-case false-case)
-    let condition = list
-        .first()
-        .and_then(|object| {
-            match object {
-                Object::Bool { value, .. } => Some(value),
-                // Object::List { value, .. } => eval_list(list, env)?
-                _ => unimplemented!()
-            }
-        });
+    let condition = match list.first() {
+        Some(object) => Some(eval_obj(object, env)?),
+        None => None
+    };
 
-    if matches!(condition, Some(true)) {
+    if condition.as_ref().map(is_truthy).unwrap_or(false) {
         list.get(1)
     } else {
         list.get(2)
     }
-    .map_or_else(|| Err(format!("follow-up action not found for the if-expression")), |o| eval_obj(o, env))
+    .map_or_else(|| Err(EvalError::invalid_syntax("if", "follow-up action not found", None)), |o| eval_tail(o, env))
+}
+
+pub fn eval_cond(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (cond (test expr ...) ... (else expr ...)) evaluates the expressions
+    // of the first clause whose test is true
+    Ok(eval_clauses("cond", list, env)?.unwrap_or(Tail::Value(Object::Void { loc: None })))
+}
+
+pub fn eval_when(form: &str, list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (when test expr ...) evaluates the expressions when the test is true
+    // and (unless test expr ...) when it is false, the value is void otherwise
+    let test = match list.first() {
+        Some(test) => eval_obj(test, env)?,
+        None => return Err(EvalError::invalid_syntax(form, "expect a test", None))
+    };
+    if is_truthy(&test) == (form == "when") {
+        eval_tail_body(&list[1..], env)
+    } else {
+        Ok(Tail::Value(Object::Void { loc: None }))
+    }
+}
+
+pub fn eval_case(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (case expr ((1 2) a) ((3) b) (else c)) evaluates the expressions of the
+    // first clause listing the value of expr, compared with eqv?
+    let key = match list.first() {
+        Some(expr) => eval_obj(expr, env)?,
+        None => return Err(EvalError::invalid_syntax("case", "expect an expression", None))
+    };
+    let clauses = &list[1..];
+    for (i, clause) in clauses.iter().enumerate() {
+        let clause = match clause {
+            Object::List { value, .. } if !value.is_empty() => value,
+            _ => return Err(EvalError::type_mismatch("((datum ...) expr ...) clause", clause))
+        };
+        let chosen = match &clause[0] {
+            Object::Symbol { value, loc } if value == "else" => {
+                if i != clauses.len() - 1 {
+                    return Err(EvalError::invalid_syntax("case", "else clause must be the last clause", loc.as_ref()));
+                }
+                true
+            },
+            Object::List { value: data, .. } => data.iter().any(|datum| datum.is_eqv(&key)),
+            obj => return Err(EvalError::type_mismatch("a list of data or else", obj))
+        };
+        if chosen {
+            return eval_tail_body(&clause[1..], env);
+        }
+    }
+    Ok(Tail::Value(Object::Void { loc: None }))
+}
+
+/// Evaluate the first clause whose test is true, None if no clause is chosen
+fn eval_clauses(form: &str, list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Option<Tail>, EvalError> {
+    for (i, clause) in list.iter().enumerate() {
+        let clause = match clause {
+            Object::List { value, .. } if !value.is_empty() => value,
+            _ => return Err(EvalError::type_mismatch("(test expr ...) clause", clause))
+        };
+
+        if matches!(&clause[0], Object::Symbol { value, .. } if value == "else") {
+            if i != list.len() - 1 {
+                return Err(EvalError::invalid_syntax(form, "else clause must be the last clause", clause[0].loc()));
+            }
+            return eval_tail_body(&clause[1..], env).map(Some);
+        }
+
+        let test = eval_obj(&clause[0], env)?;
+        if is_truthy(&test) {
+            // A clause without expressions gives the value of its test
+            return if clause.len() == 1 { Ok(Some(Tail::Value(test))) } else { eval_tail_body(&clause[1..], env).map(Some) };
+        }
+    }
+    Ok(None)
+}
+
+pub fn eval_delay(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (delay expr) postpones the evaluation of expr until the promise is forced
+    match list {
+        [expr] => {
+            Environment::track(env);
+            Ok(Object::Promise {
+                value: Rc::new(RefCell::new(Promise::Delayed(expr.clone(), env.clone()))),
+                loc: expr.loc().cloned()
+            })
+        },
+        _ => Err(EvalError::ArityMismatch { name: "delay".to_string(), expected: Arity::Exact(1), found: list.len(), loc: None })
+    }
 }
 
-pub fn eval_function_definition(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
-    // (lambda (x y) (* x y))
-    let params = list.first();
-    let body = list.get(1);
+pub fn eval_parameterize(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (parameterize ((param value) ...) body ...) gives the parameters the
+    // values, passed through their converters, while the body is evaluated,
+    // the previous values are restored even when the body raises an error
+    let bindings = match list.first() {
+        Some(Object::List { value, .. }) => value,
+        Some(obj) => return Err(EvalError::type_mismatch("((parameter value) ...)", obj)),
+        None => return Err(EvalError::invalid_syntax("parameterize", "expect ((parameter value) ...)", None))
+    };
+
+    let mut params = Vec::with_capacity(bindings.len());
+    for binding in bindings.iter() {
+        let (param, value) = match binding {
+            Object::List { value, .. } if value.len() == 2 => (&value[0], &value[1]),
+            _ => return Err(EvalError::type_mismatch("(parameter value) binding", binding))
+        };
+        let param = match &eval_obj(param, env)? {
+            Object::Parameter { value, .. } => value.clone(),
+            obj => return Err(EvalError::type_mismatch("a parameter object", obj))
+        };
+        let value = eval_obj(value, env)?;
+        let value = match &param.converter {
+            Some(converter) => apply(converter, &[value], env)?,
+            None => value,
+        };
+        params.push((param, value));
+    }
+
+    for (param, value) in params.iter_mut() {
+        std::mem::swap(&mut *param.value.borrow_mut(), value);
+    }
+    let result = eval_body(&list[1..], env);
+    // The same parameter may be bound twice, the first value saved is the outer one
+    for (param, value) in params.into_iter().rev() {
+        *param.value.borrow_mut() = value;
+    }
+    result
+}
+
+pub fn eval_guard(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (guard (e clause ...) body ...) evaluates the body, an error raised by
+    // the body is bound to e and handled by the first clause whose test is
+    // true like cond, the error is raised again when no clause is chosen
+    let (var, clauses) = match list.first() {
+        Some(Object::List { value, .. }) => match value.split_first() {
+            Some((Object::Symbol { value, .. }, clauses)) => (*value, clauses),
+            Some((obj, _)) => return Err(EvalError::type_mismatch("Symbol/identifier", obj)),
+            None => return Err(EvalError::invalid_syntax("guard", "expect (variable clause ...)", list[0].loc()))
+        },
+        Some(obj) => return Err(EvalError::type_mismatch("(variable clause ...)", obj)),
+        None => return Err(EvalError::invalid_syntax("guard", "expect (variable clause ...)", None))
+    };
+
+    let scope = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+    scope.borrow_mut().seal();
+    let err = match eval_body(&list[1..], &scope) {
+        Ok(value) => return Ok(value),
+        // Exiting and running out of fuel are not conditions the program can handle
+        Err(err @ (EvalError::Exit { .. } | EvalError::OutOfFuel { .. })) => return Err(err),
+        Err(err) => err
+    };
+
+    // The calls the error unwound through are forgotten once it is handled
+    let backtrace = Environment::with_runtime(env, |runtime| std::mem::take(&mut runtime.backtrace));
+    let handler = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+    handler.borrow_mut().set(var, err.to_condition());
+    handler.borrow_mut().seal();
+    match eval_clauses("guard", clauses, &handler)? {
+        Some(tail) => finish(tail, &handler),
+        None => {
+            Environment::with_runtime(env, |runtime| runtime.backtrace = backtrace);
+            Err(err)
+        },
+    }
+}
+
+pub fn eval_and(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (and a b ...) stops at the first false value, otherwise gives the last value
+    let Some((last, init)) = list.split_last() else {
+        return Ok(Tail::Value(Object::Bool { value: true, loc: None }));
+    };
+    for obj in init {
+        let result = eval_obj(obj, env)?;
+        if !is_truthy(&result) {
+            return Ok(Tail::Value(result));
+        }
+    }
+    eval_tail(last, env)
+}
+
+pub fn eval_or(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (or a b ...) stops at the first true value, otherwise gives the last value
+    let Some((last, init)) = list.split_last() else {
+        return Ok(Tail::Value(Object::Bool { value: false, loc: None }));
+    };
+    for obj in init {
+        let result = eval_obj(obj, env)?;
+        if is_truthy(&result) {
+            return Ok(Tail::Value(result));
+        }
+    }
+    eval_tail(last, env)
+}
+
+/// Split `((name expr) ...)` into the names and the unevaluated expressions
+fn parse_bindings(obj: Option<&Object>) -> Result<Vec<(Symbol, &Object)>, EvalError> {
+    let bindings = match obj {
+        Some(Object::List { value, .. }) => value,
+        Some(obj) => return Err(EvalError::type_mismatch("binding list", obj)),
+        None => return Err(EvalError::invalid_syntax("let", "expect binding list", None))
+    };
+
+    bindings
+        .iter()
+        .map(|binding| match binding {
+            Object::List { value, .. } => match value.as_slice() {
+                [Object::Symbol { value: name, .. }, expr] => Ok((*name, expr)),
+                _ => Err(EvalError::type_mismatch("(name expr) binding", binding))
+            },
+            _ => Err(EvalError::type_mismatch("(name expr) binding", binding))
+        })
+        .collect()
+}
+
+pub fn eval_let(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (let ((x 1) (y 2)) body) evaluates every expression in the outer scope
+    if let Some(Object::Symbol { .. }) = list.first() {
+        return eval_tail(&named_let(list)?, env);
+    }
+    let bindings = parse_bindings(list.first())?;
+    let scope = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+    for (name, expr) in bindings {
+        let val = eval_obj(expr, env)?;
+        scope.borrow_mut().set(name, val);
+    }
+    scope.borrow_mut().seal();
+    eval_tail_body(&list[1..], &scope)
+}
+
+/// Rewrite `(let name ((x 1) ...) body)` into the call
+/// `((let () (define (name x ...) body) name) 1 ...)`, so the body can loop
+/// by calling `name` while the values are computed outside of its scope
+pub(crate) fn named_let(list: &[Object]) -> Result<Object, EvalError> {
+    let name = &list[0];
+    let bindings = match list.get(1) {
+        Some(Object::List { value, .. }) => value,
+        Some(obj) => return Err(EvalError::type_mismatch("binding list", obj)),
+        None => return Err(EvalError::invalid_syntax("let", "expect binding list", name.loc()))
+    };
+    if list.len() < 3 {
+        return Err(EvalError::invalid_syntax("let", "expect a body", name.loc()));
+    }
+
+    let mut signature = vec![name.clone()];
+    let mut args = vec![];
+    for binding in bindings.iter() {
+        match binding {
+            Object::List { value, .. } if matches!(value[..], [Object::Symbol { .. }, _]) => {
+                signature.push(value[0].clone());
+                args.push(value[1].clone());
+            },
+            _ => return Err(EvalError::type_mismatch("(name expr) binding", binding))
+        }
+    }
+    let loc = name.loc();
+    let symbol = |value: &str| Object::Symbol { value: Symbol::intern(value), loc: loc.cloned() };
+    let form = |items: Vec<Object>| Object::List { value: ListItems::new(items), loc: loc.cloned() };
+    let mut define = vec![symbol("define"), form(signature)];
+    define.extend_from_slice(&list[2..]);
+    let func = form(vec![symbol("let"), form(vec![]), form(define), name.clone()]);
+    Ok(form(std::iter::once(func).chain(args).collect()))
+}
+
+pub fn eval_let_star(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (let* ((x 1) (y x)) body) sees the previous bindings, each binding
+    // gets its own scope nested in the one of the previous binding
+    let bindings = parse_bindings(list.first())?;
+    let mut scope = env.clone();
+    for (name, expr) in bindings {
+        let val = eval_obj(expr, &scope)?;
+        scope = Rc::new(RefCell::new(Environment::new(Some(scope))));
+        scope.borrow_mut().set(name, val);
+        scope.borrow_mut().seal();
+    }
+    let scope = Rc::new(RefCell::new(Environment::new(Some(scope))));
+    scope.borrow_mut().seal();
+    eval_tail_body(&list[1..], &scope)
+}
+
+pub fn eval_letrec(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (letrec ((even? (lambda ...)) (odd? (lambda ...))) body) evaluates the
+    // expressions in the new scope so that they can refer to each other
+    let bindings = parse_bindings(list.first())?;
+    let scope = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+    for (name, _) in bindings.iter() {
+        scope.borrow_mut().set(*name, Object::Void { loc: None });
+    }
+    scope.borrow_mut().seal();
+    for (name, expr) in bindings {
+        let val = eval_obj(expr, &scope)?;
+        scope.borrow_mut().set(name, val);
+    }
+    eval_tail_body(&list[1..], &scope)
+}
+
+pub fn eval_quote(list: &[Object]) -> Result<Object, EvalError> {
+    // (quote expr) gives back expr without evaluating it
+    match list {
+        [obj] => Ok(obj.clone()),
+        _ => Err(EvalError::ArityMismatch { name: "quote".to_string(), expected: Arity::Exact(1), found: list.len(), loc: None })
+    }
+}
+
+pub fn eval_quasiquote(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (quasiquote expr) works like quote but (unquote x) inside expr is
+    // replaced by the value of x and (unquote-splicing xs) by the elements of xs
+    match list {
+        [obj] => quasiquote(obj, 1, env),
+        _ => Err(EvalError::ArityMismatch { name: "quasiquote".to_string(), expected: Arity::Exact(1), found: list.len(), loc: None })
+    }
+}
+
+/// Return the name of the form if the object is a (name expr) list
+fn as_quote_form(obj: &Object) -> Option<(&str, &Object)> {
+    match obj {
+        Object::List { value, .. } => match value.as_slice() {
+            [Object::Symbol { value: name, .. }, expr]
+                if matches!(name.as_str(), "quasiquote" | "unquote" | "unquote-splicing") =>
+                Some((name.as_str(), expr)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Walk the template, the depth is increased by nested quasiquote so that
+/// only the unquotes belonging to the outermost quasiquote are evaluated
+fn quasiquote(obj: &Object, depth: usize, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The nested templates are walked recursively, on more stack when needed
+    with_stack(|| quasiquote_form(obj, depth, env))
+}
+
+fn quasiquote_form(obj: &Object, depth: usize, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let list = match obj {
+        Object::List { value, .. } => value,
+        _ => return Ok(obj.clone())
+    };
+
+    match as_quote_form(obj) {
+        Some(("unquote", expr)) if depth == 1 => return eval_obj(expr, env),
+        Some(("unquote-splicing", _)) if depth == 1 => return Err(EvalError::invalid_syntax(
+            "unquote-splicing", "only allowed inside a list", list[0].loc())),
+        Some((name, expr)) => {
+            let depth = if name == "quasiquote" { depth + 1 } else { depth - 1 };
+            return Ok(Object::List {
+                value: ListItems::new(vec![list[0].clone(), quasiquote(expr, depth, env)?]),
+                loc: obj.loc().cloned()
+            });
+        },
+        None => {}
+    }
+
+    let mut result = Vec::with_capacity(list.len());
+    for item in list.iter() {
+        match as_quote_form(item) {
+            Some(("unquote-splicing", expr)) if depth == 1 => match &eval_obj(expr, env)? {
+                Object::List { value, .. } => result.extend(value.iter().cloned()),
+                other => return Err(EvalError::TypeMismatch {
+                    expected: "a list for unquote-splicing".to_string(),
+                    found: other.to_string(),
+                    loc: expr.loc().cloned()
+                })
+            },
+            _ => result.push(quasiquote(item, depth, env)?)
+        }
+    }
+    Ok(Object::List { value: ListItems::new(result), loc: obj.loc().cloned() })
+}
+
+/// Parse the parameter list of a lambda, the improper list `(x y . rest)`
+/// and a lone symbol `args` collect the remaining arguments into a list.
+/// The parameters after `&key` are keyword parameters, written either
+/// `name` or `(name default)`, e.g. `(x &key (width 10) height)`
+pub(crate) fn parse_params(obj: Option<&Object>) -> Result<Vec<Param>, EvalError> {
+    let mut params = vec![];
+    let mut rest = match obj {
+        Some(obj) => obj,
+        None => return Err(EvalError::invalid_syntax("lambda", "expect parameter list", None))
+    };
+    // Walk the chain of pairs down to the rest parameter
+    while let Object::Pair { value, .. } = rest {
+        params.push(&value.0);
+        rest = &value.1;
+    }
+
+    let rest = match rest {
+        Object::List { value, .. } => {
+            params.extend(value.iter());
+            None
+        },
+        Object::Symbol { value, loc } => Some(Param {
+            kind: ParamKind::Variadic(*value),
+            loc: loc.clone()
+        }),
+        obj => return Err(EvalError::type_mismatch("parameter list", obj))
+    };
+
+    let mut result = vec![];
+    let mut keyword = false;
+    for obj in params {
+        let kind = match obj {
+            Object::Symbol { value, loc } if value == "&key" => {
+                if keyword || rest.is_some() {
+                    return Err(EvalError::invalid_syntax("lambda", "&key is given once and without a rest parameter", loc.as_ref()));
+                }
+                keyword = true;
+                continue;
+            },
+            Object::Symbol { value, .. } if keyword => ParamKind::Keyword { name: *value, default: None },
+            Object::Symbol { value, .. } => ParamKind::Named(*value),
+            Object::List { value, .. } if keyword => match &value[..] {
+                [Object::Symbol { value: name, .. }, default] => ParamKind::Keyword {
+                    name: *name,
+                    default: Some(default.clone())
+                },
+                _ => return Err(EvalError::type_mismatch("(name default) as keyword parameter", obj))
+            },
+            _ => return Err(EvalError::type_mismatch("Symbol as parameter", obj))
+        };
+        result.push(Param { kind, loc: obj.loc().cloned() });
+    }
+    result.extend(rest);
+    Ok(result)
+}
+
+pub fn eval_function_definition(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (lambda (x y) (* x y)), (lambda (x . rest) rest) or (lambda args args)
+    let params = parse_params(list.first())?;
+
+    let body = &list[1..];
+    if body.is_empty() {
+        return Err(EvalError::invalid_syntax("lambda", "expect function body", list[0].loc()));
+    }
+
+    Environment::track(env);
+    Ok(Object::Lambda {
+        value: Rc::new(FunctionDefinition {
+            params,
+            body: FunctionBody(body.to_vec()),
+            env: env.clone(),  // capture the defining environment
+            name: None,
+        }),
+        loc: list[0].loc().cloned()
+    })
+}
+
+pub fn eval_define_test(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (define-test "name" body ...) collects the body to be evaluated by
+    // run-tests, defining a test with the same name again replaces it
+    let name = match list.first() {
+        Some(Object::Str { value, .. }) => value.clone(),
+        Some(Object::Symbol { value, .. }) => value.to_string(),
+        Some(obj) => return Err(EvalError::type_mismatch("a string as test name", obj)),
+        None => return Err(EvalError::invalid_syntax("define-test", "expect (define-test name body ...)", None))
+    };
+    if list.len() < 2 {
+        return Err(EvalError::invalid_syntax("define-test", "expect test body", list[0].loc()));
+    }
+
+    let mut items = vec![Object::List { value: ListItems::new(vec![]), loc: list[0].loc().cloned() }];
+    items.extend_from_slice(&list[1..]);
+    let thunk = eval_function_definition(&items, env)?;
+    Environment::with_runtime(env, |runtime| {
+        match runtime.tests.iter_mut().find(|(test, _)| *test == name) {
+            Some(test) => test.1 = thunk,
+            None => runtime.tests.push((name, thunk)),
+        }
+    });
+    Ok(Object::Void { loc: None })
+}
+
+fn symbol_name(obj: Option<&Object>, form: &str, expected: &str) -> Result<String, EvalError> {
+    match obj {
+        Some(Object::Symbol { value, .. }) => Ok(value.to_string()),
+        Some(obj) => Err(EvalError::type_mismatch(expected, obj)),
+        None => Err(EvalError::invalid_syntax(form, &format!("expect {}", expected), None))
+    }
+}
+
+/// Bind a function generated by `define-record-type`, the body calls a
+/// builtin directly so shadowing its name does not break the function
+fn define_record_function(
+    env: &Rc<RefCell<Environment>>,
+    name: &str,
+    params: &[&str],
+    builtin: &str,
+    args: Vec<Object>,
+) {
+    let func = builtins::lookup(builtin).expect("the record builtins exist");
+    let mut call = vec![Object::NativeFunction { value: Rc::new(func), loc: None }];
+    call.extend(args);
+    let func = FunctionDefinition {
+        params: params
+            .iter()
+            .map(|param| Param { kind: ParamKind::Named(Symbol::intern(param)), loc: None })
+            .collect(),
+        body: FunctionBody(vec![Object::List { value: ListItems::new(call), loc: None }]),
+        env: env.clone(),
+        name: Some(name.to_string()),
+    };
+    env.borrow_mut().set(name, Object::Lambda { value: Rc::new(func), loc: None });
+}
+
+pub fn eval_define_record_type(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (define-record-type point (make-point x y) point? (x point-x set-point-x!) (y point-y))
+    const FORM: &str = "define-record-type";
+    let type_name = symbol_name(list.first(), FORM, "a type name")?;
+    let constructor = match list.get(1) {
+        Some(Object::List { value, .. }) => value
+            .iter()
+            .map(|obj| symbol_name(Some(obj), FORM, "Symbol/identifier"))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(obj) => return Err(EvalError::type_mismatch("(constructor field ...)", obj)),
+        None => return Err(EvalError::invalid_syntax(FORM, "expect a constructor", None))
+    };
+    let predicate = symbol_name(list.get(2), FORM, "a predicate name")?;
+
+    // Every field spec is (field accessor) or (field accessor modifier)
+    let mut specs = vec![];
+    for spec in list.iter().skip(3) {
+        let names = match spec {
+            Object::List { value, .. } if value.len() == 2 || value.len() == 3 => value
+                .iter()
+                .map(|obj| symbol_name(Some(obj), FORM, "Symbol/identifier"))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(EvalError::type_mismatch("(field accessor [modifier])", spec))
+        };
+        specs.push(names);
+    }
+
+    let fields: Vec<_> = specs.iter().map(|names| names[0].clone()).collect();
+    let record_type = Object::RecordType {
+        value: Rc::new(RecordType { name: type_name.clone(), fields: fields.clone() }),
+        loc: list[0].loc().cloned()
+    };
+
+    let (constructor, params) = match constructor.split_first() {
+        Some(split) => split,
+        None => return Err(EvalError::invalid_syntax(FORM, "expect a constructor name", list[1].loc()))
+    };
+    if let Some(param) = params.iter().find(|param| !fields.contains(param)) {
+        return Err(EvalError::invalid_syntax(FORM, &format!("{} is not a field of {}", param, type_name), list[1].loc()));
+    }
+    // Fields not initialized by the constructor start as #f
+    let mut args = vec![record_type.clone()];
+    args.extend(fields.iter().map(|field| match params.contains(field) {
+        true => Object::Symbol { value: Symbol::intern(field), loc: None },
+        false => Object::Bool { value: false, loc: None }
+    }));
+    let params: Vec<_> = params.iter().map(String::as_str).collect();
+    define_record_function(env, constructor, &params, "make-record", args);
+
+    let obj = || Object::Symbol { value: Symbol::intern("obj"), loc: None };
+    define_record_function(env, &predicate, &["obj"], "record?", vec![obj(), record_type.clone()]);
+    for (index, names) in specs.iter().enumerate() {
+        let index = Object::Integer { value: index as i128, loc: None };
+        let args = vec![obj(), record_type.clone(), index.clone()];
+        define_record_function(env, &names[1], &["obj"], "record-ref", args.clone());
+        if let Some(modifier) = names.get(2) {
+            let mut args = args;
+            args.push(Object::Symbol { value: Symbol::intern("value"), loc: None });
+            define_record_function(env, modifier, &["obj", "value"], "record-set!", args);
+        }
+    }
+
+    env.borrow_mut().set(&type_name, record_type);
+    Ok(Object::Void { loc: None })
+}
+
+pub fn eval_defmacro(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // (defmacro name (x y) body)
+    let name = match list.first() {
+        Some(Object::Symbol { value, .. }) => *value,
+        Some(obj) => return Err(EvalError::type_mismatch("Symbol/identifier", obj)),
+        None => return Err(EvalError::invalid_syntax("defmacro", "expect macro name", None))
+    };
+
+    let mut value = match &eval_function_definition(&list[1..], env)? {
+        Object::Lambda { value, .. } => value.clone(),
+        _ => unreachable!()
+    };
+    Rc::make_mut(&mut value).name = Some(name.to_string());
+
+    let loc = list[0].loc().cloned();
+    env.borrow_mut().set(name, Object::Macro { value, loc });
+    Ok(Object::Void { loc: None })
 }
 
-pub fn eval_function_call(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
-    todo!()
+/// Look the head of the list up, return the macro if it names one
+fn lookup_macro(list: &[Object], env: &Rc<RefCell<Environment>>) -> Option<Rc<FunctionDefinition>> {
+    match list.first() {
+        Some(Object::Symbol { value, .. }) => match &env.borrow().get(value) {
+            Some(Object::Macro { value, .. }) => Some(value.clone()),
+            _ => None
+        },
+        _ => None
+    }
 }
 
-pub fn eval_builtin_func(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
-    todo!()
+/// Call the macro with the unevaluated argument forms, the result is
+/// the form to be evaluated in place of the macro call
+pub fn apply_macro(func: &FunctionDefinition, list: &[Object]) -> Result<Object, EvalError> {
+    apply_function(func, &list[1..])
 }
 
-pub fn eval_builtin_plus_func(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
-    todo!()
-}
\ No newline at end of file
+/// Expand the form once if it is a macro call, the result may be
+/// another macro call and the forms inside it are left as they are
+pub fn expand_1(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    match obj {
+        Object::List { value, .. } => match lookup_macro(value, env) {
+            Some(func) => apply_macro(&func, value),
+            None => Ok(obj.clone())
+        },
+        _ => Ok(obj.clone())
+    }
+}
+
+/// Expand every macro call in the form until no macro call is left,
+/// quoted data is left untouched but for the reader macro forms
+/// `(reader-macro #\d datum)` read from `#d datum`, which are replaced
+/// by the value of the reader macro of the character called with the datum
+pub fn expand(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    // The nested forms are expanded recursively, on more stack when needed
+    with_stack(|| expand_form(obj, env))
+}
+
+fn expand_form(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let list = match obj {
+        Object::List { value, .. } => value,
+        _ => return Ok(obj.clone())
+    };
+
+    // The expansions are counted as nested calls, so a macro expanding
+    // to its own call fails instead of expanding forever
+    if let Some(func) = lookup_macro(list, env) {
+        let expanded = apply_macro(&func, list)?;
+        let _depth = CallDepth::enter(env)?;
+        return expand(&expanded, env);
+    }
+    if let Some(read) = read_macro(list, env)? {
+        let _depth = CallDepth::enter(env)?;
+        return expand(&read, env);
+    }
+
+    // The parameters of a lambda, the name and the parameters of a
+    // function definition and the specs of a record type are never macro calls
+    let skipped = match list.first() {
+        Some(Object::Symbol { value, .. }) => match value.as_str() {
+            "quote" | "quasiquote" if !has_reader_macro(obj) => return Ok(obj.clone()),
+            "quote" | "quasiquote" => return expand_reader_macros(obj, env),
+            "define-record-type" => return Ok(obj.clone()),
+            "lambda" | "define" => 2,
+            "defmacro" => 3,
+            "case" => return expand_case(list, obj.loc(), env),
+            "let" | "let*" | "letrec" => return expand_let(list, obj.loc(), env),
+            "cond" => return expand_cond(list, obj.loc(), env),
+            "guard" => return expand_guard(list, obj.loc(), env),
+            _ => 0
+        },
+        _ => 0
+    };
+
+    let mut value = Vec::with_capacity(list.len());
+    for (i, item) in list.iter().enumerate() {
+        if i < skipped {
+            value.push(item.clone());
+        } else {
+            value.push(expand(item, env)?);
+        }
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: obj.loc().cloned() })
+}
+
+/// Expand the key and the expressions of the clauses of `case`, the data
+/// of the clauses are never macro calls
+fn expand_case(list: &[Object], loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let mut value = vec![list[0].clone()];
+    for (i, item) in list.iter().enumerate().skip(1) {
+        if i > 1 {
+            value.push(expand_clause(item, 1, env)?);
+        } else {
+            value.push(expand(item, env)?);
+        }
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: loc.cloned() })
+}
+
+/// Expand the tests and the expressions of the clauses of `cond`,
+/// the clauses themselves are never macro calls
+fn expand_cond(list: &[Object], loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let mut value = vec![list[0].clone()];
+    for clause in list[1..].iter() {
+        value.push(expand_clause(clause, 0, env)?);
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: loc.cloned() })
+}
+
+/// Expand the clauses of `guard` like the ones of `cond` and its body,
+/// the variable bound to the error is never a macro call
+fn expand_guard(list: &[Object], loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let mut value = vec![list[0].clone()];
+    for (i, item) in list.iter().enumerate().skip(1) {
+        match item {
+            Object::List { value: spec, loc } if i == 1 && !spec.is_empty() => {
+                let mut expanded = vec![spec[0].clone()];
+                for clause in spec[1..].iter() {
+                    expanded.push(expand_clause(clause, 0, env)?);
+                }
+                value.push(Object::List { value: ListItems::new(expanded), loc: loc.clone() });
+            },
+            item => value.push(expand(item, env)?),
+        }
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: loc.cloned() })
+}
+
+/// Expand the expressions of the bindings and the body of `let`, `let*`
+/// and `letrec`, the names of a named let and of the bindings are never macro calls
+fn expand_let(list: &[Object], loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let named = matches!(list.get(1), Some(Object::Symbol { .. }));
+    let bindings = if named { 2 } else { 1 };
+    let mut value = Vec::with_capacity(list.len());
+    for (i, item) in list.iter().enumerate() {
+        match item {
+            Object::List { value: items, loc } if i == bindings => {
+                let items = items.iter().map(|binding| expand_clause(binding, 1, env)).collect::<Result<_, _>>()?;
+                value.push(Object::List { value: ListItems::new(items), loc: loc.clone() });
+            },
+            item if i <= bindings => value.push(item.clone()),
+            item => value.push(expand(item, env)?),
+        }
+    }
+    Ok(Object::List { value: ListItems::new(value), loc: loc.cloned() })
+}
+
+/// Expand the items of the clause but the first `kept` ones, anything else
+/// than a list is left for the evaluation of the form to reject
+fn expand_clause(clause: &Object, kept: usize, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let Object::List { value, loc } = clause else {
+        return Ok(clause.clone());
+    };
+    let mut expanded = Vec::with_capacity(value.len());
+    for (i, item) in value.iter().enumerate() {
+        if i < kept {
+            expanded.push(item.clone());
+        } else {
+            expanded.push(expand(item, env)?);
+        }
+    }
+    Ok(Object::List { value: ListItems::new(expanded), loc: loc.clone() })
+}
+
+/// Call the reader macro of the character with the datum of the form
+/// `(reader-macro #\d datum)`, None is returned for the other lists
+fn read_macro(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Option<Object>, EvalError> {
+    let [Object::Symbol { value, .. }, Object::Char { value: c, loc }, datum] = list else {
+        return Ok(None);
+    };
+    if value.as_str() != READER_MACRO {
+        return Ok(None);
+    }
+    match Environment::with_runtime(env, |runtime| runtime.reader_macros.get(c).cloned()) {
+        Some(handler) => apply(&handler, std::slice::from_ref(datum), env).map(Some),
+        None => Err(EvalError::invalid_syntax(READER_MACRO, &format!("no reader macro for #{}", c), loc.as_ref())),
+    }
+}
+
+fn has_reader_macro(obj: &Object) -> bool {
+    // The nested data is searched recursively, on more stack when needed
+    with_stack(|| match obj {
+        Object::List { value, .. } => {
+            matches!(value.first(), Some(Object::Symbol { value, .. }) if value.as_str() == READER_MACRO)
+                || value.iter().any(has_reader_macro)
+        },
+        Object::Vector { value, .. } => value.iter().any(has_reader_macro),
+        Object::Pair { value, .. } => has_reader_macro(&value.0) || has_reader_macro(&value.1),
+        _ => false,
+    })
+}
+
+/// Replace the reader macro forms anywhere in the data, e.g. in quoted
+/// data or in the data given to `read`, since they are part of the
+/// source rather than code
+pub fn expand_reader_macros(obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    if !has_reader_macro(obj) {
+        return Ok(obj.clone());
+    }
+    let expand_all = |items: &[Object]| items.iter().map(|item| expand_reader_macros(item, env)).collect::<Result<Vec<_>, _>>();
+    // The nested data is expanded recursively, on more stack when needed
+    with_stack(|| match obj {
+        Object::List { value, loc } => match read_macro(value, env)? {
+            Some(read) => Ok(read),
+            None => Ok(Object::List { value: ListItems::new(expand_all(value)?), loc: loc.clone() }),
+        },
+        Object::Vector { value, loc } => Ok(Object::Vector { value: Rc::new(expand_all(value)?), loc: loc.clone() }),
+        Object::Pair { value, loc } => {
+            let pair = (expand_reader_macros(&value.0, env)?, expand_reader_macros(&value.1, env)?);
+            Ok(Object::Pair { value: Rc::new(pair), loc: loc.clone() })
+        },
+        _ => Ok(obj.clone()),
+    })
+}
+
+pub fn eval_function_call(list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // (func arg1 arg2 ...)
+    let func = eval_obj(&list[0], env)?;
+
+    // Macro calls which are not expanded before evaluation, e.g.
+    // those built at runtime, are expanded on the fly
+    if let Object::Macro { value, .. } = &func {
+        return eval_macro_call(value, list, env);
+    }
+
+    let args = list[1..]
+        .iter()
+        .map(|obj| eval_obj(obj, env))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The calls of functions are left to the caller, the traced
+    // functions print their calls so they are called in place
+    match &func {
+        Object::Lambda { value, .. } if !hook::is_installed() && !builtins::trace::is_traced(value, env) => {
+            return Ok(Tail::Call(value.clone(), args, list[0].loc().cloned()));
+        },
+        _ => {}
+    }
+
+    let call = || match &func {
+        Object::Lambda { value, .. } => apply_function(value, &args).map_err(|e| trace(e, value.label(), list[0].loc(), env)),
+        Object::NativeFunction { value, .. } => {
+            eval_native_func(value, &args, env).map_err(|e| trace(e, &value.name, list[0].loc(), env))
+        },
+        Object::Parameter { value, .. } => builtins::control::parameter_value(value, &args),
+        _ => Err(EvalError::NotCallable { found: func.to_string(), loc: list[0].loc().cloned() })
+    };
+    if hook::is_installed() {
+        return call_hooked(&func, &args, env, call).map(Tail::Value);
+    }
+    call().map(Tail::Value)
+}
+
+/// Expand the macro call and evaluate the expansion, kept out of
+/// `eval_function_call` so the frames of the calls stay small
+#[inline(never)]
+fn eval_macro_call(func: &FunctionDefinition, list: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Tail, EvalError> {
+    // The macro gets the forms as they are written
+    let list: Vec<_> = list.iter().map(unresolve).collect();
+    let expanded = apply_macro(func, &list)?;
+    eval_tail(&resolve(&expand(&expanded, env)?), env)
+}
+
+/// Record the call in the backtrace of the runtime as the error unwinds
+/// through it, exiting is not an error so it is never traced
+pub fn trace(err: EvalError, name: &str, loc: Option<&Location>, env: &Rc<RefCell<Environment>>) -> EvalError {
+    if !matches!(err, EvalError::Exit { .. }) {
+        let frame = Frame { name: name.to_string(), loc: loc.cloned() };
+        Environment::with_runtime(env, |runtime| runtime.backtrace.push(frame));
+    }
+    err
+}
+
+/// Call a builtin or user defined function with evaluated arguments
+pub fn apply(func: &Object, args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let call = || match func {
+        Object::Lambda { value, .. } => apply_function(value, args),
+        Object::NativeFunction { value, .. } => eval_native_func(value, args, env),
+        Object::Parameter { value, .. } => builtins::control::parameter_value(value, args),
+        _ => Err(EvalError::NotCallable { found: func.to_string(), loc: func.loc().cloned() })
+    };
+    if hook::is_installed() {
+        return call_hooked(func, args, env, call);
+    }
+    call()
+}
+
+/// Make the call telling the hook about it, kept out of the callers
+/// like `eval_hooked`
+#[inline(never)]
+fn call_hooked(
+    func: &Object,
+    args: &[Object],
+    env: &Rc<RefCell<Environment>>,
+    call: impl FnOnce() -> Result<Object, EvalError>,
+) -> Result<Object, EvalError> {
+    let name = match func {
+        Object::Lambda { value, .. } => value.label(),
+        Object::NativeFunction { value, .. } => value.name.as_str(),
+        _ => return call(),
+    };
+    hook::before_call(name, args, env)?;
+    let result = call();
+    hook::after_call(name, &result);
+    result
+}
+
+/// Bind the arguments to the parameters in a new child scope of the
+/// environment captured by the closure and evaluate the body in it
+pub fn apply_function(func: &FunctionDefinition, args: &[Object]) -> Result<Object, EvalError> {
+    let scope = bind_arguments(func, args)?;
+    let _depth = CallDepth::enter(&scope)?;
+    hook::enter_function(func.label(), args, &scope)?;
+    with_stack(|| {
+        if builtins::trace::is_traced(func, &scope) {
+            return builtins::trace::call(func, args, &scope);
+        }
+        // The calls in tail position replace the call of the function
+        // one after the other, so the loops run in constant stack
+        let mut tail = eval_tail_body(&func.body.0, &scope)?;
+        loop {
+            let (func, args, loc) = match tail {
+                Tail::Value(value) => return Ok(value),
+                Tail::Call(func, args, loc) => (func, args, loc),
+            };
+            tail = bind_arguments(&func, &args)
+                .and_then(|scope| eval_tail_body(&func.body.0, &scope))
+                .map_err(|e| trace(e.or_loc(loc.as_ref()), func.label(), loc.as_ref(), &scope))?;
+        }
+    })
+}
+
+/// The stack left below which the nested evaluation moves to a new segment
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// The size of the segments the stack grows by
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+/// Run the nested evaluation on a new segment of stack once the stack of
+/// the thread is nearly used up, so the depth limit is hit before the
+/// stack overflows whatever stack the caller runs on
+#[cfg(not(target_arch = "wasm32"))]
+pub fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
+
+/// The stack of a WebAssembly module cannot be grown, the depth limit
+/// has to fit the stack given by the host
+#[cfg(target_arch = "wasm32")]
+pub fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Count a call as nested in the calls being evaluated until the guard
+/// is dropped, so runaway recursions fail before the stack overflows
+pub struct CallDepth(Rc<RefCell<Environment>>);
+
+impl CallDepth {
+    pub fn enter(env: &Rc<RefCell<Environment>>) -> Result<Self, EvalError> {
+        let root = Environment::root(env);
+        let (depth, limit) = Environment::with_runtime(&root, |runtime| {
+            runtime.depth += 1;
+            (runtime.depth, runtime.max_depth)
+        });
+        let guard = CallDepth(root);
+        if depth > limit {
+            return Err(EvalError::RecursionLimit { limit, loc: None });
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for CallDepth {
+    fn drop(&mut self) {
+        Environment::with_runtime(&self.0, |runtime| runtime.depth -= 1);
+    }
+}
+
+/// The number of arguments accepted by a function taking the parameters,
+/// the keyword arguments are counted as any other argument
+pub(crate) fn arity(params: &[Param]) -> Arity {
+    let positional = params.iter().filter(|param| matches!(param.kind, ParamKind::Named(_))).count();
+    if positional == params.len() {
+        Arity::Exact(positional)
+    } else {
+        Arity::AtLeast(positional)
+    }
+}
+
+/// The scope a call of the function is evaluated in, holding the arguments
+/// bound to the parameters, the arity is checked here. The scope is sealed
+/// for the body to address the parameters, see [`Environment::seal`]
+pub fn bind_arguments(func: &FunctionDefinition, args: &[Object]) -> Result<Rc<RefCell<Environment>>, EvalError> {
+    let variadic = matches!(func.params.last(), Some(Param { kind: ParamKind::Variadic(_), .. }));
+    let positional = func.params
+        .iter()
+        .filter(|param| matches!(param.kind, ParamKind::Named(_)))
+        .count();
+    let keywords = func.params.len() - positional - variadic as usize;
+    let expected = arity(&func.params);
+    if !expected.accepts(args.len()) {
+        return Err(EvalError::ArityMismatch {
+            name: func.name.clone().unwrap_or_else(|| "lambda".to_string()),
+            expected,
+            found: args.len(),
+            loc: None
+        })
+    }
+
+    let scope = Rc::new(RefCell::new(Environment::new(Some(func.env.clone()))));
+    let given = keyword_args(func, &args[positional..], keywords > 0)?;
+    for (i, param) in func.params.iter().enumerate() {
+        match &param.kind {
+            ParamKind::Named(name) => scope.borrow_mut().set(name, args[i].clone()),
+            ParamKind::Variadic(name) => {
+                let rest = Object::List { value: ListItems::new(args[i..].to_vec()), loc: None };
+                scope.borrow_mut().set(name, rest)
+            },
+            // Defaults are evaluated in the scope so they can refer to the previous parameters
+            ParamKind::Keyword { name, default } => {
+                let value = match (given.get(name.as_str()), default) {
+                    (Some(value), _) => (*value).clone(),
+                    (None, Some(default)) => eval_obj(default, &scope)?,
+                    (None, None) => Object::Bool { value: false, loc: None }
+                };
+                scope.borrow_mut().set(name, value)
+            }
+        }
+    }
+    scope.borrow_mut().seal();
+    Ok(scope)
+}
+
+/// Match the `:name value` arguments following the positional ones
+/// against the keyword parameters of the function
+fn keyword_args<'a>(
+    func: &FunctionDefinition,
+    args: &'a [Object],
+    has_keywords: bool,
+) -> Result<HashMap<&'a str, &'a Object>, EvalError> {
+    let mut given = HashMap::new();
+    if !has_keywords {
+        return Ok(given);
+    }
+
+    let name = func.name.as_deref().unwrap_or("lambda");
+    for pair in args.chunks(2) {
+        let keyword = match &pair[0] {
+            Object::Keyword { value, .. } => value.as_str(),
+            obj => return Err(EvalError::type_mismatch(&format!("a keyword argument for {}", name), obj))
+        };
+        let invalid = |message: String| EvalError::InvalidKeywordArgument {
+            keyword: pair[0].to_string(),
+            message,
+            loc: pair[0].loc().cloned()
+        };
+        let declared = func.params.iter().any(|param| {
+            matches!(&param.kind, ParamKind::Keyword { name, .. } if name == keyword)
+        });
+        if !declared {
+            return Err(invalid(format!("{} has no such keyword parameter", name)));
+        }
+        let value = pair.get(1).ok_or_else(|| invalid("expect a value after the keyword".to_string()))?;
+        if given.insert(keyword, value).is_some() {
+            return Err(invalid("given more than once".to_string()));
+        }
+    }
+    Ok(given)
+}
+
+pub fn eval_native_func(func: &NativeFunction, args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+    let name = func.name.as_str();
+    if !func.arity.accepts(args.len()) {
+        return Err(EvalError::ArityMismatch { name: name.to_string(), expected: func.arity, found: args.len(), loc: None });
+    }
+    match &func.func {
+        NativeFn::Builtin(builtin) => {
+            if builtins::SIDE_EFFECTS.contains(&name) {
+                let allowed = Environment::with_runtime(env, |runtime| {
+                    runtime.sandbox.as_ref().is_none_or(|allowed| allowed.contains(name))
+                });
+                if !allowed {
+                    return Err(EvalError::Forbidden { name: name.to_string(), loc: None });
+                }
+            }
+            builtin(args, env)
+        },
+        NativeFn::Closure(closure) => closure(args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::tokenize;
+    use super::super::parser::parse;
+    use crate::error::{LexError, ParseError};
+
+    fn run(prog: &str) -> Result<Object, EvalError> {
+        let mut tokens = tokenize("evaluator_test.rs", prog)?;
+        let program = parse(&mut tokens)?;
+        let env = Rc::new(RefCell::new(Environment::new(None)));
+        eval(program, &env)
+    }
+
+    #[test]
+    fn test_function_call() {
+        let result = run("((lambda (x y) (+ x y)) 1 2)").unwrap();
+        assert!(matches!(result, Object::Integer { value: 3, .. }));
+
+        // Closures keep the environment they are created in
+        let prog = "(define make-adder (lambda (n) (lambda (x) (+ x n))))\n\
+                    (define add5 (make-adder 5))\n\
+                    (add5 10.5)";
+        let result = run(prog).unwrap();
+        assert!(matches!(result, Object::Float { value, .. } if value == 15.5));
+
+        // Wrong number of arguments
+        assert!(run("((lambda (x) x) 1 2)").is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        let result = run("'(undefined-func 1 2)").unwrap();
+        assert!(matches!(result, Object::List { ref value, .. } if value.len() == 3));
+        let result = run("(quote undefined-symbol)").unwrap();
+        assert!(matches!(result, Object::Symbol { ref value, .. } if value == "undefined-symbol"));
+    }
+
+    #[test]
+    fn test_quasiquote() {
+        let result = run("(define xs '(2 3))\n`(1 ,(+ 1 1) ,@xs 4)").unwrap();
+        let values: Vec<_> = match &result {
+            Object::List { value, .. } => value.iter().map(|o| o.to_string()).collect(),
+            _ => panic!("Expect a list"),
+        };
+        assert_eq!(values, vec!["1", "2", "2", "3", "4"]);
+
+        // Only the innermost unquote is at the level of the outer quasiquote
+        let result = run("`(a `(b ,(c ,(+ 1 2))))").unwrap();
+        assert_eq!(format!("{:?}", result).matches("Integer").count(), 1);
+
+        assert!(run(",x").is_err());
+        assert!(run("`(1 ,@2)").is_err());
+    }
+
+    #[test]
+    fn test_defmacro() {
+        // The argument forms reach the macro body unevaluated
+        let prog = "(defmacro swap-call (a b f) `(,f ,b ,a))\n\
+                    (swap-call 1 2.5 +)";
+        let result = run(prog).unwrap();
+        assert!(matches!(result, Object::Float { value, .. } if value == 3.5));
+
+        let prog = "(defmacro my-quote (x) `(quote ,x))\n\
+                    (my-quote (not evaluated))";
+        let result = run(prog).unwrap();
+        assert!(matches!(result, Object::List { ref value, .. } if value.len() == 2));
+
+        // Macros used inside a lambda body are expanded before the lambda is created
+        let prog = "(defmacro twice (x) `(+ ,x ,x))\n\
+                    (define f (lambda (n) (twice n)))\n\
+                    (f 21)";
+        let result = run(prog).unwrap();
+        assert!(matches!(result, Object::Integer { value: 42, .. }));
+    }
+
+    #[test]
+    fn test_macro_names_bound() {
+        // The names bound by the forms are not macro calls even when a macro has the name
+        let cases = [
+            ("(defmacro twice (x) `(+ ,x ,x)) (define (twice n) n) (+ 10 20)", "30"),
+            ("(defmacro m (x) x) (define (f m) m) (f 5)", "5"),
+            ("(defmacro m (x) x) (let ((m 5)) m)", "5"),
+            ("(defmacro m (x) x) (let* ((m 5) (n (+ m 1))) n)", "6"),
+            ("(defmacro m (x) x) (letrec ((m 5) (n (lambda () m))) (n))", "5"),
+            ("(defmacro m (x) x) (let loop ((m 0)) (if (< m 3) (loop (+ m 1)) m))", "3"),
+            ("(defmacro m (x) x) (define (f m) (cond (m 'yes) (else 'no))) (f #f)", "no"),
+            ("(defmacro m (x) x) (define (f m) (cond (m) (else 'no))) (f 7)", "7"),
+            ("(defmacro e (x) x) (guard (e (#t 'caught)) (raise 1))", "caught"),
+            ("(defmacro m (x) x) (guard (e ((m #f) 'yes) (else (m 'no))) (raise 1))", "no"),
+            ("(defmacro x (a) a) (define-record-type point (make-point x y) point? (x px) (y py)) (px (make-point 1 2))", "1"),
+            // The expressions of the bindings and the clauses are still expanded
+            ("(defmacro twice (x) `(+ ,x ,x)) (cond ((= (twice 1) 2) (twice 3)) (else 0))", "6"),
+            ("(defmacro twice (x) `(+ ,x ,x)) (let ((n (twice 2))) (twice n))", "8"),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(run(prog).unwrap().to_string(), expected, "{}", prog);
+        }
+    }
+
+    #[test]
+    fn test_macroexpand() {
+        let macros = "(defmacro my-unless (c e) `(my-if ,c #f ,e))\n\
+                      (defmacro my-if (c t e) `(cond (,c ,t) (else ,e)))\n";
+        let cases = [
+            ("(macroexpand-1 '(my-unless x (my-unless y 1)))", "'(my-if x #f (my-unless y 1))"),
+            ("(macroexpand '(my-unless x (my-unless y 1)))", "'(cond (x #f) (else (cond (y #f) (else 1))))"),
+            // Quoted data and the forms which are not macro calls are left as they are
+            ("(macroexpand-1 '(f (my-unless x 1)))", "'(f (my-unless x 1))"),
+            ("(macroexpand ''(my-unless x 1))", "''(my-unless x 1)"),
+            ("(macroexpand 42)", "42"),
+        ];
+        for (expr, expected) in cases {
+            let prog = format!("{}(equal? {} {})", macros, expr, expected);
+            assert!(matches!(run(&prog), Ok(Object::Bool { value: true, .. })), "{}", expr);
+        }
+        assert!(matches!(run("(macroexpand)"), Err(EvalError::ArityMismatch { .. })));
+    }
+
+    #[test]
+    fn test_reader_macro() {
+        let macros = "(set-reader-macro! #\\{ (lambda (items) `(list ,@(map (lambda (x) `(cons ',x #t)) items))))\n\
+                      (set-reader-macro! #\\d (lambda (text) (string->number text)))\n";
+        let cases = [
+            ("#{a b}", "'((a . #t) (b . #t))"),
+            ("(+ 1 #d\"41\")", "42"),
+            // Quoted data and the data read at runtime are read the same way
+            ("'(x #(#d\"2\"))", "'(x #(2))"),
+            ("(read-string \"#d\\\"3\\\"\")", "3"),
+        ];
+        for (expr, expected) in cases {
+            let prog = format!("{}(equal? {} {})", macros, expr, expected);
+            assert!(matches!(run(&prog), Ok(Object::Bool { value: true, .. })), "{}", expr);
+        }
+        assert!(matches!(run("#d\"1\""), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(set-reader-macro! #\\d 1)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_let() {
+        let result = run("(define x 1)\n(let ((x 10) (y x)) (+ x y))").unwrap();
+        assert!(matches!(result, Object::Integer { value: 11, .. }));
+
+        let result = run("(define x 1)\n(let* ((x 10) (y x)) (+ x y))").unwrap();
+        assert!(matches!(result, Object::Integer { value: 20, .. }));
+
+        // The bindings do not leak into the enclosing scope
+        assert!(run("(let ((z 1)) z)\nz").is_err());
+
+        let prog = "(letrec ((f (lambda () g)) (g 5)) (f))";
+        let result = run(prog).unwrap();
+        assert!(matches!(result, Object::Integer { value: 5, .. }));
+        assert!(run("(let ((f (lambda () g)) (g 5)) (f))").is_err());
+    }
+
+    #[test]
+    fn test_when_unless() {
+        assert!(matches!(run("(when (> 2 1) (define x 1) (+ x 1))").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(when #f (car '()))").unwrap(), Object::Void { .. }));
+        assert!(matches!(run("(unless #f 1 2)").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(unless 0 (car '()))").unwrap(), Object::Void { .. }));
+        assert!(matches!(run("(when #t)").unwrap(), Object::Void { .. }));
+        assert!(matches!(run("(unless)"), Err(EvalError::InvalidSyntax { .. })));
+    }
+
+    #[test]
+    fn test_case() {
+        let prog = "(define (kind x) (case x ((1 2 3) 'small) ((a b) 'letter) ((#\\x \"s\") 'other) (else 'unknown)))\n";
+        let kind = |arg: &str| run(&format!("{}(kind {})", prog, arg)).unwrap().to_string();
+        assert_eq!(kind("2"), "small");
+        assert_eq!(kind("'b"), "letter");
+        assert_eq!(kind("#\\x"), "other");
+        // The numbers are compared with eqv?, so with their exactness
+        assert_eq!(kind("2.0"), "unknown");
+        assert_eq!(kind("'(1)"), "unknown");
+
+        assert!(matches!(run("(case 5 ((1) 'one))").unwrap(), Object::Void { .. }));
+        assert!(matches!(run("(case (+ 1 1) ((2) (define y 1) (+ y 1)))").unwrap(), Object::Integer { value: 2, .. }));
+        // The data are not expanded as macro calls
+        let prog = "(defmacro two () 2)\n(case 'two ((two) 'symbol) (else 'expanded))";
+        assert_eq!(run(prog).unwrap().to_string(), "symbol");
+        assert!(matches!(run("(case 1 (else 1) ((1) 2))"), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(case 1 (1 2))"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(case)"), Err(EvalError::InvalidSyntax { .. })));
+    }
+
+    #[test]
+    fn test_named_let() {
+        let prog = "(let loop ((i 0) (acc '())) (if (< i 3) (loop (+ i 1) (cons i acc)) acc))";
+        assert_eq!(run(prog).unwrap().to_string(), "(2 1 0)");
+        // The values are computed outside the scope of the loop
+        let prog = "(define (loop x) 10)
+(let loop ((i (loop 1))) (if (> i 12) i (loop (+ i 1))))";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 13, .. }));
+        assert!(run("(let loop ((i 0)) i)
+loop").is_err());
+        assert!(matches!(run("(let loop () 5)").unwrap(), Object::Integer { value: 5, .. }));
+
+        assert!(matches!(run("(let loop ((i 0)))"), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(let loop (i 0) i)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(let loop ((i 0)) (loop))"), Err(EvalError::ArityMismatch { .. })));
+    }
+
+    #[test]
+    fn test_tail_call() {
+        // Far deeper than the depth limit, the calls in tail position are not nested
+        let prog = "(let loop ((i 0)) (if (< i 100000) (loop (+ i 1)) i))";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 100000, .. }));
+        let prog = "(define (even? n) (cond ((= n 0) #t) (else (odd? (- n 1)))))
+(define (odd? n) (and (> n 0) (even? (- n 1))))
+(even? 100000)";
+        assert_eq!(run(prog).unwrap().to_string(), "true");
+        let prog = "(define (count n) (when (> n 0) (let ((m (- n 1))) (begin (count m)))))\n(count 100000)";
+        assert!(matches!(run(prog).unwrap(), Object::Void { .. }));
+        // The calls which are not in tail position are still counted
+        let prog = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))\n(f 100000)";
+        assert!(matches!(run(prog), Err(EvalError::RecursionLimit { .. })));
+    }
+
+    #[test]
+    fn test_cond() {
+        let mut tokens = tokenize("evaluator_test.rs", "(define pick (lambda (a b) (cond (a 1) (b 2) (else 3))))").unwrap();
+        let env = Rc::new(RefCell::new(Environment::new(None)));
+        env.borrow_mut().set("no", Object::Bool { value: false, loc: None });
+        eval(parse(&mut tokens).unwrap(), &env).unwrap();
+        let pick = |args: &str| {
+            let mut tokens = tokenize("evaluator_test.rs", format!("(pick {})", args).as_str()).unwrap();
+            eval(parse(&mut tokens).unwrap(), &env).unwrap()
+        };
+        assert!(matches!(pick("'x 'x"), Object::Integer { value: 1, .. }));
+        assert!(matches!(pick("no 'x"), Object::Integer { value: 2, .. }));
+        assert!(matches!(pick("no no"), Object::Integer { value: 3, .. }));
+
+        let result = run("(cond ((quote found)))").unwrap();
+        assert!(matches!(result, Object::Symbol { ref value, .. } if value == "found"));
+        assert!(matches!(run("(cond)").unwrap(), Object::Void { .. }));
+        assert!(run("(cond (else 1) (1 2))").is_err());
+    }
+
+    #[test]
+    fn test_and_or() {
+        assert!(matches!(run("(and)").unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run("(or)").unwrap(), Object::Bool { value: false, .. }));
+        assert!(matches!(run("(and 1 2)").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(or 1 2)").unwrap(), Object::Integer { value: 1, .. }));
+
+        // The remaining operands are never evaluated
+        assert!(matches!(run("(or 1 undefined)").unwrap(), Object::Integer { value: 1, .. }));
+        assert!(matches!(run("(and (or) undefined)").unwrap(), Object::Bool { value: false, .. }));
+        assert!(run("(and 1 undefined)").is_err());
+    }
+
+    #[test]
+    fn test_begin() {
+        let prog = "(define f (lambda (x) (begin (define y (+ x 1)) (+ y 1))))\n(f 1)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(begin)").unwrap(), Object::Void { .. }));
+    }
+
+    #[test]
+    fn test_set() {
+        // The closure mutates the binding of the enclosing scope
+        let prog = "(define make-counter (lambda () (let ((n 0)) (lambda () (set! n (+ n 1)) n))))\n\
+                    (define counter (make-counter))\n\
+                    (counter)\n\
+                    (counter)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 2, .. }));
+
+        // Unlike define, set! does not create a binding in the local scope
+        let prog = "(define x 1)\n((lambda () (set! x 5)))\nx";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 5, .. }));
+        assert!(run("(set! undefined 1)").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let int = |prog: &str| match run(prog).unwrap() {
+            Object::Integer { value, .. } => value,
+            obj => panic!("Expect Integer but {} found", obj),
+        };
+        let float = |prog: &str| match run(prog).unwrap() {
+            Object::Float { value, .. } => value,
+            obj => panic!("Expect Float but {} found", obj),
+        };
+        assert_eq!(int("(+)"), 0);
+        assert_eq!(int("(+ 1 2 3)"), 6);
+        assert_eq!(int("(- 10 1 2)"), 7);
+        assert_eq!(int("(- 5)"), -5);
+        assert_eq!(int("(* 2 3 4)"), 24);
+        assert_eq!(int("(/ 12 2 3)"), 2);
+        assert_eq!(int("(% 7 3)"), 1);
+        assert_eq!(float("(+ 1 2.5)"), 3.5);
+        assert_eq!(float("(/ 1 2.0)"), 0.5);
+        assert_eq!(float("(* 2 0.25)"), 0.5);
+
+        assert!(run("(/ 1 0)").is_err());
+        assert!(run("(+ 1 'a)").is_err());
+        assert!(run("(-)").is_err());
+    }
+
+    #[test]
+    fn test_comparison() {
+        let truth = |prog: &str| is_truthy(&run(prog).unwrap());
+        assert!(truth("(< 1 2)"));
+        assert!(!truth("(> 1 2)"));
+        assert!(truth("(= 2 2.0)"));
+        assert!(truth("(>= 2 2)"));
+        assert!(truth("(<= 1.5 2)"));
+        assert!(truth("(/= 1 2)"));
+
+        let prog = "(define fact (lambda (n) (if (<= n 1) 1 (* n (fact (- n 1))))))\n(fact 10)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 3628800, .. }));
+    }
+
+    #[test]
+    fn test_cons() {
+        let result = run("(cons 1 (cons 2 '()))").unwrap();
+        assert!(matches!(result, Object::List { ref value, .. } if value.len() == 2));
+        assert_eq!(run("(cons 1 2)").unwrap().to_string(), "(1 . 2)");
+        assert_eq!(run("(car (cdr (list 1 2 3)))").unwrap().to_string(), "2");
+        assert_eq!(run("(cdr (cons 1 2))").unwrap().to_string(), "2");
+
+        let truth = |prog: &str| is_truthy(&run(prog).unwrap());
+        assert!(truth("(pair? (cons 1 2))"));
+        assert!(truth("(pair? '(1))"));
+        assert!(!truth("(pair? '())"));
+        assert!(truth("(null? (cdr '(1)))"));
+        assert!(run("(car '())").is_err());
+
+        let prog = "(define sum (lambda (xs) (if (null? xs) 0 (+ (car xs) (sum (cdr xs))))))\n\
+                    (sum (list 1 2 3 4))";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 10, .. }));
+
+        // The lists share their items instead of copying them
+        assert!(truth("(define xs '(1 2 3)) (eq? (cdr xs) (cdr xs))"));
+        let prog = "(define xs (cons 1 (cons 2 '())))\n(define a (cons 3 xs))\n(define b (cons 4 xs))\n(list a b xs (cdr a))";
+        assert_eq!(run(prog).unwrap().to_string(), "((3 1 2) (4 1 2) (1 2) (1 2))");
+        let prog = "(define (build n xs) (if (= n 0) xs (build (- n 1) (cons n xs))))
+(define (walk xs n) (if (null? xs) n (walk (cdr xs) (+ n (car xs)))))
+(walk (build 20000 '()) 0)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 200010000, .. }));
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let list = |prog: &str| match &run(prog).unwrap() {
+            Object::List { value, .. } => value.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            obj => panic!("Expect List but {} found", obj),
+        };
+        assert_eq!(list("(map (lambda (x) (* x x)) '(1 2 3))"), vec!["1", "4", "9"]);
+        assert_eq!(list("(map + '(1 2 3) '(10 20))"), vec!["11", "22"]);
+        assert_eq!(list("(filter (lambda (x) (> x 1)) '(1 2 3))"), vec!["2", "3"]);
+        assert_eq!(list("(fold cons '() '(1 2 3))"), vec!["3", "2", "1"]);
+
+        assert!(matches!(run("(fold + 0 '(1 2 3))").unwrap(), Object::Integer { value: 6, .. }));
+        assert!(matches!(run("(reduce + 0 '(1 2 3))").unwrap(), Object::Integer { value: 6, .. }));
+        assert!(matches!(run("(reduce + 0 '())").unwrap(), Object::Integer { value: 0, .. }));
+        assert!(run("(map 1 '(1))").is_err());
+
+        // The folds and for-each take the lists in parallel too
+        assert_eq!(run("(fold-left cons '() '(1 2 3))").unwrap().to_string(), "(((() . 1) . 2) . 3)");
+        assert_eq!(list("(fold-right cons '() '(1 2 3))"), vec!["1", "2", "3"]);
+        assert_eq!(list("(fold-left (lambda (acc x y) (cons (* x y) acc)) '() '(1 2 3) '(4 5))"), vec!["10", "4"]);
+        assert_eq!(list("(fold-right (lambda (x y acc) (cons (- x y) acc)) '() '(5 6 7) '(1 2))"), vec!["4", "4"]);
+        assert!(matches!(run("(fold-right + 0 '())").unwrap(), Object::Integer { value: 0, .. }));
+        let prog = "(define total 0) (for-each (lambda (x y) (set! total (+ total (* x y)))) '(1 2 3) '(4 5 6)) total";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 32, .. }));
+        assert!(matches!(run("(for-each car '(1))"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(fold-left +)"), Err(EvalError::ArityMismatch { expected: Arity::AtLeast(3), .. })));
+
+        assert_eq!(list("(sort '(3 1 2 5 4) <)"), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(run("(sort #(3 1 2) >)").unwrap().to_string(), "#(3 2 1)");
+        // The pairs with the same key keep their order
+        let prog = "(sort '((1 . a) (0 . b) (1 . c) (0 . d)) (lambda (x y) (< (car x) (car y))))";
+        assert_eq!(list(prog), vec!["(0 . b)", "(0 . d)", "(1 . a)", "(1 . c)"]);
+        assert!(list("(sort '() <)").is_empty());
+        assert!(matches!(run("(sort '(1 a) <)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(sort 1 <)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_string() {
+        let string = |prog: &str| match &run(prog).unwrap() {
+            Object::Str { value, .. } => value.clone(),
+            obj => panic!("Expect Str but {} found", obj),
+        };
+        assert_eq!(string("(string-append \"foo\" \"bar\" \"\")"), "foobar");
+        assert_eq!(string("(substring \"hello\" 1 3)"), "el");
+        assert_eq!(string("(substring \"hello\" 2)"), "llo");
+        assert!(matches!(run("(string-ref \"hello\" 4)").unwrap(), Object::Char { value: 'o', .. }));
+        assert!(matches!(run("(string-length \"hello\")").unwrap(), Object::Integer { value: 5, .. }));
+        assert!(is_truthy(&run("(string? \"\")").unwrap()));
+        assert!(!is_truthy(&run("(string? 'a)").unwrap()));
+
+        let err = run("(substring \"abc\" 2 1)").unwrap_err();
+        assert_eq!(err.message(), "Invalid argument to substring: start 2 is greater than end 1");
+        assert_eq!(run("(substring \"abc\" 1 4)").unwrap_err().message(), "Index 4 is out of range for length 3");
+        assert!(run("(string-ref \"hello\" 5)").is_err());
+
+        // The strings are indexed by characters
+        assert!(matches!(run("(string-length \"h\u{e9}llo \u{1F600}\")").unwrap(), Object::Integer { value: 7, .. }));
+        assert_eq!(string("(substring \"h\u{e9}llo\" 1 3)"), "\u{e9}l");
+        assert!(matches!(run("(string-ref \"\u{1F600}\u{e9}\" 1)").unwrap(), Object::Char { value: '\u{e9}', .. }));
+        assert!(matches!(run("(string-ref \"\u{e9}\" 1)"), Err(EvalError::IndexOutOfRange { index: 1, length: 1, .. })));
+        // A combining character is a character of its own
+        assert!(matches!(run("(string-length \"e\u{301}\")").unwrap(), Object::Integer { value: 2, .. }));
+        assert_eq!(string("(define λ \"\u{e9}t\u{e9}\") (define café (string-ref λ 2)) (list->string (list café))"), "\u{e9}");
+        assert!(run("(string-append \"a\" 1)").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let string = |prog: &str| match &run(prog).unwrap() {
+            Object::Str { value, .. } => value.clone(),
+            obj => panic!("Expect Str but {} found", obj),
+        };
+        assert_eq!(string("(format \"Hello ~a, you are ~s~%\" \"bob\" \"here\")"), "Hello bob, you are \"here\"\n");
+        assert_eq!(string("(format \"[~5a][~5d][~05d][~~]\" 'ab 42 7)"), "[ab   ][   42][00007][~]");
+        assert_eq!(string("(format \"~s\" #\\a)"), "#\\a");
+
+        assert!(matches!(run("(format \"~a ~a\" 1)"), Err(EvalError::ArityMismatch { expected: Arity::AtLeast(3), .. })));
+        assert!(matches!(run("(format \"~a\" 1 2)"), Err(EvalError::ArityMismatch { expected: Arity::Exact(2), .. })));
+        assert!(matches!(run("(format \"~d\" \"a\")"), Err(EvalError::TypeMismatch { .. })));
+        assert_eq!(run("(format \"~q\")").unwrap_err().message(), "Invalid argument to format: unknown directive ~q");
+        assert_eq!(run("(format \"a~\")").unwrap_err().message(), "Invalid argument to format: expect a directive after ~");
+        assert_eq!(run("(format \"~q\")").unwrap_err().label(), "invalid argument");
+    }
+
+    #[test]
+    fn test_bool() {
+        assert!(matches!(run("#t").unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run("false").unwrap(), Object::Bool { value: false, .. }));
+        assert!(matches!(run("(if #f 1 2)").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(and true #false)").unwrap(), Object::Bool { value: false, .. }));
+    }
+
+    #[test]
+    fn test_char() {
+        assert!(matches!(run("#\\a").unwrap(), Object::Char { value: 'a', .. }));
+        assert!(matches!(run("#\\newline").unwrap(), Object::Char { value: '\n', .. }));
+        assert!(is_truthy(&run("(char? #\\space)").unwrap()));
+        assert!(!is_truthy(&run("(char? \"a\")").unwrap()));
+        assert!(matches!(run("#\\bogus"), Err(EvalError::Lex(LexError::InvalidLiteral { .. }))));
+    }
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(run("(/ 1 3)").unwrap().to_string(), "1/3");
+        assert_eq!(run("(+ 1/3 1/6)").unwrap().to_string(), "1/2");
+        assert!(matches!(run("(* 2/3 3/2)").unwrap(), Object::Integer { value: 1, .. }));
+        assert!(matches!(run("(+ 1/2 0.5)").unwrap(), Object::Float { value, .. } if value == 1.0));
+        assert!(matches!(run("6/3").unwrap(), Object::Integer { value: 2, .. }));
+        assert_eq!(run("(% 7/2 1)").unwrap().to_string(), "1/2");
+        assert!(is_truthy(&run("(< 1/3 1/2)").unwrap()));
+        assert!(is_truthy(&run("(= 1/2 0.5)").unwrap()));
+        assert!(is_truthy(&run("(rational? 1/2)").unwrap()));
+        assert!(!is_truthy(&run("(rational? 0.5)").unwrap()));
+        assert!(matches!(run("(denominator (/ 6 4))").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(run("(/ 1/2 0)").is_err());
+    }
+
+    #[test]
+    fn test_big_integer() {
+        let max = i128::MAX.to_string();
+        let result = run(format!("(+ {} 1)", max).as_str()).unwrap();
+        assert!(matches!(result, Object::BigInteger { .. }));
+        assert_eq!(result.to_string(), "170141183460469231731687303715884105728");
+
+        // Results fitting in i128 are Integers again
+        let result = run(format!("(- (+ {} 10) 10)", max).as_str()).unwrap();
+        assert!(matches!(result, Object::Integer { value: i128::MAX, .. }));
+
+        let prog = "(define fact (lambda (n) (if (<= n 1) 1 (* n (fact (- n 1))))))\n(fact 40)";
+        assert_eq!(run(prog).unwrap().to_string(), "815915283247897734345611269596115894272000000000");
+        assert!(matches!(run("(< 1 1000000000000000000000000000000000000000000)").unwrap(), Object::Bool { value: true, .. }));
+        assert_eq!(run("(/ 1 1000000000000000000000000000000000000000000)").unwrap().to_string(),
+                   "1/1000000000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let cases = [
+            ("(< 1 2 3)", true), ("(< 1 3 2)", false), ("(> 3 2 1)", true), ("(> 3 1 2)", false),
+            ("(<= 1 1 2)", true), ("(<= 1 2 1)", false), ("(>= 2 2 1)", true), ("(= 1 1 1.0 2/2)", true),
+            ("(= 1 1 2)", false), ("(< 1)", true), ("(/= 1 2 3)", true), ("(/= 1 2 1)", false),
+            ("(< 1 (sqrt -1) 2)", false), ("(/= 1 (sqrt -1))", true),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(is_truthy(&run(prog).unwrap()), expected, "{}", prog);
+        }
+        assert!(matches!(run("(<)"), Err(EvalError::ArityMismatch { expected: Arity::AtLeast(1), .. })));
+        // Every argument is checked even when the result is already known
+        assert!(matches!(run("(< 2 1 \"a\")"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let min = i128::MIN.to_string();
+        assert_eq!(run(format!("(- {})", min).as_str()).unwrap().to_string(), "170141183460469231731687303715884105728");
+        assert_eq!(run(format!("(/ {} -1)", min).as_str()).unwrap().to_string(), "170141183460469231731687303715884105728");
+        assert!(matches!(run(format!("(% {} -1)", min).as_str()).unwrap(), Object::Integer { value: 0, .. }));
+
+        // Only an exact zero divisor is an error
+        assert!(matches!(run("(% 7 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(% 1/2 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(/ 1.5 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(/ 100000000000000000000000000000000000000000 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(/ 1 0.0)").unwrap(), Object::Float { value, .. } if value == f64::INFINITY));
+        assert!(matches!(run("(% 1 0.0)").unwrap(), Object::Float { value, .. } if value.is_nan()));
+
+        // The error points at the division and can be caught
+        match run("(define (f x) (/ x 0))\n(+ 1\n   (f 2))") {
+            Err(EvalError::DivisionByZero { loc: Some(loc) }) => assert_eq!(loc.rol(), 1),
+            other => panic!("Expect DivisionByZero but {:?} found", other),
+        }
+        assert!(matches!(run("(guard (e (#t 'caught)) (% 1 0))").unwrap(), Object::Symbol { value, .. } if value == "caught"));
+
+        // Indices past usize do not wrap around
+        assert!(matches!(run("(string-ref \"abc\" 18446744073709551616)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(vector-ref (vector 1) 18446744073709551616)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_integer_division() {
+        let cases = [
+            ("(quotient 7 2)", "3"), ("(quotient -7 2)", "-3"), ("(quotient 7 -2)", "-3"), ("(quotient -7 -2)", "3"),
+            ("(remainder 7 2)", "1"), ("(remainder -7 2)", "-1"), ("(remainder 7 -2)", "1"), ("(remainder -7 -2)", "-1"),
+            ("(modulo 7 2)", "1"), ("(modulo -7 2)", "1"), ("(modulo 7 -2)", "-1"), ("(modulo -7 -2)", "-1"),
+            ("(modulo 6 -2)", "0"), ("(modulo -7.0 2)", "1"), ("(quotient 7.0 2)", "3"),
+            ("(modulo -100000000000000000000000000000000000000001 10)", "9"),
+            ("(quotient -170141183460469231731687303715884105728 -1)", "170141183460469231731687303715884105728"),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(run(prog).unwrap().to_string(), expected, "{}", prog);
+        }
+        assert!(matches!(run("(modulo -7.0 2)").unwrap(), Object::Float { .. }));
+        assert!(matches!(run("(modulo 7 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(quotient 7 0.0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(quotient 7/2 2)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(remainder 7.5 2)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_math() {
+        assert!(matches!(run("(sqrt 16)").unwrap(), Object::Integer { value: 4, .. }));
+        assert_eq!(run("(sqrt 9/4)").unwrap().to_string(), "3/2");
+        assert!(matches!(run("(sqrt 2)").unwrap(), Object::Float { value, .. } if value == 2f64.sqrt()));
+        assert!(matches!(run("(sqrt -4)").unwrap(), Object::Float { value, .. } if value.is_nan()));
+        assert_eq!(run("(expt 2 100)").unwrap().to_string(), "1267650600228229401496703205376");
+        assert_eq!(run("(expt 2 -2)").unwrap().to_string(), "1/4");
+        assert_eq!(run("(expt 2/3 2)").unwrap().to_string(), "4/9");
+        assert!(matches!(run("(expt 4 0.5)").unwrap(), Object::Float { value, .. } if value == 2.0));
+        assert!(matches!(run("(expt 0 -1)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(exp 0)").unwrap(), Object::Float { value, .. } if value == 1.0));
+        assert!(matches!(run("(log 1)").unwrap(), Object::Float { value, .. } if value == 0.0));
+        assert!(matches!(run("(log 8 2)").unwrap(), Object::Float { value, .. } if (value - 3.0).abs() < 1e-12));
+        assert!(matches!(run("(sin 0)").unwrap(), Object::Float { value, .. } if value == 0.0));
+        assert!(matches!(run("(cos 0)").unwrap(), Object::Float { value, .. } if value == 1.0));
+        assert!(matches!(run("(tan 0)").unwrap(), Object::Float { value, .. } if value == 0.0));
+        assert!(matches!(run("(atan 1 1)").unwrap(), Object::Float { value, .. } if value == std::f64::consts::FRAC_PI_4));
+        assert!(matches!(run("(atan 1 2 3)"), Err(EvalError::ArityMismatch { .. })));
+
+        // Exact numbers round to exact integers, Floats stay Floats
+        assert!(matches!(run("(floor 7/2)").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(floor -7/2)").unwrap(), Object::Integer { value: -4, .. }));
+        assert!(matches!(run("(ceiling 7/2)").unwrap(), Object::Integer { value: 4, .. }));
+        assert!(matches!(run("(truncate -7/2)").unwrap(), Object::Integer { value: -3, .. }));
+        assert!(matches!(run("(round 7/2)").unwrap(), Object::Integer { value: 4, .. }));
+        assert!(matches!(run("(round 5/2)").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(round 5)").unwrap(), Object::Integer { value: 5, .. }));
+        assert!(matches!(run("(floor 2.5)").unwrap(), Object::Float { value, .. } if value == 2.0));
+        assert!(matches!(run("(round -2.5)").unwrap(), Object::Float { value, .. } if value == -2.0));
+        assert!(matches!(run("(sqrt \"4\")"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_json() {
+        let cases = [
+            ("(hash-ref (json-parse \"{\\\"a\\\": {\\\"b\\\": [1, 2.5]}}\") \"a\")", "#hash((b . #(1 2.5)))"),
+            ("(vector-ref (json-parse \"[true, \\\"x\\\", 100000000000000000000000000000000000000000]\") 2)",
+             "100000000000000000000000000000000000000000"),
+            ("(json-parse \"null\")", "null"),
+            ("(json-parse \"[1e400, -1e400, 2.5]\")", "#(inf -inf 2.5)"),
+            ("(json-stringify (vector 1 \"a\\\"b\" #t 'null 1/2 (list)))", "[1,\"a\\\"b\",true,null,0.5,[]]"),
+            ("(json-stringify 100000000000000000000000000000000000000000)", "100000000000000000000000000000000000000000"),
+            ("(let ((h (make-hash))) (hash-set! h 'k '(1 2)) (json-stringify h))", "{\"k\":[1,2]}"),
+            ("(json-stringify (json-parse \" {\\\"a\\\" : [ {} ] } \"))", "{\"a\":[{}]}"),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(run(prog).unwrap().to_string(), expected, "{}", prog);
+        }
+        for prog in ["(json-parse \"[1,\")", "(json-parse \"{a: 1}\")", "(json-parse \"1 2\")"] {
+            assert!(matches!(run(prog), Err(EvalError::Decode { .. })), "{}", prog);
+        }
+        for prog in ["(json-stringify (cons 1 2))", "(json-stringify car)", "(json-stringify (sqrt -1))",
+                     "(let ((h (make-hash))) (hash-set! h 1 2) (json-stringify h))"] {
+            assert!(matches!(run(prog), Err(EvalError::TypeMismatch { .. })), "{}", prog);
+        }
+    }
+
+    #[test]
+    fn test_native_function() {
+        let car = run("car").unwrap();
+        assert!(matches!(&car, Object::NativeFunction { value, .. } if value.name == "car"));
+        assert_eq!(car.to_string(), "#<procedure car>");
+        assert_eq!(run("(define first car) (first '(1 2))").unwrap().to_string(), "1");
+        assert!(is_truthy(&run("(equal? (map car '((1) (2))) '(1 2))").unwrap()));
+    }
+
+    #[test]
+    fn test_equality() {
+        let cases = [
+            ("(eq? 'a 'a)", true), ("(eq? 'a 'b)", false), ("(eq? '() '())", true), ("(eq? 2 2)", true),
+            ("(eq? (gensym) (gensym))", false), ("(let ((l '(1 2))) (eq? l l))", true), ("(eq? '(1) '(1))", false),
+            ("(eq? car car)", true), ("(let ((f (lambda () 1))) (eq? f f))", true), ("(eq? (lambda () 1) (lambda () 1))", false),
+            ("(eqv? 2 2)", true), ("(eqv? 2 2.0)", false), ("(eqv? 1/2 (/ 2 4))", true), ("(eqv? 100000000000000000000000000000000000000000 100000000000000000000000000000000000000000)", true),
+            ("(eqv? #\\a #\\a)", true), ("(eqv? \"ab\" \"ab\")", true), ("(eqv? (vector 1) (vector 1))", false),
+            ("(equal? '(1 #(2 \"a\")) (list 1 (vector 2 \"a\")))", true), ("(equal? '(1 (2 3)) '(1 (2 4)))", false),
+            ("(equal? (cons 1 2) (cons 1 2))", true), ("(equal? (cons 1 2) '(1 2))", false), ("(equal? '(1 2) '(1 2 3))", false),
+            ("(equal? 1 1.0)", false), ("(equal? (make-hash) (make-hash))", false),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(is_truthy(&run(prog).unwrap()), expected, "{}", prog);
+        }
+        assert!(matches!(run("(equal? 1)"), Err(EvalError::ArityMismatch { .. })));
+    }
+
+    #[test]
+    fn test_conversion() {
+        let cases = [
+            ("(string->number \"42\")", "42"), ("(string->number \"-1/2\")", "-1/2"), ("(string->number \"#xff\")", "255"),
+            ("(string->number \"ff\" 16)", "255"), ("(string->number \"-101\" 2)", "-5"),
+            ("(number->string 255 16)", "ff"), ("(number->string -1/3 2)", "-1/11"), ("(number->string 1.0)", "1.0"),
+            ("(number->string 100000000000000000000000000000000000000000)", "100000000000000000000000000000000000000000"),
+            ("(symbol->string 'abc)", "abc"), ("(list->string (list #\\a #\\b))", "ab"), ("(list->string '())", ""),
+        ];
+        for (prog, expected) in cases {
+            assert_eq!(run(prog).unwrap().to_string(), expected, "{}", prog);
+        }
+        assert!(matches!(run("(string->number \"2.5\")").unwrap(), Object::Float { value, .. } if value == 2.5));
+        assert!(matches!(run("(string->number (number->string 3.0))").unwrap(), Object::Float { .. }));
+        assert!(is_truthy(&run("(eq? (string->symbol \"a\") 'a)").unwrap()));
+        assert!(is_truthy(&run("(equal? (string->list \"ab\") (list #\\a #\\b))").unwrap()));
+
+        // Strings which are not a number give #f, other failures are errors
+        for prog in ["(string->number \"abc\")", "(string->number \" 42\")", "(string->number \"1 2\")",
+                     "(string->number \"\")", "(string->number \"12\" 2)", "(string->number \"--1\" 16)"] {
+            assert!(matches!(run(prog).unwrap(), Object::Bool { value: false, .. }), "{}", prog);
+        }
+        assert!(matches!(run("(string->number 42)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(number->string 1.5 2)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(number->string 1 37)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(symbol->string \"a\")"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(list->string (list #\\a 1))"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_vector() {
+        // Vector literals evaluate to themselves, their elements are not evaluated
+        assert!(matches!(&run("#(1 \"a\" (x y))").unwrap(), Object::Vector { value, .. } if value.len() == 3));
+        assert_eq!(run("#(a #(1 2))").unwrap().to_string(), "#(a #(1 2))");
+        assert_eq!(run("(vector 1 (+ 1 1) 3)").unwrap().to_string(), "#(1 2 3)");
+        assert_eq!(run("(make-vector 2 'x)").unwrap().to_string(), "#(x x)");
+        assert!(matches!(run("(vector-ref #(1 2 3) 2)").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(vector-length (make-vector 4))").unwrap(), Object::Integer { value: 4, .. }));
+        assert!(is_truthy(&run("(vector? #())").unwrap()));
+        assert!(!is_truthy(&run("(vector? '())").unwrap()));
+        assert!(matches!(run("(vector-ref #(1 2) 2)"), Err(EvalError::IndexOutOfRange { index: 2, length: 2, .. })));
+    }
+
+    #[test]
+    fn test_hash_table() {
+        let prog = "(define h (make-hash))\n(define alias h)\n(hash-set! h 'a 1)\n(hash-set! alias \"b\" 2)\n(hash-set! h 'a 3)";
+        assert!(matches!(run(&format!("{}\n(hash-ref h 'a)", prog)).unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run(&format!("{}\n(hash-ref h \"b\")", prog)).unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run(&format!("{}\n(hash-count h)", prog)).unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run(&format!("{}\n(hash-ref h 'c 0)", prog)).unwrap(), Object::Integer { value: 0, .. }));
+        assert!(!is_truthy(&run(&format!("{}\n(hash-remove! h 'a)\n(hash-has-key? alias 'a)", prog)).unwrap()));
+        assert!(matches!(run(&format!("{}\n(hash-ref h 'c)", prog)), Err(EvalError::KeyNotFound { .. })));
+        assert!(matches!(run("(hash-set! (make-hash) '(1) 1)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_define_function() {
+        let prog = "(define (add x y) (+ x y))\n(define (answer) 42)\n(add (answer) 1)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 43, .. }));
+
+        let prog = "(define (fact n) (if (<= n 1) 1 (* n (fact (- n 1)))))\n(fact 5)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 120, .. }));
+
+        // The function is named after the definition
+        let prog = "(define (add x y) (+ x y))\n(add 1)";
+        assert!(matches!(run(prog), Err(EvalError::ArityMismatch { name, .. }) if name == "add"));
+        assert!(matches!(run("(define (add x y))"), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(define (1 x) x)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_lexical_addressing() {
+        let show = |prog: &str| run(prog).unwrap().to_string();
+        // The slots are read and written through the frames of the closures
+        let prog = "(define (counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n)))\n\
+                    (define c (counter))\n(c)\n(c)";
+        assert_eq!(show(prog), "2");
+        assert_eq!(show("(let* ((x 1) (y (+ x 1))) (letrec ((f (lambda () (* x y)))) (f)))"), "2");
+        assert_eq!(show("(define (f x) (let loop ((i 0) (acc '())) (if (= i x) acc (loop (+ i 1) (cons i acc)))))\n(f 3)"), "(2 1 0)");
+
+        // A variable is looked up by name until its definition is made, and
+        // when a definition elsewhere than at the top of a body shadows it
+        let prog = "(define x 'global)\n\
+                    (define (f shadow) (define (g) x) (define a (g)) (when shadow (define x 'inner)) (list a (g)))\n\
+                    (list (f #f) (f #t))";
+        assert_eq!(show(prog), "((global global) (global inner))");
+        let prog = "(define x 'global)\n(define (f) (define a x) (define x 'local) (list a x))\n(f)";
+        assert_eq!(show(prog), "(global local)");
+        assert!(matches!(run("(define (f) (set! y 1))\n(f)"), Err(EvalError::UndefinedSymbol { name, .. }) if name == "y"));
+
+        // The forms given to a macro at runtime and to eval are written with symbols
+        let prog = "(define (f x) (defmacro quoted (e) `(quote ,e)) (list (symbol->string (quoted x)) (eval '(+ 1 2))))\n(f 1)";
+        assert_eq!(show(prog), "(x 3)");
+    }
+
+    #[test]
+    fn test_variadic() {
+        assert!(matches!(&run("((lambda args args) 1 2 3)").unwrap(), Object::List { value, .. } if value.len() == 3));
+        assert!(matches!(run("((lambda args (null? args)))").unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run("((lambda (a b . rest) (+ a b (car rest))) 1 2 3 4)").unwrap(), Object::Integer { value: 6, .. }));
+        assert!(matches!(run("((lambda (a . rest) (null? rest)) 1)").unwrap(), Object::Bool { value: true, .. }));
+
+        let prog = "(define (sum . xs) (fold + 0 xs))\n(sum 1 2 3 4)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 10, .. }));
+
+        let prog = "(define (f a . rest) a)\n(f)";
+        assert!(matches!(run(prog), Err(EvalError::ArityMismatch { expected: Arity::AtLeast(1), found: 0, .. })));
+        assert!(matches!(run("(lambda (a . 1) a)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_keyword_args() {
+        let prog = "(define (rect x &key (width 10) (height width) border) (list x width height border))\n";
+        let result = run(&format!("{}(rect 1 :height 3)", prog)).unwrap();
+        assert!(matches!(&result, Object::List { value, .. } if matches!(value[..], [
+            Object::Integer { value: 1, .. }, Object::Integer { value: 10, .. },
+            Object::Integer { value: 3, .. }, Object::Bool { value: false, .. }
+        ])));
+        // Defaults may refer to the previous parameters
+        let result = run(&format!("{}(rect 1 :border #t :width 2)", prog)).unwrap();
+        assert!(matches!(&result, Object::List { value, .. } if matches!(value[..], [
+            _, Object::Integer { value: 2, .. }, Object::Integer { value: 2, .. }, Object::Bool { value: true, .. }
+        ])));
+        assert!(matches!(run(":width").unwrap(), Object::Keyword { value, .. } if value == "width"));
+
+        let err = |call: &str| run(&format!("{}{}", prog, call)).unwrap_err();
+        assert!(matches!(err("(rect 1 :depth 2)"), EvalError::InvalidKeywordArgument { keyword, .. } if keyword == ":depth"));
+        assert!(matches!(err("(rect 1 :width)"), EvalError::InvalidKeywordArgument { .. }));
+        assert!(matches!(err("(rect 1 :width 1 :width 2)"), EvalError::InvalidKeywordArgument { .. }));
+        assert!(matches!(err("(rect 1 2)"), EvalError::TypeMismatch { .. }));
+        assert!(matches!(err("(rect)"), EvalError::ArityMismatch { expected: Arity::AtLeast(1), .. }));
+        assert!(matches!(run("(lambda (a &key b . rest) a)"), Err(EvalError::InvalidSyntax { .. })));
+    }
+
+    #[test]
+    fn test_keywords() {
+        assert!(is_truthy(&run("(keyword? :a)").unwrap()));
+        assert!(is_truthy(&run("(keyword? ':a)").unwrap()));
+        assert!(!is_truthy(&run("(keyword? 'a)").unwrap()));
+        assert!(!is_truthy(&run("(eq? :a (string->symbol \":a\"))").unwrap()));
+        assert!(is_truthy(&run("(eq? :a :a)").unwrap()));
+        assert!(matches!(&run("(keyword->string :width)").unwrap(), Object::Str { value, .. } if value == "width"));
+        assert!(matches!(run("(keyword->string 'width)"), Err(EvalError::TypeMismatch { .. })));
+        // Keywords are hash table keys distinct from the symbols
+        let prog = "(define h (make-hash)) (hash-set! h :a 1) (hash-set! h 'a 2) (list (hash-ref h :a) (hash-ref h 'a))";
+        assert_eq!(run(prog).unwrap().to_string(), "(1 2)");
+        assert_eq!(run("(list :a 'b \":c\")").unwrap().to_string(), "(:a b :c)");
+    }
+
+    #[test]
+    fn test_shared_objects() {
+        // Looking a list or a function up shares it instead of copying it
+        let env = Rc::new(RefCell::new(Environment::new(None)));
+        let mut tokens = tokenize("evaluator_test.rs", "(define xs '(1 2 3))\n(define (f) xs)").unwrap();
+        eval(parse(&mut tokens).unwrap(), &env).unwrap();
+        let lookup = |prog: &str| eval(parse(&mut tokenize("evaluator_test.rs", prog).unwrap()).unwrap(), &env).unwrap();
+        match &(lookup("xs"), lookup("(f)")) {
+            (Object::List { value: a, .. }, Object::List { value: b, .. }) => assert!(ListItems::ptr_eq(a, b)),
+            objs => panic!("Expect two lists but {:?} found", objs),
+        }
+        match &(lookup("f"), lookup("f")) {
+            (Object::Lambda { value: a, .. }, Object::Lambda { value: b, .. }) => assert!(Rc::ptr_eq(a, b)),
+            objs => panic!("Expect two lambdas but {:?} found", objs),
+        }
+    }
+
+    #[test]
+    fn test_dotted_pair() {
+        assert_eq!(run("'(1 . 2)").unwrap().to_string(), "(1 . 2)");
+        assert_eq!(run("'(1 2 . 3)").unwrap().to_string(), "(1 2 . 3)");
+        assert_eq!(run("(cons 1 (cons 2 3))").unwrap().to_string(), "(1 2 . 3)");
+        assert!(matches!(run("(cdr '(1 . 2))").unwrap(), Object::Integer { value: 2, .. }));
+        assert_eq!(run("(cdr '(1 2 . 3))").unwrap().to_string(), "(2 . 3)");
+        assert!(matches!(run("(pair? '(1 . 2))").unwrap(), Object::Bool { value: true, .. }));
+        // A list in the cdr gives a proper list
+        assert!(matches!(&run("'(1 . (2 3))").unwrap(), Object::List { value, .. } if value.len() == 3));
+    }
+
+    #[test]
+    fn test_define_record_type() {
+        let prog = "(define-record-type point (make-point x y) point? (x point-x set-point-x!) (y point-y))\n\
+                    (define p (make-point 1 2))\n";
+        assert_eq!(run(&format!("{}p", prog)).unwrap().to_string(), "#<point 1 2>");
+        assert!(matches!(run(&format!("{}(point-y p)", prog)).unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run(&format!("{}(set-point-x! p 10)\n(point-x p)", prog)).unwrap(), Object::Integer { value: 10, .. }));
+        assert!(matches!(run(&format!("{}(point? p)", prog)).unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run(&format!("{}(point? 1)", prog)).unwrap(), Object::Bool { value: false, .. }));
+
+        // Records of another type with the same layout are rejected
+        let other = "(define-record-type other (make-other x y) other? (x other-x) (y other-y))\n";
+        assert!(matches!(run(&format!("{}{}(point-x (make-other 1 2))", prog, other)), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run(&format!("{}(make-point 1)", prog)), Err(EvalError::ArityMismatch { .. })));
+
+        // Fields missing from the constructor start as #f
+        let prog = "(define-record-type node (make-node value) node? (value node-value) (next node-next set-node-next!))\n\
+                    (node-next (make-node 1))";
+        assert!(matches!(run(prog).unwrap(), Object::Bool { value: false, .. }));
+        assert!(matches!(run("(define-record-type t (make-t z) t? (x t-x))"), Err(EvalError::InvalidSyntax { .. })));
+    }
+
+    #[test]
+    fn test_guard() {
+        let prog = "(guard (e ((string? e) (string-append \"caught \" e))) (+ 1 (raise \"oops\")))";
+        assert!(matches!(&run(prog).unwrap(), Object::Str { value, .. } if value == "caught oops"));
+        assert!(matches!(run("(guard (e (#t 0)) (+ 1 2))").unwrap(), Object::Integer { value: 3, .. }));
+
+        // Errors raised by error carry the message and the irritants
+        let prog = "(guard (e ((error-object? e) (error-object-message e))) (error \"bad value\" 1 2))";
+        assert!(matches!(&run(prog).unwrap(), Object::Str { value, .. } if value == "bad value"));
+        let prog = "(guard (e ((error-object? e) (error-object-irritants e))) (error \"bad value\" 1 2))";
+        assert!(matches!(&run(prog).unwrap(), Object::List { value, .. } if value.len() == 2));
+
+        // Errors of the builtins are caught as error objects
+        let prog = "(guard (e ((error-object? e) (error-object-message e))) (/ 1 0))";
+        assert!(matches!(&run(prog).unwrap(), Object::Str { value, .. } if value == "Division by zero"));
+        assert!(matches!(run("(guard (e (else 'handled)) (undefined))").unwrap(), Object::Symbol { value, .. } if value == "handled"));
+
+        // The error is raised again when no clause handles it
+        assert!(matches!(run("(guard (e ((string? e) e)) (raise 1))"), Err(EvalError::Raised { .. })));
+        assert!(matches!(run("(guard (e ((string? e) e)) (car 1))"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(raise 'oops)"), Err(EvalError::Raised { value, .. }) if matches!(*value, Object::Symbol { .. })));
+
+        // Exiting is never caught
+        assert!(matches!(run("(guard (e (else 0)) (exit 3))"), Err(EvalError::Exit { code: 3 })));
+        assert!(matches!(run("(exit #f)"), Err(EvalError::Exit { code: 1 })));
+        assert!(matches!(run("(exit)"), Err(EvalError::Exit { code: 0 })));
+        assert!(matches!(run("(exit \"1\")"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(exit 255)"), Err(EvalError::Exit { code: 255 })));
+        assert!(matches!(run("(exit 256)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(exit -1)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_assert() {
+        assert!(matches!(run("(assert (= 1 1))").unwrap(), Object::Void { .. }));
+        assert!(matches!(run("(assert-equal '(1 #(2)) (list 1 (vector 2)))").unwrap(), Object::Void { .. }));
+        match run("(define x 1)\n(assert (= x 2) \"x is 2\")") {
+            Err(e @ EvalError::AssertionFailed { loc: Some(_), .. }) => {
+                assert_eq!(e.message(), "Assertion failed: x is 2");
+                assert_eq!(e.loc().unwrap().rol(), 2);
+            },
+            other => panic!("Expect AssertionFailed but {:?} found", other),
+        }
+        match run("(assert-equal \"a\" 1)") {
+            Err(e @ EvalError::AssertionFailed { .. }) => assert_eq!(e.message(), "Assertion failed: expected \"a\" but got 1"),
+            other => panic!("Expect AssertionFailed but {:?} found", other),
+        }
+        assert!(matches!(run("(guard (e (#t 'caught)) (assert #f))").unwrap(), Object::Symbol { value, .. } if value == "caught"));
+
+        // The tests run in the order of definition, a failing test does not stop the others
+        let prog = "(define log '())\n\
+                    (define-test \"first\" (set! log (cons 1 log)))\n\
+                    (define-test \"failing\" (assert-equal 1 2) (set! log (cons 'unreachable log)))\n\
+                    (define-test \"last\" (set! log (cons 3 log)))\n\
+                    (equal? (list (run-tests) log) '(#f (3 1)))";
+        assert!(is_truthy(&run(prog).unwrap()));
+        let prog = "(define-test \"t\" (assert #f))\n(define-test \"t\" (assert #t))\n(run-tests)";
+        assert!(is_truthy(&run(prog).unwrap()));
+        assert!(matches!(run("(define-test \"t\" (exit 2))\n(run-tests)"), Err(EvalError::Exit { code: 2 })));
+        assert!(matches!(run("(define-test \"empty\")"), Err(EvalError::InvalidSyntax { .. })));
+    }
+
+    #[test]
+    fn test_dynamic_wind() {
+        let prog = "(define log '())\n\
+                    (define (note x) (lambda () (set! log (cons x log))))\n";
+        let wind = "(dynamic-wind (note 'before) (lambda () ((note 'during)) 42) (note 'after))";
+        assert!(matches!(run(&format!("{}{}", prog, wind)).unwrap(), Object::Integer { value: 42, .. }));
+        match &run(&format!("{}{}\nlog", prog, wind)).unwrap() {
+            Object::List { value, .. } => {
+                let names: Vec<_> = value.iter().map(|obj| obj.to_string()).collect();
+                assert_eq!(names, vec!["after", "during", "before"]);
+            },
+            obj => panic!("Expect (after during before) but {} found", obj),
+        }
+
+        // after is called when the thunk raises an error, which is raised again
+        let wind = "(guard (e (#t log)) (dynamic-wind (note 'before) (lambda () (raise 'oops)) (note 'after)))";
+        match &run(&format!("{}{}", prog, wind)).unwrap() {
+            Object::List { value, .. } => {
+                let names: Vec<_> = value.iter().map(|obj| obj.to_string()).collect();
+                assert_eq!(names, vec!["after", "before"]);
+            },
+            obj => panic!("Expect (after before) but {} found", obj),
+        }
+    }
+
+    #[test]
+    fn test_delay_force() {
+        let prog = "(define count 0)\n\
+                    (define p (delay (begin (set! count (+ count 1)) (* 6 7))))\n";
+        assert!(matches!(run(&format!("{}count", prog)).unwrap(), Object::Integer { value: 0, .. }));
+        assert!(matches!(run(&format!("{}(force p)", prog)).unwrap(), Object::Integer { value: 42, .. }));
+        // The expression is evaluated at most once
+        assert!(matches!(run(&format!("{}(force p)\n(force p)\ncount", prog)).unwrap(), Object::Integer { value: 1, .. }));
+        assert!(matches!(run("(force 1)").unwrap(), Object::Integer { value: 1, .. }));
+        assert!(matches!(run("(promise? (delay x))").unwrap(), Object::Bool { value: true, .. }));
+
+        // Lazy streams only compute the forced elements
+        let prog = "(define (ints n) (cons n (delay (ints (+ n 1)))))\n\
+                    (define (nth s k) (if (= k 0) (car s) (nth (force (cdr s)) (- k 1))))\n\
+                    (nth (ints 0) 20)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 20, .. }));
+    }
+
+    #[test]
+    fn test_parameterize() {
+        let prog = "(define width (make-parameter 10))\n\
+                    (define (show) (width))\n";
+        let eval = |expr: &str| run(&format!("{}{}", prog, expr)).unwrap().to_string();
+        assert_eq!(eval("(show)"), "10");
+        // The callees see the value, which is restored after the body
+        assert_eq!(eval("(list (parameterize ((width 20)) (show)) (show))"), "(20 10)");
+        assert_eq!(eval("(parameterize ((width 20)) (parameterize ((width 30)) (show)))"), "30");
+        assert_eq!(eval("(parameterize ((width 1) (width 2)) (show)) (show)"), "10");
+        // and when the body raises an error
+        assert_eq!(eval("(guard (e (#t (show))) (parameterize ((width 20)) (car '())))"), "10");
+
+        // The converter is applied to the initial value and to the values given to parameterize
+        let prog = "(define p (make-parameter 1 (lambda (x) (* x 2))))\n";
+        assert_eq!(run(&format!("{}(list (p) (parameterize ((p 5)) (p)) (p))", prog)).unwrap().to_string(), "(2 10 2)");
+        assert!(matches!(run("(parameter? (make-parameter 1))").unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run("((make-parameter 1) 2)"), Err(EvalError::ArityMismatch { .. })));
+        assert!(matches!(run("(parameterize ((car 1)) 2)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_eval() {
+        assert!(matches!(run("(eval '(+ 1 2))").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(eval (list '* 2 3))").unwrap(), Object::Integer { value: 6, .. }));
+        assert!(matches!(run("(define x 5)\n(eval 'x)").unwrap(), Object::Integer { value: 5, .. }));
+
+        // Evaluated in the caller's environment unless one is given
+        let prog = "(define x 1)\n(define (f x) (list (eval 'x) (eval 'x (interaction-environment))))\n(f 2)";
+        match &run(prog).unwrap() {
+            Object::List { value, .. } => assert!(matches!(value[..], [
+                Object::Integer { value: 2, .. }, Object::Integer { value: 1, .. }
+            ])),
+            obj => panic!("Expect (2 1) but {} found", obj),
+        }
+
+        // Macros are expanded before evaluation
+        let prog = "(defmacro twice (e) `(begin ,e ,e))\n(define n 0)\n(eval '(twice (set! n (+ n 1))))\nn";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(eval 'x 1)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_gensym() {
+        assert!(matches!(run("(gensym)").unwrap(), Object::Symbol { value, .. } if value == "#:g1"));
+        assert!(matches!(run("(gensym)\n(gensym \"tmp\")").unwrap(), Object::Symbol { value, .. } if value == "#:tmp2"));
+        // The symbol bound by the macro does not capture the one of the caller
+        let prog = "(defmacro swap! (a b) (let ((tmp (gensym))) `(let ((,tmp ,a)) (set! ,a ,b) (set! ,b ,tmp))))\n\
+                    (define tmp 1)\n(define y 2)\n(swap! tmp y)\n(list tmp y)";
+        assert!(matches!(&run(prog).unwrap(), Object::List { value, .. } if matches!(value[..], [
+            Object::Integer { value: 2, .. }, Object::Integer { value: 1, .. }
+        ])));
+        assert!(matches!(run("'#:g1"), Err(EvalError::Lex(LexError::InvalidLiteral { .. }))));
+    }
+
+    #[test]
+    fn test_read_string() {
+        assert!(matches!(run("(eval (read-string \"(+ 1 2) ignored\"))").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(&run("(read-string \"#;skipped 'x\")").unwrap(), Object::List { value, .. } if value.len() == 2));
+        assert!(matches!(run("(read-string \" ;; only a comment\")").unwrap(), Object::Eof { .. }));
+        assert!(matches!(run("(read-string \"(1 2\")"), Err(EvalError::Parse(ParseError::UnclosedList { .. }))));
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let prog = "(setenv \"RSLISP_TEST_VAR\" \"value\")\n(getenv \"RSLISP_TEST_VAR\")";
+        assert!(matches!(&run(prog).unwrap(), Object::Str { value, .. } if value == "value"));
+        let prog = "(setenv \"RSLISP_TEST_VAR\" #f)\n(getenv \"RSLISP_TEST_VAR\")";
+        assert!(matches!(run(prog).unwrap(), Object::Bool { value: false, .. }));
+        assert!(matches!(run("(getenv \"A=B\")"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(setenv \"RSLISP_TEST_VAR\" 1)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_random() {
+        let prog = "(random-seed! 7)\n(define a (list (random-integer 1000) (random)))\n\
+                    (random-seed! 7)\n(list a (list (random-integer 1000) (random)))";
+        match &run(prog).unwrap() {
+            Object::List { value, .. } => assert_eq!(value[0].to_string(), value[1].to_string()),
+            obj => panic!("Expect two lists but {} found", obj),
+        }
+        assert!(matches!(run("(random-integer 1)").unwrap(), Object::Integer { value: 0, .. }));
+        assert!(matches!(run("(random)").unwrap(), Object::Float { value, .. } if (0.0..1.0).contains(&value)));
+        assert!(matches!(run("(random-integer 0)"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_time() {
+        let prog = "(define start (current-milliseconds))\n(sleep 1/50)\n(- (current-milliseconds) start)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value, .. } if value >= 20));
+        assert!(matches!(run("(> (current-time) 1700000000)").unwrap(), Object::Bool { value: true, .. }));
+        assert!(matches!(run("(sleep -1)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(sleep \"1\")"), Err(EvalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process() {
+        assert!(matches!(run("(system \"exit 3\")").unwrap(), Object::Integer { value: 3, .. }));
+        let prog = "(run-process \"sh\" '(\"-c\" \"echo out; echo err >&2; exit 2\") :capture #t)";
+        match &run(prog).unwrap() {
+            Object::List { value, .. } => {
+                assert!(matches!(&value[0], Object::Integer { value: 2, .. }));
+                assert!(matches!(&value[1], Object::Str { value, .. } if value == "out\n"));
+                assert!(matches!(&value[2], Object::Str { value, .. } if value == "err\n"));
+            },
+            obj => panic!("Expect (status stdout stderr) but {} found", obj),
+        }
+        let prog = "(car (run-process \"true\"))";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 0, .. }));
+        assert!(matches!(run("(run-process \"rslisp-missing-program\")"), Err(EvalError::Io { .. })));
+        assert!(matches!(run("(run-process \"true\" '() :quiet #t)"), Err(EvalError::InvalidKeywordArgument { .. })));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(run("(+ 1 undefined)"), Err(EvalError::UndefinedSymbol { name, .. }) if name == "undefined"));
+        assert!(matches!(run("(car 1 2)"), Err(EvalError::ArityMismatch { expected: Arity::Exact(1), found: 2, .. })));
+        assert!(matches!(run("(+ 1 \"a\")"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(run("(1 2)"), Err(EvalError::NotCallable { .. })));
+        assert!(matches!(run("(/ 1 0)"), Err(EvalError::DivisionByZero { .. })));
+        assert!(matches!(run("(string-ref \"abc\" 3)"), Err(EvalError::IndexOutOfRange { index: 3, length: 3, .. })));
+        assert!(matches!(run("(lambda (x))"), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(car 1"), Err(EvalError::Parse(ParseError::UnclosedList { .. }))));
+
+        // The location of the undefined symbol is reported
+        match run("\n  (f 1)") {
+            Err(EvalError::UndefinedSymbol { loc: Some(loc), .. }) => assert_eq!(loc.rol(), 2),
+            other => panic!("Expect UndefinedSymbol but {:?} found", other),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! The formatter of `rslisp fmt`, reprinting the source with consistent
+//! indentation and line breaks while keeping its comments
+//!
+//! Lists fitting in [`WIDTH`] stay on one line. Longer calls put their
+//! arguments under the first one, the special forms with a body, e.g.
+//! `define` or `let`, indent their body by 2 columns and the other lists
+//! put their items under the first one. The atoms are written the way they
+//! are in the source and a blank line between two expressions is kept.
+//!
+//! ```
+//! use rslisp::format::format;
+//!
+//! let source = "(define (f x)\n(if (> x 0) x (- x))) ;; abs";
+//! assert_eq!(format("<string>", source).unwrap(), "(define (f x) (if (> x 0) x (- x))) ;; abs\n");
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::error::EvalError;
+use crate::evaluator::with_stack;
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::parser::{parse, written_with, Label, Labels, Object};
+use crate::pretty::{render, Doc};
+
+/// The width the formatted lines are kept within when possible
+pub const WIDTH: usize = 80;
+
+/// The number of arguments kept on the line of a special form,
+/// the arguments after them are its body
+fn distinguished(head: &str) -> Option<usize> {
+    let count = match head {
+        "begin" => 0,
+        "define" | "lambda" | "let" | "let*" | "letrec" | "letrec*" | "when" | "unless" | "case" | "guard"
+        | "parameterize" | "define-syntax" | "syntax-rules" | "define-test" | "define-record-type" => 1,
+        "defmacro" | "do" => 2,
+        _ => return None,
+    };
+    Some(count)
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Atom { text: String, symbol: bool },
+    /// A line comment, which always ends its line
+    Comment(String),
+    List { open: String, items: Vec<Node> },
+    /// A quote or a datum comment and the expression it applies to
+    Prefix(String, Box<Node>),
+}
+
+/// An expression or a comment with the lines of the source it spans
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    start: usize,
+    end: usize,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // The nodes nested in the node are dropped recursively, on more stack when needed
+        let kind = std::mem::replace(&mut self.kind, NodeKind::Comment(String::new()));
+        with_stack(|| drop(kind));
+    }
+}
+
+impl Node {
+    fn ends_with_comment(&self) -> bool {
+        match &self.kind {
+            NodeKind::Comment(_) => true,
+            NodeKind::Prefix(_, node) => node.ends_with_comment(),
+            _ => false,
+        }
+    }
+}
+
+/// Read the nodes from the tokens of a source known to parse
+struct Reader<'a> {
+    source: &'a str,
+    tokens: VecDeque<Token>,
+}
+
+impl Reader<'_> {
+    fn next_token(&mut self) -> Option<Token> {
+        let mut token = self.tokens.pop_front()?;
+        while matches!(token.kind(), TokenKind::IGNORE) {
+            token = self.tokens.pop_front()?;
+        }
+        Some(token)
+    }
+
+    fn expect_token(&mut self) -> Token {
+        self.next_token().expect("the source parses")
+    }
+
+    /// The node starting with the token
+    fn node(&mut self, token: Token) -> Node {
+        // The nested lists are read recursively, on more stack when needed
+        with_stack(|| self.read_node(token))
+    }
+
+    fn read_node(&mut self, token: Token) -> Node {
+        let text = self.source[token.range()].to_string();
+        let start = token.loc().rol();
+        let kind = match token.kind() {
+            TokenKind::LeftParenthesis | TokenKind::VectorParenthesis | TokenKind::Dispatch('{' | '[') => {
+                let mut items = vec![];
+                loop {
+                    let token = self.expect_token();
+                    if matches!(token.kind(), TokenKind::RightParenthesis | TokenKind::ClosingBracket(_)) {
+                        return Node { kind: NodeKind::List { open: text, items }, start, end: token.loc().rol() };
+                    }
+                    items.push(self.node(token));
+                }
+            },
+            TokenKind::Quote | TokenKind::Quasiquote | TokenKind::Unquote | TokenKind::UnquoteSplicing
+            | TokenKind::DatumComment | TokenKind::Dispatch(_) => {
+                let dispatch = matches!(token.kind(), TokenKind::Dispatch(_));
+                let token = self.expect_token();
+                let node = self.node(token);
+                let end = node.end;
+                // `#d 1` keeps its space since `#d1` is a symbol
+                let text = match &node.kind {
+                    NodeKind::Atom { text: atom, .. } if dispatch && !atom.starts_with('"') => text + " ",
+                    _ => text,
+                };
+                return Node { kind: NodeKind::Prefix(text, Box::new(node)), start, end };
+            },
+            TokenKind::Comment(_) if !text.starts_with("#|") => NodeKind::Comment(text),
+            TokenKind::Symbol(_) => NodeKind::Atom { text, symbol: true },
+            _ => NodeKind::Atom { text, symbol: false },
+        };
+        Node { kind, start, end: token.loc().end_rol() }
+    }
+}
+
+/// A node laid out as a document, `hard` when a comment ends it
+struct Item {
+    doc: Doc,
+    hard: bool,
+    start: usize,
+    end: usize,
+}
+
+impl Item {
+    /// Move the document out, it is laid out once so it is never copied
+    fn take_doc(&mut self) -> Doc {
+        std::mem::replace(&mut self.doc, Doc::concat(vec![]))
+    }
+}
+
+/// The documents of the nodes, a comment on the last line of the node
+/// before it stays on that line
+fn items(nodes: &[Node]) -> Vec<Item> {
+    let mut items: Vec<Item> = vec![];
+    for node in nodes {
+        if let (NodeKind::Comment(text), Some(last)) = (&node.kind, items.last_mut()) {
+            if node.start == last.end && !last.hard {
+                let doc = last.take_doc();
+                last.doc = Doc::concat(vec![doc, Doc::text(" "), Doc::text(text)]);
+                last.hard = true;
+                continue;
+            }
+        }
+        items.push(Item { doc: doc(node), hard: node.ends_with_comment(), start: node.start, end: node.end });
+    }
+    items
+}
+
+/// What separates two items, a blank line between them is kept
+fn separator(before: &Item, after: &Item, line: Doc) -> Doc {
+    match (after.start > before.end + 1, before.hard) {
+        (true, _) => Doc::concat(vec![Doc::HardLine, Doc::HardLine]),
+        (false, true) => Doc::HardLine,
+        (false, false) => line,
+    }
+}
+
+/// The items separated by `line` unless a line break is needed
+fn join(items: &mut [Item], line: Doc) -> Doc {
+    let mut docs = vec![];
+    for i in 0..items.len() {
+        if i > 0 {
+            docs.push(separator(&items[i - 1], &items[i], line.clone()));
+        }
+        docs.push(items[i].take_doc());
+    }
+    Doc::concat(docs)
+}
+
+fn doc(node: &Node) -> Doc {
+    // The nested lists are laid out recursively, on more stack when needed
+    with_stack(|| match &node.kind {
+        NodeKind::Atom { text, .. } | NodeKind::Comment(text) => Doc::text(text),
+        NodeKind::Prefix(prefix, node) => Doc::concat(vec![Doc::text(prefix), doc(node)]),
+        NodeKind::List { open, items: nodes } => list(open, nodes),
+    })
+}
+
+fn list(open: &str, nodes: &[Node]) -> Doc {
+    let closing = match open {
+        "#{" => "}",
+        "#[" => "]",
+        _ => ")",
+    };
+    let mut items = items(nodes);
+    let Some(last) = items.last() else {
+        return Doc::text(format!("{}{}", open, closing));
+    };
+    // The closing parenthesis cannot follow a line comment
+    let close = match last.hard {
+        true => Doc::concat(vec![Doc::HardLine, Doc::text(closing)]),
+        false => Doc::text(closing),
+    };
+    let head = match &nodes[0].kind {
+        NodeKind::Atom { text, symbol: true } if open == "(" && !items[0].hard => Some(text.as_str()),
+        _ => None,
+    };
+
+    // The name of a named let is kept on the line along with the bindings
+    let named_let = head == Some("let")
+        && matches!(nodes.get(1).map(|node| &node.kind), Some(NodeKind::Atom { symbol: true, .. }));
+    let doc = match head.map(|head| (head, distinguished(head).map(|count| count + usize::from(named_let)))) {
+        // (define (f x)
+        //   body)
+        Some((_, Some(count))) => {
+            let count = count.min(items.len() - 1);
+            let mut docs = vec![Doc::text(open), items[0].take_doc()];
+            for i in 1..=count {
+                docs.push(separator(&items[i - 1], &items[i], Doc::text(" ")).nest(2));
+                docs.push(items[i].take_doc());
+            }
+            if items.len() > count + 1 {
+                let body = join(&mut items[count + 1..], Doc::Line);
+                docs.push(Doc::concat(vec![separator(&items[count], &items[count + 1], Doc::Line), body]).nest(2));
+            }
+            docs.push(close);
+            Doc::concat(docs)
+        },
+        // (f arg
+        //    arg)
+        Some((_, None)) if items.len() > 1 => {
+            let separator = separator(&items[0], &items[1], Doc::text(" "));
+            let args = Doc::concat(vec![join(&mut items[1..], Doc::Line), close]).align();
+            Doc::concat(vec![Doc::text(open), items[0].take_doc(), separator, args])
+        },
+        // (item
+        //  item)
+        _ => Doc::concat(vec![Doc::text(open), Doc::concat(vec![join(&mut items, Doc::Line), close]).align()]),
+    };
+    doc.align().group()
+}
+
+/// Format the source, it must parse. The result ends with a line break
+/// unless it is empty
+pub fn format(fname: &str, source: &str) -> Result<String, EvalError> {
+    parse(&mut tokenize(fname, source)?)?;
+    let mut reader = Reader { source, tokens: tokenize(fname, source)? };
+    let nodes: Vec<Node> = std::iter::from_fn(|| reader.next_token().map(|token| reader.node(token))).collect();
+
+    let mut items = items(&nodes);
+    if items.is_empty() {
+        return Ok(String::new());
+    }
+    let mut formatted = render(&join(&mut items, Doc::HardLine), WIDTH);
+    formatted.push('\n');
+    Ok(formatted)
+}
+
+/// Lay the forms out the way [`format`] does, for the forms which have no
+/// source, e.g. the ones made by macros. The result ends with a line break
+/// unless there is no form
+pub fn format_forms(forms: &[Object]) -> String {
+    let nodes: Vec<Node> = forms.iter().map(|form| object_node(form, &mut Labels::new(form))).collect();
+    let mut items = items(&nodes);
+    if items.is_empty() {
+        return String::new();
+    }
+    let mut formatted = render(&join(&mut items, Doc::HardLine), WIDTH);
+    formatted.push('\n');
+    formatted
+}
+
+/// Lay the object out within `width` columns when possible, nested lists
+/// are broken and indented the way [`format`] does. The atoms are written
+/// the way `write` prints them
+///
+/// ```
+/// use rslisp::format::pretty_print;
+/// use rslisp::Interpreter;
+///
+/// let obj = Interpreter::new().eval_str("'(data (\"first\" 1) (\"second\" 2))").unwrap();
+/// assert_eq!(pretty_print(&obj, 20), "(data (\"first\" 1)\n      (\"second\" 2))");
+/// ```
+pub fn pretty_print(obj: &Object, width: usize) -> String {
+    render(&doc(&object_node(obj, &mut Labels::new(obj))), width)
+}
+
+/// The character written for a quote form and the quoted object,
+/// e.g. `'x` for `(quote x)`
+fn quoted(list: &[Object]) -> Option<(&'static str, &Object)> {
+    let prefix = match list {
+        [Object::Symbol { value, .. }, _] => match value.as_str() {
+            "quote" => "'",
+            "quasiquote" => "`",
+            "unquote" => ",",
+            "unquote-splicing" => ",@",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((prefix, &list[1]))
+}
+
+/// The node of an object, all on the same line so no blank line is kept
+fn object_node(obj: &Object, labels: &mut Labels) -> Node {
+    // The nested objects are read recursively, on more stack when needed
+    with_stack(|| build_node(obj, labels))
+}
+
+fn build_node(obj: &Object, labels: &mut Labels) -> Node {
+    let node = |kind| Node { kind, start: 0, end: 0 };
+    let atom = |text: String| NodeKind::Atom { text, symbol: false };
+    let list = |open: &str, items| NodeKind::List { open: open.to_string(), items };
+    // The records are printed as atoms, labels included
+    if let Object::Record { .. } = obj {
+        return node(atom(written_with(obj, labels)));
+    }
+    // The containers in a cycle are printed once, e.g. #0=#hash((self . #0#))
+    let label = match labels.label(obj) {
+        Some(Label::Reference(n)) => return node(atom(format!("#{}#", n))),
+        Some(Label::Define(n)) => Some(format!("#{}=", n)),
+        None => None,
+    };
+    let kind = match obj {
+        Object::List { value, .. } => match quoted(value) {
+            Some((prefix, obj)) => NodeKind::Prefix(prefix.to_string(), Box::new(object_node(obj, labels))),
+            None => list("(", value.iter().map(|item| object_node(item, labels)).collect()),
+        },
+        Object::Vector { value, .. } => list("#(", value.iter().map(|item| object_node(item, labels)).collect()),
+        Object::Pair { value, .. } => {
+            let mut items = vec![object_node(&value.0, labels)];
+            let mut rest = &value.1;
+            while let Object::Pair { value, .. } = rest {
+                items.push(object_node(&value.0, labels));
+                rest = &value.1;
+            }
+            match rest {
+                Object::List { value, .. } => items.extend(value.iter().map(|item| object_node(item, labels))),
+                obj => items.extend([node(atom(".".to_string())), object_node(obj, labels)]),
+            }
+            list("(", items)
+        },
+        Object::HashTable { value, .. } => {
+            let entries = value.borrow().iter()
+                .map(|(key, item)| {
+                    let entry = vec![object_node(&key.to_object(), labels), node(atom(".".to_string())), object_node(item, labels)];
+                    node(list("(", entry))
+                })
+                .collect();
+            list("#hash(", entries)
+        },
+        Object::Symbol { value, .. } => NodeKind::Atom { text: value.to_string(), symbol: true },
+        obj => atom(written_with(obj, labels)),
+    };
+    match label {
+        Some(label) => node(NodeKind::Prefix(label, Box::new(node(kind)))),
+        None => node(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(source: &str) -> Object {
+        match &parse(&mut tokenize("format_test.rsl", source).unwrap()).unwrap() {
+            Object::Module { value, .. } => Object::List { value: value.to_vec().into(), loc: None },
+            obj => obj.clone(),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let cases = [
+            ("(+   1\n 2)", "(+ 1 2)\n"),
+            ("", ""),
+            (";; only\n", ";; only\n"),
+            ("(define x 1)\n\n\n\n(define y 2)", "(define x 1)\n\n(define y 2)\n"),
+            ("'(a   #(1 2) . b)", "'(a #(1 2) . b)\n"),
+            ("(f ;; first\n x)", "(f ;; first\n x)\n"),
+            ("(f x ;; last\n)", "(f x ;; last\n   )\n"),
+            ("(let ((x 1))\n;; comment\nx)", "(let ((x 1))\n  ;; comment\n  x)\n"),
+            ("(let loop ((i 0))\n;; again\n(loop i))", "(let loop ((i 0))\n  ;; again\n  (loop i))\n"),
+            ("#;  (skipped)  \"a\\\"b\"", "#;(skipped)\n\"a\\\"b\"\n"),
+            ("(list #d  \"x\" #d   1 #{a  b} #[])", "(list #d\"x\" #d 1 #{a b} #[])\n"),
+            ("(define (f items) (map (lambda (item) (string-append \"item: \" (number->string item))) items))",
+             "(define (f items)\n  (map (lambda (item) (string-append \"item: \" (number->string item))) items))\n"),
+            ("((lambda (x) x) (list 'some-long-symbol 'another-long-symbol 'and-yet-another-one 'and-more))",
+             "((lambda (x) x)\n (list 'some-long-symbol 'another-long-symbol 'and-yet-another-one 'and-more))\n"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(format("format_test.rsl", source).unwrap(), expected, "{}", source);
+        }
+        assert!(matches!(format("format_test.rsl", "(1 2"), Err(EvalError::Parse(_))));
+    }
+
+    #[test]
+    fn test_format_forms() {
+        let source = "(define (f a . rest) `(,a ,@rest #(\"s\\\"\" #\\space))) '(1 . (2 3))";
+        let Object::List { value: forms, .. } = &program(source) else { unreachable!() };
+        let formatted = format_forms(forms);
+        assert_eq!(formatted, "(define (f a . rest) `(,a ,@rest #(\"s\\\"\" #\\space)))\n'(1 2 3)\n");
+        assert!(program(source).is_equal(&program(&formatted)));
+        assert_eq!(format_forms(&[]), "");
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let Object::List { value: forms, .. } = &program("(define (f x) (if (> x 0) x (- x))) #(1 \"two\" #\\3 4.0)") else {
+            unreachable!()
+        };
+        assert_eq!(pretty_print(&forms[0], 80), "(define (f x) (if (> x 0) x (- x)))");
+        assert_eq!(pretty_print(&forms[0], 20), "(define (f x)\n  (if (> x 0)\n      x\n      (- x)))");
+        assert_eq!(pretty_print(&forms[1], 10), "#(1\n  \"two\"\n  #\\3\n  4.0)");
+
+        let hash = Object::HashTable { value: Default::default(), loc: None };
+        assert_eq!(pretty_print(&hash, 80), "#hash()");
+    }
+
+    #[test]
+    fn test_format_is_stable() {
+        let sample = "#!/usr/bin/env rslisp\n\
+                      (define (walk items acc) (cond ((null? items) acc) ((even? (car items)) (walk (cdr items) \
+                      (cons (* 2 (car items)) acc))) (else (walk (cdr items) acc)))) ;; doubles\n\
+                      (let loop ((i 0)) (when (< i 3) #| body |# (display `(,i ,@(list i))) (loop (+ i 1))))\n\
+                      (define-record-type point (make-point x y) point? (x point-x) (y point-y set-point-y!))";
+        let file = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/test.rslisp")).unwrap();
+        for source in [sample, file.as_str()] {
+            let formatted = format("format_test.rsl", source).unwrap();
+            assert!(program(source).is_equal(&program(&formatted)), "{}", formatted);
+            assert_eq!(format("format_test.rsl", &formatted).unwrap(), formatted);
+            assert!(formatted.lines().all(|line| line.chars().count() <= WIDTH && !line.ends_with(' ')), "{}", formatted);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Bounded evaluation
+//!
+//! The fuel is the number of evaluation steps a program may still take when
+//! it is evaluated by [`crate::Interpreter::eval_with_fuel`]. Every object
+//! the evaluator evaluates and every instruction the VM executes burns one
+//! step, the program fails with [`EvalError::OutOfFuel`] once none is left.
+//! The fuel is kept per thread like the symbols rather than in the runtime
+//! so burning a step does not look the root environment up.
+
+use std::cell::Cell;
+
+use crate::error::EvalError;
+
+thread_local! {
+    /// None when the evaluation is not bounded
+    static FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Burn one step, failing once the fuel runs out
+pub fn burn() -> Result<(), EvalError> {
+    FUEL.with(|fuel| match fuel.get() {
+        None => Ok(()),
+        Some(0) => Err(EvalError::OutOfFuel { loc: None }),
+        Some(steps) => {
+            fuel.set(Some(steps - 1));
+            Ok(())
+        },
+    })
+}
+
+/// Run `f` with at most `steps` steps. Within an enclosing bounded run
+/// the fuel left to it is a limit as well and the steps taken are burnt from it
+pub fn with_fuel<R>(steps: u64, f: impl FnOnce() -> R) -> R {
+    let outer = FUEL.with(|fuel| fuel.get());
+    let steps = outer.map_or(steps, |outer| outer.min(steps));
+    // The fuel is restored even if `f` panics
+    let _restore = Restore { outer, steps };
+    FUEL.with(|fuel| fuel.set(Some(steps)));
+    f()
+}
+
+struct Restore {
+    outer: Option<u64>,
+    steps: u64,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        FUEL.with(|fuel| {
+            let burnt = self.steps - fuel.get().unwrap_or(0);
+            fuel.set(self.outer.map(|outer| outer - burnt));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuel() {
+        assert!(burn().is_ok());
+        with_fuel(2, || {
+            assert!(burn().is_ok());
+            // The inner run is limited by the fuel left to the outer one
+            with_fuel(10, || {
+                assert!(burn().is_ok());
+                assert!(matches!(burn(), Err(EvalError::OutOfFuel { .. })));
+            });
+            assert!(burn().is_err());
+        });
+        assert!(burn().is_ok());
+    }
+}
@@ -0,0 +1,334 @@
+//! Cycle collection for environments
+//!
+//! A closure holds the environment it is defined in, which usually holds
+//! the closure back, so the reference counts never drop to zero. Every
+//! environment captured by a closure or a promise is tracked by the
+//! runtime, [`collect`] walks the objects reachable from the tracked
+//! environments and counts the references coming from inside that graph.
+//! A node referenced more times than the graph accounts for is held from
+//! outside, e.g. by the interpreter or a call being evaluated, and keeps
+//! everything it reaches alive. The tracked environments left are garbage,
+//! their bindings are cleared to break the cycles. The evaluator collects
+//! them itself every so many environments tracked, see
+//! [`crate::runtime::Runtime::track`], besides `(gc)` and the REPL.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::evaluator::{with_stack, Environment};
+use crate::parser::{FunctionDefinition, Items, ListItems, ParamKind, Promise};
+use crate::parser::Object;
+use crate::runtime::COLLECT_INTERVAL;
+
+/// A node is any value behind an `Rc`, identified by its address
+#[derive(Default)]
+struct Node {
+    strong: usize,
+    internal: usize,
+    children: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: HashMap<usize, Node>,
+}
+
+fn id<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+impl Graph {
+    /// Insert the node unless it is already visited, return whether it is new
+    fn enter<T: ?Sized>(&mut self, rc: &Rc<T>) -> bool {
+        if self.nodes.contains_key(&id(rc)) {
+            return false;
+        }
+        self.nodes.insert(id(rc), Node { strong: Rc::strong_count(rc), ..Node::default() });
+        true
+    }
+
+    /// A node which cannot be inspected, e.g. borrowed at the moment,
+    /// is kept alive
+    fn pin(&mut self, node: usize) {
+        if let Some(node) = self.nodes.get_mut(&node) {
+            node.strong = usize::MAX;
+        }
+    }
+
+    fn edge(&mut self, parent: usize, child: usize) {
+        if let Some(node) = self.nodes.get_mut(&parent) {
+            node.children.push(child);
+        }
+    }
+
+    fn visit_env(&mut self, env: &Rc<RefCell<Environment>>) -> usize {
+        let node = id(env);
+        if !self.enter(env) {
+            return node;
+        }
+        let env = match env.try_borrow() {
+            Ok(env) => env,
+            Err(_) => {
+                self.pin(node);
+                return node;
+            }
+        };
+        if let Some(parent) = env.parent() {
+            let child = self.visit_env(parent);
+            self.edge(node, child);
+        }
+        for obj in env.values() {
+            self.visit_obj(node, obj);
+        }
+        for obj in env.runtime().iter().flat_map(|runtime| runtime.modules.values()) {
+            self.visit_obj(node, obj);
+        }
+        node
+    }
+
+    fn visit_func(&mut self, func: &Rc<FunctionDefinition>) -> usize {
+        let node = id(func);
+        if !self.enter(func) {
+            return node;
+        }
+        let child = self.visit_env(&func.env);
+        self.edge(node, child);
+        for param in func.params.iter() {
+            if let ParamKind::Keyword { default: Some(default), .. } = &param.kind {
+                self.visit_obj(node, default);
+            }
+        }
+        for obj in func.body.0.iter() {
+            self.visit_obj(node, obj);
+        }
+        node
+    }
+
+    /// Add the edge from the parent node to the node holding the items
+    /// of a list or a vector
+    fn visit_items<T>(&mut self, parent: usize, rc: &Rc<T>, items: &[Object]) {
+        self.edge(parent, id(rc));
+        if self.enter(rc) {
+            for item in items {
+                self.visit_obj(id(rc), item);
+            }
+        }
+    }
+
+    /// Add the edges from the parent node to the cells of the list, walking
+    /// them one after the other so long lists do not exhaust the stack
+    fn visit_list(&mut self, parent: usize, list: &ListItems) {
+        let mut parent = parent;
+        let mut list = list;
+        loop {
+            match list.items() {
+                Items::Empty => return,
+                Items::Slice(items, _) => return self.visit_items(parent, items, items),
+                Items::Cons(cell) => {
+                    self.edge(parent, id(cell));
+                    if !self.enter(cell) {
+                        return;
+                    }
+                    self.visit_obj(id(cell), &cell.car);
+                    for item in cell.items.get().into_iter().flatten() {
+                        self.visit_obj(id(cell), item);
+                    }
+                    parent = id(cell);
+                    list = &cell.cdr;
+                },
+            }
+        }
+    }
+
+    /// Add the edges from the parent node to the nodes held by the object
+    fn visit_obj(&mut self, parent: usize, obj: &Object) {
+        // The nested objects are visited recursively, on more stack when needed
+        with_stack(|| self.visit_children(parent, obj))
+    }
+
+    fn visit_children(&mut self, parent: usize, obj: &Object) {
+        match obj {
+            Object::Lambda { value, .. } | Object::Macro { value, .. } => {
+                let child = self.visit_func(value);
+                self.edge(parent, child);
+            },
+            Object::Environment { value, .. } => {
+                let child = self.visit_env(value);
+                self.edge(parent, child);
+            },
+            Object::List { value, .. } => self.visit_list(parent, value),
+            Object::Vector { value, .. } => self.visit_items(parent, value, value),
+            Object::Pair { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    self.visit_obj(id(value), &value.0);
+                    self.visit_obj(id(value), &value.1);
+                }
+            },
+            Object::Error { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    for item in value.irritants.iter() {
+                        self.visit_obj(id(value), item);
+                    }
+                }
+            },
+            Object::HashTable { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    match value.try_borrow() {
+                        Ok(table) => table.values().for_each(|item| self.visit_obj(id(value), item)),
+                        Err(_) => self.pin(id(value)),
+                    }
+                }
+            },
+            Object::Record { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    match value.try_borrow() {
+                        Ok(record) => record.fields.iter().for_each(|item| self.visit_obj(id(value), item)),
+                        Err(_) => self.pin(id(value)),
+                    }
+                }
+            },
+            Object::Promise { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    match value.try_borrow() {
+                        Ok(promise) => match &*promise {
+                            Promise::Delayed(expr, env) => {
+                                self.visit_obj(id(value), expr);
+                                let child = self.visit_env(env);
+                                self.edge(id(value), child);
+                            },
+                            Promise::Forced(result) => self.visit_obj(id(value), result),
+                        },
+                        Err(_) => self.pin(id(value)),
+                    }
+                }
+            },
+            Object::Parameter { value, .. } => {
+                self.edge(parent, id(value));
+                if self.enter(value) {
+                    match value.value.try_borrow() {
+                        Ok(current) => self.visit_obj(id(value), &current),
+                        Err(_) => self.pin(id(value)),
+                    }
+                    if let Some(converter) = &value.converter {
+                        self.visit_obj(id(value), converter);
+                    }
+                }
+            },
+            Object::Module { value, .. } => {
+                for item in value.iter() {
+                    self.visit_obj(parent, item);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// The nodes reachable from the nodes held from outside the graph
+    fn live(&mut self) -> HashSet<usize> {
+        let edges: Vec<usize> = self.nodes.values().flat_map(|node| node.children.iter().copied()).collect();
+        for child in edges {
+            if let Some(node) = self.nodes.get_mut(&child) {
+                node.internal += 1;
+            }
+        }
+
+        let mut stack: Vec<usize> = self.nodes
+            .iter()
+            .filter(|(_, node)| node.strong > node.internal)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut live = HashSet::new();
+        while let Some(node) = stack.pop() {
+            if live.insert(node) {
+                stack.extend(self.nodes[&node].children.iter().copied());
+            }
+        }
+        live
+    }
+}
+
+/// Break the cycles of the tracked environments no longer reachable,
+/// return the number of environments freed
+pub fn collect(env: &Rc<RefCell<Environment>>) -> usize {
+    let tracked: Vec<_> = Environment::with_runtime(env, |runtime| {
+        runtime.environments.retain(|_, env| env.strong_count() > 0);
+        runtime.environments.values().filter_map(|env| env.upgrade()).collect()
+    });
+
+    let mut graph = Graph::default();
+    for env in tracked.iter() {
+        graph.visit_env(env);
+    }
+    // Do not count the references held by `tracked` itself
+    for env in tracked.iter() {
+        if let Some(node) = graph.nodes.get_mut(&id(env)) {
+            node.strong = node.strong.saturating_sub(1);
+        }
+    }
+
+    let live = graph.live();
+    let (garbage, kept): (Vec<_>, Vec<_>) = tracked.into_iter().partition(|env| !live.contains(&id(env)));
+    Environment::with_runtime(env, |runtime| {
+        runtime.tracked_since_collect = 0;
+        runtime.collect_threshold = (kept.len() * 2).max(COLLECT_INTERVAL);
+    });
+    for env in garbage.iter() {
+        env.borrow_mut().clear();
+    }
+    garbage.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Backend, Interpreter};
+    use crate::runtime::COLLECT_INTERVAL;
+    use crate::parser::Object;
+
+    #[test]
+    fn test_collect() {
+        let interp = Interpreter::new();
+        // Every call leaves a scope holding a closure holding the scope
+        interp.eval_str("(define (make n) (define (get) n) get)\n(define keep (make 42))").unwrap();
+        interp.collect_garbage();
+        interp.eval_str("(make 1)\n(make 2)\n(make 3)").unwrap();
+        assert_eq!(interp.collect_garbage(), 3);
+        assert_eq!(interp.collect_garbage(), 0);
+
+        // Reachable closures survive
+        assert!(matches!(interp.eval_str("(keep)").unwrap(), Object::Integer { value: 42, .. }));
+        interp.eval_str("(define p (let ((x 5)) (delay (* x 2))))").unwrap();
+        interp.eval_str("(define h (make-hash))\n(hash-set! h 'f (make 7))").unwrap();
+        interp.collect_garbage();
+        assert!(matches!(interp.eval_str("(force p)").unwrap(), Object::Integer { value: 10, .. }));
+        assert!(matches!(interp.eval_str("((hash-ref h 'f))").unwrap(), Object::Integer { value: 7, .. }));
+
+        // Scopes of the calls being evaluated are kept
+        let prog = "(define (f x) (define (g) x) (gc) (g))\n(f 9)";
+        assert!(matches!(interp.eval_str(prog).unwrap(), Object::Integer { value: 9, .. }));
+    }
+
+    #[test]
+    fn test_collect_while_running() {
+        // A script never calling (gc) leaves no more than an interval of garbage
+        let path = std::env::temp_dir().join(format!("rslisp_gc_{}.rsl", std::process::id()));
+        std::fs::write(&path, "(define (make n) (define (get) n) get)\n\
+                               (define keep (make 42))\n\
+                               (define (loop i) (if (< i 30000) (begin (make i) (loop (+ i 1))) (keep)))\n\
+                               (loop 0)").unwrap();
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let interp = Interpreter::with_backend(backend);
+            let result = interp.eval_file(path.to_str().unwrap());
+            assert!(matches!(result, Ok(Object::Integer { value: 42, .. })), "{:?}", result);
+            assert!(interp.collect_garbage() < COLLECT_INTERVAL);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Evaluator hooks
+//!
+//! A [`Hook`] is told about the lists the tree-walking evaluator evaluates
+//! and the functions it calls, e.g. to stop the program at a breakpoint,
+//! see [`crate::debugger`]. The bytecode VM does not call the hooks. The
+//! hook is installed per thread like the fuel rather than in the runtime
+//! so checking for it does not look the root environment up.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::parser::Object;
+
+/// What the evaluator reports to the hook, an error returned by the hook
+/// is raised in the program where the hook is called
+pub trait Hook {
+    /// Before the list is evaluated in the environment
+    fn before_eval(&self, _form: &Object, _env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        Ok(())
+    }
+
+    /// After the list is evaluated, whether it succeeded or failed
+    fn after_eval(&self, _form: &Object, _result: &Result<Object, EvalError>) {}
+
+    /// Before the function is called with the evaluated arguments, the
+    /// environment is the one of the caller
+    fn before_call(&self, _name: &str, _args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        Ok(())
+    }
+
+    /// After the function called returns or fails, unless the hook
+    /// failed the call in `before_call`
+    fn after_call(&self, _name: &str, _result: &Result<Object, EvalError>) {}
+
+    /// After the arguments of a function defined in the program are bound
+    /// to its parameters, before its body is evaluated in the environment
+    fn enter_function(&self, _name: &str, _args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static HOOK: RefCell<Option<Rc<dyn Hook>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the hook installed, the hook installed before is
+/// restored afterwards
+pub fn with_hook<R>(hook: Rc<dyn Hook>, f: impl FnOnce() -> R) -> R {
+    let outer = HOOK.with(|current| current.borrow_mut().replace(hook));
+    // The outer hook is restored even if `f` panics
+    let _restore = Restore(outer);
+    f()
+}
+
+struct Restore(Option<Rc<dyn Hook>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        HOOK.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+fn current() -> Option<Rc<dyn Hook>> {
+    HOOK.with(|current| current.borrow().clone())
+}
+
+pub(crate) fn is_installed() -> bool {
+    HOOK.with(|current| current.borrow().is_some())
+}
+
+pub(crate) fn before_eval(form: &Object, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    match current() {
+        Some(hook) => hook.before_eval(form, env),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn after_eval(form: &Object, result: &Result<Object, EvalError>) {
+    if let Some(hook) = current() {
+        hook.after_eval(form, result);
+    }
+}
+
+pub(crate) fn before_call(name: &str, args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    match current() {
+        Some(hook) => hook.before_call(name, args, env),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn after_call(name: &str, result: &Result<Object, EvalError>) {
+    if let Some(hook) = current() {
+        hook.after_call(name, result);
+    }
+}
+
+pub(crate) fn enter_function(name: &str, args: &[Object], env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    match current() {
+        Some(hook) => hook.enter_function(name, args, env),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,351 @@
+//! Incremental parsing of a source buffer being edited, for the editors
+//!
+//! A [`Document`] keeps the tokens and the objects of the top level forms
+//! of the source. After an edit only the forms around the edited bytes are
+//! read again: the forms before the edit are kept as they are, and the
+//! lexer stops as soon as it is back at the start of a form following the
+//! edit, outside of any list, whose tokens and objects are moved to their
+//! new place rather than read again. The document is always the same as
+//! the one read from the whole new source.
+//!
+//! ```
+//! use rslisp::incremental::Document;
+//!
+//! let mut doc = Document::new("<buffer>", "(define x 1)\n(define y 2)\n(+ x y)");
+//! // Replace the 2 with 20, only the second form is read again
+//! let changed = doc.edit(23, 1, "20");
+//! assert_eq!(changed, 1..2);
+//! assert_eq!(doc.source(), "(define x 1)\n(define y 20)\n(+ x y)");
+//! let sum = doc.forms()[2].datum().unwrap().as_ref().unwrap();
+//! assert_eq!(sum.loc().unwrap().rol(), 3);
+//! ```
+
+use std::{collections::VecDeque, iter::Peekable, ops::Range, rc::Rc, vec::IntoIter};
+
+use crate::evaluator::with_stack;
+use crate::error::{LexError, ParseError};
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::location::Location;
+use crate::parser::{parse_datum, Object};
+
+/// A top level datum with the comments and the whitespace preceding it
+#[derive(Debug)]
+pub struct Form {
+    tokens: Vec<Token>,
+    /// None for the comments and the whitespace at the end of the source
+    datum: Option<Result<Object, ParseError>>,
+}
+
+impl Form {
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn datum(&self) -> Option<&Result<Object, ParseError>> {
+        self.datum.as_ref()
+    }
+
+    /// The bytes of the source the form is read from
+    pub fn range(&self) -> Range<usize> {
+        let start = self.tokens.first().map_or(0, |token| token.range().start);
+        start..self.tokens.last().map_or(start, |token| token.range().end)
+    }
+}
+
+/// A source and the forms read from it, see the [module](self)
+#[derive(Debug)]
+pub struct Document {
+    fname: String,
+    source: String,
+    forms: Vec<Form>,
+    /// The error which stopped the lexer, the forms end before it
+    error: Option<LexError>,
+}
+
+impl Document {
+    pub fn new(fname: &str, source: &str) -> Self {
+        let mut doc = Self { fname: fname.to_string(), source: source.to_string(), forms: Vec::new(), error: None };
+        let (tokens, error) = doc.relex(0, 0, &mut Vec::new().into_iter().peekable(), 0);
+        doc.forms = read_forms(tokens);
+        doc.error = error;
+        doc
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn forms(&self) -> &[Form] {
+        &self.forms
+    }
+
+    pub fn error(&self) -> Option<&LexError> {
+        self.error.as_ref()
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.forms.iter().flat_map(|form| form.tokens.iter())
+    }
+
+    /// The objects read, without the forms which failed to parse
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.forms.iter().filter_map(|form| form.datum.as_ref()?.as_ref().ok())
+    }
+
+    /// Replace the `old_len` bytes at `offset` with `text` and read the
+    /// changed forms again, returning the indices of the forms read in
+    /// [`Document::forms`]. It panics like [`String::replace_range`] when
+    /// the bytes are out of the source or not on a character boundary
+    pub fn edit(&mut self, offset: usize, old_len: usize, text: &str) -> Range<usize> {
+        let old_end = position(&self.source, offset + old_len);
+        self.source.replace_range(offset..offset + old_len, text);
+        let shift = Shift {
+            bytes: text.len() as isize - old_len as isize,
+            old_end,
+            new_end: position(&self.source, offset + text.len()),
+        };
+
+        // The forms ending before the edit are kept, even a token ending
+        // right at the edit could be extended by the text inserted
+        let kept = self.forms
+            .iter()
+            .take_while(|form| form.datum.is_some() && form.range().end < offset)
+            .count();
+        let mut old = self.forms.split_off(kept).into_iter().peekable();
+        let start = self.forms.last().map_or(0, |form| form.range().end);
+        let (tokens, error) = self.relex(start, offset + text.len(), &mut old, shift.bytes);
+        let mut forms = read_forms(tokens);
+        let changed = kept..kept + forms.len();
+
+        let mut moved = old.map(|form| shift.form(form)).peekable();
+        // The comments read last belong to the form following them
+        if matches!(forms.last(), Some(Form { datum: None, .. })) && moved.peek().is_some() {
+            let (mut tokens, mut next) = (forms.pop().unwrap().tokens, moved.next().unwrap());
+            tokens.append(&mut next.tokens);
+            next.tokens = tokens;
+            forms.push(next);
+        }
+        forms.extend(moved);
+        self.forms.append(&mut forms);
+        self.error = error;
+        changed
+    }
+
+    /// The tokens from the byte offset `start` on, up to the start of one
+    /// of the `old` forms once past `edit_end`, the forms skipped are taken
+    /// from `old`, which is left empty if the lexer reaches the end or fails
+    fn relex(
+        &self,
+        start: usize,
+        edit_end: usize,
+        old: &mut Peekable<IntoIter<Form>>,
+        shift: isize,
+    ) -> (Vec<Token>, Option<LexError>) {
+        let mut tokens = Vec::new();
+        let mut depth = 0usize;
+        for token in Lexer::starting_at(&self.fname, &self.source, start) {
+            let token = match token {
+                Ok(token) => token,
+                Err(err) => {
+                    old.for_each(drop);
+                    return (tokens, Some(err));
+                },
+            };
+            match token.kind() {
+                TokenKind::LeftParenthesis | TokenKind::VectorParenthesis | TokenKind::Dispatch('{' | '[') => depth += 1,
+                TokenKind::RightParenthesis | TokenKind::ClosingBracket(_) => depth = depth.saturating_sub(1),
+                _ => {},
+            }
+            let end = token.range().end;
+            tokens.push(token);
+            // An unclosed list or a quote waiting for its datum could
+            // take the tokens of the old forms
+            if end < edit_end || depth > 0 {
+                continue;
+            }
+            let end = end as isize - shift;
+            while old.next_if(|form| (form.range().start as isize) < end).is_some() {}
+            if old.peek().is_some_and(|form| form.range().start as isize == end) && is_complete(&tokens) {
+                return (tokens, None);
+            }
+        }
+        old.for_each(drop);
+        (tokens, None)
+    }
+}
+
+/// Split the tokens into the forms, parsing their data
+fn read_forms(tokens: Vec<Token>) -> Vec<Form> {
+    let mut queue: VecDeque<Token> = tokens.iter().cloned().collect();
+    let mut tokens = tokens.into_iter();
+    let mut forms = Vec::new();
+    while !queue.is_empty() {
+        let before = queue.len();
+        let datum = parse_datum(&mut queue).transpose();
+        forms.push(Form { tokens: tokens.by_ref().take(before - queue.len()).collect(), datum });
+    }
+    forms
+}
+
+/// Whether the last datum of the tokens ends with them, rather than
+/// missing the tokens following them
+fn is_complete(tokens: &[Token]) -> bool {
+    let mut queue: VecDeque<Token> = tokens.iter().cloned().collect();
+    loop {
+        match parse_datum(&mut queue) {
+            Ok(Some(_)) => {},
+            Ok(None) => return true,
+            Err(ParseError::UnclosedList { .. }) => return false,
+            Err(
+                ParseError::MissingQuotedExpression { .. }
+                | ParseError::MissingCommentedExpression { .. }
+                | ParseError::MissingDispatchedExpression { .. },
+            ) if queue.is_empty() => {
+                // The quote is closed by a parenthesis, or by the end of the tokens
+                let last = tokens.last().map(|token| token.kind());
+                return matches!(last, Some(TokenKind::RightParenthesis | TokenKind::ClosingBracket(_)));
+            },
+            Err(_) => {},
+        }
+    }
+}
+
+/// The 1-based line and column of the byte offset, counted like the lexer does
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// How the places following an edit move
+struct Shift {
+    bytes: isize,
+    /// The position of the end of the edit before and after it
+    old_end: (usize, usize),
+    new_end: (usize, usize),
+}
+
+impl Shift {
+    fn offset(&self, offset: usize) -> usize {
+        (offset as isize + self.bytes) as usize
+    }
+
+    /// The place of a position following the edit, only the columns of
+    /// the line the edit ends on change
+    fn position(&self, (rol, col): (usize, usize)) -> (usize, usize) {
+        if rol == self.old_end.0 {
+            (self.new_end.0, col - self.old_end.1 + self.new_end.1)
+        } else {
+            (rol - self.old_end.0 + self.new_end.0, col)
+        }
+    }
+
+    fn loc(&self, loc: &Option<Location>) -> Option<Location> {
+        loc.as_ref().map(|loc| loc.moved(|pos| self.position(pos)))
+    }
+
+    fn form(&self, form: Form) -> Form {
+        let tokens: Vec<_> = form.tokens
+            .into_iter()
+            .map(|token| {
+                let range = token.range();
+                let loc = token.loc().moved(|pos| self.position(pos));
+                token.moved(self.offset(range.start)..self.offset(range.end), loc)
+            })
+            .collect();
+        let datum = match form.datum {
+            Some(Ok(obj)) => Some(Ok(self.object(&obj))),
+            // The errors are rare, they are read again from the moved tokens
+            Some(Err(_)) => parse_datum(&mut tokens.iter().cloned().collect()).transpose(),
+            None => None,
+        };
+        Form { tokens, datum }
+    }
+
+    /// The object read by the parser with its locations moved
+    fn object(&self, obj: &Object) -> Object {
+        // The nested objects are moved recursively, on more stack when needed
+        with_stack(|| match obj {
+            Object::List { value, loc } => Object::List {
+                value: value.iter().map(|item| self.object(item)).collect(),
+                loc: self.loc(loc),
+            },
+            Object::Vector { value, loc } => Object::Vector {
+                value: Rc::new(value.iter().map(|item| self.object(item)).collect()),
+                loc: self.loc(loc),
+            },
+            Object::Pair { value, loc } => Object::Pair {
+                value: Rc::new((self.object(&value.0), self.object(&value.1))),
+                loc: self.loc(loc),
+            },
+            Object::Integer { value, loc } => Object::Integer { value: *value, loc: self.loc(loc) },
+            Object::BigInteger { value, loc } => Object::BigInteger { value: value.clone(), loc: self.loc(loc) },
+            Object::Rational { value, loc } => Object::Rational { value: value.clone(), loc: self.loc(loc) },
+            Object::Float { value, loc } => Object::Float { value: *value, loc: self.loc(loc) },
+            Object::Bool { value, loc } => Object::Bool { value: *value, loc: self.loc(loc) },
+            Object::Char { value, loc } => Object::Char { value: *value, loc: self.loc(loc) },
+            Object::Str { value, loc } => Object::Str { value: value.clone(), loc: self.loc(loc) },
+            Object::Symbol { value, loc } => Object::Symbol { value: *value, loc: self.loc(loc) },
+            Object::Keyword { value, loc } => Object::Keyword { value: *value, loc: self.loc(loc) },
+            // The parser makes no other objects
+            _ => obj.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The forms as they compare, with their locations
+    fn dump(doc: &Document) -> Vec<String> {
+        doc.forms().iter().map(|form| format!("{:?}", form)).collect()
+    }
+
+    #[test]
+    fn test_edit_matches_full_read() {
+        let source = ";; header\n(define (f x)\n  '(a . b))\n#;(skipped) (g \"s\" #(1 2))\n\n(h λ :key) ;; end";
+        let texts = ["", "(", ")", "'", "\"", " ", "\n", "x y", "#;", "é\n ;c\n"];
+        for offset in 0..=source.len() {
+            if !source.is_char_boundary(offset) {
+                continue;
+            }
+            for old_len in [0, 1, 3] {
+                let end = (offset + old_len).min(source.len());
+                if !source.is_char_boundary(end) {
+                    continue;
+                }
+                for text in texts {
+                    let mut doc = Document::new("<buffer>", source);
+                    doc.edit(offset, end - offset, text);
+                    let expected = Document::new("<buffer>", doc.source());
+                    assert_eq!(dump(&doc), dump(&expected), "{:?} at {}..{}", text, offset, end);
+                    assert_eq!(doc.error(), expected.error());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_edit_reads_changed_forms() {
+        let source: String = (0..100).map(|i| format!("(define x{} {})\n", i, i)).collect();
+        let mut doc = Document::new("<buffer>", &source);
+        assert_eq!(doc.forms().len(), 101);
+        let offset = doc.forms()[50].range().end - 1;
+        // Inserting lines only reads the edited form, the following ones move
+        assert_eq!(doc.edit(offset, 0, "\n\n 5"), 50..51);
+        let last = doc.objects().last().unwrap();
+        assert_eq!(last.loc().unwrap().rol(), 102);
+
+        // An unclosed list reads up to the end
+        assert_eq!(doc.edit(0, 0, "("), 0..1);
+        assert!(matches!(doc.forms()[0].datum(), Some(Err(ParseError::UnclosedList { .. }))));
+        assert_eq!(doc.edit(0, 1, ""), 0..101);
+
+        // The forms after a string left open are lost until it is closed
+        assert_eq!(doc.edit(0, 0, "\""), 0..0);
+        assert!(matches!(doc.error(), Some(LexError::UnterminatedString { .. })));
+        assert_eq!(doc.edit(0, 1, ""), 0..101);
+        assert!(doc.error().is_none());
+    }
+}
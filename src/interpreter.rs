@@ -0,0 +1,730 @@
+use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+
+use crate::diagnostic;
+use crate::error::EvalError;
+use crate::evaluator::{eval, eval_obj, expand, Environment};
+use crate::resolve::resolve;
+use crate::fuel;
+use crate::gc;
+use crate::lexer::tokenize;
+use crate::native::IntoNative;
+use crate::optimize::{self, Warning};
+use crate::parser::{parse, Object};
+use crate::runtime::Frame;
+use crate::vm::Vm;
+
+/// The most calls shown in the backtrace of an error
+const MAX_FRAMES: usize = 20;
+
+/// How the programs are evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    /// Walk the tree of objects, see [`crate::evaluator`]
+    #[default]
+    TreeWalk,
+    /// Compile to bytecode first, see [`crate::vm`]
+    Vm,
+}
+
+/// An embeddable rslisp interpreter holding its own global environment
+///
+/// ```
+/// use rslisp::{Interpreter, Object};
+///
+/// let interp = Interpreter::new();
+/// interp.set_global("x", Object::Integer { value: 41, loc: None });
+/// let result = interp.eval_str("(+ x 1)").unwrap();
+/// assert_eq!(result.to_string(), "42");
+/// ```
+pub struct Interpreter {
+    env: Rc<RefCell<Environment>>,
+    /// The latest source evaluated under each filename, kept for rendering errors
+    sources: RefCell<HashMap<String, String>>,
+    /// Only set for the bytecode backend
+    vm: Option<Vm>,
+    /// Whether the forms are rewritten by [`optimize::optimize`]
+    optimize: bool,
+    /// The warnings of the optimizer, None unless they are requested
+    warnings: Option<RefCell<Vec<Warning>>>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_backend(Backend::TreeWalk)
+    }
+
+    pub fn with_backend(backend: Backend) -> Self {
+        Self {
+            env: Rc::new(RefCell::new(Environment::new(None))),
+            sources: RefCell::new(HashMap::new()),
+            vm: match backend {
+                Backend::TreeWalk => None,
+                Backend::Vm => Some(Vm::new()),
+            },
+            optimize: false,
+            warnings: None,
+        }
+    }
+
+    /// Remove the unused bindings and the dead branches of the
+    /// forms before evaluating them, see [`crate::optimize`]
+    pub fn set_optimize(&mut self, enabled: bool) {
+        self.optimize = enabled;
+    }
+
+    /// Collect what the optimizer finds in the forms evaluated,
+    /// whether or not the forms are optimized
+    pub fn set_warnings(&mut self, enabled: bool) {
+        self.warnings = enabled.then(|| RefCell::new(vec![]));
+    }
+
+    /// The warnings collected since the last call
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        self.warnings.as_ref().map(|warnings| warnings.take()).unwrap_or_default()
+    }
+
+    /// Fail with [`EvalError::RecursionLimit`] once more calls are nested,
+    /// the default is [`crate::runtime::DEFAULT_MAX_DEPTH`]. The stack grows
+    /// as the calls nest, so the limit does not depend on the stack of the thread
+    pub fn set_max_depth(&mut self, depth: usize) {
+        Environment::with_runtime(&self.env, |runtime| runtime.max_depth = depth);
+    }
+
+    /// Run the programs in a sandbox where the builtins acting outside of the
+    /// program, e.g. on the files or the processes, fail with [`EvalError::Forbidden`]
+    /// except the ones in `allowed`, see [`crate::builtins::SIDE_EFFECTS`]
+    ///
+    /// ```
+    /// use rslisp::{EvalError, Interpreter};
+    ///
+    /// let mut interp = Interpreter::new();
+    /// interp.set_sandbox(&["getenv"]);
+    /// assert!(interp.eval_str("(getenv \"HOME\")").is_ok());
+    /// assert!(matches!(interp.eval_str("(system \"ls\")"), Err(EvalError::Forbidden { .. })));
+    /// ```
+    pub fn set_sandbox(&mut self, allowed: &[&str]) {
+        let allowed = allowed.iter().map(|name| name.to_string()).collect();
+        Environment::with_runtime(&self.env, |runtime| runtime.sandbox = Some(allowed));
+    }
+
+    /// Collect what the programs write to the standard output instead of
+    /// printing it, e.g. where there is no terminal, see [`Interpreter::take_output`]
+    pub fn set_capture_output(&mut self, enabled: bool) {
+        let output = enabled.then(|| Rc::new(RefCell::new(String::new())));
+        Environment::with_runtime(&self.env, |runtime| runtime.output = output);
+    }
+
+    /// The output captured since the last call
+    ///
+    /// ```
+    /// use rslisp::Interpreter;
+    ///
+    /// let mut interp = Interpreter::new();
+    /// interp.set_capture_output(true);
+    /// interp.eval_str("(display \"hello\") (newline)").unwrap();
+    /// assert_eq!(interp.take_output(), "hello\n");
+    /// ```
+    pub fn take_output(&self) -> String {
+        Environment::with_runtime(&self.env, |runtime| runtime.output.as_ref().map(|output| output.take()))
+            .unwrap_or_default()
+    }
+
+    /// The global environment the programs are evaluated in
+    pub fn env(&self) -> &Rc<RefCell<Environment>> {
+        &self.env
+    }
+
+    /// Evaluate the source code and return the value of the last expression
+    pub fn eval_str(&self, source: &str) -> Result<Object, EvalError> {
+        self.eval_source("<string>", source)
+    }
+
+    /// Evaluate the source code taking at most `steps` evaluation steps,
+    /// the evaluation fails with [`EvalError::OutOfFuel`] past them so
+    /// untrusted programs can be run for a bounded time, see [`crate::fuel`]
+    ///
+    /// ```
+    /// use rslisp::{EvalError, Interpreter};
+    ///
+    /// let interp = Interpreter::new();
+    /// let result = interp.eval_with_fuel("(define (loop) (loop)) (loop)", 100);
+    /// assert!(matches!(result, Err(EvalError::OutOfFuel { .. })));
+    /// ```
+    pub fn eval_with_fuel(&self, source: &str, steps: u64) -> Result<Object, EvalError> {
+        fuel::with_fuel(steps, || self.eval_str(source))
+    }
+
+    /// Expand the macro calls of the source without running it, only the
+    /// macro definitions and the `set-reader-macro!` calls are evaluated so
+    /// the later forms can use them
+    ///
+    /// ```
+    /// use rslisp::Interpreter;
+    ///
+    /// let interp = Interpreter::new();
+    /// let forms = interp.expand_source("<string>", "(defmacro twice (e) `(begin ,e ,e)) (twice (f))").unwrap();
+    /// interp.set_global("form", forms[1].clone());
+    /// assert_eq!(interp.eval_str("(equal? form '(begin (f) (f)))").unwrap().to_string(), "true");
+    /// ```
+    pub fn expand_source(&self, fname: &str, source: &str) -> Result<Vec<Object>, EvalError> {
+        let forms = match &parse(&mut tokenize(fname, source)?)? {
+            Object::Module { value, .. } => value.to_vec(),
+            obj => vec![obj.clone()],
+        };
+        let mut expanded = Vec::with_capacity(forms.len());
+        for form in forms.iter() {
+            let form = expand(form, &self.env)?;
+            // The macros and the reader macros apply to the following forms
+            if let Object::List { value, .. } = &form {
+                if matches!(value.first(), Some(Object::Symbol { value, .. }) if value == "defmacro" || value == "set-reader-macro!") {
+                    eval_obj(&form, &self.env)?;
+                }
+            }
+            expanded.push(form);
+        }
+        Ok(expanded)
+    }
+
+    /// Read the file and evaluate its content
+    pub fn eval_file<P: AsRef<Path>>(&self, path: P) -> Result<Object, EvalError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| EvalError::Io { path: path.display().to_string(), message: e.to_string() })?;
+        // Requires in the file are looked up next to it
+        Environment::with_runtime(&self.env, |runtime| runtime.files.push(path.to_path_buf()));
+        let result = self.eval_source(path.to_string_lossy().as_ref(), content.as_str());
+        Environment::with_runtime(&self.env, |runtime| runtime.files.pop());
+        result
+    }
+
+    /// Bind a Rust closure as a function in the global environment, the
+    /// number of arguments is checked from the parameters of the closure,
+    /// see [`crate::native`]
+    ///
+    /// ```
+    /// use rslisp::{EvalError, Interpreter, Object};
+    ///
+    /// let interp = Interpreter::new();
+    /// interp.register_fn("count", |args: &[Object]| Ok(Object::Integer { value: args.len() as i128, loc: None }));
+    /// interp.register_fn("twice", |obj: Object| match obj {
+    ///     Object::Integer { value, .. } => Ok(Object::Integer { value: value * 2, loc: None }),
+    ///     obj => Err(EvalError::type_mismatch("an integer", &obj)),
+    /// });
+    /// assert_eq!(interp.eval_str("(twice (count 1 2 3))").unwrap().to_string(), "6");
+    /// assert!(matches!(interp.eval_str("(twice 1 2)"), Err(EvalError::ArityMismatch { .. })));
+    /// ```
+    pub fn register_fn<Args>(&self, name: &str, func: impl IntoNative<Args>) {
+        let func = Object::NativeFunction { value: Rc::new(func.into_native(name)), loc: None };
+        self.env.borrow_mut().set(name, func);
+    }
+
+    /// Call the closure with the datum following `#` and the character
+    /// where it is read, the value returned is evaluated in its place like
+    /// the expansion of a macro. `#{` and `#[` give the list of the data
+    /// up to the closing `}` or `]`
+    ///
+    /// ```
+    /// use rslisp::{EvalError, Interpreter, Object};
+    ///
+    /// let interp = Interpreter::new();
+    /// interp.set_reader_macro('u', |datum: Object| match &datum {
+    ///     Object::Str { value, loc } => Ok(Object::Str { value: value.to_uppercase(), loc: loc.clone() }),
+    ///     obj => Err(EvalError::type_mismatch("a string after #u", obj)),
+    /// });
+    /// assert_eq!(interp.eval_str("(string-append #u\"ab\" \"c\")").unwrap().to_string(), "ABc");
+    /// ```
+    pub fn set_reader_macro<Args>(&self, c: char, func: impl IntoNative<Args>) {
+        let func = Object::NativeFunction { value: Rc::new(func.into_native(&format!("#{}", c))), loc: None };
+        Environment::with_runtime(&self.env, |runtime| runtime.reader_macros.insert(c, func));
+    }
+
+    /// Look a variable up from the global environment
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.env.borrow().get(name)
+    }
+
+    /// Bind a variable in the global environment
+    pub fn set_global(&self, name: &str, value: Object) {
+        self.env.borrow_mut().set(name, value);
+    }
+
+    /// Free the environments of the closures no longer reachable,
+    /// see [`crate::gc::collect`]. Return the number of environments freed
+    pub fn collect_garbage(&self) -> usize {
+        gc::collect(&self.env)
+    }
+
+    /// Render the error with an excerpt of the source it comes from,
+    /// see [`diagnostic::render`]. Files evaluated by the program itself,
+    /// e.g. with `load`, are read again to render the excerpt
+    pub fn render_error(&self, err: &EvalError) -> String {
+        let sources = self.sources.borrow();
+        let source = match err.loc() {
+            Some(loc) => match sources.get(loc.filename()) {
+                Some(source) => source.clone(),
+                None => std::fs::read_to_string(loc.filename()).unwrap_or_default(),
+            },
+            None => String::new(),
+        };
+        let mut rendered = diagnostic::render(err, source.as_str());
+        let backtrace = self.backtrace();
+        if !backtrace.is_empty() {
+            rendered.push_str("\nbacktrace:");
+            // The calls in the middle of a deep recursion are left out
+            for (i, frame) in backtrace.iter().enumerate() {
+                if i == MAX_FRAMES / 2 && backtrace.len() > MAX_FRAMES {
+                    rendered.push_str(&format!("\n  ... {} calls omitted", backtrace.len() - MAX_FRAMES));
+                }
+                if i < MAX_FRAMES / 2 || i >= backtrace.len().saturating_sub(MAX_FRAMES / 2) {
+                    rendered.push_str(&format!("\n  {}: {}", i, frame));
+                }
+            }
+        }
+        rendered
+    }
+
+    /// The calls the latest error of the evaluated programs unwound
+    /// through, the innermost call first
+    pub fn backtrace(&self) -> Vec<Frame> {
+        Environment::with_runtime(&self.env, |runtime| runtime.backtrace.clone())
+    }
+
+    fn eval_source(&self, fname: &str, source: &str) -> Result<Object, EvalError> {
+        Environment::with_runtime(&self.env, |runtime| runtime.backtrace.clear());
+        self.sources.borrow_mut().insert(fname.to_string(), source.to_string());
+        let mut tokens = tokenize(fname, source)?;
+        let program = parse(&mut tokens)?;
+        let forms = match &program {
+            Object::Module { value, .. } if self.vm.is_some() || self.optimize || self.warnings.is_some() => value.clone(),
+            _ => return eval(program, &self.env),
+        };
+
+        let mut result = Object::Void { loc: None };
+        for form in forms.iter() {
+            let form = self.rewrite(form)?;
+            result = match &self.vm {
+                Some(vm) => vm.eval(&form, &self.env)?,
+                None => eval_obj(&resolve(&form), &self.env)?,
+            };
+        }
+        Ok(result)
+    }
+
+    /// Expand the top level form then run the optimizer over it if requested
+    fn rewrite(&self, form: &Object) -> Result<Object, EvalError> {
+        let form = expand(form, &self.env)?;
+        if !self.optimize && self.warnings.is_none() {
+            return Ok(form);
+        }
+        let mut warnings = vec![];
+        let optimized = optimize::optimize(&form, &mut warnings);
+        if let Some(collected) = &self.warnings {
+            collected.borrow_mut().extend(warnings);
+        }
+        Ok(if self.optimize { optimized } else { form })
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format;
+    use crate::parser::{ListItems, Written};
+    use crate::runtime::DEFAULT_MAX_DEPTH;
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("rslisp_load_{}.rsl", std::process::id()));
+        std::fs::write(&path, "(define (square x) (* x x))\n(square 3)").unwrap();
+
+        let interp = Interpreter::new();
+        interp.set_global("path", Object::Str { value: path.to_string_lossy().to_string(), loc: None });
+        let result = interp.eval_str("(load path)").unwrap();
+        assert!(matches!(result, Object::Integer { value: 9, .. }));
+        assert!(matches!(interp.eval_str("(square 4)").unwrap(), Object::Integer { value: 16, .. }));
+
+        // The error points into the loaded file
+        std::fs::write(&path, "(define x 1)\n(car x x)").unwrap();
+        let err = interp.eval_str("(load path)").unwrap_err();
+        assert!(interp.render_error(&err).contains("2 | (car x x)"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(interp.eval_str("(load path)"), Err(EvalError::Io { .. })));
+    }
+
+    #[test]
+    fn test_require() {
+        let dir = std::env::temp_dir().join(format!("rslisp_require_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("main.rsl"), "(define count 0)\n(require 'lib/util)\n(require \"lib/util.rsl\")\n(list count (twice 2))").unwrap();
+        // Requires inside the module are relative to the module itself
+        std::fs::write(dir.join("lib/util.rsl"), "(require 'helper)\n(set! count (+ count 1))\n(define (twice x) (double x))").unwrap();
+        std::fs::write(dir.join("lib/helper.rsl"), "(define (double x) (* 2 x))").unwrap();
+
+        let interp = Interpreter::new();
+        let result = interp.eval_file(dir.join("main.rsl")).unwrap();
+        match &result {
+            // The module is evaluated once
+            Object::List { value, .. } => assert!(matches!(value[..], [
+                Object::Integer { value: 1, .. }, Object::Integer { value: 4, .. }
+            ])),
+            obj => panic!("Expect (1 4) but {} found", obj),
+        }
+        assert!(matches!(interp.eval_str("(require 'missing)"), Err(EvalError::ModuleNotFound { .. })));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_config() {
+        let dir = std::env::temp_dir().join(format!("rslisp_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.toml"), "name = \"rslisp\"\n[server]\nports = [80, 443]\nratio = 0.5\n").unwrap();
+        std::fs::write(dir.join("a.yaml"), "name: rslisp\nserver:\n  ports: [80, 443]\n  debug: true\n  user: ~\n").unwrap();
+        std::fs::write(dir.join("bad.toml"), "name = ").unwrap();
+        std::fs::write(dir.join("bad.yaml"), "a: [1").unwrap();
+
+        let interp = Interpreter::new();
+        interp.set_global("dir", Object::Str { value: dir.to_string_lossy().to_string(), loc: None });
+        for reader in ["read-toml", "read-yaml"] {
+            let ext = &reader[5..];
+            let prog = format!("(define config ({} (string-append dir \"/a.{}\")))", reader, ext);
+            interp.eval_str(&prog).unwrap();
+            assert_eq!(interp.eval_str("(hash-ref config \"name\")").unwrap().to_string(), "rslisp");
+            let ports = interp.eval_str("(hash-ref (hash-ref config \"server\") \"ports\")").unwrap();
+            assert_eq!(ports.to_string(), "#(80 443)");
+
+            let prog = format!("({} (string-append dir \"/bad.{}\"))", reader, ext);
+            assert!(matches!(interp.eval_str(&prog), Err(EvalError::Decode { .. })), "{}", prog);
+            let prog = format!("({} (string-append dir \"/missing.{}\"))", reader, ext);
+            assert!(matches!(interp.eval_str(&prog), Err(EvalError::Io { .. })), "{}", prog);
+        }
+        assert_eq!(interp.eval_str("(hash-ref (hash-ref config \"server\") \"debug\")").unwrap().to_string(), "true");
+        assert_eq!(interp.eval_str("(hash-ref (hash-ref config \"server\") \"user\")").unwrap().to_string(), "null");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ports() {
+        let path = std::env::temp_dir().join(format!("rslisp_ports_{}.txt", std::process::id()));
+        let interp = Interpreter::new();
+        interp.set_global("path", Object::Str { value: path.to_string_lossy().to_string(), loc: None });
+        interp.eval_str("(define out (open-output-file path))\n\
+                         (write-string \"first\nsecond\" out)\n\
+                         (newline out)\n\
+                         (write \"third\" out)\n\
+                         (newline out)\n\
+                         (display \"fourth\" out)\n\
+                         (close-port out)\n\
+                         (close-port out)").unwrap();
+
+        interp.eval_str("(define in (open-input-file path))").unwrap();
+        assert_eq!(interp.eval_str("(read-line in)").unwrap().to_string(), "first");
+        assert_eq!(interp.eval_str("(read-line in)").unwrap().to_string(), "second");
+        assert_eq!(interp.eval_str("(read-line in)").unwrap().to_string(), "\"third\"");
+        assert_eq!(interp.eval_str("(read-line in)").unwrap().to_string(), "fourth");
+        assert!(matches!(interp.eval_str("(eof-object? (read-line in))").unwrap(), Object::Bool { value: true, .. }));
+        interp.eval_str("(close-port in)").unwrap();
+        assert!(matches!(interp.eval_str("(read-line in)"), Err(EvalError::Io { .. })));
+        assert!(matches!(interp.eval_str("(write-string \"x\" in)"), Err(EvalError::TypeMismatch { .. })));
+        assert!(matches!(interp.eval_str("(display 1 out)"), Err(EvalError::Io { .. })));
+
+        // read takes a datum spanning several lines
+        std::fs::write(&path, "(define\n  x 1) ;; comment\n'y").unwrap();
+        interp.eval_str("(define in (open-input-file path))").unwrap();
+        assert!(matches!(&interp.eval_str("(read in)").unwrap(), Object::List { value, .. } if value.len() == 3));
+        assert_eq!(interp.eval_str("(car (cdr (read in)))").unwrap().to_string(), "y");
+        assert!(matches!(interp.eval_str("(read in)").unwrap(), Object::Eof { .. }));
+        std::fs::write(&path, "(1 2").unwrap();
+        assert!(matches!(interp.eval_str("(read (open-input-file path))"), Err(EvalError::Parse(_))));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(interp.eval_str("(open-input-file path)"), Err(EvalError::Io { .. })));
+        assert!(matches!(interp.eval_str("(eof-object? \"\")").unwrap(), Object::Bool { value: false, .. }));
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut interp = Interpreter::new();
+        interp.set_optimize(true);
+        interp.set_warnings(true);
+        interp.eval_str("(defmacro unless (c x) `(if ,c #f ,x))").unwrap();
+        let result = interp.eval_str("(define (f) (let ((x 1)) (unless #t x)))\n(f)").unwrap();
+        assert!(matches!(result, Object::Bool { value: false, .. }));
+        let warnings: Vec<_> = interp.take_warnings().into_iter().map(|w| w.message).collect();
+        assert_eq!(warnings, ["unreachable if branch", "unused variable `x`"]);
+        assert!(interp.take_warnings().is_empty());
+
+        // Without optimizing the warnings are still reported
+        let mut interp = Interpreter::new();
+        interp.set_warnings(true);
+        assert!(interp.eval_str("(let ((x (car '()))) 1)").is_err());
+        assert_eq!(interp.take_warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let prog = "(define (inner x) (/ x 0))\n(define (outer x) (+ 1 (inner x)))\n(outer 5)";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let interp = Interpreter::with_backend(backend);
+            let err = interp.eval_str(prog).unwrap_err();
+            let names: Vec<_> = interp.backtrace().into_iter().map(|frame| frame.name).collect();
+            assert_eq!(names, ["/", "inner", "outer"], "{:?}", backend);
+            assert_eq!(interp.backtrace()[2].loc.as_ref().unwrap().rol(), 3);
+            assert!(interp.render_error(&err).contains("backtrace:\n  0: / at <string>:1:"));
+
+            // Handled errors and successful evaluations leave no backtrace
+            interp.eval_str("(guard (e (#t 0)) (outer 1))").unwrap();
+            assert!(interp.backtrace().is_empty());
+            assert!(interp.eval_str("(guard (e ((string? e) 0)) (outer 1))").is_err());
+            assert_eq!(interp.backtrace().len(), 3);
+            interp.eval_str("1").unwrap();
+            assert!(interp.backtrace().is_empty());
+        }
+
+        // Deep recursions are shortened
+        let interp = Interpreter::new();
+        let err = interp.eval_str("(define (f n) (if (= n 0) (car n) (+ 1 (f (- n 1)))))\n(f 100)").unwrap_err();
+        assert_eq!(interp.backtrace().len(), 102);
+        let rendered = interp.render_error(&err);
+        assert!(rendered.contains("  ... 82 calls omitted\n  92: f"));
+        assert_eq!(rendered.lines().filter(|line| line.contains(": f at")).count(), 19);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let prog = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut interp = Interpreter::with_backend(backend);
+            interp.set_max_depth(50);
+            interp.eval_str(prog).unwrap();
+            assert!(matches!(interp.eval_str("(f 40)").unwrap(), Object::Integer { value: 40, .. }));
+            match interp.eval_str("(f 100)") {
+                Err(EvalError::RecursionLimit { limit: 50, loc: Some(_) }) => {},
+                other => panic!("Expect RecursionLimit but {:?} found", other),
+            }
+            assert!(interp.backtrace().len() > 50);
+
+            // The calls unwound are not counted anymore
+            assert!(matches!(interp.eval_str("(guard (e (#t 'caught)) (f 100))").unwrap(), Object::Symbol { .. }));
+            assert!(matches!(interp.eval_str("(f 45)").unwrap(), Object::Integer { value: 45, .. }));
+            assert!(interp.eval_str("(map f '(1 60))").is_err());
+            assert!(matches!(interp.eval_str("(f 45)").unwrap(), Object::Integer { value: 45, .. }));
+        }
+    }
+
+    #[test]
+    fn test_depth_on_caller_stack() {
+        // The tests run on threads with small stacks
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let interp = Interpreter::with_backend(backend);
+            let prog = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1))))) (f 4000)";
+            assert!(matches!(interp.eval_str(prog).unwrap(), Object::Integer { value: 4000, .. }));
+            for prog in ["(define (g) (+ 1 (g))) (g)", "(defmacro m () '(m)) (m)", "(defmacro n () '(begin (n))) (n)"] {
+                match interp.eval_str(prog) {
+                    Err(EvalError::RecursionLimit { limit: DEFAULT_MAX_DEPTH, .. }) => {},
+                    other => panic!("Expect RecursionLimit but {:?} found", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // The nested data is read, printed, compared and dropped on more
+        // stack when needed, whatever the depth
+        let source = format!("{}{}", "(\n".repeat(5000), ")".repeat(5000));
+        let written = format!("{}{}", "(".repeat(5000), ")".repeat(5000));
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut interp = Interpreter::with_backend(backend);
+            interp.set_capture_output(true);
+            let prog = format!("(define a '{}) (define b `{})
+                                (define c (let loop ((n 4999) (c '())) (if (= n 0) c (loop (- n 1) (list c)))))
+                                (define v (let loop ((n 5000) (v '())) (if (= n 0) v (loop (- n 1) (vector v)))))
+                                (write a) (and (equal? a b) (equal? a c) (equal? v v))", source, source);
+            assert!(matches!(interp.eval_str(&prog).unwrap(), Object::Bool { value: true, .. }));
+            assert_eq!(interp.take_output(), written);
+            interp.eval_str("(set! a #f) (set! b #f) (set! c #f) (set! v #f)").unwrap();
+
+            let prog = "(define (build n acc) (if (= n 0) acc (build (- n 1) (cons n acc))))
+                        (define d (build 20000 '())) (write d) (equal? d (build 20000 '()))";
+            assert!(matches!(interp.eval_str(prog).unwrap(), Object::Bool { value: true, .. }));
+            assert_eq!(interp.take_output().len(), 108895);
+        }
+
+        let forms = Interpreter::new().expand_source("<test>", &format!("'{}", source)).unwrap();
+        assert_eq!(format::format_forms(&forms), format!("'{}\n", written));
+        assert_eq!(format::format("<test>", &source).unwrap(), format!("{}\n", written));
+    }
+
+    #[test]
+    fn test_fuel() {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let interp = Interpreter::with_backend(backend);
+            interp.eval_str("(define (count n) (if (= n 0) 'done (count (- n 1))))").unwrap();
+            assert!(matches!(interp.eval_with_fuel("(count 10)", 1000).unwrap(), Object::Symbol { .. }));
+            assert!(matches!(interp.eval_with_fuel("(count 100)", 1000), Err(EvalError::OutOfFuel { .. })));
+            // The program cannot catch it and the fuel only bounds the given evaluation
+            let prog = "(guard (e (#t (count 100))) (count 100))";
+            assert!(matches!(interp.eval_with_fuel(prog, 1000), Err(EvalError::OutOfFuel { .. })));
+            assert!(matches!(interp.eval_str("(count 100)").unwrap(), Object::Symbol { .. }));
+
+            // The loops run out of fuel on the stack of the caller
+            for prog in ["(let loop ((i 0)) (loop (+ i 1)))", "(define (f) (f)) (f)"] {
+                assert!(matches!(interp.eval_with_fuel(prog, 100_000), Err(EvalError::OutOfFuel { .. })), "{}", prog);
+            }
+            let prog = "(define (g) (+ 1 (g))) (g)";
+            assert!(matches!(interp.eval_with_fuel(prog, 1000), Err(EvalError::OutOfFuel { .. })));
+            assert!(matches!(interp.eval_with_fuel(prog, 1_000_000), Err(EvalError::RecursionLimit { .. })));
+        }
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let mut interp = Interpreter::new();
+        interp.set_sandbox(&["getenv"]);
+        for prog in ["(open-input-file \"Cargo.toml\")", "(load \"lib.rsl\")", "(require 'lib)", "(setenv \"X\" \"1\")",
+                     "(run-process \"true\" '())", "(sleep 10)", "(current-time)", "(current-milliseconds)", "(read)",
+                     // Builtins reached through another name or through eval are disabled as well
+                     "(define run system)\n(run \"true\")", "(eval '(system \"true\") (interaction-environment))"] {
+            assert!(matches!(interp.eval_str(prog), Err(EvalError::Forbidden { .. })), "{}", prog);
+        }
+        assert!(interp.eval_str("(getenv \"PATH\")").is_ok());
+        assert!(interp.eval_str("(display \"\")").is_ok());
+        let prog = "(guard (e ((error-object? e) (error-object-message e))) (system \"true\"))";
+        assert_eq!(interp.eval_str(prog).unwrap().to_string(), "system is not allowed in the sandbox");
+    }
+
+    #[test]
+    fn test_register_fn() {
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let interp = Interpreter::with_backend(backend);
+            interp.register_fn("sum", |args: &[Object]| {
+                let mut sum = 0;
+                for arg in args {
+                    match arg {
+                        Object::Integer { value, .. } => sum += value,
+                        obj => return Err(EvalError::type_mismatch("an integer", obj)),
+                    }
+                }
+                Ok(Object::Integer { value: sum, loc: None })
+            });
+            interp.register_fn("pair", |a: Object, b: Object| Ok(Object::List { value: ListItems::new(vec![a, b]), loc: None }));
+            // A registered function replaces the builtin of the same name
+            interp.register_fn("car", |_: Object| Ok(Object::Str { value: "mine".into(), loc: None }));
+
+            assert_eq!(interp.eval_str("(sum 1 2 (sum 3 4))").unwrap().to_string(), "10");
+            assert_eq!(interp.eval_str("(sum)").unwrap().to_string(), "0");
+            assert_eq!(interp.eval_str("(car (map sum '(1 2)))").unwrap().to_string(), "mine");
+            assert_eq!(interp.eval_str("(equal? (cdr (pair 1 2)) (cdr (list 1 2)))").unwrap().to_string(), "true");
+            assert!(matches!(interp.eval_str("(sum 1 \"2\")"), Err(EvalError::TypeMismatch { .. })));
+            let err = interp.eval_str("(pair 1)").unwrap_err();
+            assert_eq!(err.message(), "Expect 2 arguments but 1 given when calling pair");
+        }
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let mut interp = Interpreter::new();
+        interp.set_capture_output(true);
+        interp.eval_str("(display 1) (write \"a\") (println 2 3) (write-string \"b\" (current-output-port))").unwrap();
+        interp.eval_str("(define-test \"t\" (assert #t)) (run-tests)").unwrap();
+        assert_eq!(interp.take_output(), "1\"a\"2 3\nbtest t ... ok\n1 passed; 0 failed\n");
+        assert_eq!(interp.take_output(), "");
+        interp.set_capture_output(false);
+        assert_eq!(interp.take_output(), "");
+    }
+
+    #[test]
+    fn test_write_read_back() {
+        let mut interp = Interpreter::new();
+        interp.set_capture_output(true);
+        let obj = interp.eval_str("(define obj (list 1 2.0 \"a\\nb\" #\\space 'c (vector 'd \"e\") (cons 'f 'g))) (write obj) obj").unwrap();
+        let written = interp.take_output();
+        let read = interp.eval_str(&format!("'{}", written)).unwrap();
+        assert!(obj.is_equal(&read), "{}", written);
+
+        // Void has no written form, so it is not written as a symbol
+        for prog in ["(write (list 1 (when #f 2)))", "(write (vector (cons 1 (when #f 2))))", "(format \"~s\" (list (when #f 2)))"] {
+            let err = interp.eval_str(prog).unwrap_err();
+            assert_eq!(err.label(), "invalid argument", "{}", prog);
+        }
+        assert_eq!(interp.take_output(), "");
+    }
+
+    #[test]
+    fn test_pp() {
+        let mut interp = Interpreter::new();
+        interp.set_capture_output(true);
+        interp.eval_str("(pp '(a \"b\")) (pp (map (lambda (i) (list 'item i (* i 1000000))) '(1 2 3 4 5)))").unwrap();
+        let expected = "(a \"b\")\n\
+                        ((item 1 1000000)\n \
+                         (item 2 2000000)\n \
+                         (item 3 3000000)\n \
+                         (item 4 4000000)\n \
+                         (item 5 5000000))\n";
+        assert_eq!(interp.take_output(), expected);
+    }
+
+    #[test]
+    fn test_trace() {
+        let prog = "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))\n\
+                    (define (last l) (if (null? (cdr l)) (car l) (last (cdr l))))\n\
+                    (trace fact last)\n\
+                    (fact 2) (last '(\"a\" \"b\"))\n\
+                    (untrace fact)\n\
+                    (fact 3)";
+        let expected = "[1] (fact 2)\n  [2] (fact 1)\n    [3] (fact 0)\n    [3] => 1\n  [2] => 1\n[1] => 2\n\
+                        [1] (last (\"a\" \"b\"))\n  [2] (last (\"b\"))\n  [2] => \"b\"\n[1] => \"b\"\n";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut interp = Interpreter::with_backend(backend);
+            interp.set_capture_output(true);
+            assert_eq!(interp.eval_str(prog).unwrap().to_string(), "6");
+            assert_eq!(interp.take_output(), expected, "{:?}", backend);
+
+            // The errors are printed on the way out
+            interp.eval_str("(define (f x) (car x)) (trace f)").unwrap();
+            assert!(interp.eval_str("(f 1)").is_err());
+            assert!(interp.take_output().starts_with("[1] (f 1)\n[1] raised "));
+            interp.eval_str("(untrace) (f '(1))").unwrap();
+            assert_eq!(interp.take_output(), "");
+        }
+        assert!(Interpreter::new().eval_str("(trace car)").is_err());
+    }
+
+    #[test]
+    fn test_print_cycles() {
+        let interp = Interpreter::new();
+        let prog = "(define h (make-hash)) (hash-set! h 'self h)\n\
+                    (define-record-type node (make-node next) node? (next node-next set-node-next!))\n\
+                    (define n (make-node 1)) (set-node-next! n (list \"a\" n h))";
+        interp.eval_str(prog).unwrap();
+        assert_eq!(interp.eval_str("h").unwrap().to_string(), "#0=#hash((self . #0#))");
+        assert_eq!(interp.eval_str("n").unwrap().to_string(), "#0=#<node (a #0# #1=#hash((self . #1#)))>");
+        let n = interp.eval_str("n").unwrap();
+        assert_eq!(Written(&n).to_string(), "#0=#<node (\"a\" #0# #1=#hash((self . #1#)))>");
+        assert_eq!(format::pretty_print(&interp.eval_str("(list h h)").unwrap(), 80), "(#0=#hash((self . #0#)) #0#)");
+        assert_eq!(format::pretty_print(&interp.eval_str("(vector n)").unwrap(), 80), "#(#0=#<node (\"a\" #0# #1=#hash((self . #1#)))>)");
+
+        // Shared data without cycle is printed as many times as it is held
+        interp.eval_str("(define empty (make-hash))").unwrap();
+        assert_eq!(interp.eval_str("(list empty empty)").unwrap().to_string(), "(#hash() #hash())");
+    }
+
+    #[test]
+    fn test_globals() {
+        let interp = Interpreter::new();
+        interp.eval_str("(define double (lambda (x) (+ x x)))").unwrap();
+        interp.set_global("n", Object::Integer { value: 21, loc: None });
+        interp.eval_str("(define result (double n))").unwrap();
+
+        let result = interp.get_global("result").unwrap();
+        assert!(matches!(result, Object::Integer { value: 42, .. }));
+        assert!(interp.get_global("missing").is_none());
+    }
+}
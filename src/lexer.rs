@@ -1,36 +1,67 @@
-use std::collections::VecDeque;
+use std::{
+    collections::VecDeque,
+    iter::Peekable,
+    ops::Range,
+    str::Chars,
+};
 
 use nom::{
     branch::alt,
     bytes::complete::{tag, take, take_till, take_till1, take_while1},
-    multi::fold_many0,
+    character::complete::digit1,
+    combinator::{not, opt, recognize},
     number::complete::recognize_float,
-    sequence::preceded,
-    IResult,
+    sequence::{delimited, pair, preceded},
+    IResult, Slice,
 };
 use nom_locate::{position, LocatedSpan};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
 
+use crate::error::LexError;
 use crate::location::Location;
 
 type Span<'a> = LocatedSpan<&'a str>;
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     LeftParenthesis,
     RightParenthesis,
+    /// `#(` which opens a vector literal
+    VectorParenthesis,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
     Integer(i128),
+    BigInteger(BigInt),
+    Rational(BigRational),
     Float(f64),
+    Bool(bool),
+    Char(char),
     Str(String),
     Symbol(String),
+    /// `:name`, holding the name without the colon
+    Keyword(String),
     Comment(String),
+    /// `#;` which comments out the following expression
+    DatumComment,
+    /// `#` and the character dispatching to a reader macro, e.g. `#d`,
+    /// `#{` and `#[` open a list closed by `}` and `]`
+    Dispatch(char),
+    /// `}` or `]`, closing the list opened by `#{` or `#[`
+    ClosingBracket(char),
     IGNORE,
     UNKNOWN,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     loc: Location,
     kind: TokenKind,
+    range: Range<usize>,
 }
 
 impl Token {
@@ -40,24 +71,114 @@ impl Token {
     pub fn kind(&self) -> &TokenKind {
         &self.kind
     }
+    /// The bytes of the content the token is read from
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// The same token read at another place of the content
+    pub(crate) fn moved(self, range: Range<usize>, loc: Location) -> Self {
+        Self { loc, range, ..self }
+    }
 }
 
 /// match a &str into left-parenthese or right-parenthese token
 fn match_paren(s: Span) -> IResult<Span, TokenKind> {
-    let (s, result) = alt((tag("("), tag(")")))(s)?;
+    let (s, result) = alt((tag("("), tag(")"), tag("#(")))(s)?;
     let kind = match *result.fragment() {
         "(" => TokenKind::LeftParenthesis,
+        "#(" => TokenKind::VectorParenthesis,
         ")" => TokenKind::RightParenthesis,
         _ => TokenKind::UNKNOWN,
     };
     Ok((s, kind))
 }
 
+/// match a &str into quote, quasiquote, unquote or unquote-splicing token
+fn match_quote(s: Span) -> IResult<Span, TokenKind> {
+    let (s, result) = alt((tag("'"), tag("`"), tag(",@"), tag(",")))(s)?;
+    let kind = match *result.fragment() {
+        "'" => TokenKind::Quote,
+        "`" => TokenKind::Quasiquote,
+        ",@" => TokenKind::UnquoteSplicing,
+        "," => TokenKind::Unquote,
+        _ => TokenKind::UNKNOWN,
+    };
+    Ok((s, kind))
+}
+
+/// match the sign and radix prefix of `#x-1F` into (sign, radix character)
+fn match_hash_radix(s: Span) -> IResult<Span, (Option<Span>, Span)> {
+    let (s, prefix) = preceded(tag("#"), alt((tag("x"), tag("o"), tag("b"))))(s)?;
+    let (s, sign) = opt(alt((tag("-"), tag("+"))))(s)?;
+    Ok((s, (sign, prefix)))
+}
+
+/// match the sign and radix prefix of `-0x1F` into (sign, radix character)
+fn match_zero_radix(s: Span) -> IResult<Span, (Option<Span>, Span)> {
+    let (s, sign) = opt(alt((tag("-"), tag("+"))))(s)?;
+    let (s, prefix) = preceded(tag("0"), alt((tag("x"), tag("o"), tag("b"))))(s)?;
+    Ok((s, (sign, prefix)))
+}
+
+/// match a &str into integer token written in hexadecimal, octal or binary,
+/// either with the `#x`, `#o`, `#b` prefix or the `0x`, `0o`, `0b` prefix
+fn match_radix_integer(s: Span) -> IResult<Span, TokenKind> {
+    let (s, (sign, prefix)) = alt((match_hash_radix, match_zero_radix))(s)?;
+    let (s, digits) = take_till1(is_delimiter)(s)?;
+
+    let radix = match *prefix.fragment() {
+        "x" => 16,
+        "o" => 8,
+        _ => 2,
+    };
+    let negative = sign.map(|sign| *sign.fragment() == "-").unwrap_or(false);
+    let kind = match BigInt::parse_bytes(digits.fragment().as_bytes(), radix) {
+        Some(num) if negative => integer_kind(-num),
+        Some(num) => integer_kind(num),
+        None => TokenKind::UNKNOWN,
+    };
+    Ok((s, kind))
+}
+
+/// Integers which do not fit in i128 become BigInteger tokens
+fn integer_kind(value: BigInt) -> TokenKind {
+    match value.to_i128() {
+        Some(n) => TokenKind::Integer(n),
+        None => TokenKind::BigInteger(value),
+    }
+}
+
+/// match a &str into rational token like `-1/3`, the value is reduced
+/// and becomes an integer token when the denominator is 1, e.g. `4/2`
+fn match_rational(s: Span) -> IResult<Span, TokenKind> {
+    let (s, numer) = recognize(pair(opt(alt((tag("-"), tag("+")))), digit1))(s)?;
+    let (s, denom) = preceded(tag("/"), digit1)(s)?;
+    // `1/2x` is not a rational
+    let (s, _) = not(take_till1(is_delimiter))(s)?;
+
+    let numer = numer.fragment().parse::<BigInt>();
+    let denom = denom.fragment().parse::<BigInt>();
+    let kind = match (numer, denom) {
+        (Ok(_), Ok(denom)) if denom.is_zero() => TokenKind::UNKNOWN,
+        (Ok(numer), Ok(denom)) => {
+            let value = BigRational::new(numer, denom);
+            if value.is_integer() {
+                integer_kind(value.to_integer())
+            } else {
+                TokenKind::Rational(value)
+            }
+        },
+        _ => TokenKind::UNKNOWN,
+    };
+    Ok((s, kind))
+}
+
 /// match a &str into integer or float token
 fn match_numeric(s: Span) -> IResult<Span, TokenKind> {
     let (s, result) = recognize_float(s)?;
-    let kind = if let Ok(num) = result.fragment().parse::<i128>() {
-        TokenKind::Integer(num)
+    let kind = if let Ok(num) = result.fragment().parse::<BigInt>() {
+        integer_kind(num)
     } else if let Ok(num) = result.fragment().parse::<f64>() {
         TokenKind::Float(num)
     } else {
@@ -66,12 +187,81 @@ fn match_numeric(s: Span) -> IResult<Span, TokenKind> {
     Ok((s, kind))
 }
 
-/// match a &str into String token
+/// match a &str into boolean token, `#t`, `#true` and `true` are true
+/// while `#f`, `#false` and `false` are false
+fn match_bool(s: Span) -> IResult<Span, TokenKind> {
+    let (rest, result) = take_till1(is_delimiter)(s)?;
+    let kind = match *result.fragment() {
+        "#t" | "#true" | "true" => TokenKind::Bool(true),
+        "#f" | "#false" | "false" => TokenKind::Bool(false),
+        _ => return Err(nom::Err::Error(nom::error::Error::new(s, nom::error::ErrorKind::Tag))),
+    };
+    Ok((rest, kind))
+}
+
+/// match a &str into character token, e.g. `#\a`, `#\space` or `#\newline`
+fn match_char(s: Span) -> IResult<Span, TokenKind> {
+    let (s, _) = tag("#\\")(s)?;
+    // The first character is taken even if it is a delimiter, e.g. `#\(`
+    let (s, first) = take(1usize)(s)?;
+    let (s, rest) = take_till(is_delimiter)(s)?;
+    if rest.fragment().is_empty() {
+        let c = first.fragment().chars().next().unwrap_or_default();
+        return Ok((s, TokenKind::Char(c)));
+    }
+
+    let name = format!("{}{}", first.fragment(), rest.fragment());
+    let kind = match name.as_str() {
+        "space" => TokenKind::Char(' '),
+        "newline" | "linefeed" => TokenKind::Char('\n'),
+        "tab" => TokenKind::Char('\t'),
+        "return" => TokenKind::Char('\r'),
+        "nul" | "null" => TokenKind::Char('\0'),
+        "alarm" => TokenKind::Char('\x07'),
+        "backspace" => TokenKind::Char('\x08'),
+        "escape" => TokenKind::Char('\x1b'),
+        "delete" => TokenKind::Char('\x7f'),
+        _ => TokenKind::UNKNOWN,
+    };
+    Ok((s, kind))
+}
+
+/// match `#` followed by a character and a delimiter, or `#{` and `#[`,
+/// into the dispatch token of a reader macro, e.g. `#d"2024-01-01"`.
+/// `#define` is still a symbol
+fn match_dispatch(s: Span) -> IResult<Span, TokenKind> {
+    let (rest, c) = preceded(tag("#"), take(1usize))(s)?;
+    let c = c.fragment().chars().next().unwrap_or_default();
+    let delimited = rest.fragment().chars().next().is_none_or(is_delimiter);
+    match c {
+        '{' | '[' => Ok((rest, TokenKind::Dispatch(c))),
+        _ if !is_delimiter(c) && c != '#' && delimited => Ok((rest, TokenKind::Dispatch(c))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(s, nom::error::ErrorKind::Tag))),
+    }
+}
+
+/// match `}` or `]` into the closing bracket token
+fn match_closing_bracket(s: Span) -> IResult<Span, TokenKind> {
+    let (s, result) = alt((tag("}"), tag("]")))(s)?;
+    Ok((s, TokenKind::ClosingBracket(result.fragment().chars().next().unwrap_or_default())))
+}
+
+/// match a &str into String token, a string holding an invalid escape
+/// sequence does not match
 fn match_string(s: Span) -> IResult<Span, TokenKind> {
     let (s, _) = tag("\"")(s)?;
-    let (string, true_size) = match_string_helper(s.fragment());
+    let Ok((string, true_size)) = match_string_helper(s.fragment()) else {
+        return Err(nom::Err::Error(nom::error::Error::new(s, nom::error::ErrorKind::Escaped)));
+    };
     let (s, _) = take(true_size)(s)?;
     let (s, _) = tag("\"")(s)?;
+    Ok((s, TokenKind::Str(string)))
+}
+
+/// match a `#r"C:\path"` raw string into String token, the characters up
+/// to the closing quote are taken as they are, backslashes included
+fn match_raw_string(s: Span) -> IResult<Span, TokenKind> {
+    let (s, string) = delimited(tag("#r\""), take_till(|c: char| c == '"'), tag("\""))(s)?;
     Ok((s, TokenKind::Str(string.to_string())))
 }
 
@@ -80,19 +270,16 @@ fn match_string(s: Span) -> IResult<Span, TokenKind> {
 /// Since the transformed string should contain less character than that
 /// of the original string. The length of the returned string should be
 /// different from the second element of the returned tuple
-fn match_string_helper(rest: &str) -> (String, usize) {
+/// The escape sequences are `\n`, `\t`, `\r`, `\\`, `\"`, `\0` and
+/// `\u{1F600}`, the characters of the first invalid one are the error
+fn match_string_helper(rest: &str) -> Result<(String, usize), Range<usize>> {
     // string will copy the character and transform the escape character
     let mut string = String::new();
     // This is a counter that is going to skip
     let mut counter = 0;
     let mut peekable = rest.chars().peekable();
-    loop {
-        let current_char = if let Some(char) = peekable.next_if(|&x| x != '"') {
-            char
-        } else {
-            break;
-        };
-
+    while let Some(current_char) = peekable.next_if(|&x| x != '"') {
+        let start = counter;
         // update the counter
         counter += 1;
 
@@ -102,21 +289,70 @@ fn match_string_helper(rest: &str) -> (String, usize) {
         }
 
         // Handle the character after
-        if let Some(next_char) = peekable.next() {
-            counter += 1;
-            string.push(next_char);
-        } else {
+        let Some(next_char) = peekable.next() else {
             break;
+        };
+        counter += 1;
+        let escaped = match next_char {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' | '"' => Some(next_char),
+            'u' => {
+                let (c, size) = match_unicode_escape(&mut peekable);
+                counter += size;
+                c
+            },
+            _ => None,
+        };
+        match escaped {
+            Some(c) => string.push(c),
+            None => return Err(start..counter),
         }
     }
-    (string, counter)
+    Ok((string, counter))
 }
 
-/// match a &str into Identifier
+/// Read the `{1F600}` following `\u` into the character, None if it is not
+/// up to 6 hexadecimal digits of a Unicode scalar value, along with the
+/// number of characters read
+fn match_unicode_escape(chars: &mut Peekable<Chars>) -> (Option<char>, usize) {
+    if chars.next_if_eq(&'{').is_none() {
+        return (None, 0);
+    }
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_hexdigit) {
+        digits.push(c);
+    }
+    if chars.next_if_eq(&'}').is_none() {
+        return (None, digits.len() + 1);
+    }
+    let c = match digits.len() {
+        1..=6 => u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32),
+        _ => None,
+    };
+    (c, digits.len() + 2)
+}
+
+/// Characters that end a symbol or any other atom
+fn is_delimiter(c: char) -> bool {
+    let skipped = ['(', ')', '"', '\'', '`', ',', '{', '}', '[', ']'];
+    c.is_whitespace() || skipped.contains(&c)
+}
+
+/// match a &str into Identifier, or into Keyword when it starts with a
+/// colon, e.g. `:width`
 fn match_symbol(s: Span) -> IResult<Span, TokenKind> {
-    let skipped = ['(', ')', '"', '\''];
-    let (s, result) = take_till1(|c: char| c.is_whitespace() || skipped.contains(&c))(s)?;
-    let kind = TokenKind::Symbol(result.to_string());
+    let (s, result) = take_till1(is_delimiter)(s)?;
+    let text = *result.fragment();
+    // Symbols starting with `#:` are made by gensym only, so they
+    // never clash with the symbols of the source
+    let kind = match text.strip_prefix(':') {
+        _ if text.starts_with("#:") => TokenKind::UNKNOWN,
+        Some(name) if !name.is_empty() => TokenKind::Keyword(name.to_string()),
+        _ => TokenKind::Symbol(text.to_string()),
+    };
     Ok((s, kind))
 }
 
@@ -125,37 +361,194 @@ fn match_ignore(s: Span) -> IResult<Span, TokenKind> {
     Ok((s, TokenKind::IGNORE))
 }
 
+fn match_datum_comment(s: Span) -> IResult<Span, TokenKind> {
+    let (s, _) = tag("#;")(s)?;
+    Ok((s, TokenKind::DatumComment))
+}
+
 fn match_comment(s: Span) -> IResult<Span, TokenKind> {
     let (s, result) = preceded(tag(";;"), take_till(|c: char| c == '\n'))(s)?;
     let kind = TokenKind::Comment(result.to_string());
     Ok((s, kind))
 }
 
+/// match a `#!/usr/bin/env rslisp` line at the very start of the content,
+/// which lets the scripts be run directly on Unix
+fn match_shebang(s: Span) -> IResult<Span, TokenKind> {
+    if s.location_offset() != 0 {
+        return Err(nom::Err::Error(nom::error::Error::new(s, nom::error::ErrorKind::Tag)));
+    }
+    let (s, line) = preceded(tag("#!"), take_till(|c: char| c == '\n'))(s)?;
+    Ok((s, TokenKind::Comment(line.to_string())))
+}
+
+/// match a `#| ... |#` block comment, block comments can be nested
+/// e.g. `#| outer #| inner |# still outer |#`
+fn match_block_comment(s: Span) -> IResult<Span, TokenKind> {
+    let (rest, _) = tag("#|")(s)?;
+    let text = rest.fragment();
+    let mut depth = 1;
+    let mut i = 0;
+    while depth > 0 {
+        let next = &text[i..];
+        if next.starts_with("|#") {
+            depth -= 1;
+            i += 2;
+        } else if next.starts_with("#|") {
+            depth += 1;
+            i += 2;
+        } else if let Some(c) = next.chars().next() {
+            i += c.len_utf8();
+        } else {
+            // The comment is never closed
+            return Err(nom::Err::Failure(nom::error::Error::new(s, nom::error::ErrorKind::TakeUntil)));
+        }
+    }
+    let (rest, comment) = take(text[..i].chars().count())(rest)?;
+    let kind = TokenKind::Comment(comment.fragment()[..i - 2].to_string());
+    Ok((rest, kind))
+}
+
+/// The 1-based line and column of the start of the span, the column
+/// counts the characters from the start of the line rather than the bytes
+fn line_column(s: Span) -> (usize, usize) {
+    (s.location_line() as usize, s.get_utf8_column())
+}
+
 fn match_pattern(s: Span) -> IResult<Span, Token> {
     let (s, pos) = position(s)?;
     let (s, kind) = alt((
+        match_shebang,
+        match_block_comment,
+        match_datum_comment,
         match_paren,
+        match_quote,
+        match_radix_integer,
+        match_rational,
         match_numeric,
+        match_bool,
+        match_char,
+        match_raw_string,
+        match_dispatch,
+        match_closing_bracket,
         match_string,
-        match_symbol,
         match_comment,
+        match_symbol,
         match_ignore,
     ))(s)?;
 
-    let loc = Location::new(
+    let loc = Location::span(
         "".to_string(),  // filename will be set in the tokenizer
-        pos.location_line() as usize,
-        pos.location_offset() + 1
+        line_column(pos),
+        line_column(s),
     );
-    Ok((s, Token { loc, kind }))
+    let range = pos.location_offset()..s.location_offset();
+    Ok((s, Token { loc, kind, range }))
 }
 
-pub fn tokenize<'a>(fname: &'a str, content: &'a str) -> IResult<Span<'a>, VecDeque<Token>> {
-    fold_many0(match_pattern, VecDeque::new, |mut acc: VecDeque<Token>, mut item| {
-        item.loc.set_filename(fname.to_string());
-        acc.push_back(item);
-        acc
-    })(Span::new(content))
+/// Lazily split the content into tokens, yielding an error at the first
+/// character which does not start any token or at the first invalid
+/// literal, after which the lexer is exhausted
+///
+/// ```
+/// use rslisp::lexer::{Lexer, TokenKind};
+///
+/// let mut lexer = Lexer::new("<string>", "(+ 1 2)");
+/// assert_eq!(lexer.next().unwrap().unwrap().kind(), &TokenKind::LeftParenthesis);
+/// assert_eq!(lexer.count(), 6);
+/// ```
+pub struct Lexer<'a> {
+    fname: &'a str,
+    content: &'a str,
+    rest: Span<'a>,
+    failed: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(fname: &'a str, content: &'a str) -> Self {
+        Self {
+            fname,
+            content,
+            rest: Span::new(content),
+            failed: false,
+        }
+    }
+
+    /// The lexer of the content from the byte offset on, which must be the
+    /// start of a token. The locations still count from the start of the content
+    pub(crate) fn starting_at(fname: &'a str, content: &'a str, offset: usize) -> Self {
+        Self {
+            fname,
+            content,
+            rest: Span::new(content).slice(offset..),
+            failed: false,
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, LexError> {
+        let rest = self.rest;
+        let (s, mut token) = match match_pattern(rest) {
+            Ok(result) => result,
+            Err(_) => {
+                let (rol, col) = line_column(rest);
+                let loc = Location::new(self.fname.to_string(), rol, col);
+                // A string literal or a block comment only fails to match
+                // when it is never closed
+                if rest.fragment().starts_with("#|") {
+                    return Err(LexError::UnterminatedComment { loc });
+                }
+                if rest.fragment().starts_with("#r\"") {
+                    return Err(LexError::UnterminatedString { loc });
+                }
+                return Err(match rest.fragment().chars().next().unwrap_or_default() {
+                    '"' => match match_string_helper(&rest.fragment()[1..]) {
+                        Err(escape) => self.invalid_escape(rest, escape),
+                        Ok(_) => LexError::UnterminatedString { loc },
+                    },
+                    found => LexError::UnexpectedCharacter { found, loc },
+                });
+            }
+        };
+        token.loc.set_filename(self.fname.to_string());
+
+        if token.kind == TokenKind::UNKNOWN {
+            let text = &self.content[rest.location_offset()..s.location_offset()];
+            return Err(LexError::InvalidLiteral { text: text.to_string(), loc: token.loc });
+        }
+        self.rest = s;
+        Ok(token)
+    }
+
+    /// The error of the escape sequence at the range of characters
+    /// following the opening quote of the string
+    fn invalid_escape(&self, string: Span, escape: Range<usize>) -> LexError {
+        let text = string.fragment().chars().skip(escape.start + 1).take(escape.len()).collect();
+        let at = match take::<_, _, nom::error::Error<Span>>(escape.start + 1)(string) {
+            Ok((at, _)) => at,
+            Err(_) => string,
+        };
+        let (rol, col) = line_column(at);
+        let loc = Location::new(self.fname.to_string(), rol, col);
+        LexError::InvalidEscape { text, loc }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.rest.fragment().is_empty() {
+            return None;
+        }
+        let result = self.next_token();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/// Split the whole content into tokens, see [`Lexer`]
+pub fn tokenize(fname: &str, content: &str) -> Result<VecDeque<Token>, LexError> {
+    Lexer::new(fname, content).collect()
 }
 
 #[cfg(test)]
@@ -168,20 +561,96 @@ mod tests {
         assert_eq!(result, TokenKind::LeftParenthesis);
     }
 
+    #[test]
+    fn test_match_vector_paren() {
+        let (rest, result) = match_paren(Span::new("#(1 2)")).unwrap();
+        assert_eq!(result, TokenKind::VectorParenthesis);
+        assert_eq!(*rest.fragment(), "1 2)");
+    }
+
+    #[test]
+    fn test_match_quote() {
+        let (rest, result) = match_quote(Span::new("'(1 2)")).unwrap();
+        assert_eq!(result, TokenKind::Quote);
+        assert_eq!(*rest.fragment(), "(1 2)");
+
+        let (_, result) = match_quote(Span::new("`(1 ,x ,@xs)")).unwrap();
+        assert_eq!(result, TokenKind::Quasiquote);
+        let (_, result) = match_quote(Span::new(",x")).unwrap();
+        assert_eq!(result, TokenKind::Unquote);
+        let (_, result) = match_quote(Span::new(",@xs")).unwrap();
+        assert_eq!(result, TokenKind::UnquoteSplicing);
+    }
+
     #[test]
     fn test_match_identifier() {
         let (_, result1) = match_symbol(Span::new("monster? true)")).unwrap();
         let (_, result2) = match_symbol(Span::new("define ")).unwrap();
         assert_eq!(result1, TokenKind::Symbol("monster?".to_string()));
         assert_eq!(result2, TokenKind::Symbol("define".to_string()));
+        let (_, result3) = match_symbol(Span::new(":width 1")).unwrap();
+        let (_, result4) = match_symbol(Span::new(": 1")).unwrap();
+        assert_eq!(result3, TokenKind::Keyword("width".to_string()));
+        assert_eq!(result4, TokenKind::Symbol(":".to_string()));
     }
 
     #[test]
     fn test_match_numeric() {
         let (_, result1) = match_numeric(Span::new("123")).unwrap();
         let (_, result2) = match_numeric(Span::new("123.123")).unwrap();
+        let (_, result3) = match_numeric(Span::new("170141183460469231731687303715884105728")).unwrap();
         assert_eq!(result1, TokenKind::Integer(123));
         assert_eq!(result2, TokenKind::Float(123.123));
+        assert_eq!(result3, TokenKind::BigInteger(BigInt::from(i128::MAX) + 1));
+    }
+
+    #[test]
+    fn test_match_radix_integer() {
+        let parse = |s| match_radix_integer(Span::new(s)).unwrap().1;
+        assert_eq!(parse("#x1F"), TokenKind::Integer(31));
+        assert_eq!(parse("#x-1f)"), TokenKind::Integer(-31));
+        assert_eq!(parse("#o17"), TokenKind::Integer(15));
+        assert_eq!(parse("#b1010"), TokenKind::Integer(10));
+        assert_eq!(parse("0xff"), TokenKind::Integer(255));
+        assert_eq!(parse("-0b11"), TokenKind::Integer(-3));
+        assert_eq!(parse("#b102"), TokenKind::UNKNOWN);
+        assert!(match_radix_integer(Span::new("-#x1F")).is_err());
+        assert!(match_radix_integer(Span::new("10")).is_err());
+    }
+
+    #[test]
+    fn test_match_rational() {
+        let parse = |s| match_rational(Span::new(s)).unwrap().1;
+        let ratio = |n: i32, d: i32| BigRational::new(n.into(), d.into());
+        assert_eq!(parse("1/3"), TokenKind::Rational(ratio(1, 3)));
+        assert_eq!(parse("-2/6)"), TokenKind::Rational(ratio(-1, 3)));
+        assert_eq!(parse("4/2"), TokenKind::Integer(2));
+        assert_eq!(parse("1/0"), TokenKind::UNKNOWN);
+        assert!(match_rational(Span::new("1/2x")).is_err());
+        assert!(match_rational(Span::new("1.5/2")).is_err());
+    }
+
+    #[test]
+    fn test_match_bool() {
+        let (_, result1) = match_bool(Span::new("#t)")).unwrap();
+        let (_, result2) = match_bool(Span::new("false ")).unwrap();
+        assert_eq!(result1, TokenKind::Bool(true));
+        assert_eq!(result2, TokenKind::Bool(false));
+        assert!(match_bool(Span::new("true?")).is_err());
+        assert!(match_bool(Span::new("#test")).is_err());
+    }
+
+    #[test]
+    fn test_match_char() {
+        let (_, result1) = match_char(Span::new("#\\a)")).unwrap();
+        let (_, result2) = match_char(Span::new("#\\space")).unwrap();
+        let (rest, result3) = match_char(Span::new("#\\()")).unwrap();
+        let (_, result4) = match_char(Span::new("#\\bogus")).unwrap();
+        assert_eq!(result1, TokenKind::Char('a'));
+        assert_eq!(result2, TokenKind::Char(' '));
+        assert_eq!(result3, TokenKind::Char('('));
+        assert_eq!(*rest.fragment(), ")");
+        assert_eq!(result4, TokenKind::UNKNOWN);
     }
 
     #[test]
@@ -189,9 +658,9 @@ mod tests {
         let string1 = "This is the string\"";
         let string2 = "This is the string with \\\"Inner String\\\" Done!\"";
         let string3 = "This is the string with \\\"Inner String\\\"\"";
-        let result1 = match_string_helper(string1);
-        let result2 = match_string_helper(string2);
-        let result3 = match_string_helper(string3);
+        let result1 = match_string_helper(string1).unwrap();
+        let result2 = match_string_helper(string2).unwrap();
+        let result3 = match_string_helper(string3).unwrap();
         assert_eq!(result1.0, "This is the string");
         assert_eq!(result1.1, 18);
         assert_eq!(result2.0, "This is the string with \"Inner String\" Done!");
@@ -200,6 +669,33 @@ mod tests {
         assert_eq!(result3.1, 40);
     }
 
+    #[test]
+    fn test_match_string_escapes() {
+        let (string, size) = match_string_helper("a\\nb\\t\\r\\\\\\0\\u{1F600}\\u{e9}\"").unwrap();
+        assert_eq!(string, "a\nb\t\r\\\0\u{1F600}\u{e9}");
+        assert_eq!(size, 27);
+        // The range of characters of the invalid escape sequence
+        assert_eq!(match_string_helper("ab\\qc\""), Err(2..4));
+        assert_eq!(match_string_helper("\\u{D800}\""), Err(0..8));
+        assert_eq!(match_string_helper("\\u{1234567}\""), Err(0..11));
+        assert_eq!(match_string_helper("\\u{12\""), Err(0..5));
+        assert_eq!(match_string_helper("\\u12\""), Err(0..2));
+    }
+
+    #[test]
+    fn test_match_raw_string() {
+        let (rest, result) = match_raw_string(Span::new("#r\"C:\\dir\\n\\d+\" x")).unwrap();
+        assert_eq!(result, TokenKind::Str("C:\\dir\\n\\d+".to_string()));
+        assert_eq!(*rest.fragment(), " x");
+        assert!(match_raw_string(Span::new("#r\"unclosed")).is_err());
+
+        // It is not the dispatch token of the `r` reader macro
+        let tokens = tokenize("lexer_test.rs", "(f #r\"\\\")").unwrap();
+        assert_eq!(tokens[3].kind(), &TokenKind::Str("\\".to_string()));
+        let result = tokenize("lexer_test.rs", "(f #r\"a)\n");
+        assert!(matches!(result, Err(LexError::UnterminatedString { .. })));
+    }
+
     #[test]
     fn test_match_string() {
         let (_, result1) = match_string(Span::new("\"FooBar\"")).unwrap();
@@ -221,6 +717,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shebang() {
+        let tokens = tokenize("lexer_test.rs", "#!/usr/bin/env rslisp\n(f)").unwrap();
+        assert_eq!(tokens[0].kind(), &TokenKind::Comment("/usr/bin/env rslisp".to_string()));
+        assert_eq!(tokens[2].loc().rol(), 2);
+        // Only the first line may be a shebang
+        let tokens = tokenize("lexer_test.rs", "(f)\n#!/usr/bin/env rslisp").unwrap_or_default();
+        assert!(tokens.iter().all(|token| !matches!(token.kind(), TokenKind::Comment(_))));
+    }
+
     #[test]
     fn test_ignore() {
         let (_, result) = match_ignore(Span::new("           123")).unwrap();
@@ -231,7 +737,7 @@ mod tests {
     fn test_tokenize() {
         let prog = "(define x 10)\n(define y 20.13)\n(+ x y)";
         let result = tokenize("lexer_test.rs", prog);
-        let tokens = result.unwrap().1;
+        let tokens = result.unwrap();
         let kinds: Vec<_> = tokens.iter().map(|token| token.kind()).collect();
         println!("{:#?}", kinds);
         assert_eq!(
@@ -263,4 +769,100 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenize_span() {
+        let tokens = tokenize("lexer_test.rs", "(define name \"str\")").unwrap();
+        let spans: Vec<_> = tokens
+            .iter()
+            .filter(|token| token.kind() != &TokenKind::IGNORE)
+            .map(|token| (token.loc().col(), token.loc().end_col()))
+            .collect();
+        assert_eq!(spans, vec![(1, 2), (2, 8), (9, 13), (14, 19), (19, 20)]);
+        assert!(tokens.iter().all(|token| token.loc().rol() == 1 && token.loc().end_rol() == 1));
+    }
+
+    #[test]
+    fn test_match_block_comment() {
+        let (rest, result) = match_block_comment(Span::new("#| a #| b |# c |#(+ 1 2)")).unwrap();
+        assert_eq!(result, TokenKind::Comment(" a #| b |# c ".to_string()));
+        assert_eq!(*rest.fragment(), "(+ 1 2)");
+
+        let tokens = tokenize("lexer_test.rs", "(+ 1 #| 2\n 3 |# 4)").unwrap();
+        assert_eq!(tokens.iter().filter(|token| matches!(token.kind(), TokenKind::Integer(_))).count(), 2);
+        assert!(matches!(tokenize("lexer_test.rs", "#| #| |# (+ 1 2)"), Err(LexError::UnterminatedComment { .. })));
+    }
+
+    #[test]
+    fn test_match_datum_comment() {
+        let (rest, result) = match_datum_comment(Span::new("#;(f x)")).unwrap();
+        assert_eq!(result, TokenKind::DatumComment);
+        assert_eq!(*rest.fragment(), "(f x)");
+    }
+
+    #[test]
+    fn test_match_dispatch() {
+        let (rest, result) = match_dispatch(Span::new("#d\"2024\"")).unwrap();
+        assert_eq!(result, TokenKind::Dispatch('d'));
+        assert_eq!(*rest.fragment(), "\"2024\"");
+        let (rest, result) = match_dispatch(Span::new("#{1 2}")).unwrap();
+        assert_eq!(result, TokenKind::Dispatch('{'));
+        assert_eq!(*rest.fragment(), "1 2}");
+        assert!(match_dispatch(Span::new("#define")).is_err());
+        assert!(match_dispatch(Span::new("#\"s\"")).is_err());
+
+        let kinds: Vec<_> = tokenize("lexer_test.rs", "#[a]").unwrap().into_iter().map(|token| token.kind).collect();
+        assert_eq!(kinds, [TokenKind::Dispatch('['), TokenKind::Symbol("a".to_string()), TokenKind::ClosingBracket(']')]);
+    }
+
+    #[test]
+    fn test_lexer() {
+        // Tokens are produced on demand, the error ends the iteration
+        let mut lexer = Lexer::new("lexer_test.rs", "(a \"b)");
+        assert_eq!(lexer.next().unwrap().unwrap().kind(), &TokenKind::LeftParenthesis);
+        assert_eq!(lexer.next().unwrap().unwrap().kind(), &TokenKind::Symbol("a".to_string()));
+        assert_eq!(lexer.next().unwrap().unwrap().kind(), &TokenKind::IGNORE);
+        assert!(matches!(lexer.next(), Some(Err(LexError::UnterminatedString { .. }))));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_token_columns() {
+        // The columns restart on each line and count the characters
+        let tokens = tokenize("lexer_test.rs", "(a\n  \"é\" b)\n\tc").unwrap();
+        let locs: Vec<_> = tokens
+            .iter()
+            .filter(|token| token.kind() != &TokenKind::IGNORE)
+            .map(|token| (token.loc().rol(), token.loc().col(), token.loc().end_col()))
+            .collect();
+        assert_eq!(locs, [(1, 1, 2), (1, 2, 3), (2, 3, 6), (2, 7, 8), (2, 8, 9), (3, 2, 3)]);
+
+        let result = tokenize("lexer_test.rs", "(f\n  \"ü\\q\")");
+        assert!(matches!(result, Err(LexError::InvalidEscape { ref loc, .. }) if (loc.rol(), loc.col()) == (2, 5)));
+    }
+
+    #[test]
+    fn test_tokenize_error() {
+        let result = tokenize("lexer_test.rs", "(define c #\\bogus)");
+        assert!(matches!(result, Err(LexError::InvalidLiteral { ref text, .. }) if text == "#\\bogus"));
+
+        // The unterminated string is reported where it starts
+        let result = tokenize("lexer_test.rs", "(display \"abc\\\" def)");
+        match result {
+            Err(LexError::UnterminatedString { loc }) => assert_eq!((loc.rol(), loc.col()), (1, 10)),
+            other => panic!("Expect UnterminatedString but {:?} found", other),
+        }
+
+        // The invalid escape sequence is reported where it is
+        let result = tokenize("lexer_test.rs", "(display \"a\\tb\\qc\")");
+        match result {
+            Err(LexError::InvalidEscape { text, loc }) => {
+                assert_eq!(text, "\\q");
+                assert_eq!((loc.rol(), loc.col()), (1, 15));
+            },
+            other => panic!("Expect InvalidEscape but {:?} found", other),
+        }
+        let result = tokenize("lexer_test.rs", "\"\\u{110000}\"\n");
+        assert!(matches!(result, Err(LexError::InvalidEscape { ref text, .. }) if text == "\\u{110000}"));
+    }
 }
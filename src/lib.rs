@@ -0,0 +1,29 @@
+pub mod builtins;
+pub mod debugger;
+pub mod diagnostic;
+pub mod error;
+pub mod location;
+pub mod evaluator;
+pub mod format;
+pub mod fuel;
+pub mod gc;
+pub mod hook;
+pub mod incremental;
+pub mod interpreter;
+pub mod lexer;
+pub mod lint;
+pub mod native;
+pub mod optimize;
+pub mod parser;
+pub mod pretty;
+pub mod profile;
+pub mod resolve;
+pub mod runtime;
+pub mod symbol;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::{EvalError, LexError, ParseError};
+pub use interpreter::{Backend, Interpreter};
+pub use parser::Object;
@@ -0,0 +1,416 @@
+//! The checks of `rslisp lint`, reporting what is likely a mistake in
+//! a program without running it
+//!
+//! The forms are macro expanded first, the macros defined by the program
+//! are evaluated in a sandbox to do so, then checked for:
+//!
+//! - the unused internal definitions, local variables and parameters and
+//!   the unreachable branches of `if` and clauses of `cond`, as found by
+//!   [`crate::optimize::analyze`],
+//! - the top level definitions no other form of the file references. A
+//!   module gives the value of its last form to the files requiring it, so
+//!   what it exports is referenced there,
+//! - the bindings shadowing an outer binding or a builtin,
+//! - the calls giving the wrong number of arguments to a function defined
+//!   by the program, or to a builtin, see [`crate::builtins::BUILTINS`].
+//!
+//! ```
+//! use rslisp::lint::lint;
+//!
+//! let warnings = lint("<string>", "(define (f x) (car x x))\n(f 1 2)").unwrap();
+//! let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+//! assert_eq!(messages, ["`car` expects 1 arguments but 2 given", "`f` expects 1 arguments but 2 given"]);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::builtins::BUILTINS;
+use crate::error::{Arity, EvalError};
+use crate::evaluator::{arity, named_let, parse_params};
+use crate::interpreter::Interpreter;
+use crate::location::Location;
+use crate::optimize::{analyze, is_reported, occurs, Warning};
+use crate::parser::{Object, ParamKind};
+use crate::symbol::Symbol;
+
+/// The special forms, whose operands are not all expressions
+const SPECIAL_FORMS: &[&str] = &[
+    "define", "set!", "if", "when", "unless", "cond", "case", "begin", "and", "or", "lambda", "defmacro", "guard", "delay",
+    "parameterize", "define-record-type", "define-test", "let", "let*", "letrec", "quote", "quasiquote", "unquote",
+    "unquote-splicing",
+];
+
+/// Check the source, it must parse and its macros must expand.
+/// The warnings are sorted by location
+pub fn lint(fname: &str, source: &str) -> Result<Vec<Warning>, EvalError> {
+    let mut interp = Interpreter::new();
+    interp.set_sandbox(&[]);
+    let expanded = interp.expand_source(fname, source)?;
+
+    let mut linter = Linter { scopes: vec![HashMap::new()], assigned: HashSet::new(), warnings: vec![] };
+    for form in expanded.iter() {
+        linter.warnings.extend(analyze(form));
+        linter.collect_assigned(form);
+    }
+    for form in expanded.iter() {
+        linter.define_globals(form);
+    }
+    linter.unused_globals(&expanded);
+    for form in expanded.iter() {
+        linter.check(form);
+    }
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.loc.as_ref().map(|loc| (loc.rol(), loc.col())).unwrap_or((usize::MAX, 0)));
+    Ok(warnings)
+}
+
+fn head(obj: &Object) -> Option<&str> {
+    match obj {
+        Object::List { value, .. } => match value.first() {
+            Some(Object::Symbol { value, .. }) => Some(value.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Split the signature of `(define (name x y) body)` or
+/// `(define (name . args) body)` into the name and the parameters
+fn split_signature(signature: &Object) -> Option<(&Object, Object)> {
+    match signature {
+        Object::List { value, .. } => value.split_first().map(|(name, params)| {
+            (name, Object::List { value: params.to_vec().into(), loc: None })
+        }),
+        Object::Pair { value, .. } => Some((&value.0, value.1.clone())),
+        _ => None,
+    }
+}
+
+/// The name and the arity of the function a definition binds, the
+/// arity is None when the value is not a lambda
+fn definition(list: &[Object]) -> Option<(Symbol, Option<&Location>, Option<Arity>)> {
+    match list {
+        [_, signature, _, ..] if !matches!(signature, Object::Symbol { .. }) => match split_signature(signature) {
+            Some((Object::Symbol { value, loc }, params)) => {
+                Some((*value, loc.as_ref(), parse_params(Some(&params)).ok().map(|params| arity(&params))))
+            },
+            _ => None,
+        },
+        [_, Object::Symbol { value, loc }, expr] => Some((*value, loc.as_ref(), lambda_arity(expr))),
+        _ => None,
+    }
+}
+
+struct Linter {
+    /// The variables in scope, the innermost scope last, with the arity of
+    /// the ones known to be functions. The first scope is the global one
+    scopes: Vec<HashMap<Symbol, Option<Arity>>>,
+    /// The variables given another value by `set!`, whose arity is unknown
+    assigned: HashSet<Symbol>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn collect_assigned(&mut self, obj: &Object) {
+        if let Object::List { value, .. } = obj {
+            match &value[..] {
+                [Object::Symbol { value: set, .. }, Object::Symbol { value: name, .. }, _] if set == "set!" => {
+                    self.assigned.insert(*name);
+                },
+                _ if head(obj) == Some("quote") => return,
+                _ => {},
+            }
+            value.iter().for_each(|item| self.collect_assigned(item));
+        }
+    }
+
+    /// Bind the top level definitions, a function may be called before
+    /// it is defined. A name defined more than once has no known arity
+    fn define_globals(&mut self, obj: &Object) {
+        let list = match obj {
+            Object::List { value, .. } => value,
+            _ => return,
+        };
+        match head(obj) {
+            Some("begin") => list[1..].iter().for_each(|item| self.define_globals(item)),
+            Some("define") => {
+                if let Some((name, _, arity)) = definition(list) {
+                    let arity = if self.scopes[0].contains_key(&name) { None } else { arity };
+                    self.scopes[0].insert(name, arity);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Report the top level definitions, including the ones in a top level
+    /// `begin`, which no other top level form references
+    fn unused_globals(&mut self, forms: &[Object]) {
+        let mut flat = vec![];
+        let mut pending: Vec<&Object> = forms.iter().rev().collect();
+        while let Some(form) = pending.pop() {
+            match (head(form), form) {
+                (Some("begin"), Object::List { value, .. }) => pending.extend(value[1..].iter().rev()),
+                _ => flat.push(form),
+            }
+        }
+        for (i, form) in flat.iter().enumerate() {
+            let definition = match (head(form), form) {
+                (Some("define"), Object::List { value, .. }) => definition(value),
+                _ => None,
+            };
+            if let Some((name, loc, _)) = definition {
+                let used = flat.iter().enumerate().any(|(j, other)| j != i && occurs(name, other));
+                if !used && is_reported(name) {
+                    self.warnings.push(Warning::new(format!("unused definition `{}`", name), loc));
+                }
+            }
+        }
+    }
+
+    /// Bind a local variable, reporting the binding it shadows
+    fn bind(&mut self, name: Symbol, loc: Option<&Location>, arity: Option<Arity>) {
+        if is_reported(name) {
+            let outer = self.scopes[..self.scopes.len() - 1].iter().any(|scope| scope.contains_key(&name));
+            if outer {
+                self.warnings.push(Warning::new(format!("`{}` shadows an outer binding", name), loc));
+            } else if !self.scopes[0].contains_key(&name) && BUILTINS.iter().any(|(builtin, _, _)| name == *builtin) {
+                self.warnings.push(Warning::new(format!("`{}` shadows a builtin", name), loc));
+            }
+        }
+        self.scopes.last_mut().expect("the global scope").insert(name, arity);
+    }
+
+    /// The arity of the function the variable is bound to, if known
+    fn arity(&self, name: Symbol) -> Option<Arity> {
+        if self.assigned.contains(&name) {
+            return None;
+        }
+        match self.scopes.iter().rev().find_map(|scope| scope.get(&name)) {
+            Some(arity) => *arity,
+            None => BUILTINS.iter().find(|(builtin, _, _)| name == *builtin).map(|(_, arity, _)| *arity),
+        }
+    }
+
+    fn check(&mut self, obj: &Object) {
+        let list = match obj {
+            Object::List { value, .. } if !value.is_empty() => value,
+            _ => return,
+        };
+        match (head(obj), &list[1..]) {
+            (Some("quote"), _) => {},
+            (Some("quasiquote"), [template]) => self.check_template(template, 1),
+            (Some("lambda"), [params, body @ ..]) => {
+                self.scopes.push(HashMap::new());
+                self.bind_params(params);
+                self.check_body(body);
+                self.scopes.pop();
+            },
+            (Some("define"), [signature @ (Object::List { .. } | Object::Pair { .. }), body @ ..]) => {
+                // The name is bound by the enclosing body
+                let Some((_, params)) = split_signature(signature) else {
+                    return;
+                };
+                self.scopes.push(HashMap::new());
+                self.bind_params(&params);
+                self.check_body(body);
+                self.scopes.pop();
+            },
+            // The named let is checked as the call of the function it defines
+            (Some("let"), [Object::Symbol { .. }, ..]) => {
+                if let Ok(call) = named_let(&list[1..]) {
+                    self.check(&call);
+                }
+            },
+            (Some("let" | "let*" | "letrec"), [Object::List { value: bindings, .. }, body @ ..]) => {
+                let bindings: Vec<_> = bindings
+                    .iter()
+                    .filter_map(|binding| match binding {
+                        Object::List { value, .. } => match &value[..] {
+                            [Object::Symbol { value: name, loc }, expr] => Some((*name, loc.as_ref(), expr)),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect();
+                let form = head(obj);
+                if form == Some("let") {
+                    bindings.iter().for_each(|(_, _, expr)| self.check(expr));
+                }
+                self.scopes.push(HashMap::new());
+                for (name, loc, expr) in bindings.iter() {
+                    if form == Some("let*") {
+                        self.check(expr);
+                    }
+                    self.bind(*name, *loc, lambda_arity(expr));
+                }
+                if form == Some("letrec") {
+                    bindings.iter().for_each(|(_, _, expr)| self.check(expr));
+                }
+                self.check_body(body);
+                self.scopes.pop();
+            },
+            (Some("guard"), [Object::List { value: handler, .. }, body @ ..]) => {
+                body.iter().for_each(|item| self.check(item));
+                if let Some((Object::Symbol { value, loc }, clauses)) = handler.split_first() {
+                    self.scopes.push(HashMap::new());
+                    self.bind(*value, loc.as_ref(), None);
+                    clauses.iter().for_each(|clause| self.check_items(clause));
+                    self.scopes.pop();
+                }
+            },
+            (Some("cond"), clauses) => {
+                // The optimizer leaves the clauses following an else clause to the evaluator
+                let taken = clauses.iter().position(|clause| head(clause) == Some("else"));
+                for clause in taken.map_or(&[][..], |i| &clauses[i + 1..]) {
+                    self.warnings.push(Warning::new("unreachable cond clause".to_string(), clause.loc()));
+                }
+                clauses.iter().for_each(|clause| self.check_items(clause));
+            },
+            (Some("case"), [key, clauses @ ..]) => {
+                // The data of the clauses are not expressions
+                self.check(key);
+                for clause in clauses {
+                    if let Object::List { value, .. } = clause {
+                        value.iter().skip(1).for_each(|expr| self.check(expr));
+                    }
+                }
+            },
+            (Some("parameterize"), [Object::List { value: bindings, .. }, body @ ..]) => {
+                bindings.iter().for_each(|binding| self.check_items(binding));
+                body.iter().for_each(|form| self.check(form));
+            },
+            (Some("define" | "set!"), [_, expr]) => self.check(expr),
+            (Some("begin" | "if" | "when" | "unless" | "and" | "or" | "delay"), items) => items.iter().for_each(|item| self.check(item)),
+            (Some("define-test"), [_, body @ ..]) => self.check_body(body),
+            (Some(form), _) if SPECIAL_FORMS.contains(&form) => {},
+            (_, args) => {
+                if let Object::Symbol { value: name, loc } = &list[0] {
+                    match self.arity(*name) {
+                        Some(arity) if !arity.accepts(args.len()) => self.warnings.push(Warning::new(
+                            format!("`{}` expects {} arguments but {} given", name, arity, args.len()),
+                            loc.as_ref(),
+                        )),
+                        _ => {},
+                    }
+                }
+                list.iter().for_each(|item| self.check(item));
+            },
+        }
+    }
+
+    /// Check every item of a list, e.g. a clause of `cond`
+    fn check_items(&mut self, obj: &Object) {
+        if let Object::List { value, .. } = obj {
+            value.iter().for_each(|item| self.check(item));
+        }
+    }
+
+    /// Check the forms of a body, whose internal definitions are in
+    /// scope in the whole body
+    fn check_body(&mut self, body: &[Object]) {
+        for form in body {
+            if let (Some("define"), Object::List { value, .. }) = (head(form), form) {
+                if let Some((name, loc, arity)) = definition(value) {
+                    self.bind(name, loc, arity);
+                }
+            }
+        }
+        body.iter().for_each(|form| self.check(form));
+    }
+
+    fn bind_params(&mut self, params: &Object) {
+        for param in parse_params(Some(params)).unwrap_or_default() {
+            let name = match param.kind {
+                ParamKind::Named(name) | ParamKind::Variadic(name) | ParamKind::Keyword { name, .. } => name,
+            };
+            self.bind(name, param.loc.as_ref(), None);
+        }
+    }
+
+    /// Check the unquoted expressions of a quasiquote template
+    fn check_template(&mut self, obj: &Object, depth: usize) {
+        let list = match obj {
+            Object::List { value, .. } => value,
+            _ => return,
+        };
+        match (head(obj), &list[1..]) {
+            (Some("unquote" | "unquote-splicing"), [expr]) if depth == 1 => self.check(expr),
+            (Some("unquote" | "unquote-splicing"), [expr]) => self.check_template(expr, depth - 1),
+            (Some("quasiquote"), [expr]) => self.check_template(expr, depth + 1),
+            _ => list.iter().for_each(|item| self.check_template(item, depth)),
+        }
+    }
+}
+
+fn lambda_arity(expr: &Object) -> Option<Arity> {
+    match expr {
+        Object::List { value, .. } if head(expr) == Some("lambda") => parse_params(value.get(1)).ok().map(|params| arity(&params)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(prog: &str) -> Vec<String> {
+        lint("<test>", prog).unwrap().into_iter().map(|w| w.message).collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        // The top level definitions are used by the other forms, a module
+        // exports the value of its last form
+        assert_eq!(run("(define (f x) x)\n(define y 1)\n(define (g) (g))\n(begin (define z 2))\n(f y)"), [
+            "unused definition `g`",
+            "unused definition `z`",
+        ]);
+        assert_eq!(run("(define (f) 1)\n(define (g) 2)\n(define _h 3)\n(list f)"), ["unused definition `g`"]);
+        assert_eq!(run("(define (_f x) (define (helper) x) (define y 2) y)"), ["unused definition `helper`"]);
+        assert_eq!(run("(define (_f x) (cond (#f 1) (#t 2) (x 3)))"), [
+            "unreachable cond clause",
+            "unreachable cond clause",
+        ]);
+        assert_eq!(run("(define (_f x) (cond (x 1) (else 2) (#t 3)))"), ["unreachable cond clause"]);
+        assert_eq!(run("(define (_f x) (case (car x x) ((car) 1) (else (cdr))))"), [
+            "`car` expects 1 arguments but 2 given",
+            "`cdr` expects 1 arguments but 0 given",
+        ]);
+        assert_eq!(run("(define p (make-parameter 1))\n(parameterize ((p (car))) (cdr 1 2))"), [
+            "`car` expects 1 arguments but 0 given",
+            "`cdr` expects 1 arguments but 2 given",
+        ]);
+
+        assert_eq!(run("(define x 1)\n(define (_f x) (let ((x x)) x))"), [
+            "`x` shadows an outer binding",
+            "`x` shadows an outer binding",
+        ]);
+        assert_eq!(run("(lambda (list) list)"), ["`list` shadows a builtin"]);
+        // Redefining a builtin at the top level replaces it
+        assert!(run("(define (filter x) x)\n(filter 1)").is_empty());
+        assert!(run("(lambda (_x) (lambda (_x) _x))").is_empty());
+
+        assert_eq!(run("(car 1 2)"), ["`car` expects 1 arguments but 2 given"]);
+        // The arities of the builtins are the ones they are declared with
+        assert_eq!(run("(- )\n(map car)\n(display 1 2 3)\n(+)"), [
+            "`-` expects at least 1 arguments but 0 given",
+            "`map` expects at least 2 arguments but 1 given",
+            "`display` expects 1 to 2 arguments but 3 given",
+        ]);
+        assert_eq!(run("(g 1)\n(define (g a b . rest) a)"), ["`g` expects at least 2 arguments but 1 given"]);
+        assert_eq!(run("(letrec ((h (lambda (n) (h n n)))) (h 1))"), ["`h` expects 1 arguments but 2 given"]);
+        assert_eq!(run("(let loop ((i 0)) (if (< i 3) (loop i 1) (car i i)))"), [
+            "`loop` expects 1 arguments but 2 given",
+            "`car` expects 1 arguments but 2 given",
+        ]);
+        // Quoted data, reassigned variables and parameters are not checked
+        assert_eq!(run("'(car 1 2)\n(define (k) 1)\n(set! k car)\n(k 1)\n(lambda (car) (car))"), ["`car` shadows a builtin"]);
+        assert_eq!(run("(define (f x) x)\n`(f ,(f 1 2))"), ["`f` expects 1 arguments but 2 given"]);
+
+        // The macros are expanded before the checks
+        assert_eq!(run("(defmacro one (f) `(,f 1))\n(one cons)"), ["`cons` expects 2 arguments but 1 given"]);
+        assert!(matches!(lint("<test>", "(car"), Err(EvalError::Parse(_))));
+    }
+}
@@ -1,25 +1,64 @@
+use std::rc::Rc;
+
+/// A span of the source, from the start position up to
+/// the end position exclusively. The objects carry their location, so the
+/// file name is shared by the clones rather than copied
 #[derive(Debug, Clone, PartialEq)]
 pub struct Location {
-    filename: String,
+    filename: Rc<str>,
     rol: usize,
-    col: usize
+    col: usize,
+    end_rol: usize,
+    end_col: usize
 }
 
 impl Location {
+    /// A location pointing at a single position
     pub fn new(filename: String, rol: usize, col: usize) -> Self {
         Self {
-            filename,
+            filename: filename.into(),
             rol,
             col,
+            end_rol: rol,
+            end_col: col,
         }
     }
 
+    /// A location spanning from (rol, col) up to (end_rol, end_col)
+    pub fn span(filename: String, (rol, col): (usize, usize), (end_rol, end_col): (usize, usize)) -> Self {
+        Self {
+            filename: filename.into(),
+            rol,
+            col,
+            end_rol,
+            end_col,
+        }
+    }
+
+    /// The span from the start of this location to the end of the other one
+    pub fn to(&self, other: &Location) -> Self {
+        Self {
+            filename: self.filename.clone(),
+            rol: self.rol,
+            col: self.col,
+            end_rol: other.end_rol,
+            end_col: other.end_col,
+        }
+    }
+
+    /// The location with both of its (line, column) positions passed through `f`
+    pub(crate) fn moved(&self, f: impl Fn((usize, usize)) -> (usize, usize)) -> Self {
+        let (rol, col) = f((self.rol, self.col));
+        let (end_rol, end_col) = f((self.end_rol, self.end_col));
+        Self { filename: self.filename.clone(), rol, col, end_rol, end_col }
+    }
+
     pub fn set_filename(&mut self, s: String) {
-        self.filename = s;
+        self.filename = s.into();
     }
 
     pub fn filename(&self) -> &str {
-        &self.filename.as_str()
+        &self.filename
     }
 
     pub fn rol(&self) -> usize {
@@ -29,6 +68,14 @@ impl Location {
     pub fn col(&self) -> usize {
         self.col
     }
+
+    pub fn end_rol(&self) -> usize {
+        self.end_rol
+    }
+
+    pub fn end_col(&self) -> usize {
+        self.end_col
+    }
 }
 
 impl std::fmt::Display for Location {
@@ -1,15 +1,262 @@
-mod location;
-mod evaluator;
-mod lexer;
-mod parser;
+mod dump;
+mod repl;
 
-use lexer::tokenize;
+use std::process::ExitCode;
+use std::rc::Rc;
 
-fn main() -> std::io::Result<()> {
-    // Testing file read operation
-    let fname = std::env::args().nth(1).unwrap();
-    let content = std::fs::read_to_string(fname.as_str())?;
+use rslisp::debugger::Debugger;
+use rslisp::parser::Written;
+use rslisp::profile::{CountingAllocator, Profiler};
+use rslisp::runtime::DEFAULT_MAX_DEPTH;
+use rslisp::{diagnostic, format, hook, lint, Backend, EvalError, Interpreter, Object};
 
-    let tokens = tokenize(fname.as_str(), content.as_str());
-    Ok(())
+const USAGE: &str = "usage: rslisp [--vm] [-O] [--warn] [--max-depth n] [--sandbox] [--profile] [--dump-tokens | --dump-ast | --expand] [file | -e expr]
+       rslisp fmt [--check] file...
+       rslisp lint file...
+       rslisp debug file";
+
+// Counts the allocations reported by --profile
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Print a view of the file instead of running it
+type Dump = fn(&str, &str) -> Result<String, EvalError>;
+
+/// The status of a command line given the wrong way
+const USAGE_ERROR: u8 = 2;
+
+/// The status the program ends with, the one given to `exit` or
+/// a failure if any error is left uncaught
+fn exit_code(result: &Result<Object, EvalError>) -> ExitCode {
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        // A status the parent process cannot see is a failure rather than truncated
+        Err(EvalError::Exit { code }) => u8::try_from(*code).map_or(ExitCode::FAILURE, ExitCode::from),
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+fn main() -> std::io::Result<ExitCode> {
+    let mut backend = Backend::TreeWalk;
+    let mut optimize = false;
+    let mut warn = false;
+    let mut max_depth = DEFAULT_MAX_DEPTH;
+    let mut sandbox = false;
+    let mut profile = false;
+    let mut dump: Option<Dump> = None;
+    let mut fname = None;
+    let mut expr = None;
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("fmt").is_some() {
+        return fmt(args);
+    }
+    if args.next_if_eq("lint").is_some() {
+        return lint(args);
+    }
+    if args.next_if_eq("debug").is_some() {
+        return debug(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--eval" => match args.next() {
+                Some(arg) => expr = Some(arg),
+                None => {
+                    eprintln!("missing expression after {}\n{}", arg, USAGE);
+                    return Ok(ExitCode::from(USAGE_ERROR));
+                },
+            },
+            "--vm" => backend = Backend::Vm,
+            "-O" => optimize = true,
+            "--warn" => warn = true,
+            "--sandbox" => sandbox = true,
+            "--profile" => profile = true,
+            "--max-depth" => match args.next().and_then(|arg| arg.parse().ok()).filter(|depth| *depth > 0) {
+                Some(depth) => max_depth = depth,
+                None => {
+                    eprintln!("expect a positive number after {}\n{}", arg, USAGE);
+                    return Ok(ExitCode::from(USAGE_ERROR));
+                },
+            },
+            "--dump-tokens" => dump = Some(dump::tokens),
+            "--dump-ast" => dump = Some(dump::ast),
+            "--expand" => dump = Some(dump::expanded),
+            _ if arg.starts_with('-') => {
+                eprintln!("unknown option {}\n{}", arg, USAGE);
+                return Ok(ExitCode::from(USAGE_ERROR));
+            },
+            _ => fname = Some(arg),
+        }
+    }
+
+    if fname.is_some() && expr.is_some() {
+        eprintln!("give either a file or an expression\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+
+    if fname.is_none() && expr.is_none() && dump.is_some() {
+        eprintln!("no program to dump\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+    // The hooks are only called by the tree-walking evaluator
+    if profile && backend == Backend::Vm {
+        eprintln!("--profile is not supported with --vm\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+
+    let mut interp = Interpreter::with_backend(backend);
+    interp.set_optimize(optimize);
+    interp.set_warnings(warn);
+    interp.set_max_depth(max_depth);
+    if sandbox {
+        interp.set_sandbox(&[]);
+    }
+    if !profile {
+        return run(interp, dump, fname, expr);
+    }
+    // The report is printed once the program ends, even by calling exit
+    let profiler = Rc::new(Profiler::new());
+    let code = hook::with_hook(profiler.clone(), || run(interp, dump, fname, expr));
+    eprint!("{}", profiler.report());
+    code
+}
+
+/// Format the files in place, or only report the files which are not
+/// formatted with `--check`, the status is a failure if any is reported
+fn fmt(args: impl Iterator<Item = String>) -> std::io::Result<ExitCode> {
+    let mut check = false;
+    let mut files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with('-') => {
+                eprintln!("unknown option {}\n{}", arg, USAGE);
+                return Ok(ExitCode::from(USAGE_ERROR));
+            },
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("no file to format\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+
+    let mut failed = false;
+    for fname in files {
+        let source = std::fs::read_to_string(&fname)?;
+        match format::format(&fname, &source) {
+            Ok(formatted) if formatted == source => {},
+            Ok(_) if check => {
+                eprintln!("{} is not formatted", fname);
+                failed = true;
+            },
+            Ok(formatted) => std::fs::write(&fname, formatted)?,
+            Err(e) => {
+                eprintln!("{}", diagnostic::render(&e, &source));
+                failed = true;
+            },
+        }
+    }
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Print the warnings found in the files, the status is a failure
+/// if there is any warning
+fn lint(args: impl Iterator<Item = String>) -> std::io::Result<ExitCode> {
+    let mut files = vec![];
+    for arg in args {
+        if arg.starts_with('-') {
+            eprintln!("unknown option {}\n{}", arg, USAGE);
+            return Ok(ExitCode::from(USAGE_ERROR));
+        }
+        files.push(arg);
+    }
+    if files.is_empty() {
+        eprintln!("no file to lint\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+
+    let mut failed = false;
+    for fname in files {
+        let source = std::fs::read_to_string(&fname)?;
+        match lint::lint(&fname, &source) {
+            Ok(warnings) => {
+                for warning in warnings.iter() {
+                    eprintln!("{}", warning);
+                }
+                failed |= !warnings.is_empty();
+            },
+            Err(e) => {
+                eprintln!("{}", diagnostic::render(&e, &source));
+                failed = true;
+            },
+        }
+    }
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Run the file in the step debugger, the commands are read from stdin
+fn debug(args: impl Iterator<Item = String>) -> std::io::Result<ExitCode> {
+    let mut files = vec![];
+    for arg in args {
+        if arg.starts_with('-') {
+            eprintln!("unknown option {}\n{}", arg, USAGE);
+            return Ok(ExitCode::from(USAGE_ERROR));
+        }
+        files.push(arg);
+    }
+    if files.len() != 1 {
+        eprintln!("give one file to debug\n{}", USAGE);
+        return Ok(ExitCode::from(USAGE_ERROR));
+    }
+    let fname = files.remove(0);
+
+    let interp = Interpreter::new();
+    let debugger = Debugger::new(Box::new(std::io::stdin().lock()), Box::new(std::io::stdout()));
+    let result = hook::with_hook(Rc::new(debugger), || interp.eval_file(fname.as_str()));
+    match &result {
+        Ok(_) | Err(EvalError::Exit { .. }) => {},
+        Err(e) => eprintln!("{}", interp.render_error(e)),
+    }
+    Ok(exit_code(&result))
+}
+
+fn run(interp: Interpreter, dump: Option<Dump>, fname: Option<String>, expr: Option<String>) -> std::io::Result<ExitCode> {
+    // Without a program to run, drop into the interactive mode
+    let (fname, source) = match (fname, expr) {
+        (Some(fname), _) => (fname, None),
+        (None, Some(expr)) => ("<string>".to_string(), Some(expr)),
+        (None, None) => return repl::run(interp).map_err(|e| std::io::Error::other(e.to_string())),
+    };
+
+    if let Some(dump) = dump {
+        let source = match source {
+            Some(source) => source,
+            None => std::fs::read_to_string(&fname)?,
+        };
+        return Ok(match dump(fname.as_str(), source.as_str()) {
+            Ok(text) => {
+                println!("{}", text);
+                ExitCode::SUCCESS
+            },
+            Err(e) => {
+                eprintln!("{}", diagnostic::render(&e, source.as_str()));
+                ExitCode::FAILURE
+            },
+        });
+    }
+
+    let result = match &source {
+        Some(source) => interp.eval_str(source.as_str()),
+        None => interp.eval_file(fname.as_str()),
+    };
+    for warning in interp.take_warnings() {
+        eprintln!("{}", warning);
+    }
+    match &result {
+        // The value of an expression given on the command line is printed
+        Ok(obj) if source.is_some() && !matches!(obj, Object::Void { .. }) => println!("{}", Written(obj)),
+        Ok(_) | Err(EvalError::Exit { .. }) => {},
+        Err(e) => eprintln!("{}", interp.render_error(e)),
+    }
+    Ok(exit_code(&result))
 }
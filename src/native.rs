@@ -0,0 +1,91 @@
+//! Functions implemented in Rust, i.e. the builtins and the closures
+//! registered by the embedders
+//!
+//! Any closure returning `Result<Object, EvalError>` can be registered with
+//! [`crate::Interpreter::register_fn`], the arity is taken from its parameters:
+//! a closure taking `&[Object]` accepts any number of arguments while one
+//! taking up to four `Object`s accepts exactly that many.
+
+use std::rc::Rc;
+
+use crate::builtins::BuiltinFn;
+use crate::error::{Arity, EvalError};
+use crate::parser::Object;
+
+/// A closure registered by the embedder, called with the evaluated arguments
+pub type Closure = Rc<dyn Fn(&[Object]) -> Result<Object, EvalError>>;
+
+/// The Rust code behind a native function
+#[derive(Clone)]
+pub enum NativeFn {
+    /// One of [`crate::builtins::BUILTINS`]
+    Builtin(BuiltinFn),
+    Closure(Closure),
+}
+
+/// A function bound to `name`, the number of arguments is checked
+/// against `arity` before `func` is called
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub func: NativeFn,
+}
+
+impl NativeFunction {
+    /// The builtins check the types of their arguments themselves
+    pub fn builtin(name: &str, arity: Arity, func: BuiltinFn) -> Self {
+        NativeFunction { name: name.to_string(), arity, func: NativeFn::Builtin(func) }
+    }
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction").field("name", &self.name).field("arity", &self.arity).finish()
+    }
+}
+
+/// Closures which can be registered as functions, `Args` tells the
+/// signatures apart and is inferred from the closure
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+/// The arguments of a closure taking the slice of all the arguments
+pub struct Variadic;
+
+impl<F> IntoNative<Variadic> for F
+where
+    F: Fn(&[Object]) -> Result<Object, EvalError> + 'static,
+{
+    fn into_native(self, name: &str) -> NativeFunction {
+        NativeFunction { name: name.to_string(), arity: Arity::AtLeast(0), func: NativeFn::Closure(Rc::new(self)) }
+    }
+}
+
+macro_rules! impl_into_native {
+    ($count:expr; $($arg:ident),*) => {
+        impl<F> IntoNative<($(impl_into_native!(@object $arg),)*)> for F
+        where
+            F: Fn($(impl_into_native!(@object $arg)),*) -> Result<Object, EvalError> + 'static,
+        {
+            #[allow(unused_variables, unused_mut)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let func = move |args: &[Object]| {
+                    let mut args = args.iter().cloned();
+                    // The arity is checked before the call
+                    $(let $arg = args.next().unwrap_or(Object::Void { loc: None });)*
+                    self($($arg),*)
+                };
+                NativeFunction { name: name.to_string(), arity: Arity::Exact($count), func: NativeFn::Closure(Rc::new(func)) }
+            }
+        }
+    };
+    (@object $arg:ident) => { Object };
+}
+
+impl_into_native!(0;);
+impl_into_native!(1; a);
+impl_into_native!(2; a, b);
+impl_into_native!(3; a, b, c);
+impl_into_native!(4; a, b, c, d);
@@ -0,0 +1,420 @@
+//! Unused-binding and dead-branch elimination
+//!
+//! The pass rewrites macro expanded forms:
+//!
+//! - the bindings of `let`, `let*` and `letrec` and the internal
+//!   definitions of a body which are never referenced are removed
+//!   when computing their value has no side effect,
+//! - the branches of `if` and the clauses of `cond` which cannot be
+//!   taken because their condition is a constant are pruned.
+//!
+//! Every removal, as well as the unused bindings kept because of their
+//! side effects and the unused parameters, is reported as a [`Warning`].
+//! A binding is considered referenced as soon as its name appears in the
+//! scope, even in quoted data which might be given to `eval`.
+
+use crate::evaluator::{is_truthy, with_stack};
+use crate::location::Location;
+use crate::parser::{ListItems, Object};
+use crate::symbol::Symbol;
+
+/// Something suspicious found in the program, which is still valid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub loc: Option<Location>,
+}
+
+impl Warning {
+    pub(crate) fn new(message: String, loc: Option<&Location>) -> Self {
+        Self { message, loc: loc.cloned() }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "warning: {}", self.message)?;
+        match &self.loc {
+            Some(loc) => write!(f, "\n --> {}:{}:{}", loc.filename(), loc.rol(), loc.col()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Report what [`optimize`] would remove without rewriting the form
+pub fn analyze(obj: &Object) -> Vec<Warning> {
+    let mut warnings = vec![];
+    optimize(obj, &mut warnings);
+    warnings
+}
+
+/// Rewrite the form, which must already be macro expanded, the
+/// warnings are appended to `warnings`
+pub fn optimize(obj: &Object, warnings: &mut Vec<Warning>) -> Object {
+    // The nested forms are rewritten recursively, on more stack when needed
+    with_stack(|| optimize_form(obj, warnings))
+}
+
+fn optimize_form(obj: &Object, warnings: &mut Vec<Warning>) -> Object {
+    let list = match obj {
+        Object::List { value, .. } if !value.is_empty() => value,
+        _ => return obj.clone(),
+    };
+    let form = match &list[0] {
+        Object::Symbol { value, .. } => value.as_str(),
+        _ => "",
+    };
+    match (form, &list[1..]) {
+        ("if", [condition, then, otherwise]) => {
+            let condition = optimize(condition, warnings);
+            match constant_truth(&condition) {
+                Some(truth) => {
+                    let (taken, dropped) = if truth { (then, otherwise) } else { (otherwise, then) };
+                    warnings.push(Warning::new("unreachable if branch".to_string(), dropped.loc()));
+                    optimize(taken, warnings)
+                },
+                None => {
+                    let (then, otherwise) = (optimize(then, warnings), optimize(otherwise, warnings));
+                    with_items(obj, vec![list[0].clone(), condition, then, otherwise])
+                },
+            }
+        },
+        ("cond", clauses) if is_clauses(clauses) => optimize_cond(obj, clauses, warnings),
+        ("lambda", [params, body @ ..]) if !body.is_empty() => {
+            check_params(params, list, warnings);
+            let mut items = vec![list[0].clone(), params.clone()];
+            items.extend(optimize_body(body, warnings));
+            with_items(obj, items)
+        },
+        ("define", [signature @ Object::List { .. }, body @ ..]) if !body.is_empty() => {
+            if let Object::List { value, .. } = signature {
+                if let Some((_, params)) = value.split_first() {
+                    check_params(&Object::List { value: ListItems::new(params.to_vec()), loc: None }, list, warnings);
+                }
+            }
+            let mut items = vec![list[0].clone(), signature.clone()];
+            items.extend(optimize_body(body, warnings));
+            with_items(obj, items)
+        },
+        ("let" | "let*" | "letrec", [Object::List { value: bindings, .. }, body @ ..]) => {
+            match parse_bindings(bindings) {
+                Some(bindings) => optimize_let(obj, form, bindings, body, warnings),
+                None => obj.clone(),
+            }
+        },
+        // The calls and the special forms whose parts are all expressions
+        ("begin" | "when" | "unless" | "and" | "or" | "delay" | "define-test", _)
+        | ("define" | "set!", [Object::Symbol { .. }, _]) => with_items(obj, list.iter().map(|item| optimize(item, warnings)).collect()),
+        (
+            "define" | "set!" | "if" | "cond" | "case" | "lambda" | "defmacro" | "guard" | "parameterize"
+            | "define-record-type" | "let" | "let*" | "letrec" | "quote" | "quasiquote" | "unquote" | "unquote-splicing",
+            _,
+        ) => obj.clone(),
+        _ => with_items(obj, list.iter().map(|item| optimize(item, warnings)).collect()),
+    }
+}
+
+/// Whether the value of the expression is always true or always false
+fn constant_truth(obj: &Object) -> Option<bool> {
+    match obj {
+        Object::Bool { .. }
+        | Object::Integer { .. }
+        | Object::BigInteger { .. }
+        | Object::Rational { .. }
+        | Object::Float { .. }
+        | Object::Char { .. }
+        | Object::Str { .. }
+        | Object::Keyword { .. }
+        | Object::Vector { .. } => Some(is_truthy(obj)),
+        Object::List { value, .. } => match &value[..] {
+            [Object::Symbol { value: quote, .. }, quoted] if quote == "quote" => Some(is_truthy(quoted)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether evaluating the expression can be skipped, it neither
+/// has side effects nor raises errors
+fn is_pure(obj: &Object) -> bool {
+    match obj {
+        Object::List { value, .. } => matches!(
+            &value[..],
+            [Object::Symbol { value, .. }, _, ..] if value == "quote" || value == "lambda"
+        ),
+        Object::Symbol { .. } | Object::Pair { .. } | Object::Module { .. } => false,
+        _ => true,
+    }
+}
+
+/// Whether the symbol appears anywhere in the object
+pub(crate) fn occurs(name: Symbol, obj: &Object) -> bool {
+    // The nested objects are searched recursively, on more stack when needed
+    with_stack(|| match obj {
+        Object::Symbol { value, .. } => *value == name,
+        Object::List { value, .. } => value.iter().any(|item| occurs(name, item)),
+        Object::Vector { value, .. } => value.iter().any(|item| occurs(name, item)),
+        Object::Pair { value, .. } => occurs(name, &value.0) || occurs(name, &value.1),
+        Object::Module { value, .. } => value.iter().any(|item| occurs(name, item)),
+        _ => false,
+    })
+}
+
+fn count(name: Symbol, obj: &Object) -> usize {
+    with_stack(|| match obj {
+        Object::Symbol { value, .. } => usize::from(*value == name),
+        Object::List { value, .. } => value.iter().map(|item| count(name, item)).sum(),
+        Object::Vector { value, .. } => value.iter().map(|item| count(name, item)).sum(),
+        Object::Pair { value, .. } => count(name, &value.0) + count(name, &value.1),
+        _ => 0,
+    })
+}
+
+/// Names starting with an underscore are unused on purpose, and the
+/// symbols made by gensym come from macros rather than from the user
+pub(crate) fn is_reported(name: Symbol) -> bool {
+    !name.as_str().starts_with('_') && !name.as_str().starts_with('#')
+}
+
+fn with_items(obj: &Object, items: Vec<Object>) -> Object {
+    Object::List { value: ListItems::new(items), loc: obj.loc().cloned() }
+}
+
+/// Report the parameters appearing nowhere else in the function
+fn check_params(params: &Object, function: &[Object], warnings: &mut Vec<Warning>) {
+    let mut names = vec![];
+    let mut rest = params;
+    while let Object::Pair { value, .. } = rest {
+        names.push(&value.0);
+        rest = &value.1;
+    }
+    match rest {
+        Object::List { value, .. } => names.extend(value.iter()),
+        obj => names.push(obj),
+    }
+
+    for obj in names {
+        // Keyword parameters with a default are written (name default)
+        let (name, loc) = match obj {
+            Object::Symbol { value, loc } if value != "&key" => (*value, loc),
+            Object::List { value, .. } => match value.first() {
+                Some(Object::Symbol { value, loc }) => (*value, loc),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if is_reported(name) && function.iter().map(|item| count(name, item)).sum::<usize>() == 1 {
+            warnings.push(Warning::new(format!("unused parameter `{}`", name), loc.as_ref()));
+        }
+    }
+}
+
+/// Optimize the forms of a body and remove the internal definitions
+/// never referenced by the other forms, the last form gives the value
+/// of the body so it is always kept
+fn optimize_body(body: &[Object], warnings: &mut Vec<Warning>) -> Vec<Object> {
+    let forms: Vec<Object> = body.iter().map(|obj| optimize(obj, warnings)).collect();
+    let mut kept = Vec::with_capacity(forms.len());
+    for (i, form) in forms.iter().enumerate() {
+        let definition = match form {
+            Object::List { value, .. } if i + 1 < forms.len() => match &value[..] {
+                [Object::Symbol { value: define, .. }, Object::Symbol { value: name, loc }, expr] if define == "define" => {
+                    Some((*name, loc, is_pure(expr)))
+                },
+                [Object::Symbol { value: define, .. }, Object::List { value: signature, .. }, _, ..] if define == "define" => {
+                    match signature.first() {
+                        Some(Object::Symbol { value: name, loc }) => Some((*name, loc, true)),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some((name, loc, pure)) = definition {
+            let used = forms.iter().enumerate().any(|(j, other)| j != i && occurs(name, other));
+            if !used && is_reported(name) {
+                warnings.push(Warning::new(format!("unused definition `{}`", name), loc.as_ref()));
+            }
+            if !used && pure {
+                continue;
+            }
+        }
+        kept.push(form.clone());
+    }
+    kept
+}
+
+/// Check the clauses are written `(test expr ...)` with `else` last
+fn is_clauses(clauses: &[Object]) -> bool {
+    clauses.iter().enumerate().all(|(i, clause)| match clause {
+        Object::List { value, .. } => match value.first() {
+            Some(Object::Symbol { value, .. }) if value == "else" => i + 1 == clauses.len(),
+            Some(_) => true,
+            None => false,
+        },
+        _ => false,
+    })
+}
+
+fn optimize_cond(obj: &Object, clauses: &[Object], warnings: &mut Vec<Warning>) -> Object {
+    let list = match obj {
+        Object::List { value, .. } => value,
+        _ => unreachable!(),
+    };
+    let mut items = vec![list[0].clone()];
+    let mut clauses = clauses.iter();
+    for clause in clauses.by_ref() {
+        let clause = match clause {
+            Object::List { value, .. } => with_items(clause, value.iter().map(|item| optimize(item, warnings)).collect()),
+            _ => unreachable!(),
+        };
+        let truth = match &clause {
+            Object::List { value, .. } => match &value[0] {
+                Object::Symbol { value, .. } if value == "else" => Some(true),
+                test => constant_truth(test),
+            },
+            _ => unreachable!(),
+        };
+        match truth {
+            Some(false) => warnings.push(Warning::new("unreachable cond clause".to_string(), clause.loc())),
+            Some(true) => {
+                items.push(clause);
+                break;
+            },
+            None => items.push(clause),
+        }
+    }
+    // The clauses following a clause always taken
+    for clause in clauses {
+        warnings.push(Warning::new("unreachable cond clause".to_string(), clause.loc()));
+    }
+    with_items(obj, items)
+}
+
+/// Split `((name expr) ...)`, None if a binding is written another way
+fn parse_bindings(bindings: &[Object]) -> Option<Vec<(&Object, Symbol, &Object)>> {
+    bindings
+        .iter()
+        .map(|binding| match binding {
+            Object::List { value, .. } => match &value[..] {
+                [obj @ Object::Symbol { value: name, .. }, expr] => Some((obj, *name, expr)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn optimize_let(
+    obj: &Object,
+    form: &str,
+    bindings: Vec<(&Object, Symbol, &Object)>,
+    body: &[Object],
+    warnings: &mut Vec<Warning>,
+) -> Object {
+    let list = match obj {
+        Object::List { value, .. } => value,
+        _ => unreachable!(),
+    };
+    let exprs: Vec<Object> = bindings.iter().map(|(_, _, expr)| optimize(expr, warnings)).collect();
+    let body = optimize_body(body, warnings);
+
+    let mut kept = vec![];
+    for (i, (name_obj, name, _)) in bindings.iter().enumerate() {
+        // The scope of the binding depends on the form
+        let in_exprs = match form {
+            "let*" => exprs[i + 1..].iter().any(|expr| occurs(*name, expr)),
+            "letrec" => exprs.iter().enumerate().any(|(j, expr)| j != i && occurs(*name, expr)),
+            _ => false,
+        };
+        let used = in_exprs || body.iter().any(|obj| occurs(*name, obj));
+        if !used && is_reported(*name) {
+            warnings.push(Warning::new(format!("unused variable `{}`", name), name_obj.loc()));
+        }
+        if used || !is_pure(&exprs[i]) {
+            let binding = vec![(*name_obj).clone(), exprs[i].clone()];
+            kept.push(Object::List { value: ListItems::new(binding), loc: name_obj.loc().cloned() });
+        }
+    }
+
+    let mut items = vec![list[0].clone(), Object::List { value: ListItems::new(kept), loc: list[1].loc().cloned() }];
+    items.extend(body);
+    with_items(obj, items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+
+    fn run(prog: &str) -> (String, Vec<String>) {
+        let mut tokens = tokenize("<test>", prog).unwrap();
+        let program = match &parse(&mut tokens).unwrap() {
+            Object::Module { value, .. } => value.clone(),
+            obj => panic!("Expect a module but {} found", obj),
+        };
+        let mut warnings = vec![];
+        let optimized = optimize(&program[0], &mut warnings);
+        (show(&optimized), warnings.into_iter().map(|w| w.message).collect())
+    }
+
+    fn show(obj: &Object) -> String {
+        match obj {
+            Object::List { value, .. } => format!("({})", value.iter().map(show).collect::<Vec<_>>().join(" ")),
+            obj => obj.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_unused_bindings() {
+        let (obj, warnings) = run("(let ((x 1) (y (display 2)) (_z 3) (w 4)) w)");
+        assert_eq!(obj, "(let ((y (display 2)) (w 4)) w)");
+        assert_eq!(warnings, ["unused variable `x`", "unused variable `y`"]);
+
+        // Only the later bindings of let* and every binding of letrec see a binding
+        let (obj, _) = run("(let* ((x 1) (y x) (z 2)) y)");
+        assert_eq!(obj, "(let* ((x 1) (y x)) y)");
+        let (obj, warnings) = run("(letrec ((even? (lambda (n) (odd? n))) (odd? (lambda (n) (even? n)))) 0)");
+        assert_eq!(obj, "(letrec ((even? (lambda (n) (odd? n))) (odd? (lambda (n) (even? n)))) 0)");
+        assert!(warnings.is_empty());
+
+        // Quoted data may still be evaluated
+        let (obj, _) = run("(let ((x 1)) (eval 'x))");
+        assert_eq!(obj, "(let ((x 1)) (eval (quote x)))");
+
+        let (obj, warnings) = run("(lambda (a b) (define (helper) a) (define n (read)) (define m 1) m)");
+        assert_eq!(obj, "(lambda (a b) (define n (read)) (define m 1) m)");
+        assert_eq!(warnings, [
+            "unused parameter `b`",
+            "unused definition `helper`",
+            "unused definition `n`",
+        ]);
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        let (obj, warnings) = run("(if #t (f 1) (f 2))");
+        assert_eq!(obj, "(f 1)");
+        assert_eq!(warnings, ["unreachable if branch"]);
+        let (obj, _) = run("(if '#f 1 (if 0 2 3))");
+        assert_eq!(obj, "2");
+        let (obj, warnings) = run("(if x 1)");
+        assert_eq!(obj, "(if x 1)");
+        assert!(warnings.is_empty());
+
+        let (obj, warnings) = run("(cond (#f 1) (x 2) (\"yes\" 3) (y 4) (else 5))");
+        assert_eq!(obj, "(cond (x 2) (yes 3))");
+        assert_eq!(warnings.len(), 3);
+        // An else clause not in the last place is left to the evaluator
+        let (obj, _) = run("(cond (else 1) (x 2))");
+        assert_eq!(obj, "(cond (else 1) (x 2))");
+
+        // Quoted forms are data
+        let (obj, warnings) = run("(quote (if #t 1 2))");
+        assert_eq!(obj, "(quote (if true 1 2))");
+        assert!(warnings.is_empty());
+    }
+}
@@ -1,11 +1,35 @@
-use std::collections::VecDeque;
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::error::{LexError, ParseError};
 use crate::location::Location;
-use crate::lexer::{Token, TokenKind};
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::evaluator::{with_stack, Environment};
+use crate::native::NativeFunction;
+use crate::symbol::Symbol;
+
+/// The head of the forms read from `#d datum`, see [`crate::evaluator::expand`]
+pub const READER_MACRO: &str = "reader-macro";
 
 #[derive(Debug, Clone)]
 pub struct FunctionDefinition {
     pub params: Vec<Param>,
     pub body: FunctionBody,
+    /// The environment captured when the lambda is created
+    pub env: Rc<RefCell<Environment>>,
+    pub name: Option<String>,
+}
+
+impl FunctionDefinition {
+    /// The name shown in the backtraces
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or("lambda")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -16,13 +40,301 @@ pub struct Param {
 
 #[derive(Debug, Clone)]
 pub enum ParamKind {
-    Named(String),
-    Variadic,
+    Named(Symbol),
+    /// The rest parameter bound to the list of the remaining arguments,
+    /// always the last parameter
+    Variadic(Symbol),
+    /// A parameter declared after `&key`, given as `:name value` at the
+    /// call site, the default is evaluated when the argument is missing
+    Keyword { name: Symbol, default: Option<Object> },
 }
 
 #[derive(Debug, Clone)]
 pub struct FunctionBody(pub Vec<Object>);
 
+/// The type created by `define-record-type`, records are of
+/// the same type only if they share the same `RecordType`
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub kind: Rc<RecordType>,
+    /// The values in the order of `kind.fields`
+    pub fields: Vec<Object>,
+}
+
+/// The error object raised by `error` or made of an error of the
+/// interpreter when it is caught by `guard`
+#[derive(Debug)]
+pub struct ErrorObject {
+    pub message: String,
+    pub irritants: Vec<Object>,
+}
+
+/// The state of a promise created by `delay`
+#[derive(Debug)]
+pub enum Promise {
+    /// The expression and the environment it is evaluated in
+    Delayed(Object, Rc<RefCell<Environment>>),
+    /// The value remembered after the first `force`
+    Forced(Object),
+}
+
+/// A parameter object created by `make-parameter`, its value is changed
+/// for the extent of a `parameterize` body
+#[derive(Debug)]
+pub struct Parameter {
+    pub value: RefCell<Object>,
+    /// The function the values given to `parameterize` are passed through
+    pub converter: Option<Object>,
+}
+
+/// The items of a list. `cdr` shares them with the list it is taken from
+/// and `cons` makes a cell holding the new item and the list it is put in
+/// front of, so both take constant time
+#[derive(Clone)]
+pub struct ListItems(Items);
+
+#[derive(Clone, Default)]
+pub(crate) enum Items {
+    #[default]
+    Empty,
+    /// The items of the vector from the index on, the vector is shared
+    /// by the lists taken from the same list
+    Slice(Rc<Vec<Object>>, usize),
+    Cons(Rc<ConsCell>),
+}
+
+/// An item put in front of a list by `cons`
+pub(crate) struct ConsCell {
+    pub(crate) car: Object,
+    pub(crate) cdr: ListItems,
+    len: usize,
+    /// Every item of the list next to each other, copied the first time
+    /// the list is borrowed as a slice
+    pub(crate) items: OnceCell<Vec<Object>>,
+}
+
+impl Drop for ConsCell {
+    fn drop(&mut self) {
+        // The cells no other list holds are dropped one after the other
+        // rather than recursively, so long lists do not exhaust the stack
+        let mut next = std::mem::take(&mut self.cdr);
+        while let Items::Cons(cell) = next.0 {
+            match Rc::try_unwrap(cell) {
+                Ok(mut cell) => next = std::mem::take(&mut cell.cdr),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl ListItems {
+    pub fn new(items: Vec<Object>) -> Self {
+        match items.is_empty() {
+            true => ListItems(Items::Empty),
+            false => ListItems(Items::Slice(Rc::new(items), 0)),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Object] {
+        match &self.0 {
+            Items::Empty => &[],
+            Items::Slice(items, start) => &items[*start..],
+            Items::Cons(cell) => cell.items.get_or_init(|| {
+                let mut items = Vec::with_capacity(cell.len);
+                items.push(cell.car.clone());
+                let mut next = &cell.cdr;
+                loop {
+                    match &next.0 {
+                        Items::Cons(cell) => match cell.items.get() {
+                            Some(rest) => break items.extend(rest.iter().cloned()),
+                            None => {
+                                items.push(cell.car.clone());
+                                next = &cell.cdr;
+                            },
+                        },
+                        Items::Empty | Items::Slice(..) => break items.extend(next.as_slice().iter().cloned()),
+                    }
+                }
+                items
+            }),
+        }
+    }
+
+    /// The number of items, without copying the items of the cells
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            Items::Empty => 0,
+            Items::Slice(items, start) => items.len() - start,
+            Items::Cons(cell) => cell.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn first(&self) -> Option<&Object> {
+        match &self.0 {
+            Items::Empty => None,
+            Items::Slice(items, start) => items.get(*start),
+            Items::Cons(cell) => Some(&cell.car),
+        }
+    }
+
+    /// The items but the first one, without copying them
+    pub fn rest(&self) -> Self {
+        match &self.0 {
+            Items::Empty => ListItems(Items::Empty),
+            Items::Slice(items, start) => ListItems(Items::Slice(items.clone(), (start + 1).min(items.len()))),
+            Items::Cons(cell) => cell.cdr.clone(),
+        }
+    }
+
+    /// The item followed by the items, which are shared rather than copied
+    pub fn cons(&self, item: Object) -> Self {
+        ListItems(Items::Cons(Rc::new(ConsCell {
+            car: item,
+            cdr: self.clone(),
+            len: self.len() + 1,
+            items: OnceCell::new(),
+        })))
+    }
+
+    /// Whether both are the same items of the same list
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        match (&a.0, &b.0) {
+            (Items::Empty, Items::Empty) => true,
+            (Items::Slice(a, i), Items::Slice(b, j)) => Rc::ptr_eq(a, b) && i == j,
+            (Items::Cons(a), Items::Cons(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Whether no other list holds the items
+    fn is_unique(&self) -> bool {
+        match &self.0 {
+            Items::Empty => false,
+            Items::Slice(items, _) => Rc::strong_count(items) == 1,
+            Items::Cons(cell) => Rc::strong_count(cell) == 1,
+        }
+    }
+
+    /// How the items are held, e.g. to walk the cells one after the other
+    pub(crate) fn items(&self) -> &Items {
+        &self.0
+    }
+}
+
+impl Default for ListItems {
+    fn default() -> Self {
+        ListItems(Items::Empty)
+    }
+}
+
+impl std::ops::Deref for ListItems {
+    type Target = [Object];
+
+    fn deref(&self) -> &[Object] {
+        self.as_slice()
+    }
+}
+
+impl From<Vec<Object>> for ListItems {
+    fn from(items: Vec<Object>) -> Self {
+        ListItems::new(items)
+    }
+}
+
+impl FromIterator<Object> for ListItems {
+    fn from_iter<I: IntoIterator<Item = Object>>(iter: I) -> Self {
+        ListItems::new(iter.into_iter().collect())
+    }
+}
+
+impl std::fmt::Debug for ListItems {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A source or a sink of characters, the reader or the
+/// writer is dropped when the port is closed
+pub enum Port {
+    Input { name: String, reader: Option<Box<dyn std::io::BufRead>> },
+    Output { name: String, writer: Option<Box<dyn std::io::Write>> },
+}
+
+impl Port {
+    pub fn name(&self) -> &str {
+        match self {
+            Port::Input { name, .. } | Port::Output { name, .. } => name,
+        }
+    }
+}
+
+impl std::fmt::Debug for Port {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Port::Input { name, reader } => f.debug_struct("Input").field("name", name).field("open", &reader.is_some()).finish(),
+            Port::Output { name, writer } => f.debug_struct("Output").field("name", name).field("open", &writer.is_some()).finish(),
+        }
+    }
+}
+
+/// The objects which can be used as keys of a hash table, floats
+/// are compared by their bits so NaN can be used as a key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i128),
+    BigInteger(BigInt),
+    Rational(BigRational),
+    Float(u64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Symbol(Symbol),
+    Keyword(Symbol),
+}
+
+impl HashKey {
+    /// None for objects which cannot be hashed, e.g. lists and functions
+    pub fn from_object(obj: &Object) -> Option<Self> {
+        let key = match obj {
+            Object::Integer { value, .. } => HashKey::Integer(*value),
+            Object::BigInteger { value, .. } => HashKey::BigInteger(value.clone()),
+            Object::Rational { value, .. } => HashKey::Rational(value.clone()),
+            Object::Float { value, .. } => HashKey::Float(value.to_bits()),
+            Object::Bool { value, .. } => HashKey::Bool(*value),
+            Object::Char { value, .. } => HashKey::Char(*value),
+            Object::Str { value, .. } => HashKey::Str(value.clone()),
+            Object::Symbol { value, .. } => HashKey::Symbol(*value),
+            Object::Keyword { value, .. } => HashKey::Keyword(*value),
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    pub fn to_object(&self) -> Object {
+        match self {
+            HashKey::Integer(value) => Object::Integer { value: *value, loc: None },
+            HashKey::BigInteger(value) => Object::BigInteger { value: value.clone(), loc: None },
+            HashKey::Rational(value) => Object::Rational { value: value.clone(), loc: None },
+            HashKey::Float(bits) => Object::Float { value: f64::from_bits(*bits), loc: None },
+            HashKey::Bool(value) => Object::Bool { value: *value, loc: None },
+            HashKey::Char(value) => Object::Char { value: *value, loc: None },
+            HashKey::Str(value) => Object::Str { value: value.clone(), loc: None },
+            HashKey::Symbol(value) => Object::Symbol { value: *value, loc: None },
+            HashKey::Keyword(value) => Object::Keyword { value: *value, loc: None },
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Object {
     Void {
@@ -32,6 +344,15 @@ pub enum Object {
         value: i128,
         loc: Option<Location>
     },
+    /// Integers which do not fit in i128
+    BigInteger {
+        value: BigInt,
+        loc: Option<Location>
+    },
+    Rational {
+        value: BigRational,
+        loc: Option<Location>
+    },
     Float {
         value: f64,
         loc: Option<Location>
@@ -40,20 +361,99 @@ pub enum Object {
         value: bool,
         loc: Option<Location>
     },
+    Char {
+        value: char,
+        loc: Option<Location>
+    },
     Str {
         value: String,
         loc: Option<Location>
     },
     Symbol {
-        value: String,
+        value: Symbol,
+        loc: Option<Location>
+    },
+    /// A reference to a local variable resolved to the slot of its frame,
+    /// only found in the forms being evaluated, see [`crate::resolve`]
+    Local {
+        value: Symbol,
+        depth: usize,
+        slot: usize,
+        loc: Option<Location>
+    },
+    /// `:name` which evaluates to itself, the value is the name without
+    /// the colon
+    Keyword {
+        value: Symbol,
         loc: Option<Location>
     },
+    // Functions, lists, vectors and pairs are shared by reference so
+    // looking them up or passing them around never copies them
     Lambda {
-        value: FunctionDefinition,
+        value: Rc<FunctionDefinition>,
+        loc: Option<Location>
+    },
+    Macro {
+        value: Rc<FunctionDefinition>,
+        loc: Option<Location>
+    },
+    /// A function implemented in Rust, see [`crate::native`]
+    NativeFunction {
+        value: Rc<NativeFunction>,
         loc: Option<Location>
     },
     List {
-        value: Vec<Object>,
+        value: ListItems,
+        loc: Option<Location>
+    },
+    /// Fixed-length sequence with constant time indexing, written as `#(1 2 3)`
+    Vector {
+        value: Rc<Vec<Object>>,
+        loc: Option<Location>
+    },
+    /// A mutable table shared by every copy of the object
+    HashTable {
+        value: Rc<RefCell<HashMap<HashKey, Object>>>,
+        loc: Option<Location>
+    },
+    RecordType {
+        value: Rc<RecordType>,
+        loc: Option<Location>
+    },
+    /// An instance of a record type, mutated in place by the field modifiers
+    Record {
+        value: Rc<RefCell<Record>>,
+        loc: Option<Location>
+    },
+    Error {
+        value: Rc<ErrorObject>,
+        loc: Option<Location>
+    },
+    Promise {
+        value: Rc<RefCell<Promise>>,
+        loc: Option<Location>
+    },
+    Port {
+        value: Rc<RefCell<Port>>,
+        loc: Option<Location>
+    },
+    Parameter {
+        value: Rc<Parameter>,
+        loc: Option<Location>
+    },
+    /// The object read at the end of an input port
+    Eof {
+        loc: Option<Location>
+    },
+    /// An environment made available to the programs, e.g. for `eval`
+    Environment {
+        value: Rc<RefCell<Environment>>,
+        loc: Option<Location>
+    },
+    /// A cons cell whose cdr is not a list, cells whose cdr is
+    /// a list are always represented by `List`
+    Pair {
+        value: Rc<(Object, Object)>,
         loc: Option<Location>
     },
     Module {
@@ -67,58 +467,402 @@ impl Object {
         let location = match self {
             Object::Void { loc } => loc,
             Object::Integer { loc, .. } => loc,
+            Object::BigInteger { loc, .. } => loc,
+            Object::Rational { loc, .. } => loc,
             Object::Float { loc, .. } => loc,
             Object::Bool { loc, .. } => loc,
+            Object::Char { loc, .. } => loc,
             Object::Str { loc, .. } => loc,
             Object::Symbol { loc, .. } => loc,
+            Object::Local { loc, .. } => loc,
+            Object::Keyword { loc, .. } => loc,
             Object::Lambda { loc, .. } => loc,
+            Object::Macro { loc, .. } => loc,
+            Object::NativeFunction { loc, .. } => loc,
             Object::List { loc, .. } => loc,
+            Object::Vector { loc, .. } => loc,
+            Object::HashTable { loc, .. } => loc,
+            Object::RecordType { loc, .. } => loc,
+            Object::Record { loc, .. } => loc,
+            Object::Error { loc, .. } => loc,
+            Object::Promise { loc, .. } => loc,
+            Object::Port { loc, .. } => loc,
+            Object::Parameter { loc, .. } => loc,
+            Object::Eof { loc } => loc,
+            Object::Environment { loc, .. } => loc,
+            Object::Pair { loc, .. } => loc,
             Object::Module { loc, .. } => loc,
         };
 
         location.as_ref()
     }
+
+    /// Whether both objects are the same object, shared objects like lists and
+    /// functions are compared by reference. Objects which are copied when passed
+    /// around, e.g. numbers, characters and strings, are compared by value and
+    /// numbers are only the same when they have the same exactness
+    pub fn is_eqv(&self, other: &Object) -> bool {
+        match (self, other) {
+            (Object::Void { .. }, Object::Void { .. }) | (Object::Eof { .. }, Object::Eof { .. }) => true,
+            (Object::Integer { value: a, .. }, Object::Integer { value: b, .. }) => a == b,
+            (Object::BigInteger { value: a, .. }, Object::BigInteger { value: b, .. }) => a == b,
+            (Object::Rational { value: a, .. }, Object::Rational { value: b, .. }) => a == b,
+            (Object::Float { value: a, .. }, Object::Float { value: b, .. }) => a.to_bits() == b.to_bits(),
+            (Object::Bool { value: a, .. }, Object::Bool { value: b, .. }) => a == b,
+            (Object::Char { value: a, .. }, Object::Char { value: b, .. }) => a == b,
+            (Object::Str { value: a, .. }, Object::Str { value: b, .. }) => a == b,
+            (Object::Symbol { value: a, .. }, Object::Symbol { value: b, .. }) => a == b,
+            (Object::Keyword { value: a, .. }, Object::Keyword { value: b, .. }) => a == b,
+            (Object::Lambda { value: a, .. }, Object::Lambda { value: b, .. })
+            | (Object::Macro { value: a, .. }, Object::Macro { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::NativeFunction { value: a, .. }, Object::NativeFunction { value: b, .. }) => Rc::ptr_eq(a, b),
+            // Every empty list is the same object
+            (Object::List { value: a, .. }, Object::List { value: b, .. }) => ListItems::ptr_eq(a, b) || (a.is_empty() && b.is_empty()),
+            (Object::Vector { value: a, .. }, Object::Vector { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::HashTable { value: a, .. }, Object::HashTable { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::RecordType { value: a, .. }, Object::RecordType { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Record { value: a, .. }, Object::Record { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Error { value: a, .. }, Object::Error { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Promise { value: a, .. }, Object::Promise { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Port { value: a, .. }, Object::Port { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Parameter { value: a, .. }, Object::Parameter { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Environment { value: a, .. }, Object::Environment { value: b, .. }) => Rc::ptr_eq(a, b),
+            (Object::Pair { value: a, .. }, Object::Pair { value: b, .. }) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Whether both objects have the same structure, lists, pairs and vectors
+    /// are compared element by element and any other object with `is_eqv`
+    pub fn is_equal(&self, other: &Object) -> bool {
+        let equal = |a: &[Object], b: &[Object]| a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.is_equal(b));
+        // The nested objects are compared recursively, on more stack when needed
+        with_stack(|| match (self, other) {
+            (Object::List { value: a, .. }, Object::List { value: b, .. }) => equal(a, b),
+            (Object::Vector { value: a, .. }, Object::Vector { value: b, .. }) => equal(a, b),
+            (Object::Pair { value: a, .. }, Object::Pair { value: b, .. }) => a.0.is_equal(&b.0) && a.1.is_equal(&b.1),
+            (a, b) => a.is_eqv(b),
+        })
+    }
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        // The objects held by the last holder of a list, a vector or a pair
+        // are dropped recursively, on more stack when needed
+        match self {
+            Object::List { value, .. } if value.is_unique() => {
+                let items = std::mem::take(value);
+                with_stack(|| drop(items));
+            },
+            Object::Vector { value, .. } => if let Some(items) = Rc::get_mut(value) {
+                let items = std::mem::take(items);
+                with_stack(|| drop(items));
+            },
+            Object::Pair { value, .. } => if let Some(pair) = Rc::get_mut(value) {
+                let pair = std::mem::replace(pair, (Object::Void { loc: None }, Object::Void { loc: None }));
+                with_stack(|| drop(pair));
+            },
+            _ => {},
+        }
+    }
 }
 
 impl std::fmt::Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        Printer { write: false, labels: &mut Labels::new(self) }.print(self, f)
+    }
+}
+
+/// The printed representation used by `write`, which is read back as an
+/// equal object for the data, e.g. the strings are quoted and escaped and
+/// the characters are written `#\a`, while [`Object`] is displayed the way
+/// `display` prints it
+///
+/// ```
+/// use rslisp::parser::Written;
+/// use rslisp::Interpreter;
+///
+/// let obj = Interpreter::new().eval_str("(list \"a \\\"b\\\"\" #\\c 1.0 (vector 'd))").unwrap();
+/// assert_eq!(obj.to_string(), "(a \"b\" c 1 #(d))");
+/// assert_eq!(Written(&obj).to_string(), "(\"a \\\"b\\\"\" #\\c 1.0 #(d))");
+/// ```
+pub struct Written<'a>(pub &'a Object);
+
+impl Written<'_> {
+    /// Whether a Void is found in the data, no text is read back as Void
+    /// so it cannot be written. Only the lists, the vectors and the pairs
+    /// are searched, the other containers are not written as data anyway
+    pub fn has_void(&self) -> bool {
+        let mut pending = vec![self.0];
+        while let Some(obj) = pending.pop() {
+            match obj {
+                Object::Void { .. } => return true,
+                Object::List { value, .. } => pending.extend(value.iter()),
+                Object::Vector { value, .. } => pending.extend(value.iter()),
+                Object::Pair { value, .. } => pending.extend([&value.0, &value.1]),
+                _ => {},
+            }
+        }
+        false
+    }
+}
+
+impl std::fmt::Display for Written<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Printer { write: true, labels: &mut Labels::new(self.0) }.print(self.0, f)
+    }
+}
+
+/// The label of a container which is part of a cycle, printed `#0=` before
+/// the container the first time and `#0#` in place of it afterwards
+pub(crate) enum Label {
+    Define(usize),
+    Reference(usize),
+}
+
+/// The labels of the containers found in a cycle of an object. Only the
+/// hash tables and the records can be changed to hold themselves
+pub(crate) struct Labels {
+    cyclic: HashSet<usize>,
+    labels: HashMap<usize, usize>,
+}
+
+impl Labels {
+    pub(crate) fn new(obj: &Object) -> Self {
+        let mut cyclic = HashSet::new();
+        find_cycles(obj, &mut vec![], &mut HashSet::new(), &mut cyclic);
+        Labels { cyclic, labels: HashMap::new() }
+    }
+
+    /// The label to print for the object, None if it is not in a cycle
+    pub(crate) fn label(&mut self, obj: &Object) -> Option<Label> {
+        let address = container(obj).filter(|address| self.cyclic.contains(address))?;
+        match self.labels.get(&address) {
+            Some(n) => Some(Label::Reference(*n)),
+            None => {
+                let n = self.labels.len();
+                self.labels.insert(address, n);
+                Some(Label::Define(n))
+            },
+        }
+    }
+}
+
+/// Write the string quoted, with the escape sequences the reader knows
+/// in place of the characters which cannot be read back as they are
+fn write_string(value: &str, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:X}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// The address of the objects which can be changed to hold other objects
+fn container(obj: &Object) -> Option<usize> {
+    match obj {
+        Object::HashTable { value, .. } => Some(Rc::as_ptr(value) as *const () as usize),
+        Object::Record { value, .. } => Some(Rc::as_ptr(value) as *const () as usize),
+        _ => None,
+    }
+}
+
+/// The objects held by the object which are printed
+fn children(obj: &Object) -> Vec<Object> {
+    match obj {
+        Object::List { value, .. } => value.to_vec(),
+        Object::Vector { value, .. } => value.to_vec(),
+        Object::Module { value, .. } => value.clone(),
+        Object::Pair { value, .. } => vec![value.0.clone(), value.1.clone()],
+        Object::HashTable { value, .. } => value.borrow().values().cloned().collect(),
+        Object::Record { value, .. } => value.borrow().fields.clone(),
+        Object::Error { value, .. } => value.irritants.clone(),
+        _ => vec![],
+    }
+}
+
+/// Collect the containers reached again while walking through them, each
+/// container is only walked through once so shared data is not walked again
+fn find_cycles(obj: &Object, path: &mut Vec<usize>, visited: &mut HashSet<usize>, cyclic: &mut HashSet<usize>) {
+    let address = container(obj);
+    if let Some(address) = address {
+        if path.contains(&address) {
+            cyclic.insert(address);
+        }
+        if !visited.insert(address) {
+            return;
+        }
+        path.push(address);
+    }
+    for child in children(obj).iter() {
+        with_stack(|| find_cycles(child, path, visited, cyclic));
+    }
+    if address.is_some() {
+        path.pop();
+    }
+}
+
+/// The object printed the way `write` does, the containers in a cycle are
+/// labelled by `labels` which may already have labelled the enclosing ones
+pub(crate) fn written_with(obj: &Object, labels: &mut Labels) -> String {
+    let mut text = String::new();
+    let _ = Printer { write: true, labels }.print(obj, &mut text);
+    text
+}
+
+struct Printer<'a> {
+    /// Print the objects the way `write` does rather than `display`
+    write: bool,
+    labels: &'a mut Labels,
+}
+
+impl Printer<'_> {
+    fn items(&mut self, items: &[Object], f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            self.print(item, f)?;
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, obj: &Object, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        // The nested objects are printed recursively, on more stack when needed
+        with_stack(|| self.print_object(obj, f))
+    }
+
+    fn print_object(&mut self, obj: &Object, f: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        match self.labels.label(obj) {
+            Some(Label::Reference(n)) => return write!(f, "#{}#", n),
+            Some(Label::Define(n)) => write!(f, "#{}=", n)?,
+            None => {},
+        }
+        let write = self.write;
+        match obj {
             Object::Void { .. } => write!(f, "Void"),
             Object::Integer { value, .. } => write!(f, "{}", value),
+            Object::BigInteger { value, .. } => write!(f, "{}", value),
+            Object::Rational { value, .. } => write!(f, "{}", value),
+            // A float without fraction is read back as a float
+            Object::Float { value, .. } if write && value.is_finite() && value.fract() == 0.0 => write!(f, "{:.1}", value),
             Object::Float { value, .. } => write!(f, "{}", value),
             Object::Bool { value, .. } => write!(f, "{}", value),
+            Object::Char { value, .. } if write => match value {
+                ' ' => write!(f, "#\\space"),
+                '\n' => write!(f, "#\\newline"),
+                '\t' => write!(f, "#\\tab"),
+                '\r' => write!(f, "#\\return"),
+                '\0' => write!(f, "#\\nul"),
+                c => write!(f, "#\\{}", c),
+            },
+            Object::Char { value, .. } => write!(f, "{}", value),
+            Object::Str { value, .. } if write => write_string(value, f),
             Object::Str { value, .. } => write!(f, "{}", value),
-            Object::Symbol { value, .. } => write!(f, "{}", value),
-            Object::Lambda { value, .. } => write!(f, "{:?}", value),
-            Object::List { value, .. } => write!(f, "{:?}", value),
-            Object::Module { value, .. } => write!(f, "{:?}", value),
+            Object::Symbol { value, .. } | Object::Local { value, .. } => write!(f, "{}", value),
+            Object::Keyword { value, .. } => write!(f, ":{}", value),
+            Object::Lambda { value, .. } => match &value.name {
+                Some(name) => write!(f, "#<procedure {}>", name),
+                None => write!(f, "#<procedure>"),
+            },
+            Object::Macro { value, .. } => write!(f, "#<macro {}>", value.label()),
+            Object::NativeFunction { value, .. } => write!(f, "#<procedure {}>", value.name),
+            Object::List { value, .. } => {
+                write!(f, "(")?;
+                self.items(value, f)?;
+                write!(f, ")")
+            },
+            Object::Vector { value, .. } => {
+                write!(f, "#(")?;
+                self.items(value, f)?;
+                write!(f, ")")
+            },
+            Object::HashTable { value, .. } => {
+                write!(f, "#hash(")?;
+                for (i, (key, item)) in value.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "(")?;
+                    self.print(&key.to_object(), f)?;
+                    write!(f, " . ")?;
+                    self.print(item, f)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            },
+            Object::RecordType { value, .. } => write!(f, "#<record-type {}>", value.name),
+            Object::Record { value, .. } => {
+                let record = value.borrow();
+                write!(f, "#<{}", record.kind.name)?;
+                for field in record.fields.iter() {
+                    write!(f, " ")?;
+                    self.print(field, f)?;
+                }
+                write!(f, ">")
+            },
+            Object::Error { value, .. } => {
+                write!(f, "#<error {:?}", value.message)?;
+                for irritant in value.irritants.iter() {
+                    write!(f, " ")?;
+                    self.print(irritant, f)?;
+                }
+                write!(f, ">")
+            },
+            Object::Promise { .. } => write!(f, "#<promise>"),
+            Object::Port { value, .. } => match &*value.borrow() {
+                Port::Input { name, .. } => write!(f, "#<input-port {}>", name),
+                Port::Output { name, .. } => write!(f, "#<output-port {}>", name),
+            },
+            Object::Parameter { .. } => write!(f, "#<parameter>"),
+            Object::Eof { .. } => write!(f, "#<eof>"),
+            Object::Environment { .. } => write!(f, "#<environment>"),
+            Object::Pair { value, .. } => {
+                // Nested pairs are printed as one improper list, e.g. (1 2 . 3)
+                write!(f, "(")?;
+                self.print(&value.0, f)?;
+                let mut cdr = &value.1;
+                while let Object::Pair { value, .. } = cdr {
+                    write!(f, " ")?;
+                    self.print(&value.0, f)?;
+                    cdr = &value.1;
+                }
+                write!(f, " . ")?;
+                self.print(cdr, f)?;
+                write!(f, ")")
+            },
+            // The forms of a file, one per line
+            Object::Module { value, .. } => {
+                for (i, item) in value.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    self.print(item, f)?;
+                }
+                Ok(())
+            },
         }
     }
 }
 
 /// Error
-/// 1. Unclosed List
+/// 1. Unclosed List, reported at its left parenthesis
 /// 2. Unexpected right parenthesis e.g. ), ())
-pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Object, String> {
-    // Assume the left parenthesis `(` has been taken
+pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
     let mut objects = VecDeque::new();
 
-    while let Some(token) = tokens.pop_front() {
-        let loc = token.loc().clone();
-        match token.kind() {
-            &TokenKind::Comment(_) | TokenKind::IGNORE => continue,
-            &TokenKind::UNKNOWN => return Err(format!("Unknown symbols found at {}", token.loc())),
-            &TokenKind::Float(n) => objects.push_back(Object::Float { value: n, loc: Some(loc) }),
-            &TokenKind::Integer(n) => objects.push_back(Object::Integer { value: n, loc: Some(loc) }),
-            &TokenKind::Str(ref s) => objects.push_back(Object::Str { value: s.clone(), loc: Some(loc) }),
-            &TokenKind::Symbol(ref s) => objects.push_back(Object::Symbol { value: s.clone(), loc: Some(loc) }),
-            &TokenKind::LeftParenthesis => {
-                let list = parse_list(tokens)?;
-                objects.push_back(Object::List{ value: Vec::from_iter(list), loc: None });
-            },
-            &TokenKind::RightParenthesis => return Err(format!(
-                "Unexpected Right parenthesis `)` at {}", token.loc()))
-        }
+    while let Some(object) = parse_datum(tokens)? {
+        objects.push_back(object)
     }
 
     Ok(Object::Module {
@@ -127,58 +871,416 @@ pub fn parse(tokens: &mut VecDeque<Token>) -> Result<Object, String> {
     })
 }
 
-pub fn parse_list(tokens: &mut VecDeque<Token>) -> Result<VecDeque<Object>, String> {
-    // Assume the left parenthesis `(` has been taken
-    let mut objects = VecDeque::new();
+/// Take the tokens of the next datum, comments are skipped.
+/// Return None when no datum is left
+pub fn parse_datum(tokens: &mut VecDeque<Token>) -> Result<Option<Object>, ParseError> {
+    while let Some(token) = tokens.pop_front() {
+        match token.kind() {
+            TokenKind::Comment(_) | TokenKind::IGNORE => continue,
+            TokenKind::DatumComment => { parse_quoted(&token, tokens)?; },
+            TokenKind::RightParenthesis => return Err(ParseError::UnexpectedRightParenthesis {
+                loc: token.loc().clone()
+            }),
+            &TokenKind::ClosingBracket(found) => return Err(ParseError::UnexpectedClosingBracket {
+                found,
+                loc: token.loc().clone()
+            }),
+            _ => return parse_object(&token, tokens).map(Some)
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the source holds whole expressions, false if more input
+/// could complete it: a list, a string or a block comment left open, or
+/// a quote at the end. Sources with other errors are complete, reading
+/// more would not fix them
+pub fn is_complete(source: &str) -> bool {
+    let mut tokens = match tokenize("", source) {
+        Ok(tokens) => tokens,
+        Err(LexError::UnterminatedString { .. } | LexError::UnterminatedComment { .. }) => return false,
+        Err(_) => return true
+    };
+    let last = tokens.iter().rev().find(|token| !matches!(token.kind(), TokenKind::Comment(_) | TokenKind::IGNORE));
+    if let Some(token) = last {
+        if matches!(token.kind(), TokenKind::Quote
+            | TokenKind::Quasiquote
+            | TokenKind::Unquote
+            | TokenKind::UnquoteSplicing
+            | TokenKind::DatumComment
+            | TokenKind::Dispatch(_)) {
+            return false;
+        }
+    }
+    !matches!(parse(&mut tokens), Err(ParseError::UnclosedList { .. }))
+}
+
+/// Build the list opened by the left parenthesis `open`, which has been
+/// taken from `tokens`. The list spans from `open` to the right parenthesis,
+/// or to the bracket closing `#{` or `#[`
+pub fn parse_list(open: &Token, tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
+    let mut objects = Vec::new();
+
+    while let Some(token) = tokens.pop_front() {
+        match token.kind() {
+            TokenKind::Comment(_) | TokenKind::IGNORE => continue,
+            TokenKind::DatumComment => { parse_quoted(&token, tokens)?; },
+            _ if closes(open, &token) => return Ok(Object::List {
+                value: ListItems::new(objects),
+                loc: Some(open.loc().to(token.loc()))
+            }),
+            TokenKind::RightParenthesis | TokenKind::ClosingBracket(_) => return Err(unexpected(&token)),
+            TokenKind::Symbol(s) if s == "." => return parse_dotted_tail(open, &token, objects, tokens),
+            _ => objects.push(parse_object(&token, tokens)?)
+        }
+    }
+    // Running out of tokens, the innermost unclosed list is reported
+    Err(ParseError::UnclosedList { loc: open.loc().clone() })
+}
 
-    // The last token is use for determining if
-    // a list is properly closed
-    let mut last_token: Option<Token> = None;
+/// Build the improper list `(a b . c)` after the dot has been taken,
+/// exactly one expression must follow the dot before the right parenthesis
+fn parse_dotted_tail(open: &Token, dot: &Token, objects: Vec<Object>, tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
+    let invalid = || ParseError::InvalidDottedList { loc: dot.loc().clone() };
+    if objects.is_empty() {
+        return Err(invalid());
+    }
 
+    let mut cdr = None;
     while let Some(token) = tokens.pop_front() {
-        let loc = token.loc().clone();
         match token.kind() {
-            &TokenKind::Comment(_) | TokenKind::IGNORE => continue,
-            &TokenKind::UNKNOWN => return Err(format!("Unknown symbols found at {}", token.loc())),
-            &TokenKind::Float(n) => objects.push_back(Object::Float { value: n, loc: Some(loc) }),
-            &TokenKind::Integer(n) => objects.push_back(Object::Integer { value: n, loc: Some(loc) }),
-            &TokenKind::Str(ref s) => objects.push_back(Object::Str { value: s.clone(), loc: Some(loc) }),
-            &TokenKind::Symbol(ref s) => objects.push_back(Object::Symbol { value: s.clone(), loc: Some(loc) }),
-            &TokenKind::LeftParenthesis => {
-                let list = parse_list(tokens)?;
-                objects.push_back(Object::List{ value: Vec::from_iter(list), loc: None });
+            TokenKind::Comment(_) | TokenKind::IGNORE => continue,
+            TokenKind::DatumComment => { parse_quoted(&token, tokens)?; },
+            _ if closes(open, &token) => {
+                let cdr = cdr.ok_or_else(invalid)?;
+                return Ok(dotted_list(objects, cdr, open.loc().to(token.loc())));
             },
-            &TokenKind::RightParenthesis => return Ok(objects)
+            TokenKind::RightParenthesis | TokenKind::ClosingBracket(_) => return Err(unexpected(&token)),
+            _ if cdr.is_none() => cdr = Some(parse_object(&token, tokens)?),
+            _ => return Err(invalid())
         }
-        // last token will never be Comment/IGNORE/UNKNOWN
-        last_token = Some(token);
     }
-    Err(format!("Unclosed List found at {}", last_token.unwrap().loc()))
+    Err(ParseError::UnclosedList { loc: open.loc().clone() })
+}
+
+/// Whether the token closes the list opened by `open`
+fn closes(open: &Token, token: &Token) -> bool {
+    match (open.kind(), token.kind()) {
+        (TokenKind::Dispatch('{'), TokenKind::ClosingBracket('}')) | (TokenKind::Dispatch('['), TokenKind::ClosingBracket(']')) => true,
+        (TokenKind::Dispatch(_), _) => false,
+        (_, kind) => *kind == TokenKind::RightParenthesis,
+    }
+}
+
+/// The error of a parenthesis or a bracket closing no list
+fn unexpected(token: &Token) -> ParseError {
+    let loc = token.loc().clone();
+    match token.kind() {
+        &TokenKind::ClosingBracket(found) => ParseError::UnexpectedClosingBracket { found, loc },
+        _ => ParseError::UnexpectedRightParenthesis { loc },
+    }
+}
+
+/// Chain the objects into pairs ending with `cdr`, a list cdr gives
+/// a proper list like `cons` does, e.g. (a . (b c)) is (a b c)
+fn dotted_list(mut objects: Vec<Object>, cdr: Object, loc: Location) -> Object {
+    if let Object::List { value, .. } = &cdr {
+        objects.extend(value.iter().cloned());
+        return Object::List { value: ListItems::new(objects), loc: Some(loc) };
+    }
+
+    objects
+        .into_iter()
+        .enumerate()
+        .rev()
+        .fold(cdr, |cdr, (i, car)| Object::Pair {
+            value: Rc::new((car, cdr)),
+            // Only the outermost pair spans the source
+            loc: if i == 0 { Some(loc.clone()) } else { None }
+        })
+}
+
+/// Build the object starting with the given token, the tokens of
+/// a nested list or a quoted expression are taken from `tokens`
+fn parse_object(token: &Token, tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
+    // The nested lists are read recursively, on more stack when needed
+    with_stack(|| parse_token(token, tokens))
+}
+
+fn parse_token(token: &Token, tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
+    let loc = token.loc().clone();
+    let object = match token.kind() {
+        &TokenKind::Float(n) => Object::Float { value: n, loc: Some(loc) },
+        &TokenKind::Integer(n) => Object::Integer { value: n, loc: Some(loc) },
+        TokenKind::BigInteger(n) => Object::BigInteger { value: n.clone(), loc: Some(loc) },
+        TokenKind::Rational(n) => Object::Rational { value: n.clone(), loc: Some(loc) },
+        &TokenKind::Bool(b) => Object::Bool { value: b, loc: Some(loc) },
+        &TokenKind::Char(c) => Object::Char { value: c, loc: Some(loc) },
+        TokenKind::Str(s) => Object::Str { value: s.clone(), loc: Some(loc) },
+        TokenKind::Symbol(s) => Object::Symbol { value: Symbol::intern(s), loc: Some(loc) },
+        TokenKind::Keyword(s) => Object::Keyword { value: Symbol::intern(s), loc: Some(loc) },
+        TokenKind::LeftParenthesis => parse_list(token, tokens)?,
+        TokenKind::VectorParenthesis => match &parse_list(token, tokens)? {
+            Object::List { value, loc } => Object::Vector { value: Rc::new(value.to_vec()), loc: loc.clone() },
+            _ => return Err(ParseError::InvalidDottedList { loc }),
+        },
+        TokenKind::Quote
+        | TokenKind::Quasiquote
+        | TokenKind::Unquote
+        | TokenKind::UnquoteSplicing => {
+            // 'expr is read as (quote expr), `expr as (quasiquote expr),
+            // ,expr as (unquote expr) and ,@expr as (unquote-splicing expr)
+            let name = match token.kind() {
+                TokenKind::Quote => "quote",
+                TokenKind::Quasiquote => "quasiquote",
+                TokenKind::Unquote => "unquote",
+                _ => "unquote-splicing",
+            };
+            let quoted = parse_quoted(token, tokens)?;
+            // The form spans from the quote character to the end of the quoted expression
+            let span = quoted.loc().map_or_else(|| loc.clone(), |end| loc.to(end));
+            Object::List {
+                value: ListItems::new(vec![Object::Symbol { value: Symbol::intern(name), loc: Some(loc) }, quoted]),
+                loc: Some(span)
+            }
+        },
+        // #d datum is read as (reader-macro #\d datum) and #{a b} as
+        // (reader-macro #\{ (a b)), the reader macro registered for the
+        // character replaces the form when it is expanded
+        &TokenKind::Dispatch(c) => {
+            let datum = match c {
+                '{' | '[' => parse_list(token, tokens)?,
+                _ => parse_quoted(token, tokens)?,
+            };
+            let span = datum.loc().map_or_else(|| loc.clone(), |end| loc.to(end));
+            let value = vec![
+                Object::Symbol { value: Symbol::intern(READER_MACRO), loc: Some(loc.clone()) },
+                Object::Char { value: c, loc: Some(loc) },
+                datum,
+            ];
+            Object::List { value: ListItems::new(value), loc: Some(span) }
+        },
+        TokenKind::Comment(_)
+        | TokenKind::DatumComment
+        | TokenKind::IGNORE
+        | TokenKind::RightParenthesis
+        | TokenKind::ClosingBracket(_) => unreachable!("handled by the caller"),
+        TokenKind::UNKNOWN => unreachable!("rejected by the lexer"),
+    };
+    Ok(object)
+}
+
+/// Parse the expression following a quote character or
+/// a datum comment `#;`, which discards the expression
+fn parse_quoted(quote: &Token, tokens: &mut VecDeque<Token>) -> Result<Object, ParseError> {
+    while let Some(token) = tokens.pop_front() {
+        match token.kind() {
+            TokenKind::Comment(_) | TokenKind::IGNORE => continue,
+            TokenKind::DatumComment => { parse_quoted(&token, tokens)?; },
+            TokenKind::RightParenthesis | TokenKind::ClosingBracket(_) => break,
+            _ => return parse_object(&token, tokens)
+        }
+    }
+    let loc = quote.loc().clone();
+    match quote.kind() {
+        TokenKind::DatumComment => Err(ParseError::MissingCommentedExpression { loc }),
+        TokenKind::Dispatch(_) => Err(ParseError::MissingDispatchedExpression { loc }),
+        _ => Err(ParseError::MissingQuotedExpression { loc }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::super::lexer::tokenize;
+    use crate::error::EvalError;
+
+    #[test]
+    fn test_list_items() {
+        let shared = Rc::new(vec![]);
+        let item = || Object::Vector { value: shared.clone(), loc: None };
+        let list = ListItems::new(vec![item(), Object::Integer { value: 2, loc: None }]);
+        let rest = list.rest();
+        assert_eq!(rest.len(), 1);
+        assert!(ListItems::ptr_eq(&rest, &list.rest()));
+        assert!(rest.rest().rest().is_empty());
+
+        // The lists put in front of the same items share them
+        let a = rest.cons(item());
+        let b = a.cons(item());
+        let c = a.cons(Object::Integer { value: 3, loc: None });
+        assert!(ListItems::ptr_eq(&b.rest(), &a) && ListItems::ptr_eq(&c.rest(), &a));
+        assert_eq!(b.len(), 3);
+        assert!(matches!(c.first(), Some(Object::Integer { value: 3, .. })));
+        assert_eq!(Rc::strong_count(&shared), 4);
+        // The items of `b` and `c` are copied once they are borrowed as a slice
+        assert_eq!(Object::List { value: c.clone(), loc: None }.to_string(), "(3 #() 2)");
+        assert_eq!(Object::List { value: b.clone(), loc: None }.to_string(), "(#() #() 2)");
+        assert_eq!(Rc::strong_count(&shared), 7);
+
+        drop((list, rest, a, b, c));
+        assert_eq!(Rc::strong_count(&shared), 1);
+
+        // A long list is dropped without exhausting the stack
+        let long = (0..1_000_000).fold(ListItems::default(), |list, i| list.cons(Object::Integer { value: i, loc: None }));
+        assert_eq!(long.len(), 1_000_000);
+        drop(long);
+    }
 
     #[test]
     fn test_parse() {
         // Test reporting error when unclosed list found
         let prog = "\"Atom!\"\n(define x 10";
-        let (_, mut tokens) = tokenize("parser_test.rs", prog).unwrap();
+        let mut tokens = tokenize("parser_test.rs", prog).unwrap();
         let test = parse(&mut tokens);
         assert!(test.is_err());
 
+        // The error points at the left parenthesis of the innermost unclosed list
+        for (prog, rol, col) in [("(", 1, 1), ("(define (f x)\n  (g x)", 1, 1), ("(f (g x) (h", 1, 10)] {
+            let mut tokens = tokenize("parser_test.rs", prog).unwrap();
+            match parse(&mut tokens) {
+                Err(ParseError::UnclosedList { loc }) => assert_eq!((loc.rol(), loc.col()), (rol, col)),
+                other => panic!("Expect UnclosedList but {:?} found", other),
+            }
+        }
+
         // Test reporting error when unexpected right parenthesis found
         let prog = "())";
-        let (_, mut tokens) = tokenize("parser_test.rs", prog).unwrap();
+        let mut tokens = tokenize("parser_test.rs", prog).unwrap();
         let test = parse(&mut tokens);
         assert!(test.is_err());
 
         // Test for Normal case
         let prog = "(define x 10)\n(define add-func (lambda (x y z) (+ x y z)))";
-        let (_, mut tokens) = tokenize("parser_test.rs", prog).unwrap();
+        let mut tokens = tokenize("parser_test.rs", prog).unwrap();
         let test = parse(&mut tokens);
         assert!(test.is_ok());
     }
+
+    #[test]
+    fn test_written() {
+        assert_eq!(Written(&Object::Str { value: "say \"hi\"".to_string(), loc: None }).to_string(), "\"say \\\"hi\\\"\"");
+        assert_eq!(Written(&Object::Str { value: "a\tb\nc\u{7}".to_string(), loc: None }).to_string(), "\"a\\tb\\nc\\u{7}\"");
+        assert_eq!(Written(&Object::Char { value: ' ', loc: None }).to_string(), "#\\space");
+        assert_eq!(Written(&Object::Char { value: 'a', loc: None }).to_string(), "#\\a");
+        assert_eq!(Written(&Object::Float { value: 2.0, loc: None }).to_string(), "2.0");
+
+        // The data are read back as equal objects
+        let prog = "(1 2.5 -3.0 1/2 \"a\\\\b\\\"c\nd\" #\\( #\\space sym #t (nested (list)) #(v \"s\") (a b . c) ())";
+        let obj = match &parse(&mut tokenize("parser_test.rs", prog).unwrap()).unwrap() {
+            Object::Module { value, .. } => value[0].clone(),
+            obj => panic!("Expect a module but {} found", obj),
+        };
+        let written = Written(&obj).to_string();
+        let read = match &parse(&mut tokenize("parser_test.rs", &written).unwrap()).unwrap() {
+            Object::Module { value, .. } => value[0].clone(),
+            obj => panic!("Expect a module but {} found", obj),
+        };
+        assert!(obj.is_equal(&read), "{}", written);
+        assert_eq!(obj.to_string(), "(1 2.5 -3 1/2 a\\b\"c\nd (   sym true (nested (list)) #(v s) (a b . c) ())");
+    }
+
+    #[test]
+    fn test_parse_quote() {
+        let prog = "'(1 2) 'x";
+        let mut tokens = tokenize("parser_test.rs", prog).unwrap();
+        let module = parse(&mut tokens).unwrap();
+        let forms = match &module {
+            Object::Module { value, .. } => value,
+            _ => unreachable!(),
+        };
+        assert_eq!(forms.len(), 2);
+        for form in forms {
+            match &form {
+                Object::List { value, .. } => {
+                    assert!(matches!(&value[0], Object::Symbol { value, .. } if value == "quote"));
+                    assert_eq!(value.len(), 2);
+                },
+                _ => panic!("Expect (quote ...) but {} found", form),
+            }
+        }
+
+        // The quoted list spans from the quote to the right parenthesis
+        let module = parse(&mut tokenize("parser_test.rs", "'(1\n 2)").unwrap()).unwrap();
+        let loc = match &module {
+            Object::Module { value, .. } => value[0].loc().cloned().unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!((loc.rol(), loc.end_rol()), (1, 2));
+
+        // Nothing to quote
+        let mut tokens = tokenize("parser_test.rs", "(list ')").unwrap();
+        assert!(parse(&mut tokens).is_err());
+    }
+
+    #[test]
+    fn test_parse_reader_macro() {
+        let parsed = |prog: &str| match &parse(&mut tokenize("parser_test.rs", prog)?)? {
+            Object::Module { value, .. } => Ok::<_, EvalError>(value[0].clone()),
+            obj => panic!("Expect a module but {} found", obj),
+        };
+        assert_eq!(Written(&parsed("#d\"2024\"").unwrap()).to_string(), "(reader-macro #\\d \"2024\")");
+        assert_eq!(Written(&parsed("#{1 #[a] (b)}").unwrap()).to_string(), "(reader-macro #\\{ (1 (reader-macro #\\[ (a)) (b)))");
+
+        assert!(matches!(parsed("(f #d)"), Err(EvalError::Parse(ParseError::MissingDispatchedExpression { .. }))));
+        assert!(matches!(parsed("#{1 2)"), Err(EvalError::Parse(ParseError::UnexpectedRightParenthesis { .. }))));
+        assert!(matches!(parsed("(1 2]"), Err(EvalError::Parse(ParseError::UnexpectedClosingBracket { found: ']', .. }))));
+        assert!(matches!(parsed("#{1 2"), Err(EvalError::Parse(ParseError::UnclosedList { .. }))));
+        assert!(!is_complete("#[1 2"));
+        assert!(!is_complete("#d"));
+    }
+
+    #[test]
+    fn test_parse_datum_comment() {
+        let prog = "#;(define x 1) (f #;a b #; #;c d e) '#;1 2";
+        let mut tokens = tokenize("parser_test.rs", prog).unwrap();
+        let module = parse(&mut tokens).unwrap();
+        let forms = match &module {
+            Object::Module { value, .. } => value,
+            _ => unreachable!(),
+        };
+        assert_eq!(forms.len(), 2);
+        match &forms[0] {
+            Object::List { value, .. } => {
+                let names: Vec<_> = value.iter().map(|obj| obj.to_string()).collect();
+                assert_eq!(names, vec!["f", "b", "e"]);
+            },
+            form => panic!("Expect (f b e) but {} found", form),
+        }
+        assert!(matches!(&forms[1], Object::List { value, .. } if value[1].to_string() == "2"));
+
+        // Nothing to comment out
+        let mut tokens = tokenize("parser_test.rs", "(f #;)").unwrap();
+        assert!(matches!(parse(&mut tokens), Err(ParseError::MissingCommentedExpression { .. })));
+    }
+
+    #[test]
+    fn test_is_complete() {
+        for source in ["", "(+ 1 2)", "(f x) ;; (", "(list ')", ")", "#\\bogus (", "'a"] {
+            assert!(is_complete(source), "{:?} should be complete", source);
+        }
+        for source in ["(define (f x)\n  (+ x", "#(1 2", "(display \"a)", "#| (f)", "(f '", "(f) #;"] {
+            assert!(!is_complete(source), "{:?} should be incomplete", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_dotted_list() {
+        let mut tokens = tokenize("parser_test.rs", "(a b . c)").unwrap();
+        let module = parse(&mut tokens).unwrap();
+        match &module {
+            Object::Module { value, .. } => {
+                assert_eq!(value[0].to_string(), "(a b . c)");
+                assert!(value[0].loc().is_some());
+            },
+            _ => unreachable!(),
+        }
+
+        for prog in ["( . a)", "(a . b c)", "(a .)", "#(a . b)"] {
+            let mut tokens = tokenize("parser_test.rs", prog).unwrap();
+            assert!(matches!(parse(&mut tokens), Err(ParseError::InvalidDottedList { .. })), "{}", prog);
+        }
+        let mut tokens = tokenize("parser_test.rs", "(a . b").unwrap();
+        assert!(matches!(parse(&mut tokens), Err(ParseError::UnclosedList { .. })));
+    }
 }
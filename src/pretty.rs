@@ -0,0 +1,141 @@
+//! A pretty-printing engine laying documents out within a width
+//!
+//! A document is made of text and of line breaks which are only taken when
+//! the group holding them does not fit on the rest of the line, see [`Doc`].
+//!
+//! ```
+//! use rslisp::pretty::{render, Doc};
+//!
+//! let doc = Doc::concat(vec![Doc::text("(if"), Doc::Line, Doc::text("x"), Doc::Line, Doc::text("y)")]).group();
+//! assert_eq!(render(&doc, 80), "(if x y)");
+//! assert_eq!(render(&doc, 4), "(if\nx\ny)");
+//! ```
+
+use crate::evaluator::with_stack;
+
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    /// A space, or a line break when the enclosing group does not fit
+    Line,
+    /// Always a line break, the groups holding it never fit on one line
+    HardLine,
+    Concat(Vec<Doc>),
+    /// The lines broken in the document are indented by more columns
+    Nest(usize, Box<Doc>),
+    /// The lines broken in the document are indented to the column it starts at
+    Align(Box<Doc>),
+    /// The document is laid out on one line when it fits, otherwise
+    /// every line of the group is broken
+    Group(Box<Doc>),
+}
+
+impl Drop for Doc {
+    fn drop(&mut self) {
+        // The documents nested in the document are dropped recursively,
+        // on more stack when needed
+        let nested = match self {
+            Doc::Concat(docs) => std::mem::take(docs),
+            Doc::Nest(_, doc) | Doc::Align(doc) | Doc::Group(doc) => vec![std::mem::replace(&mut **doc, Doc::Line)],
+            _ => return,
+        };
+        with_stack(|| drop(nested));
+    }
+}
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Doc {
+        Doc::Text(text.into())
+    }
+
+    pub fn concat(docs: Vec<Doc>) -> Doc {
+        Doc::Concat(docs)
+    }
+
+    pub fn nest(self, indent: usize) -> Doc {
+        Doc::Nest(indent, Box::new(self))
+    }
+
+    pub fn align(self) -> Doc {
+        Doc::Align(Box::new(self))
+    }
+
+    pub fn group(self) -> Doc {
+        Doc::Group(Box::new(self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// The documents left to lay out with their indentation, the next one last
+type Stack<'a> = Vec<(usize, Mode, &'a Doc)>;
+
+/// Lay the document out in lines of at most `width` columns where possible,
+/// the lines never end with spaces
+pub fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack: Stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                out.push_str(text);
+                column = match text.rfind('\n') {
+                    Some(i) => text[i + 1..].chars().count(),
+                    None => column + text.chars().count(),
+                };
+            },
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            },
+            Doc::Line | Doc::HardLine => {
+                out.truncate(out.trim_end_matches(' ').len());
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                column = indent;
+            },
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Nest(more, doc) => stack.push((indent + more, mode, doc)),
+            Doc::Align(doc) => stack.push((column, mode, doc)),
+            Doc::Group(doc) => {
+                let fits = mode == Mode::Flat || fits(width as isize - column as isize, doc, &stack);
+                stack.push((indent, if fits { Mode::Flat } else { Mode::Break }, doc));
+            },
+        }
+    }
+    out.truncate(out.trim_end_matches(' ').len());
+    out
+}
+
+/// Whether the group fits on one line followed by what comes after it
+/// up to the next line break
+fn fits(mut remaining: isize, group: &Doc, rest: &Stack) -> bool {
+    let mut stack = vec![(Mode::Flat, group)];
+    let mut rest = rest.iter().rev();
+    while remaining >= 0 {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => match text.split_once('\n') {
+                Some((line, _)) => return mode == Mode::Break && line.chars().count() as isize <= remaining,
+                None => remaining -= text.chars().count() as isize,
+            },
+            Doc::Line | Doc::HardLine if mode == Mode::Break => return true,
+            Doc::HardLine => return false,
+            Doc::Line => remaining -= 1,
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+            Doc::Nest(_, doc) | Doc::Align(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+        }
+    }
+    false
+}
@@ -0,0 +1,198 @@
+//! The profiler of `rslisp --profile`
+//!
+//! The [`Profiler`] is a [`Hook`] recording for every function called, the
+//! builtins included, the number of calls, the time spent in the calls
+//! with and without the calls they make, and the allocations they make
+//! themselves. Like the other hooks it only sees the programs evaluated by
+//! the tree-walking evaluator.
+//!
+//! The allocations are counted by [`CountingAllocator`], which has to be the
+//! global allocator of the program, they are all 0 otherwise.
+//!
+//! ```
+//! use std::rc::Rc;
+//! use rslisp::profile::Profiler;
+//! use rslisp::{hook, Interpreter};
+//!
+//! let profiler = Rc::new(Profiler::new());
+//! let interp = Interpreter::new();
+//! let prog = "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) (fib 10)";
+//! hook::with_hook(profiler.clone(), || interp.eval_str(prog)).unwrap();
+//! let fib = profiler.entries().into_iter().find(|entry| entry.name == "fib").unwrap();
+//! assert_eq!(fib.calls, 177);
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::error::EvalError;
+use crate::evaluator::Environment;
+use crate::hook::Hook;
+use crate::parser::Object;
+use crate::symbol::Symbol;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator counting the allocations, installed with
+/// `#[global_allocator] static ALLOC: CountingAllocator = CountingAllocator;`
+pub struct CountingAllocator;
+
+// SAFETY: the memory is managed by the system allocator, only the number
+// of allocations is recorded on the side
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    // Growing a block is not counted as another allocation
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// The number of allocations made so far by the whole process
+pub fn allocations() -> u64 {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// What is recorded for a function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub calls: u64,
+    /// The time spent in the calls, the recursive calls are not counted
+    /// again as they are part of the outermost call
+    pub inclusive: Duration,
+    /// The time spent in the calls minus the time of the calls they make
+    pub exclusive: Duration,
+    /// The allocations made by the calls, not by the calls they make
+    pub allocations: u64,
+}
+
+/// A call being evaluated
+struct Call {
+    name: Symbol,
+    start: Instant,
+    allocations: u64,
+    /// The time and the allocations of the calls it made so far
+    children: Duration,
+    child_allocations: u64,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    /// The calls being evaluated, the innermost call last
+    stack: RefCell<Vec<Call>>,
+    // The functions are interned so recording a call does not allocate
+    entries: RefCell<HashMap<Symbol, Entry>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The functions called so far, the most time spent in them first
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries: Vec<_> = self.entries.borrow().values().cloned().collect();
+        entries.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then_with(|| a.name.cmp(&b.name)));
+        entries
+    }
+
+    /// The entries as a table, one function per line
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>10} {:>14} {:>14} {:>12}  function\n", "calls", "inclusive ms", "exclusive ms", "allocations");
+        for entry in self.entries() {
+            report.push_str(&format!(
+                "{:>10} {:>14.3} {:>14.3} {:>12}  {}\n",
+                entry.calls,
+                entry.inclusive.as_secs_f64() * 1000.0,
+                entry.exclusive.as_secs_f64() * 1000.0,
+                entry.allocations,
+                entry.name,
+            ));
+        }
+        report
+    }
+}
+
+impl Hook for Profiler {
+    fn before_call(&self, name: &str, _args: &[Object], _env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+        let name = Symbol::intern(name);
+        self.stack.borrow_mut().push(Call {
+            name,
+            start: Instant::now(),
+            allocations: allocations(),
+            children: Duration::ZERO,
+            child_allocations: 0,
+        });
+        Ok(())
+    }
+
+    fn after_call(&self, _name: &str, _result: &Result<Object, EvalError>) {
+        let (end, total) = (Instant::now(), allocations());
+        let mut stack = self.stack.borrow_mut();
+        let Some(call) = stack.pop() else { return };
+        let elapsed = end - call.start;
+        let allocated = total - call.allocations;
+        let recursive = stack.iter().any(|outer| outer.name == call.name);
+        if let Some(caller) = stack.last_mut() {
+            caller.children += elapsed;
+            caller.child_allocations += allocated;
+        }
+
+        let mut entries = self.entries.borrow_mut();
+        let entry = entries.entry(call.name).or_insert_with(|| Entry { name: call.name.to_string(), ..Entry::default() });
+        entry.calls += 1;
+        if !recursive {
+            entry.inclusive += elapsed;
+        }
+        entry.exclusive += elapsed.saturating_sub(call.children);
+        entry.allocations += allocated.saturating_sub(call.child_allocations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::with_hook;
+    use crate::Interpreter;
+
+    #[test]
+    fn test_profiler() {
+        let prog = "(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))\n\
+                    (define (outer) (count 3) (guard (e (#t 'caught)) (car 1)))\n\
+                    (outer) (outer)";
+        let profiler = Rc::new(Profiler::new());
+        let interp = Interpreter::new();
+        with_hook(profiler.clone(), || interp.eval_str(prog)).unwrap();
+
+        let entries = profiler.entries();
+        let calls: HashMap<_, _> = entries.iter().map(|entry| (entry.name.as_str(), entry.calls)).collect();
+        assert_eq!(calls, HashMap::from([("outer", 2), ("count", 8), ("=", 8), ("+", 6), ("-", 6), ("car", 2)]));
+        // The failed calls are popped too
+        assert!(profiler.stack.borrow().is_empty());
+
+        let get = |name: &str| entries.iter().find(|entry| entry.name == name).unwrap();
+        let (outer, count) = (get("outer"), get("count"));
+        assert!(outer.exclusive <= outer.inclusive);
+        assert!(count.inclusive <= outer.inclusive);
+        assert!(profiler.report().lines().nth(1).unwrap().ends_with(&entries[0].name));
+    }
+}
@@ -0,0 +1,60 @@
+use std::process::ExitCode;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use rslisp::parser::{is_complete, Written};
+use rslisp::{EvalError, Interpreter, Object};
+
+const PROMPT: &str = "rslisp> ";
+/// Shown while the expressions entered are not complete
+const CONTINUATION_PROMPT: &str = "   ...> ";
+
+/// Read the expressions, evaluate them in the shared environment and print
+/// the result until the user hits Ctrl-D. The lines are gathered until the
+/// expressions are complete, e.g. all the parentheses are closed.
+/// `(exit code)` ends the session with the status
+pub fn run(interp: Interpreter) -> rustyline::Result<ExitCode> {
+    let mut editor = DefaultEditor::new()?;
+    let mut input = String::new();
+
+    loop {
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C discards the expressions being entered
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+
+        if input.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if !input.is_empty() {
+            input.push('\n');
+        }
+        input.push_str(line.as_str());
+        if !is_complete(input.as_str()) {
+            continue;
+        }
+        let source = std::mem::take(&mut input);
+        editor.add_history_entry(source.as_str())?;
+
+        let result = interp.eval_str(source.as_str());
+        for warning in interp.take_warnings() {
+            eprintln!("{}", warning);
+        }
+        match &result {
+            Ok(Object::Void { .. }) => {},
+            Ok(obj) => println!("{}", Written(obj)),
+            Err(EvalError::Exit { .. }) => return Ok(crate::exit_code(&result)),
+            Err(e) => eprintln!("{}", interp.render_error(e)),
+        }
+        // Closures created by the line may leave cycles behind
+        interp.collect_garbage();
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,348 @@
+//! Lexical addressing of the local variables
+//!
+//! The pass rewrites the references to the variables bound by the frames
+//! the evaluator creates into [`Object::Local`] holding their (depth, slot)
+//! address, the depth being the number of frames up the chain and the slot
+//! the index of the variable in the frame. A frame holds the parameters of
+//! a function, or the variables of `let`, `let*`, `letrec` or of the handler
+//! of `guard`, followed by the variables defined at the top of the body in
+//! the order of the definitions.
+//!
+//! The frame checks that the slot holds the variable, the variables are
+//! looked up by name otherwise, e.g. when they are not defined yet or when
+//! a definition made elsewhere than at the top of a body may shadow them,
+//! see [`Environment::get_at`]. The other variables, the global ones and
+//! the ones inside quoted data, are always looked up by name.
+//!
+//! [`Environment::get_at`]: crate::evaluator::Environment
+
+use crate::evaluator::{named_let, parse_params, with_stack};
+use crate::parser::{ListItems, Object, Param, ParamKind};
+use crate::symbol::Symbol;
+
+/// The variables of the frames enclosing a form, the innermost frame first
+pub(crate) struct Scope<'a> {
+    /// The names in the order of their slots
+    names: Vec<Symbol>,
+    parent: Option<&'a Scope<'a>>,
+}
+
+impl<'a> Scope<'a> {
+    /// A name bound twice in the frame keeps its first slot
+    pub(crate) fn new(names: impl IntoIterator<Item = Symbol>, parent: Option<&'a Scope<'a>>) -> Self {
+        let mut slots = vec![];
+        for name in names {
+            if !slots.contains(&name) {
+                slots.push(name);
+            }
+        }
+        Self { names: slots, parent }
+    }
+
+    /// The frame of a body, the variables bound by the form are followed
+    /// by the ones the body defines at its top
+    pub(crate) fn body(names: impl IntoIterator<Item = Symbol>, body: &[Object], parent: Option<&'a Scope<'a>>) -> Self {
+        Self::new(names.into_iter().chain(body.iter().filter_map(defined_name)), parent)
+    }
+
+    /// The frame of a call, the parameters are bound in order, see
+    /// [`crate::evaluator::bind_arguments`]
+    pub(crate) fn function(params: &[Param], body: &[Object], parent: Option<&'a Scope<'a>>) -> Self {
+        let names = params.iter().map(|param| match &param.kind {
+            ParamKind::Named(name) | ParamKind::Variadic(name) | ParamKind::Keyword { name, .. } => *name,
+        });
+        Self::body(names, body, parent)
+    }
+
+    /// The (depth, slot) address of the variable
+    pub(crate) fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        match self.names.iter().position(|n| *n == name) {
+            Some(slot) => Some((0, slot)),
+            None => self.parent?.resolve(name).map(|(depth, slot)| (depth + 1, slot)),
+        }
+    }
+}
+
+/// The name of `(define name expr)` or `(define (name ...) body ...)`
+fn defined_name(obj: &Object) -> Option<Symbol> {
+    let Object::List { value, .. } = obj else {
+        return None;
+    };
+    let name = match value.as_slice() {
+        [Object::Symbol { value, .. }, target, _, ..] if value.as_str() == "define" => match target {
+            Object::List { value, .. } => value.first()?,
+            Object::Pair { value, .. } => &value.0,
+            target => target,
+        },
+        _ => return None,
+    };
+    match name {
+        Object::Symbol { value, .. } => Some(*value),
+        _ => None,
+    }
+}
+
+/// Rewrite the references to the local variables of the top level form,
+/// which must already be macro expanded
+pub fn resolve(obj: &Object) -> Object {
+    resolve_obj(obj, None)
+}
+
+/// Turn the addressed variables back into symbols, for the forms given
+/// as data to a macro called at runtime
+pub fn unresolve(obj: &Object) -> Object {
+    // The nested forms are rewritten recursively, on more stack when needed
+    with_stack(|| match obj {
+        Object::Local { value, loc, .. } => Object::Symbol { value: *value, loc: loc.clone() },
+        Object::List { value, loc } => Object::List { value: ListItems::new(value.iter().map(unresolve).collect()), loc: loc.clone() },
+        _ => obj.clone(),
+    })
+}
+
+fn resolve_obj(obj: &Object, scope: Option<&Scope>) -> Object {
+    // The nested forms are rewritten recursively, on more stack when needed
+    with_stack(|| match obj {
+        Object::Symbol { value, loc } => match scope.and_then(|scope| scope.resolve(*value)) {
+            Some((depth, slot)) => Object::Local { value: *value, depth, slot, loc: loc.clone() },
+            None => obj.clone(),
+        },
+        Object::List { value, .. } if !value.is_empty() => resolve_list(obj, value, scope),
+        _ => obj.clone(),
+    })
+}
+
+fn resolve_all<'a>(items: &'a [Object], scope: Option<&'a Scope<'a>>) -> impl Iterator<Item = Object> + 'a {
+    items.iter().map(move |item| resolve_obj(item, scope))
+}
+
+/// Rebuild the list with the first `kept` items and then the others
+fn with_items(obj: &Object, list: &[Object], kept: usize, items: impl Iterator<Item = Object>) -> Object {
+    let items = list[..kept].iter().cloned().chain(items).collect();
+    Object::List { value: ListItems::new(items), loc: obj.loc().cloned() }
+}
+
+/// Rewrite the special forms written the expected way, the others are
+/// left as they are for the evaluator to report the syntax errors
+fn resolve_list(obj: &Object, list: &[Object], scope: Option<&Scope>) -> Object {
+    let form = match &list[0] {
+        Object::Symbol { value, .. } => value.as_str(),
+        _ => "",
+    };
+    match (form, &list[1..]) {
+        ("lambda", [params, body @ ..]) => match parse_params(Some(params)) {
+            Ok(params) => with_items(obj, list, 2, resolve_all(body, Some(&Scope::function(&params, body, scope)))),
+            Err(_) => obj.clone(),
+        },
+        ("defmacro", [_, params, body @ ..]) => match parse_params(Some(params)) {
+            Ok(params) => with_items(obj, list, 3, resolve_all(body, Some(&Scope::function(&params, body, scope)))),
+            Err(_) => obj.clone(),
+        },
+        ("define", [Object::Symbol { .. }, exprs @ ..]) => with_items(obj, list, 2, resolve_all(exprs, scope)),
+        // (define (name x y) body) is (define name (lambda (x y) body))
+        ("define", [signature, body @ ..]) => {
+            let params = match signature {
+                Object::List { value, loc } if !value.is_empty() => {
+                    Object::List { value: ListItems::new(value[1..].to_vec()), loc: loc.clone() }
+                },
+                Object::Pair { value, .. } => value.1.clone(),
+                _ => return obj.clone(),
+            };
+            match parse_params(Some(&params)) {
+                Ok(params) => with_items(obj, list, 2, resolve_all(body, Some(&Scope::function(&params, body, scope)))),
+                Err(_) => obj.clone(),
+            }
+        },
+        // The named let is rewritten into the call of the function it
+        // defines, the way the evaluator runs it
+        ("let", [Object::Symbol { .. }, ..]) => match named_let(&list[1..]) {
+            Ok(call) => resolve_obj(&call, scope),
+            Err(_) => obj.clone(),
+        },
+        ("let", [bindings, body @ ..]) => match binding_names(bindings) {
+            Some(names) => {
+                let bindings = resolve_bindings(bindings, scope);
+                let inner = Scope::body(names, body, scope);
+                with_items(obj, list, 1, std::iter::once(bindings).chain(resolve_all(body, Some(&inner))))
+            },
+            None => obj.clone(),
+        },
+        ("letrec", [bindings, body @ ..]) => match binding_names(bindings) {
+            Some(names) => {
+                let inner = Scope::body(names, body, scope);
+                let bindings = resolve_bindings(bindings, Some(&inner));
+                with_items(obj, list, 1, std::iter::once(bindings).chain(resolve_all(body, Some(&inner))))
+            },
+            None => obj.clone(),
+        },
+        ("let*", [bindings @ Object::List { value, loc }, body @ ..]) if binding_names(bindings).is_some() => {
+            let mut resolved = vec![];
+            let body = resolve_let_star(value, body, scope, &mut resolved);
+            let bindings = Object::List { value: ListItems::new(resolved), loc: loc.clone() };
+            with_items(obj, list, 1, std::iter::once(bindings).chain(body))
+        },
+        ("guard", [Object::List { value: spec, .. }, body @ ..]) => match spec.first() {
+            Some(Object::Symbol { value: var, .. }) => {
+                let handler = Scope::new([*var], scope);
+                let clauses = spec[1..].iter().map(|clause| resolve_clause(clause, Some(&handler)));
+                let spec = with_items(&list[1], spec, 1, clauses);
+                let inner = Scope::body([], body, scope);
+                with_items(obj, list, 1, std::iter::once(spec).chain(resolve_all(body, Some(&inner))))
+            },
+            _ => obj.clone(),
+        },
+        ("cond", clauses) => with_items(obj, list, 1, clauses.iter().map(|clause| resolve_clause(clause, scope))),
+        ("case", [key, clauses @ ..]) => {
+            // The data of the clauses are kept as they are
+            let clauses = clauses.iter().map(|clause| match clause {
+                Object::List { value, .. } if !value.is_empty() => with_items(clause, value, 1, resolve_all(&value[1..], scope)),
+                _ => clause.clone(),
+            });
+            with_items(obj, list, 1, std::iter::once(resolve_obj(key, scope)).chain(clauses))
+        },
+        ("parameterize", [Object::List { value: bindings, loc }, body @ ..]) => {
+            let bindings = bindings.iter().map(|binding| match binding {
+                Object::List { value, .. } => with_items(binding, value, 0, resolve_all(value, scope)),
+                _ => binding.clone(),
+            });
+            let bindings = Object::List { value: ListItems::new(bindings.collect()), loc: loc.clone() };
+            with_items(obj, list, 1, std::iter::once(bindings).chain(resolve_all(body, scope)))
+        },
+        // The body of a test is the body of a function without parameters
+        ("define-test", [_, body @ ..]) => with_items(obj, list, 2, resolve_all(body, Some(&Scope::body([], body, scope)))),
+        ("if" | "when" | "unless" | "begin" | "and" | "or" | "set!" | "delay", args) => {
+            with_items(obj, list, 1, resolve_all(args, scope))
+        },
+        (
+            "define" | "lambda" | "defmacro" | "let" | "let*" | "letrec" | "guard" | "case" | "parameterize"
+            | "define-test" | "define-record-type" | "quote" | "quasiquote" | "unquote" | "unquote-splicing",
+            _,
+        ) => obj.clone(),
+        _ => with_items(obj, list, 0, resolve_all(list, scope)),
+    }
+}
+
+/// The names of `((name expr) ...)`, None if a binding is written otherwise
+fn binding_names(bindings: &Object) -> Option<Vec<Symbol>> {
+    let Object::List { value, .. } = bindings else {
+        return None;
+    };
+    value.iter().map(binding_name).collect()
+}
+
+fn binding_name(binding: &Object) -> Option<Symbol> {
+    match binding {
+        Object::List { value, .. } => match value.as_slice() {
+            [Object::Symbol { value, .. }, _] => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Resolve the expressions of `((name expr) ...)` in the scope
+fn resolve_bindings(bindings: &Object, scope: Option<&Scope>) -> Object {
+    match bindings {
+        Object::List { value, loc } => {
+            let items = value.iter().map(|binding| resolve_binding(binding, scope)).collect();
+            Object::List { value: ListItems::new(items), loc: loc.clone() }
+        },
+        _ => bindings.clone(),
+    }
+}
+
+fn resolve_binding(binding: &Object, scope: Option<&Scope>) -> Object {
+    match binding {
+        Object::List { value, .. } => with_items(binding, value, 1, resolve_all(&value[1..], scope)),
+        _ => binding.clone(),
+    }
+}
+
+/// Resolve the bindings of `let*` into `resolved`, each binding gets its
+/// own frame nested in the one of the previous binding, and return the
+/// body resolved in a frame of its own
+fn resolve_let_star(bindings: &[Object], body: &[Object], scope: Option<&Scope>, resolved: &mut Vec<Object>) -> Vec<Object> {
+    match bindings.split_first() {
+        Some((binding, rest)) => {
+            resolved.push(resolve_binding(binding, scope));
+            resolve_let_star(rest, body, Some(&Scope::new(binding_name(binding), scope)), resolved)
+        },
+        None => resolve_all(body, Some(&Scope::body([], body, scope))).collect(),
+    }
+}
+
+/// Resolve the test and the expressions of a `cond` clause, `else` is kept
+fn resolve_clause(clause: &Object, scope: Option<&Scope>) -> Object {
+    match clause {
+        Object::List { value, .. } if !value.is_empty() => {
+            let kept = matches!(&value[0], Object::Symbol { value, .. } if value.as_str() == "else") as usize;
+            with_items(clause, value, kept, resolve_all(&value[kept..], scope))
+        },
+        _ => clause.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+
+    fn read(prog: &str) -> Object {
+        let mut tokens = tokenize("<test>", prog).unwrap();
+        match &parse(&mut tokens).unwrap() {
+            Object::Module { value, .. } => value[0].clone(),
+            obj => panic!("Expect a module but {} found", obj),
+        }
+    }
+
+    fn run(prog: &str) -> String {
+        show(&resolve(&read(prog)))
+    }
+
+    /// The addressed variables are shown as `name@depth:slot`
+    fn show(obj: &Object) -> String {
+        match obj {
+            Object::List { value, .. } => format!("({})", value.iter().map(show).collect::<Vec<_>>().join(" ")),
+            Object::Local { value, depth, slot, .. } => format!("{}@{}:{}", value, depth, slot),
+            obj => obj.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(run("(lambda (x y) (+ x y z))"), "(lambda (x y) (+ x@0:0 y@0:1 z))");
+        assert_eq!(
+            run("(lambda (x) (let ((y x)) (lambda () (set! x y))))"),
+            "(lambda (x) (let ((y x@0:0)) (lambda () (set! x@2:0 y@1:0))))"
+        );
+        // The definitions at the top of the body follow the parameters
+        assert_eq!(
+            run("(lambda (x) (define (f) x) (when x (define y 1)) (f))"),
+            "(lambda (x) (define (f) x@1:0) (when x@0:0 (define y 1)) (f@0:1))"
+        );
+        // Each binding of let* gets a frame, the body gets one more
+        assert_eq!(run("(let* ((x 1) (y x)) (+ x y))"), "(let* ((x 1) (y x@0:0)) (+ x@2:0 y@1:0))");
+        assert_eq!(run("(letrec ((f (lambda () (f)))) f)"), "(letrec ((f (lambda () (f@1:0)))) f@0:0)");
+        assert_eq!(
+            run("(lambda (e) (guard (e (else e)) (raise e)))"),
+            "(lambda (e) (guard (e (else e@0:0)) (raise e@1:0)))"
+        );
+    }
+
+    #[test]
+    fn test_unresolved() {
+        // Quoted data, the data of case and the named let rewritten like the evaluator does
+        assert_eq!(run("(lambda (x) '(x) `(x ,x))"), "(lambda (x) (quote (x)) (quasiquote (x (unquote x))))");
+        assert_eq!(run("(lambda (x) (case x ((x) x) (else x)))"), "(lambda (x) (case x@0:0 ((x) x@0:0) (else x@0:0)))");
+        assert_eq!(
+            run("(let loop ((i 0)) (loop i))"),
+            "((let () (define (loop i) (loop@1:0 i@0:0)) loop@0:0) 0)"
+        );
+        // A form written in an unexpected way is left for the evaluator to report
+        assert_eq!(run("(lambda (x) (let ((x)) x))"), "(lambda (x) (let ((x)) x))");
+
+        // The forms given to a macro at runtime are written back with symbols
+        assert_eq!(run("(lambda (x) (m x))"), "(lambda (x) (m x@0:0))");
+        assert_eq!(show(&unresolve(&resolve(&read("(lambda (x) (m x))")))), "(lambda (x) (m x))");
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::{Rc, Weak},
+};
+
+use crate::evaluator::Environment;
+use crate::location::Location;
+
+use crate::parser::Object;
+
+/// The number of nested calls allowed unless another limit is set
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// The fewest environments tracked between two collections run by the
+/// evaluator itself, see [`Runtime::track`]
+pub const COLLECT_INTERVAL: usize = 10_000;
+
+/// State shared by the whole program rather than by a scope, it is
+/// owned by the root environment, see [`crate::evaluator::Environment::with_runtime`]
+pub struct Runtime {
+    /// The value of every module loaded by `require`, keyed by
+    /// the canonical path of the file
+    pub modules: HashMap<PathBuf, Object>,
+    /// The files being evaluated, the innermost file is the last one
+    pub files: Vec<PathBuf>,
+    /// The number of symbols made by `gensym` so far
+    pub gensym_counter: usize,
+    /// The generator of `random`, seeded randomly unless `random-seed!` is called
+    pub random: fastrand::Rng,
+    /// The environments captured by closures and promises, which may
+    /// be part of a reference cycle, see [`crate::gc::collect`]. They are
+    /// keyed by address, which is not reused while the weak reference exists
+    pub environments: HashMap<usize, Weak<RefCell<Environment>>>,
+    /// Dropped environments are pruned from `environments` once it
+    /// grows past this length
+    pub prune_threshold: usize,
+    /// The environments tracked since the latest collection
+    pub tracked_since_collect: usize,
+    /// The cycles are collected once this many environments are tracked
+    /// since the latest collection, twice the number of environments the
+    /// latest collection left so it takes time in proportion to the garbage
+    pub collect_threshold: usize,
+    /// The tests defined by `define-test` in the order of their
+    /// definition, each one is a name and a function without parameters
+    pub tests: Vec<(String, Object)>,
+    /// The calls the latest error unwound through, the innermost call
+    /// first. Calls replaced by tail calls are not seen
+    pub backtrace: Vec<Frame>,
+    /// The number of calls being evaluated, see [`crate::evaluator::CallDepth`]
+    pub depth: usize,
+    /// The number of nested calls above which the program fails
+    /// instead of overflowing the stack
+    pub max_depth: usize,
+    /// The builtins with side effects still allowed when the program runs in
+    /// a sandbox, see [`crate::builtins::SIDE_EFFECTS`], None without sandbox
+    pub sandbox: Option<HashSet<String>>,
+    /// The text written to the standard output when it is captured
+    /// instead of printed, see [`crate::Interpreter::set_capture_output`]
+    pub output: Option<Rc<RefCell<String>>>,
+    /// The functions whose calls are printed by `trace`, keyed by the
+    /// address of their definition which is kept alive here
+    pub traced: HashMap<usize, Object>,
+    /// The number of traced calls being evaluated
+    pub trace_depth: usize,
+    /// The functions called with the datum following `#` and the
+    /// character, see [`crate::evaluator::expand`]
+    pub reader_macros: HashMap<char, Object>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self {
+            modules: HashMap::new(),
+            files: vec![],
+            gensym_counter: 0,
+            random: fastrand::Rng::new(),
+            environments: HashMap::new(),
+            prune_threshold: 0,
+            tracked_since_collect: 0,
+            collect_threshold: COLLECT_INTERVAL,
+            tests: vec![],
+            backtrace: vec![],
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            sandbox: None,
+            output: None,
+            traced: HashMap::new(),
+            trace_depth: 0,
+            reader_macros: HashMap::new(),
+        }
+    }
+}
+
+/// A call of a function, `loc` is the location of the call
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub loc: Option<Location>,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.loc {
+            Some(loc) => write!(f, "{} at {}:{}:{}", self.name, loc.filename(), loc.rol(), loc.col()),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl std::fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("modules", &self.modules.keys().collect::<Vec<_>>())
+            .field("files", &self.files)
+            .field("environments", &self.environments.len())
+            .field("tests", &self.tests.len())
+            .field("backtrace", &self.backtrace)
+            .field("depth", &self.depth)
+            .field("max_depth", &self.max_depth)
+            .field("sandbox", &self.sandbox)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+impl Runtime {
+    pub fn current_file(&self) -> Option<&PathBuf> {
+        self.files.last()
+    }
+
+    /// Track an environment captured by a closure or a promise, return
+    /// whether the cycles are to be collected now
+    pub fn track(&mut self, env: Weak<RefCell<Environment>>) -> bool {
+        self.environments.entry(env.as_ptr() as usize).or_insert(env);
+        if self.environments.len() > self.prune_threshold.max(1024) {
+            self.environments.retain(|_, env| env.strong_count() > 0);
+            self.prune_threshold = self.environments.len() * 2;
+        }
+        self.tracked_since_collect += 1;
+        self.tracked_since_collect >= self.collect_threshold
+    }
+}
@@ -0,0 +1,108 @@
+use std::{cell::RefCell, collections::HashMap};
+
+/// An interned symbol, symbols with the same name share the same id
+/// so they are compared and hashed as integers
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The names of the symbols indexed by their id, the names are leaked
+/// since symbols live as long as the program
+#[derive(Default)]
+struct Interner {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// The symbol named `name`, the same symbol is returned for the same name
+    pub fn intern(name: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(symbol) = interner.ids.get(name) {
+                return *symbol;
+            }
+            let symbol = Symbol::push(&mut interner, name);
+            let name = interner.names[symbol.0 as usize];
+            interner.ids.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// A fresh symbol which is not equal to any other symbol,
+    /// even one interned with the same name
+    pub fn uninterned(name: &str) -> Self {
+        INTERNER.with(|interner| Symbol::push(&mut interner.borrow_mut(), name))
+    }
+
+    fn push(interner: &mut Interner, name: &str) -> Self {
+        let symbol = Symbol(interner.names.len() as u32);
+        interner.names.push(Box::leak(name.to_string().into_boxed_str()));
+        symbol
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(symbol: &Symbol) -> Self {
+        *symbol
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        assert_eq!(Symbol::intern("foo"), Symbol::intern("foo"));
+        assert_ne!(Symbol::intern("foo"), Symbol::intern("bar"));
+        assert_eq!(Symbol::intern("foo").as_str(), "foo");
+
+        let fresh = Symbol::uninterned("foo");
+        assert_ne!(fresh, Symbol::intern("foo"));
+        assert_eq!(fresh.as_str(), "foo");
+    }
+}
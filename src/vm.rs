@@ -0,0 +1,990 @@
+//! Bytecode backend
+//!
+//! The forms are compiled to a flat list of instructions run on a value
+//! stack instead of walking the tree of objects. The VM shares the
+//! environments and the objects of the tree-walking evaluator, so both
+//! backends can be mixed: the rarely used special forms (case, guard,
+//! define-record-type, ...) are compiled to an [`Op::Eval`] instruction
+//! handing the form over to the evaluator.
+//!
+//! The calls of the lambdas are run in the loop of the caller rather than
+//! in a nested one, and the calls in tail position reuse the current frame.
+//! The functions are compiled along with the code creating them, in the
+//! scope they are created in. The variables bound by the frames the VM
+//! creates, the parameters of the functions and the variables of `let`,
+//! `let*` and `letrec`, are resolved at compile time to their (depth, slot)
+//! address like the evaluator addresses them, see [`crate::resolve`]. The
+//! other variables are looked up by name.
+//!
+//! A call of a function taking positional parameters whose body does not
+//! create frames or closures keeps its arguments on the value stack, the
+//! frame is only bound in an environment when the code needs one, e.g.
+//! to call a builtin which may evaluate code in it. The arithmetic of the
+//! builtins on two integers is computed without calling them.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hasher},
+    rc::{Rc, Weak},
+};
+
+use crate::builtins::{self, numeric};
+use crate::error::EvalError;
+use crate::fuel;
+use crate::evaluator::{
+    apply_function, apply_macro, bind_arguments, eval_native_func, eval_function_definition, eval_obj, expand,
+    is_truthy, named_let, parse_params, trace, with_stack, CallDepth, Environment,
+};
+use crate::location::Location;
+use crate::parser::{FunctionDefinition, ListItems, Object, Param, ParamKind};
+use crate::resolve::Scope;
+use crate::symbol::Symbol;
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Push the object
+    Const(Object),
+    /// Push the value of the variable, the slot it was last found in is
+    /// tried first, see [`Environment::get_hinted`]
+    Load(Symbol, Cell<usize>, Option<Location>),
+    /// Push the value in the slot of the frame `depth` levels up,
+    /// the variable is looked up by name if the slot cannot be used
+    LoadLocal(Symbol, usize, usize, Option<Location>),
+    /// Pop a value and bind it in the current scope, push Void
+    Define(Symbol),
+    /// Pop a value and assign it to the nearest binding, push Void
+    Set(Symbol, Option<Location>),
+    /// Pop a value and assign it to the slot like [`Op::LoadLocal`], push Void
+    SetLocal(Symbol, usize, usize, Option<Location>),
+    Pop,
+    Jump(usize),
+    /// Pop the condition and jump if it is false
+    JumpIfFalse(usize),
+    /// Jump keeping the value on the stack if it is false, otherwise pop it
+    JumpIfFalseOrPop(usize),
+    /// Jump keeping the value on the stack if it is true, otherwise pop it
+    JumpIfTrueOrPop(usize),
+    /// Push a closure of `(lambda params body ...)` over the current scope,
+    /// along with the code of its body unless the parameters are invalid,
+    /// named after the variable of `(define (name params ...) body ...)`
+    Lambda(Rc<Vec<Object>>, Option<Rc<Chunk>>, Option<Symbol>),
+    /// Evaluate the call form as a macro call and jump if the value on top
+    /// of the stack, the function being called, turns out to be a macro
+    ExpandIfMacro(Object, usize),
+    /// Pop the arguments and the function and push the result of the call
+    Call(usize, Option<Location>),
+    /// A call whose result is returned, the current frame is reused
+    TailCall(usize, Option<Location>),
+    /// Pop a value for each name and bind them in a new child scope
+    /// the following instructions are evaluated in
+    EnterScope(Vec<Symbol>),
+    ExitScope,
+    /// Evaluate the form with the tree-walking evaluator
+    Eval(Object),
+}
+
+/// The instructions of a top level form or of a function body, the
+/// value left on top of the stack is the result
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// The parameters of a function whose calls may keep the arguments on
+    /// the stack rather than bind them in an environment, see [`stack_arguments`]
+    pub arguments: Option<Vec<Symbol>>,
+}
+
+impl Chunk {
+    /// Whether a call with `argc` arguments keeps them on the stack, the
+    /// calls with a wrong number of arguments bind them to report the error
+    fn keeps_arguments(&self, argc: usize) -> bool {
+        self.arguments.as_ref().is_some_and(|names| names.len() == argc)
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    /// Point the jump at `index` to the next instruction
+    fn patch(&mut self, index: usize) {
+        let target = self.code.len();
+        match &mut self.code[index] {
+            Op::Jump(to)
+            | Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to)
+            | Op::ExpandIfMacro(_, to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn resolve(scope: Option<&Scope>, name: Symbol) -> Option<(usize, usize)> {
+    scope.and_then(|scope| scope.resolve(name))
+}
+
+/// Compile the top level form, which must already be macro expanded
+pub fn compile(obj: &Object) -> Chunk {
+    let mut chunk = Chunk::default();
+    compile_obj(obj, &mut chunk, None, true);
+    chunk
+}
+
+/// Compile the body of a function run in a frame holding its parameters
+fn compile_function(params: &[Param], body: &[Object], parent: Option<&Scope>) -> Chunk {
+    let mut chunk = Chunk::default();
+    compile_body(body, &mut chunk, Some(&Scope::function(params, body, parent)), true);
+    chunk.arguments = stack_arguments(params, &chunk.code);
+    chunk
+}
+
+/// The names of the parameters when the calls of the function can keep
+/// the arguments on the stack: the parameters are positional and distinct,
+/// and the body does not define variables, create closures or frames, or
+/// hand forms over to the evaluator, which would bind them anyway
+fn stack_arguments(params: &[Param], code: &[Op]) -> Option<Vec<Symbol>> {
+    let binds = |op: &Op| matches!(op, Op::Define(_) | Op::Lambda(..) | Op::EnterScope(_) | Op::Eval(_));
+    if code.iter().any(binds) {
+        return None;
+    }
+    let mut names = vec![];
+    for param in params {
+        match param.kind {
+            ParamKind::Named(name) if !names.contains(&name) => names.push(name),
+            _ => return None,
+        }
+    }
+    Some(names)
+}
+
+fn compile_body(body: &[Object], chunk: &mut Chunk, scope: Option<&Scope>, tail: bool) {
+    match body.split_last() {
+        Some((last, init)) => {
+            for obj in init {
+                compile_obj(obj, chunk, scope, false);
+                chunk.emit(Op::Pop);
+            }
+            compile_obj(last, chunk, scope, tail);
+        },
+        None => {
+            chunk.emit(Op::Const(Object::Void { loc: None }));
+        },
+    }
+}
+
+fn compile_obj(obj: &Object, chunk: &mut Chunk, scope: Option<&Scope>, tail: bool) {
+    match obj {
+        // The variables addressed for the evaluator are addressed again in the compiled frames
+        Object::Symbol { value, loc } | Object::Local { value, loc, .. } => {
+            chunk.emit(match resolve(scope, *value) {
+                Some((depth, slot)) => Op::LoadLocal(*value, depth, slot, loc.clone()),
+                None => Op::Load(*value, Cell::new(0), loc.clone()),
+            });
+        },
+        Object::List { value, .. } if value.is_empty() => {
+            chunk.emit(Op::Const(Object::Void { loc: None }));
+        },
+        Object::List { value, .. } => compile_list(obj, value, chunk, scope, tail),
+        // Modules and pairs are left to the evaluator, which reports the error for pairs
+        Object::Module { .. } | Object::Pair { .. } => {
+            chunk.emit(Op::Eval(obj.clone()));
+        },
+        _ => {
+            chunk.emit(Op::Const(obj.clone()));
+        },
+    }
+}
+
+/// Compile the special forms written the expected way, the others
+/// are evaluated by the evaluator which reports the syntax errors
+fn compile_list(obj: &Object, list: &[Object], chunk: &mut Chunk, scope: Option<&Scope>, tail: bool) {
+    let form = match &list[0] {
+        Object::Symbol { value, .. } => value.as_str(),
+        _ => "",
+    };
+    match (form, &list[1..]) {
+        ("quote", [quoted]) => {
+            chunk.emit(Op::Const(quoted.clone()));
+        },
+        ("if", [condition, then, otherwise]) => {
+            compile_obj(condition, chunk, scope, false);
+            let jump_else = chunk.emit(Op::JumpIfFalse(0));
+            compile_obj(then, chunk, scope, tail);
+            let jump_end = chunk.emit(Op::Jump(0));
+            chunk.patch(jump_else);
+            compile_obj(otherwise, chunk, scope, tail);
+            chunk.patch(jump_end);
+        },
+        ("when" | "unless", [condition, body @ ..]) => {
+            // (when c body ...) is (if c (begin body ...)) and (unless c
+            // body ...) is (if c (begin) (begin body ...))
+            let (then, otherwise) = if form == "when" { (body, &[][..]) } else { (&[][..], body) };
+            compile_obj(condition, chunk, scope, false);
+            let jump_else = chunk.emit(Op::JumpIfFalse(0));
+            compile_body(then, chunk, scope, tail);
+            let jump_end = chunk.emit(Op::Jump(0));
+            chunk.patch(jump_else);
+            compile_body(otherwise, chunk, scope, tail);
+            chunk.patch(jump_end);
+        },
+        ("define", [Object::Symbol { value, .. }, expr]) => {
+            compile_obj(expr, chunk, scope, false);
+            chunk.emit(Op::Define(*value));
+        },
+        ("set!", [Object::Symbol { value, loc } | Object::Local { value, loc, .. }, expr]) => {
+            compile_obj(expr, chunk, scope, false);
+            chunk.emit(match resolve(scope, *value) {
+                Some((depth, slot)) => Op::SetLocal(*value, depth, slot, loc.clone()),
+                None => Op::Set(*value, loc.clone()),
+            });
+        },
+        ("begin", body) => compile_body(body, chunk, scope, tail),
+        ("and", [init @ .., last]) | ("or", [init @ .., last]) => {
+            let jumps: Vec<_> = init
+                .iter()
+                .map(|obj| {
+                    compile_obj(obj, chunk, scope, false);
+                    chunk.emit(if form == "and" { Op::JumpIfFalseOrPop(0) } else { Op::JumpIfTrueOrPop(0) })
+                })
+                .collect();
+            compile_obj(last, chunk, scope, tail);
+            jumps.into_iter().for_each(|jump| chunk.patch(jump));
+        },
+        ("lambda", [params, body @ ..]) if !body.is_empty() => {
+            let code = parse_params(Some(params))
+                .ok()
+                .map(|params| Rc::new(compile_function(&params, body, scope)));
+            chunk.emit(Op::Lambda(Rc::new(list.to_vec()), code, None));
+        },
+        // (define (name x y) body) is (define name (lambda (x y) body)), the
+        // function is compiled in the scope it is defined in like the lambdas
+        ("define", [Object::List { value: signature, loc }, body @ ..])
+            if matches!(signature.first(), Some(Object::Symbol { .. })) && !body.is_empty() =>
+        {
+            let params = Object::List { value: ListItems::new(signature[1..].to_vec()), loc: loc.clone() };
+            compile_define_function(&list[0], &signature[0], params, body, chunk, scope);
+        },
+        ("define", [Object::Pair { value, .. }, body @ ..]) if matches!(value.0, Object::Symbol { .. }) && !body.is_empty() => {
+            compile_define_function(&list[0], &value.0, value.1.clone(), body, chunk, scope);
+        },
+        ("cond", clauses) if is_clauses(clauses) => {
+            let mut jumps = vec![];
+            for clause in clauses.iter() {
+                let Object::List { value: clause, .. } = clause else { unreachable!() };
+                if matches!(&clause[0], Object::Symbol { value, .. } if value == "else") {
+                    compile_body(&clause[1..], chunk, scope, tail);
+                    jumps.push(chunk.emit(Op::Jump(0)));
+                    continue;
+                }
+                compile_obj(&clause[0], chunk, scope, false);
+                // A clause without expressions gives the value of its test
+                if clause.len() == 1 {
+                    jumps.push(chunk.emit(Op::JumpIfTrueOrPop(0)));
+                    continue;
+                }
+                let next = chunk.emit(Op::JumpIfFalse(0));
+                compile_body(&clause[1..], chunk, scope, tail);
+                jumps.push(chunk.emit(Op::Jump(0)));
+                chunk.patch(next);
+            }
+            chunk.emit(Op::Const(Object::Void { loc: None }));
+            jumps.into_iter().for_each(|jump| chunk.patch(jump));
+        },
+        ("let", [Object::List { value: bindings, .. }, body @ ..]) if is_bindings(bindings) => {
+            // The values are computed in the outer scope
+            let mut names = vec![];
+            for binding in bindings.iter() {
+                if let Object::List { value, .. } = binding {
+                    compile_obj(&value[1], chunk, scope, false);
+                    if let Object::Symbol { value: name, .. } = &value[0] {
+                        names.push(*name);
+                    }
+                }
+            }
+            let inner = Scope::body(names.iter().copied(), body, scope);
+            chunk.emit(Op::EnterScope(names));
+            compile_body(body, chunk, Some(&inner), tail);
+            chunk.emit(Op::ExitScope);
+        },
+        // Each binding of let* gets its own frame nested in the one of the
+        // previous binding, the body is evaluated in a frame of its own
+        ("let*", [Object::List { value: bindings, .. }, body @ ..]) if is_bindings(bindings) => {
+            compile_let_star(bindings, body, chunk, scope, tail);
+            for _ in 0..=bindings.len() {
+                chunk.emit(Op::ExitScope);
+            }
+        },
+        // The variables are bound to void before the values are computed in
+        // their frame, so that the values can refer to each other
+        ("letrec", [Object::List { value: bindings, .. }, body @ ..]) if is_bindings(bindings) => {
+            let names: Vec<_> = bindings.iter().map(binding_name).collect();
+            let inner = Scope::body(names.iter().copied(), body, scope);
+            for _ in names.iter() {
+                chunk.emit(Op::Const(Object::Void { loc: None }));
+            }
+            chunk.emit(Op::EnterScope(names));
+            for binding in bindings.iter() {
+                let Object::List { value, .. } = binding else { unreachable!() };
+                let name = binding_name(binding);
+                compile_obj(&value[1], chunk, Some(&inner), false);
+                let (depth, slot) = inner.resolve(name).unwrap_or_default();
+                chunk.emit(Op::SetLocal(name, depth, slot, value[0].loc().cloned()));
+                chunk.emit(Op::Pop);
+            }
+            compile_body(body, chunk, Some(&inner), tail);
+            chunk.emit(Op::ExitScope);
+        },
+        // The named let is compiled as the call of the function it defines,
+        // so the calls of the loop in tail position reuse the frame
+        ("let", [Object::Symbol { .. }, ..]) => match named_let(&list[1..]) {
+            Ok(call) => compile_obj(&call, chunk, scope, tail),
+            Err(_) => {
+                chunk.emit(Op::Eval(obj.clone()));
+            },
+        },
+        (
+            "define" | "set!" | "if" | "when" | "unless" | "cond" | "case" | "and" | "or" | "lambda" | "defmacro"
+            | "guard" | "delay" | "parameterize" | "define-record-type" | "define-test" | "let" | "let*" | "letrec" | "quote"
+            | "quasiquote" | "unquote" | "unquote-splicing",
+            _,
+        ) => {
+            chunk.emit(Op::Eval(obj.clone()));
+        },
+        _ => {
+            compile_obj(&list[0], chunk, scope, false);
+            let expand = chunk.emit(Op::ExpandIfMacro(obj.clone(), 0));
+            for arg in list[1..].iter() {
+                compile_obj(arg, chunk, scope, false);
+            }
+            let argc = list.len() - 1;
+            let loc = obj.loc().cloned();
+            chunk.emit(if tail { Op::TailCall(argc, loc) } else { Op::Call(argc, loc) });
+            chunk.patch(expand);
+        },
+    }
+}
+
+fn compile_define_function(
+    head: &Object,
+    name: &Object,
+    params: Object,
+    body: &[Object],
+    chunk: &mut Chunk,
+    scope: Option<&Scope>,
+) {
+    let Object::Symbol { value: name, .. } = name else { unreachable!() };
+    let code = parse_params(Some(&params))
+        .ok()
+        .map(|params| Rc::new(compile_function(&params, body, scope)));
+    let lambda = [head.clone(), params].into_iter().chain(body.iter().cloned()).collect();
+    chunk.emit(Op::Lambda(Rc::new(lambda), code, Some(*name)));
+    chunk.emit(Op::Define(*name));
+}
+
+fn compile_let_star(bindings: &[Object], body: &[Object], chunk: &mut Chunk, scope: Option<&Scope>, tail: bool) {
+    match bindings.split_first() {
+        Some((binding, rest)) => {
+            let Object::List { value, .. } = binding else { unreachable!() };
+            let name = binding_name(binding);
+            compile_obj(&value[1], chunk, scope, false);
+            chunk.emit(Op::EnterScope(vec![name]));
+            compile_let_star(rest, body, chunk, Some(&Scope::new([name], scope)), tail);
+        },
+        None => {
+            chunk.emit(Op::EnterScope(vec![]));
+            compile_body(body, chunk, Some(&Scope::body([], body, scope)), tail);
+        },
+    }
+}
+
+/// Whether every binding of a let is `(name expr)`
+fn is_bindings(bindings: &[Object]) -> bool {
+    bindings.iter().all(|binding| {
+        matches!(binding, Object::List { value, .. } if matches!(value[..], [Object::Symbol { .. }, _]))
+    })
+}
+
+/// The name of a binding checked by [`is_bindings`]
+fn binding_name(binding: &Object) -> Symbol {
+    match binding {
+        Object::List { value, .. } => match &value[0] {
+            Object::Symbol { value, .. } => *value,
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Whether every clause of a cond is `(test expr ...)` and only the last
+/// one is an else clause
+fn is_clauses(clauses: &[Object]) -> bool {
+    clauses.iter().enumerate().all(|(i, clause)| match clause {
+        Object::List { value, .. } if !value.is_empty() => {
+            i == clauses.len() - 1 || !matches!(&value[0], Object::Symbol { value, .. } if value == "else")
+        },
+        _ => false,
+    })
+}
+
+/// A function along with the code of its body, the entry is
+/// dropped once the function is freed
+type Compiled = (Weak<FunctionDefinition>, Rc<Chunk>);
+
+/// Hashes the addresses of the functions, which are looked up on every call
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ *byte as u64;
+        }
+    }
+
+    fn write_usize(&mut self, address: usize) {
+        self.0 = address as u64;
+    }
+
+    fn finish(&self) -> u64 {
+        // The low bits of an address are zero, the multiplication mixes
+        // the bits into the high half which the rotation brings down
+        self.0.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32)
+    }
+}
+
+/// Runs the compiled forms. The bodies of the lambdas are compiled along
+/// with the code creating them, the bodies of the functions created by
+/// the evaluator are compiled the first time they are called
+#[derive(Default)]
+pub struct Vm {
+    /// Keyed by the address of the function
+    functions: RefCell<HashMap<usize, Compiled, BuildHasherDefault<AddressHasher>>>,
+    /// The number of functions from which the freed ones are pruned
+    prune_threshold: Cell<usize>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expand, compile and run the form
+    pub fn eval(&self, obj: &Object, env: &Rc<RefCell<Environment>>) -> Result<Object, EvalError> {
+        let form = expand(obj, env)?;
+        self.execute(Rc::new(compile(&form)), Frame { env: env.clone(), on_stack: false })
+    }
+
+    fn register(&self, func: &Rc<FunctionDefinition>, chunk: Rc<Chunk>) {
+        let mut functions = self.functions.borrow_mut();
+        functions.insert(Rc::as_ptr(func) as usize, (Rc::downgrade(func), chunk));
+        if functions.len() > self.prune_threshold.get().max(1024) {
+            functions.retain(|_, (func, _)| func.strong_count() > 0);
+            self.prune_threshold.set(functions.len() * 2);
+        }
+    }
+
+    fn function_code(&self, func: &Rc<FunctionDefinition>) -> Rc<Chunk> {
+        let key = Rc::as_ptr(func) as usize;
+        if let Some((known, chunk)) = self.functions.borrow().get(&key) {
+            // The address may be reused by another function once freed
+            if known.as_ptr() == Rc::as_ptr(func) && known.strong_count() > 0 {
+                return chunk.clone();
+            }
+        }
+        // The frames enclosing the function are unknown
+        let chunk = Rc::new(compile_function(&func.params, &func.body.0, None));
+        self.register(func, chunk.clone());
+        chunk
+    }
+
+    /// Call a function which is not a lambda run by the VM: a builtin, a
+    /// traced function or a parameter
+    fn call(
+        &self,
+        func: &Object,
+        args: &[Object],
+        loc: &Option<Location>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, EvalError> {
+        let result = match func {
+            Object::NativeFunction { value, .. } => {
+                eval_native_func(value, args, env).map_err(|e| trace(e, &value.name, loc.as_ref(), env))
+            },
+            // The traced functions are evaluated by the tree-walking
+            // evaluator, which prints their calls
+            Object::Lambda { value, .. } => {
+                apply_function(value, args).map_err(|e| trace(e, value.label(), loc.as_ref(), env))
+            },
+            Object::Parameter { value, .. } => builtins::control::parameter_value(value, args),
+            _ => Err(EvalError::NotCallable { found: func.to_string(), loc: loc.clone() }),
+        };
+        result.map_err(|e| e.or_loc(loc.as_ref()))
+    }
+
+    /// Run the chunk in the frame. The calls of the lambdas are run in the
+    /// same loop, the callers waiting on a stack of their own, so a call
+    /// does not nest in the calls of the host language
+    fn execute(&self, chunk: Rc<Chunk>, frame: Frame) -> Result<Object, EvalError> {
+        let mut current = Activation { chunk, pc: 0, frame, base: 0, func: None, entered: None, _depth: None };
+        let mut callers = vec![];
+        self.run(&mut current, &mut callers, &mut vec![]).map_err(|mut e| {
+            // The error unwinds out of the calls, the innermost first
+            loop {
+                e = current.unwind(e);
+                let Some(caller) = callers.pop() else {
+                    return e;
+                };
+                if let (Some(func), Op::Call(_, loc)) = (&current.func, &caller.chunk.code[caller.pc - 1]) {
+                    e = trace(e, func.label(), loc.as_ref(), &caller.frame.env).or_loc(loc.as_ref());
+                }
+                current = caller;
+            }
+        })
+    }
+
+    fn run(&self, current: &mut Activation, callers: &mut Vec<Activation>, stack: &mut Vec<Object>) -> Result<Object, EvalError> {
+        loop {
+            let op = match current.chunk.code.get(current.pc) {
+                Some(op) => op,
+                // The value is returned to the caller
+                None => {
+                    let value = stack.pop().unwrap_or(Object::Void { loc: None });
+                    match callers.pop() {
+                        Some(caller) => {
+                            stack.truncate(current.base - 1);
+                            stack.push(value);
+                            *current = caller;
+                            continue;
+                        },
+                        None => return Ok(value),
+                    }
+                },
+            };
+            current.pc += 1;
+            fuel::burn()?;
+
+            let frame = &mut current.frame;
+            match op {
+                Op::Const(obj) => stack.push(obj.clone()),
+                Op::Load(name, hint, loc) => {
+                    let value = frame.env.borrow().get_hinted(*name, hint);
+                    stack.push(value.ok_or_else(|| EvalError::UndefinedSymbol { name: name.to_string(), loc: loc.clone() })?);
+                },
+                Op::LoadLocal(name, depth, slot, loc) => {
+                    let value = match (frame.on_stack, *depth) {
+                        (true, 0) => Some(stack[current.base + slot].clone()),
+                        (on_stack, depth) => frame.env.borrow().get_at(depth - on_stack as usize, *slot, *name),
+                    };
+                    stack.push(match value {
+                        Some(value) => value,
+                        None => load(&frame.env, *name, loc)?,
+                    });
+                },
+                Op::Define(name) => {
+                    let value = pop(stack);
+                    frame.bind(&current.chunk, &stack[current.base..]).borrow_mut().set(name, value);
+                    stack.push(Object::Void { loc: None });
+                },
+                Op::Set(name, loc) => {
+                    let value = pop(stack);
+                    if !frame.env.borrow_mut().update(name, value) {
+                        return Err(EvalError::UndefinedSymbol { name: name.to_string(), loc: loc.clone() });
+                    }
+                    stack.push(Object::Void { loc: None });
+                },
+                Op::SetLocal(name, depth, slot, loc) => {
+                    let value = pop(stack);
+                    match (frame.on_stack, *depth) {
+                        (true, 0) => stack[current.base + slot] = value,
+                        (on_stack, depth) => {
+                            let mut env = frame.env.borrow_mut();
+                            if !env.set_at(depth - on_stack as usize, *slot, *name, value.clone()) && !env.update(name, value) {
+                                return Err(EvalError::UndefinedSymbol { name: name.to_string(), loc: loc.clone() });
+                            }
+                        },
+                    }
+                    stack.push(Object::Void { loc: None });
+                },
+                Op::Pop => {
+                    stack.pop();
+                },
+                Op::Jump(to) => current.pc = *to,
+                Op::JumpIfFalse(to) => {
+                    if !is_truthy(&pop(stack)) {
+                        current.pc = *to;
+                    }
+                },
+                Op::JumpIfFalseOrPop(to) => match stack.last() {
+                    Some(value) if !is_truthy(value) => current.pc = *to,
+                    _ => {
+                        stack.pop();
+                    },
+                },
+                Op::JumpIfTrueOrPop(to) => match stack.last() {
+                    Some(value) if is_truthy(value) => current.pc = *to,
+                    _ => {
+                        stack.pop();
+                    },
+                },
+                Op::Lambda(list, code, name) => {
+                    let mut func = eval_function_definition(&list[1..], frame.bind(&current.chunk, &stack[current.base..]))?;
+                    if let (Object::Lambda { value, .. }, Some(name)) = (&mut func, name) {
+                        Rc::make_mut(value).name = Some(name.to_string());
+                    }
+                    if let (Object::Lambda { value, .. }, Some(code)) = (&func, code) {
+                        self.register(value, code.clone());
+                    }
+                    stack.push(func);
+                },
+                Op::ExpandIfMacro(form, to) => {
+                    if let Some(Object::Macro { value, .. }) = stack.last() {
+                        let func = value.clone();
+                        stack.pop();
+                        let list = match form {
+                            Object::List { value, .. } => value,
+                            _ => unreachable!(),
+                        };
+                        let env = frame.bind(&current.chunk, &stack[current.base..]).clone();
+                        let expanded = expand(&apply_macro(&func, list)?, &env)?;
+                        let _depth = CallDepth::enter(&env)?;
+                        let frame = Frame { env, on_stack: false };
+                        stack.push(with_stack(|| self.execute(Rc::new(compile(&expanded)), frame))?);
+                        current.pc = *to;
+                    }
+                },
+                // The arguments of the lambdas become the first values of
+                // the call on the stack, the other functions get a slice
+                Op::Call(argc, loc) => {
+                    let start = stack.len() - argc;
+                    match &stack[start - 1] {
+                        Object::Lambda { value, .. } if !builtins::trace::is_traced(value, &frame.env) => {
+                            let func = value.clone();
+                            let chunk = self.function_code(&func);
+                            let frame = if chunk.keeps_arguments(*argc) {
+                                Ok(Frame { env: func.env.clone(), on_stack: true })
+                            } else {
+                                bind_arguments(&func, &stack[start..]).map(|env| Frame { env, on_stack: false })
+                            };
+                            let (depth, frame) = frame
+                                .and_then(|frame| Ok((CallDepth::enter(&frame.env)?, frame)))
+                                .map_err(|e| trace(e, func.label(), loc.as_ref(), &current.frame.env).or_loc(loc.as_ref()))?;
+                            if !frame.on_stack {
+                                stack.truncate(start);
+                            }
+                            let callee = Activation {
+                                chunk,
+                                pc: 0,
+                                frame,
+                                base: start,
+                                func: Some(func),
+                                entered: None,
+                                _depth: Some(depth),
+                            };
+                            callers.push(std::mem::replace(current, callee));
+                        },
+                        func => {
+                            let args = &stack[start..];
+                            let result = match arithmetic(func, args) {
+                                Some(value) => Ok(value),
+                                None => self.call(func, args, loc, frame.env_for(func, &current.chunk, &stack[current.base..])),
+                            };
+                            stack.truncate(start - 1);
+                            stack.push(result?);
+                        },
+                    }
+                },
+                Op::TailCall(argc, loc) => {
+                    let start = stack.len() - argc;
+                    match &stack[start - 1] {
+                        // The frame of the callee replaces the current one, the
+                        // traced functions return to print their value
+                        Object::Lambda { value, .. } if !builtins::trace::is_traced(value, &frame.env) => {
+                            let func = value.clone();
+                            current.entered = Some(TailCallSite { func: func.clone(), chunk: current.chunk.clone(), pc: current.pc - 1 });
+                            let chunk = self.function_code(&func);
+                            current.frame = if chunk.keeps_arguments(*argc) {
+                                stack.drain(current.base..start);
+                                Frame { env: func.env.clone(), on_stack: true }
+                            } else {
+                                let env = bind_arguments(&func, &stack[start..]).map_err(|e| e.or_loc(loc.as_ref()))?;
+                                stack.truncate(current.base);
+                                Frame { env, on_stack: false }
+                            };
+                            current.chunk = chunk;
+                            current.pc = 0;
+                        },
+                        func => {
+                            let args = &stack[start..];
+                            let result = match arithmetic(func, args) {
+                                Some(value) => Ok(value),
+                                None => self.call(func, args, loc, frame.env_for(func, &current.chunk, &stack[current.base..])),
+                            };
+                            stack.truncate(start - 1);
+                            stack.push(result?);
+                        },
+                    }
+                },
+                Op::EnterScope(names) => {
+                    let values = stack.split_off(stack.len() - names.len());
+                    let mut scope = Environment::new(Some(frame.bind(&current.chunk, &stack[current.base..]).clone()));
+                    for (name, value) in names.iter().zip(values) {
+                        scope.set(name, value);
+                    }
+                    scope.seal();
+                    frame.env = Rc::new(RefCell::new(scope));
+                },
+                Op::ExitScope => {
+                    let parent = frame.env.borrow().parent().cloned();
+                    if let Some(parent) = parent {
+                        frame.env = parent;
+                    }
+                },
+                Op::Eval(obj) => {
+                    let value = eval_obj(obj, frame.bind(&current.chunk, &stack[current.base..]))?;
+                    stack.push(value);
+                },
+            }
+        }
+    }
+}
+
+/// A call being run by [`Vm::execute`]
+struct Activation {
+    chunk: Rc<Chunk>,
+    pc: usize,
+    frame: Frame,
+    /// Where the values of the call start on the stack, the function
+    /// called is right below
+    base: usize,
+    /// The lambda called, None for the chunk the run starts with
+    func: Option<Rc<FunctionDefinition>>,
+    /// The function entered by the latest tail call
+    entered: Option<TailCallSite>,
+    /// Counts the call as nested until it returns
+    _depth: Option<CallDepth>,
+}
+
+impl Activation {
+    /// Trace an error unwinding out of the function entered by the
+    /// latest tail call as a call of that function
+    fn unwind(&self, e: EvalError) -> EvalError {
+        match &self.entered {
+            Some(TailCallSite { func, chunk, pc }) => match &chunk.code[*pc] {
+                Op::TailCall(_, loc) => trace(e, func.label(), loc.as_ref(), &self.frame.env),
+                _ => e,
+            },
+            None => e,
+        }
+    }
+}
+
+/// The variables of the code being run
+struct Frame {
+    env: Rc<RefCell<Environment>>,
+    /// Whether the arguments of the call are the first values of the stack
+    /// rather than bound in `env`, which is then the environment of the function
+    on_stack: bool,
+}
+
+impl Frame {
+    /// The environment holding the variables of the call, the arguments
+    /// kept on the stack are bound in a new one for the code needing them there
+    fn bind(&mut self, chunk: &Chunk, stack: &[Object]) -> &Rc<RefCell<Environment>> {
+        if self.on_stack {
+            let mut env = Environment::new(Some(self.env.clone()));
+            for (name, value) in chunk.arguments.iter().flatten().zip(stack) {
+                env.set(name, value.clone());
+            }
+            env.seal();
+            self.env = Rc::new(RefCell::new(env));
+            self.on_stack = false;
+        }
+        &self.env
+    }
+
+    /// The environment given to the function called, the builtins may
+    /// look the variables of the caller up, e.g. eval
+    fn env_for(&mut self, func: &Object, chunk: &Chunk, stack: &[Object]) -> &Rc<RefCell<Environment>> {
+        match func {
+            Object::NativeFunction { .. } => self.bind(chunk, stack),
+            _ => &self.env,
+        }
+    }
+}
+
+/// The value of the call of an arithmetic builtin computed on the spot
+fn arithmetic(func: &Object, args: &[Object]) -> Option<Object> {
+    match func {
+        Object::NativeFunction { value, .. } => numeric::integer_op(value, args),
+        _ => None,
+    }
+}
+
+/// The function entered by a tail call and the call itself, the
+/// location of the call is only looked up when an error is traced
+struct TailCallSite {
+    func: Rc<FunctionDefinition>,
+    chunk: Rc<Chunk>,
+    pc: usize,
+}
+
+fn load(env: &Rc<RefCell<Environment>>, name: Symbol, loc: &Option<Location>) -> Result<Object, EvalError> {
+    env.borrow()
+        .get(name)
+        .ok_or_else(|| EvalError::UndefinedSymbol { name: name.to_string(), loc: loc.clone() })
+}
+
+fn pop(stack: &mut Vec<Object>) -> Object {
+    stack.pop().unwrap_or(Object::Void { loc: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+
+    fn run(prog: &str) -> Result<Object, EvalError> {
+        let mut tokens = tokenize("vm_test.rs", prog)?;
+        let env = Rc::new(RefCell::new(Environment::new(None)));
+        let vm = Vm::new();
+        let mut result = Object::Void { loc: None };
+        if let Object::Module { value, .. } = &parse(&mut tokens)? {
+            for form in value.iter() {
+                result = vm.eval(form, &env)?;
+            }
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn test_vm() {
+        assert!(matches!(run("(+ 1 2)").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(if (< 1 2) 'yes 'no)").unwrap(), Object::Symbol { value, .. } if value == "yes"));
+        assert!(matches!(run("(and 1 #f 3)").unwrap(), Object::Bool { value: false, .. }));
+        assert!(matches!(run("(or #f 2 3)").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(if :a :b :c)").unwrap(), Object::Keyword { value, .. } if value == "b"));
+        assert!(matches!(run("(let ((x 1) (y 2)) (define z 3) (+ x y z))").unwrap(), Object::Integer { value: 6, .. }));
+        let prog = "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))\n(fact 10)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 3628800, .. }));
+        assert_eq!(run("(define (f x) x)\n(define (g . xs) xs)\n(list f g (g 1 2))").unwrap().to_string(), "(#<procedure f> #<procedure g> (1 2))");
+        assert!(matches!(run("(cond (#f 1) (else 2))").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(cond (#f 1) ((+ 1 2)) (else 4))").unwrap(), Object::Integer { value: 3, .. }));
+        assert!(matches!(run("(cond (#f 1))").unwrap(), Object::Void { .. }));
+        assert_eq!(run("(let* ((x 1) (y (+ x 1)) (x (* y 3))) (list x y))").unwrap().to_string(), "(6 2)");
+        let prog = "(letrec ((even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))\n\
+                            (odd? (lambda (n) (if (= n 0) #f (even? (- n 1))))))\n\
+                      (list (even? 10) (odd? 10)))";
+        assert_eq!(run(prog).unwrap().to_string(), "(true false)");
+        // Forms left to the evaluator and macros defined at runtime
+        assert!(matches!(run("(cond (else 1) (#t 2))"), Err(EvalError::InvalidSyntax { .. })));
+        assert!(matches!(run("(case (* 2 3) ((5) 1) ((6 7) 2))").unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(run("(when (< 1 2) 'a 'b)").unwrap(), Object::Symbol { value, .. } if value == "b"));
+        assert!(matches!(run("(unless (< 1 2) 'a)").unwrap(), Object::Void { .. }));
+        let prog = "(define p (make-parameter 1))\n(define (f) (p))\n(list (parameterize ((p 2)) (f)) (f))";
+        assert_eq!(run(prog).unwrap().to_string(), "(2 1)");
+        let prog = "(defmacro unless (c e) `(if ,c #f ,e))\n(define (f x) (unless x 5))\n(f #f)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 5, .. }));
+        // Builtins calling back into closures
+        assert!(matches!(run("(fold + 0 (map (lambda (x) (* x x)) '(1 2 3)))").unwrap(), Object::Integer { value: 14, .. }));
+    }
+
+    #[test]
+    fn test_vm_tail_call() {
+        // Deep enough to overflow the stack without reusing the frame
+        let prog = "(define (loop n acc) (if (= n 0) acc (loop (- n 1) (+ acc 1))))\n(loop 100000 0)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 100000, .. }));
+        let prog = "(define (count n) (when (> n 0) (count (- n 1))))\n(count 100000)";
+        assert!(matches!(run(prog).unwrap(), Object::Void { .. }));
+        let prog = "(let loop ((n 100000) (acc 0)) (if (= n 0) acc (loop (- n 1) (+ acc 1))))";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 100000, .. }));
+        // The calls which are not in tail position do not nest in the calls of the VM
+        let prog = "(define (depth n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))\n(depth 9000)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 9000, .. }));
+    }
+
+    #[test]
+    fn test_vm_stack_arguments() {
+        let arguments = |prog: &str| {
+            let mut tokens = tokenize("vm_test.rs", prog).unwrap();
+            let Object::Module { value, .. } = &parse(&mut tokens).unwrap() else { unreachable!() };
+            match &compile(&value[0]).code[0] {
+                Op::Lambda(_, Some(code), _) => code.arguments.clone().map(|names| names.len()),
+                op => panic!("Expect a lambda but {:?} found", op),
+            }
+        };
+        assert_eq!(arguments("(define (f x y) (if (< x y) (f (+ x 1) y) x))"), Some(2));
+        assert_eq!(arguments("(define (f x . rest) x)"), None);
+        assert_eq!(arguments("(define (f x) (lambda () x))"), None);
+        assert_eq!(arguments("(define (f x) (let ((y x)) y))"), None);
+
+        // The arguments are bound in an environment when the code needs one
+        assert!(matches!(run("(define (f x) (eval 'x))\n(f 42)").unwrap(), Object::Integer { value: 42, .. }));
+        let prog = "(define (f x) (twice x))\n(defmacro twice (e) `(* 2 ,e))\n(define (g x) (set! x (+ x 1)) x)\n(list (f 4) (g 1))";
+        assert_eq!(run(prog).unwrap().to_string(), "(8 2)");
+        assert!(matches!(run("(define (f x x) x)\n(f 1 2)").unwrap(), Object::Integer { value: 2, .. }));
+        let err = run("(define (f x) (car x))\n(define (g x) (+ 1 (f x)))\n(g 1)").unwrap_err();
+        assert!(matches!(&err, EvalError::TypeMismatch { .. }));
+        // The arithmetic overflowing the integers is left to the builtins
+        let prog = "(define (square x) (* x x))\n(square 170141183460469231731687303715884105727)";
+        assert!(matches!(run(prog).unwrap(), Object::BigInteger { .. }));
+    }
+
+    #[test]
+    fn test_vm_lexical_addressing() {
+        let mut tokens = tokenize("vm_test.rs", "(lambda (x y) (let ((z x)) (set! y z) (+ x z g)))").unwrap();
+        let form = match &parse(&mut tokens).unwrap() {
+            Object::Module { value, .. } => value[0].clone(),
+            obj => panic!("Expect a module but {} found", obj),
+        };
+        let code = match &compile(&form).code[..] {
+            [Op::Lambda(_, Some(code), _)] => code.clone(),
+            code => panic!("Expect a lambda but {:?} found", code),
+        };
+        let addresses: Vec<_> = code
+            .code
+            .iter()
+            .filter_map(|op| match op {
+                Op::LoadLocal(name, depth, slot, _) | Op::SetLocal(name, depth, slot, _) => {
+                    Some((name.as_str(), *depth, *slot))
+                },
+                Op::Load(name, ..) => Some((name.as_str(), usize::MAX, usize::MAX)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(addresses, [
+            ("x", 0, 0),
+            ("z", 0, 0),
+            ("y", 1, 1),
+            ("+", usize::MAX, usize::MAX),
+            ("x", 1, 0),
+            ("z", 0, 0),
+            ("g", usize::MAX, usize::MAX),
+        ]);
+
+        // Closures see the frames they are created in
+        let prog = "(define (counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n)))\n\
+                    (define c (counter))\n(c)\n(c)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 2, .. }));
+        assert!(matches!(&run("(((lambda (x) (lambda (y) (list x y))) 1) 2)").unwrap(),
+            Object::List { value, .. } if value.len() == 2));
+        // A variable defined later shadows the one of an outer frame
+        let prog = "(define (f x) (let ((y 1)) (define x 10) (set! x (+ x 1)) (+ x y)))\n(f 0)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 12, .. }));
+        let prog = "(define (f x) (let ((y 1)) (eval '(define x 5)) x))\n(f 0)";
+        assert!(matches!(run(prog).unwrap(), Object::Integer { value: 5, .. }));
+        assert!(matches!(run("(let ((x 1) (x 2)) x)").unwrap(), Object::Integer { value: 2, .. }));
+    }
+
+    #[test]
+    fn test_vm_errors() {
+        assert!(matches!(run("(+ 1 undefined)"), Err(EvalError::UndefinedSymbol { name, .. }) if name == "undefined"));
+        assert!(matches!(run("(1 2)"), Err(EvalError::NotCallable { .. })));
+        assert!(matches!(run("(set! y 1)"), Err(EvalError::UndefinedSymbol { .. })));
+        let err = run("(define (f x) x)\n(f 1 2)").unwrap_err();
+        assert!(matches!(&err, EvalError::ArityMismatch { .. }));
+        assert_eq!(err.loc().map(|loc| loc.rol()), Some(2));
+        assert!(matches!(run("(if)"), Err(EvalError::InvalidSyntax { .. })));
+    }
+}
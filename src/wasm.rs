@@ -0,0 +1,66 @@
+//! The bindings of the playground running rslisp in the browser, built with
+//! `wasm-pack build --target web -- --features wasm`
+//!
+//! The browser has no files, processes nor environment variables so the
+//! builtins acting on them are disabled, see [`Interpreter::set_sandbox`],
+//! and what the programs print is returned with their value.
+
+use wasm_bindgen::prelude::*;
+
+use crate::interpreter::Interpreter;
+use crate::parser::{Object, Written};
+
+/// An interpreter keeping the definitions of the programs between evaluations
+#[wasm_bindgen]
+pub struct Playground {
+    interp: Interpreter,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Playground {
+        let mut interp = Interpreter::new();
+        interp.set_sandbox(&[]);
+        interp.set_capture_output(true);
+        Playground { interp }
+    }
+
+    /// Evaluate the source, the result is the output of the program followed
+    /// by the value of its last expression, or by the rendered error it fails with
+    pub fn eval_str(&self, source: &str) -> Result<String, String> {
+        let result = self.interp.eval_str(source);
+        let mut text = self.interp.take_output();
+        match result {
+            Ok(Object::Void { .. }) => Ok(text),
+            Ok(obj) => {
+                text.push_str(&Written(&obj).to_string());
+                Ok(text)
+            },
+            Err(e) => {
+                text.push_str(&self.interp.render_error(&e));
+                Err(text)
+            },
+        }
+    }
+}
+
+impl Default for Playground {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playground() {
+        let playground = Playground::new();
+        assert_eq!(playground.eval_str("(define x 2) (display \"x=\") (display x) (newline) (* x 3)").unwrap(), "x=2\n6");
+        assert_eq!(playground.eval_str("(define y 1)").unwrap(), "");
+        let err = playground.eval_str("(display 1) (open-input-file \"/etc/passwd\")").unwrap_err();
+        assert!(err.starts_with("1error: open-input-file is not allowed in the sandbox"), "{}", err);
+    }
+}